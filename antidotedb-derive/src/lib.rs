@@ -0,0 +1,320 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+// #[derive(AntidoteMap)], see antidotedb::record for the field attributes
+
+#[proc_macro_derive(AntidoteMap, attributes(antidote))]
+pub fn derive_antidote_map(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+// Input the derive refuses, checked as compile_fail doctests. The first
+// example is the baseline the others break.
+
+/// ```
+/// use antidotedb::record::AntidoteMap;
+///
+/// #[derive(AntidoteMap, Default)]
+/// struct Inner {
+///     name: String,
+/// }
+///
+/// #[derive(AntidoteMap)]
+/// struct User {
+///     #[antidote(counter)]
+///     visits: i32,
+///     #[antidote(orset, key = "labels")]
+///     tags: Vec<String>,
+///     #[antidote(map)]
+///     inner: Inner,
+/// }
+/// ```
+///
+/// Only structs with named fields:
+///
+/// ```compile_fail
+/// use antidotedb::record::AntidoteMap;
+///
+/// #[derive(AntidoteMap)]
+/// enum User {
+///     Anonymous,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use antidotedb::record::AntidoteMap;
+///
+/// #[derive(AntidoteMap)]
+/// struct User(i32);
+/// ```
+///
+/// Unknown CRDTs, more than one per field, and keys that are not strings:
+///
+/// ```compile_fail
+/// use antidotedb::record::AntidoteMap;
+///
+/// #[derive(AntidoteMap)]
+/// struct User {
+///     #[antidote(lww)]
+///     name: String,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use antidotedb::record::AntidoteMap;
+///
+/// #[derive(AntidoteMap)]
+/// struct User {
+///     #[antidote(orset, rwset)]
+///     tags: Vec<String>,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use antidotedb::record::AntidoteMap;
+///
+/// #[derive(AntidoteMap)]
+/// struct User {
+///     #[antidote(key = 1)]
+///     name: String,
+/// }
+/// ```
+///
+/// Field types the CRDT cannot hold:
+///
+/// ```compile_fail
+/// use antidotedb::record::AntidoteMap;
+///
+/// #[derive(AntidoteMap)]
+/// struct User {
+///     #[antidote(counter)]
+///     visits: String,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use antidotedb::record::AntidoteMap;
+///
+/// #[derive(AntidoteMap)]
+/// struct User {
+///     score: f64,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use antidotedb::record::AntidoteMap;
+///
+/// #[derive(AntidoteMap)]
+/// struct User {
+///     #[antidote(map)]
+///     address: String,
+/// }
+/// ```
+#[cfg(doctest)]
+struct RejectedInput;
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Counter,
+    FatCounter,
+    BCounter,
+    LWWReg,
+    MVReg,
+    ORSet,
+    RWSet,
+    FlagEW,
+    FlagDW,
+    RRMap,
+    GMap,
+}
+
+impl Kind {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "counter" => Some(Kind::Counter),
+            "fatcounter" => Some(Kind::FatCounter),
+            "bcounter" => Some(Kind::BCounter),
+            "lwwreg" => Some(Kind::LWWReg),
+            "mvreg" => Some(Kind::MVReg),
+            "orset" => Some(Kind::ORSet),
+            "rwset" => Some(Kind::RWSet),
+            "flag_ew" => Some(Kind::FlagEW),
+            "flag_dw" => Some(Kind::FlagDW),
+            "map" | "rrmap" => Some(Kind::RRMap),
+            "gmap" => Some(Kind::GMap),
+            _ => None,
+        }
+    }
+
+    fn crdt(self) -> TokenStream2 {
+        let name = match self {
+            Kind::Counter => "Counter",
+            Kind::FatCounter => "FatCounter",
+            Kind::BCounter => "BCounter",
+            Kind::LWWReg => "LWWReg",
+            Kind::MVReg => "MVReg",
+            Kind::ORSet => "ORSet",
+            Kind::RWSet => "RWSet",
+            Kind::FlagEW => "FlagEW",
+            Kind::FlagDW => "FlagDW",
+            Kind::RRMap => "RRMap",
+            Kind::GMap => "GMap",
+        };
+        let name = format_ident!("{}", name);
+        quote!(::antidotedb::crdt::#name)
+    }
+}
+
+struct Field {
+    ident: syn::Ident,
+    key: String,
+    kind: Kind,
+}
+
+fn parse_field(field: &syn::Field) -> syn::Result<Field> {
+    let ident = field.ident.clone().expect("named field");
+    let mut key = ident.to_string();
+    let mut kind = None;
+
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("antidote")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("key") {
+                key = meta.value()?.parse::<LitStr>()?.value();
+                return Ok(());
+            }
+            let name = meta
+                .path
+                .get_ident()
+                .map(|ident| ident.to_string())
+                .unwrap_or_default();
+            match Kind::parse(&name) {
+                Some(_) if kind.is_some() => Err(meta.error("only one CRDT per field")),
+                Some(k) => {
+                    kind = Some(k);
+                    Ok(())
+                }
+                None => Err(meta.error("unknown antidote attribute")),
+            }
+        })?;
+    }
+
+    Ok(Field {
+        ident,
+        key,
+        kind: kind.unwrap_or(Kind::LWWReg),
+    })
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "AntidoteMap needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "AntidoteMap can only be derived for structs",
+            ))
+        }
+    };
+    let fields = fields
+        .iter()
+        .map(parse_field)
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let private = quote!(::antidotedb::record::__private);
+
+    let key_fns = fields.iter().map(|field| {
+        let key_fn = format_ident!("{}_key", field.ident);
+        let key = &field.key;
+        let crdt = field.kind.crdt();
+        quote! {
+            pub fn #key_fn() -> ::antidotedb::crdt::MapKey<#crdt> {
+                ::antidotedb::crdt::MapKey::new(#key)
+            }
+        }
+    });
+
+    let reads = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let key = &field.key;
+        let crdt = field.kind.crdt();
+        let read = match field.kind {
+            Kind::Counter | Kind::FatCounter | Kind::BCounter => {
+                quote!(#private::counter::<#crdt, _>(view, #key))
+            }
+            Kind::FlagEW | Kind::FlagDW => quote!(#private::flag::<#crdt>(view, #key)),
+            Kind::LWWReg => quote!(#private::register::<#crdt, _>(view, #key)?),
+            Kind::MVReg | Kind::ORSet | Kind::RWSet => {
+                quote!(#private::values::<#crdt, _, _>(view, #key)?)
+            }
+            Kind::RRMap | Kind::GMap => quote!(#private::map::<#crdt, _>(view, #key)?),
+        };
+        quote!(#ident: #read)
+    });
+
+    let updates = fields.iter().filter_map(|field| {
+        let ident = &field.ident;
+        let key_fn = format_ident!("{}_key", field.ident);
+        match field.kind {
+            Kind::Counter | Kind::FatCounter | Kind::BCounter => None,
+            Kind::FlagEW | Kind::FlagDW => {
+                Some(quote!(updates.push(Self::#key_fn().set_flag(self.#ident));))
+            }
+            Kind::LWWReg => Some(quote!(updates.push(Self::#key_fn().set_value(&self.#ident));)),
+            Kind::MVReg => Some(quote! {
+                if let Some(value) = #private::single(&self.#ident) {
+                    updates.push(Self::#key_fn().set_value(value));
+                }
+            }),
+            Kind::ORSet | Kind::RWSet => {
+                Some(quote!(updates.push(Self::#key_fn().add_values(&self.#ident));))
+            }
+            Kind::RRMap | Kind::GMap => Some(quote! {
+                updates.push(Self::#key_fn().update(
+                    ::antidotedb::record::AntidoteMap::updates(&self.#ident),
+                    ::std::vec::Vec::new(),
+                ));
+            }),
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#key_fns)*
+        }
+
+        impl #impl_generics ::antidotedb::record::AntidoteMap for #name #ty_generics #where_clause {
+            fn from_view(
+                view: &::antidotedb::view::MapView,
+            ) -> ::std::result::Result<Self, ::antidotedb::record::DecodeError> {
+                ::std::result::Result::Ok(#name {
+                    #(#reads,)*
+                })
+            }
+
+            fn updates(&self) -> ::std::vec::Vec<::antidotedb::crdt::MapUpdate> {
+                #[allow(unused_mut)]
+                let mut updates = ::std::vec::Vec::new();
+                #(#updates)*
+                updates
+            }
+        }
+    })
+}
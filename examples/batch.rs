@@ -0,0 +1,85 @@
+extern crate antidotedb;
+
+use antidotedb::batch::Batch;
+use antidotedb::crdt::{Counter, CounterT, Object};
+use antidotedb::server::StandIn;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::Instant;
+
+// Allocations made by the client to send the same increments as update ops
+// and as a batch, to the in-process stand-in. Only the client thread's
+// allocations are counted, the stand-in's run on its own threads.
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const OBJECTS: usize = 100;
+const ROUNDS: usize = 1000;
+
+fn allocations() -> u64 {
+    ALLOCATIONS.with(Cell::get)
+}
+
+fn main() {
+    let stand_in = StandIn::start().expect("failed to start stand-in");
+    let mut db = stand_in.connect().expect("failed to connect");
+
+    let counters: Vec<_> = (0..OBJECTS)
+        .map(|i| Counter::new(&format!("visits/{}", i), "bench"))
+        .collect();
+
+    let before = allocations();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let ops: Vec<_> = counters.iter().map(|counter| counter.inc(1)).collect();
+        let _ = db.static_update_objects(&ops, None);
+    }
+    report("update ops", allocations() - before, start);
+
+    let mut batch = Batch::new();
+    let inc = Counter::inc_op(1);
+    let before = allocations();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        batch.clear();
+        for counter in &counters {
+            batch.push(counter, &inc);
+        }
+        let _ = db.static_update_batch(&batch, None);
+    }
+    report("batch", allocations() - before, start);
+}
+
+fn report(name: &str, allocations: u64, start: Instant) {
+    let updates = (OBJECTS * ROUNDS) as f64;
+    println!(
+        "{:<12} {:>8.2} allocations/update {:>8.0} ns/update",
+        name,
+        allocations as f64 / updates,
+        start.elapsed().as_nanos() as f64 / updates
+    );
+}
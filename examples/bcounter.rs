@@ -0,0 +1,32 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{BCounter, CounterT, Object};
+use antidotedb::{AntidoteDB, Error, ErrorCode};
+
+fn main() {
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+
+    let stock = BCounter::new("123", "bcounter_test");
+
+    db.static_update_objects(&[stock.inc(2)], None)
+        .expect("failed to update");
+
+    let txn = db.start_transaction(None).expect("failed to start");
+
+    // more than what is in stock
+    let take = stock.dec(5).expect("decrement fits");
+    match db.mult_update_in_transaction(&[take], &txn) {
+        Err(Error::Rejected(ErrorCode::NoPermissions)) => println!("not enough in stock"),
+        other => println!("{:?}", other),
+    }
+
+    db.abort_transaction(&txn).expect("failed to abort");
+
+    let (values, _) = db
+        .static_read_objects(std::slice::from_ref(&stock), None)
+        .expect("failed to read");
+
+    for v in values {
+        println!("{:?}", BCounter::value(&v));
+    }
+}
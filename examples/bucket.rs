@@ -0,0 +1,30 @@
+extern crate antidotedb;
+
+use antidotedb::bucket::Set;
+use antidotedb::crdt::{CounterT, ORSet, ReadT};
+use antidotedb::AntidoteDB;
+
+fn main() {
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+
+    // every key of this bucket is prefixed with "tenant42/"
+    let users = db.bucket("users").with_prefix("tenant42/");
+
+    let visits = users.counter("visits");
+    let tags = users.orset::<String>("tags");
+
+    let txn = db.start_transaction(None).expect("failed to start");
+
+    let new_tags = vec!["rust".to_string(), "crdt".to_string()];
+    db.mult_update_in_transaction(&[visits.inc_one(), tags.add(&new_tags)], &txn)
+        .expect("failed to update");
+
+    let values = db
+        .mult_read_in_transaction(std::slice::from_ref(&tags), &txn)
+        .expect("failed to read");
+    for v in values {
+        println!("{:?}", Set::<ORSet, String>::decode(&v));
+    }
+
+    db.commit_transaction(&txn).expect("failed to commit");
+}
@@ -0,0 +1,41 @@
+extern crate antidotedb;
+
+use antidotedb::cluster::{Cluster, ClusterSession, DataCenter};
+use antidotedb::crdt::{Counter, CounterT, Object};
+
+use std::time::Duration;
+
+fn main() {
+    let cluster = Cluster::new(DataCenter::new("eu", &["localhost:8087", "localhost:8187"]))
+        .with_dc(DataCenter::new("us", &["localhost:8287", "localhost:8387"]))
+        .with_connect_timeout(Duration::from_millis(500))
+        // static reads not answered within 20ms are also sent to another node
+        .with_hedged_reads(Duration::from_millis(20));
+
+    let mut session = ClusterSession::connect(cluster).expect("no data center reachable");
+
+    let counter = Counter::new("123", "cluster_test");
+
+    session
+        .static_update_objects(&[counter.inc_one()])
+        .expect("failed to update");
+
+    // if the serving DC went down in between, this transaction is started in
+    // another DC, after the update above
+    let txn = session.start_transaction().expect("failed to start");
+
+    let values = session
+        .mult_read_in_transaction(std::slice::from_ref(&counter), &txn)
+        .expect("failed to read");
+
+    for v in values {
+        println!("{:?}", v.get_counter().get_value());
+    }
+
+    session.commit_transaction(&txn).expect("failed to commit");
+
+    println!(
+        "served by {}",
+        session.current_dc().map(DataCenter::name).unwrap_or("-")
+    );
+}
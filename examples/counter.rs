@@ -4,8 +4,8 @@ use antidotedb::crdt::{CounterT, FatCounter, Object, ObjectReset};
 use antidotedb::AntidoteDB;
 
 fn main() {
-    let mut db = AntidoteDB::connect("localhost", 8087);
-    let txn = db.start_transaction(None);
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+    let txn = db.start_transaction(None).expect("failed to start");
 
     // usage of Counter is same as FatCounter except Counter does not support reset.
     let counter = FatCounter::new("123", "fatcounter_test");
@@ -27,7 +27,7 @@ fn main() {
     .expect("failed to update");
 
     let values = db
-        .mult_read_in_transaction(&[counter.clone()], &txn)
+        .mult_read_in_transaction(std::slice::from_ref(&counter), &txn)
         .expect("failed to read");
 
     for v in values {
@@ -37,10 +37,12 @@ fn main() {
 
     let commit_time = db.commit_transaction(&txn).expect("failed to commit");
 
-    let txn2 = db.start_transaction(Some(&commit_time));
+    let txn2 = db
+        .start_transaction(Some(&commit_time))
+        .expect("failed to start");
 
     let values = db
-        .mult_read_in_transaction(&[counter.clone()], &txn2)
+        .mult_read_in_transaction(std::slice::from_ref(&counter), &txn2)
         .expect("failed to read");
 
     for v in values {
@@ -0,0 +1,48 @@
+extern crate antidotedb;
+
+use antidotedb::server::StandIn;
+use antidotedb::AntidoteDB;
+
+use std::env;
+
+// Usage: dc_admin [<dc1 address> <dc2 address>]
+// Without addresses it runs against two stand-in servers.
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let stand_ins;
+    let addresses = if args.len() == 2 {
+        args
+    } else {
+        stand_ins = [
+            StandIn::start().expect("failed to start stand-in"),
+            StandIn::start().expect("failed to start stand-in"),
+        ];
+        stand_ins.iter().map(StandIn::address).collect()
+    };
+
+    let mut dc1 = AntidoteDB::connect_with_string(&addresses[0]).expect("failed to connect");
+    let mut dc2 = AntidoteDB::connect_with_string(&addresses[1]).expect("failed to connect");
+
+    dc1.create_dc(&["antidote@node1"])
+        .expect("failed to create dc1");
+    dc2.create_dc(&["antidote@node2"])
+        .expect("failed to create dc2");
+
+    let descriptor1 = dc1
+        .get_connection_descriptor()
+        .expect("failed to get descriptor");
+    let descriptor2 = dc2
+        .get_connection_descriptor()
+        .expect("failed to get descriptor");
+
+    // both DCs are given all descriptors, as antidote expects
+    let descriptors = [descriptor1, descriptor2];
+    dc1.connect_to_dcs(&descriptors)
+        .expect("failed to connect dc1");
+    dc2.connect_to_dcs(&descriptors)
+        .expect("failed to connect dc2");
+
+    println!("connected {} and {}", addresses[0], addresses[1]);
+}
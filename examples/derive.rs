@@ -0,0 +1,60 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{MapT, Object, RRMap};
+use antidotedb::record::AntidoteMap;
+use antidotedb::AntidoteDB;
+
+#[derive(AntidoteMap, Debug, Default)]
+struct Address {
+    city: String,
+    zip: u64,
+}
+
+#[derive(AntidoteMap, Debug, Default)]
+struct User {
+    #[antidote(counter)]
+    visits: i32,
+    #[antidote(orset)]
+    tags: Vec<String>,
+    #[antidote(mvreg, key = "display_name")]
+    names: Vec<String>,
+    #[antidote(flag_ew)]
+    active: bool,
+    #[antidote(map)]
+    address: Address,
+}
+
+fn main() {
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+    let txn = db.start_transaction(None).expect("failed to start");
+
+    let map = RRMap::new("123", "derive_test");
+
+    let user = User {
+        visits: 0,
+        tags: vec!["admin".to_string()],
+        names: vec!["Ada".to_string()],
+        active: true,
+        address: Address {
+            city: "Paris".to_string(),
+            zip: 75001,
+        },
+    };
+
+    // counters are written through their typed key
+    let visit = map.apply(vec![User::visits_key().inc_one()], Vec::new());
+
+    db.mult_update_in_transaction(&[user.write(&map), visit], &txn)
+        .expect("failed to update");
+
+    let values = db
+        .mult_read_in_transaction(std::slice::from_ref(&map), &txn)
+        .expect("failed to read");
+
+    for v in values {
+        let user = User::read(&v).expect("failed to decode");
+        println!("{} visits: {:?}", user.visits, user);
+    }
+
+    db.commit_transaction(&txn).expect("failed to commit");
+}
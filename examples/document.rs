@@ -0,0 +1,43 @@
+extern crate antidotedb;
+extern crate serde_json;
+
+use antidotedb::document::Document;
+use antidotedb::AntidoteDB;
+
+use serde_json::json;
+
+fn main() {
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+
+    let doc = Document::new("order_1", "documents");
+
+    let order = json!({
+        "customer": { "name": "Ada", "vip": true },
+        "items": ["apple", "pear"],
+        "total": 12.5
+    });
+
+    let txn = db.start_transaction(None).expect("failed to start");
+    let ops = doc.write_ops(&order).expect("not a document");
+    db.mult_update_in_transaction(&ops, &txn)
+        .expect("failed to update");
+    db.commit_transaction(&txn).expect("failed to commit");
+
+    // null removes a field, other fields are left alone
+    let patch = json!({ "total": null, "customer": { "vip": false } });
+
+    let txn = db.start_transaction(None).expect("failed to start");
+    let ops = doc.patch_ops(&patch).expect("not a document");
+    db.mult_update_in_transaction(&ops, &txn)
+        .expect("failed to update");
+
+    let values = db
+        .mult_read_in_transaction(&[doc.object().clone()], &txn)
+        .expect("failed to read");
+
+    for v in values {
+        println!("{}", Document::read(&v).expect("failed to decode"));
+    }
+
+    db.commit_transaction(&txn).expect("failed to commit");
+}
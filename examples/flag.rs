@@ -4,14 +4,14 @@ use antidotedb::crdt::{FlagEW, FlagT, Object};
 use antidotedb::AntidoteDB;
 
 fn main() {
-    let mut db = AntidoteDB::connect("localhost", 8087);
-    let txn = db.start_transaction(None);
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+    let txn = db.start_transaction(None).expect("failed to start");
 
     // usage of FlagDW is same as FlagEW.
     let flag = FlagEW::new("123", "flagew_test");
 
     let values = db
-        .mult_read_in_transaction(&[flag.clone()], &txn)
+        .mult_read_in_transaction(std::slice::from_ref(&flag), &txn)
         .expect("failed to read");
 
     for v in values {
@@ -21,7 +21,9 @@ fn main() {
 
     let commit_time = db.commit_transaction(&txn).expect("failed to commit");
 
-    let txn2 = db.start_transaction(Some(&commit_time));
+    let txn2 = db
+        .start_transaction(Some(&commit_time))
+        .expect("failed to start");
 
     let op = flag.set(true);
 
@@ -29,7 +31,7 @@ fn main() {
         .expect("failed to update");
 
     let values = db
-        .mult_read_in_transaction(&[flag.clone()], &txn2)
+        .mult_read_in_transaction(std::slice::from_ref(&flag), &txn2)
         .expect("failed to read");
 
     for v in values {
@@ -43,7 +45,7 @@ fn main() {
         .expect("failed to update");
 
     let values = db
-        .mult_read_in_transaction(&[flag.clone()], &txn2)
+        .mult_read_in_transaction(std::slice::from_ref(&flag), &txn2)
         .expect("failed to read");
 
     for v in values {
@@ -54,10 +56,12 @@ fn main() {
     println!("aborting");
     db.abort_transaction(&txn2).expect("failed to abort");
 
-    let txn2 = db.start_transaction(Some(&commit_time));
+    let txn2 = db
+        .start_transaction(Some(&commit_time))
+        .expect("failed to start");
 
     let values = db
-        .mult_read_in_transaction(&[flag.clone()], &txn2)
+        .mult_read_in_transaction(std::slice::from_ref(&flag), &txn2)
         .expect("failed to read");
 
     for v in values {
@@ -0,0 +1,84 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{Counter, CounterT, LWWReg, ORSet, Object, RegT, SetT};
+use antidotedb::history::consistency::{self, Level};
+use antidotedb::history::{self, History, Recorder};
+use antidotedb::server::StandIn;
+
+use std::thread;
+
+// txn 2 sees one of the two elements txn 1 added, txn 4 misses the increment
+// of its own session, and txn 6 sees txn 5 but not txn 1, which txn 5 saw
+const ANOMALIES: &str = r#"{"format":"antidote-history","version":2}
+{"txn":1,"session":0,"static":true,"snapshot":null,"commit_time":"v1.AQQMW4w","outcome":"committed","ops":[{"update":"orset h/a add {x}"},{"update":"orset h/b add {x}"}]}
+{"txn":2,"session":1,"static":false,"snapshot":null,"commit_time":"v1.AQQMW4w","outcome":"committed","ops":[{"read":"orset h/a","value":["x"]},{"read":"orset h/b","value":[]}]}
+{"txn":3,"session":2,"static":true,"snapshot":null,"commit_time":"v1.AgcMYEU","outcome":"committed","ops":[{"update":"counter h/c inc 2"}]}
+{"txn":4,"session":2,"static":true,"snapshot":"v1.AgcMYEU","commit_time":"v1.AgcMYEU","outcome":"committed","ops":[{"read":"counter h/c","value":0}]}
+{"txn":5,"session":3,"static":false,"snapshot":null,"commit_time":"v1.AwYMXrI","outcome":"committed","ops":[{"read":"orset h/a","value":["x"]},{"update":"lwwreg h/r set v"}]}
+{"txn":6,"session":4,"static":false,"snapshot":null,"commit_time":"v1.AwYMXrI","outcome":"committed","ops":[{"read":"lwwreg h/r","value":"v"},{"read":"orset h/b","value":[]}]}
+"#;
+
+fn main() {
+    let stand_in = StandIn::start().expect("failed to start stand-in");
+    let path = std::env::temp_dir().join("antidote-history.jsonl");
+    let recorder = Recorder::create(&path).expect("failed to create history");
+
+    // sessions add their own elements and count them, then read everything
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let mut session = recorder.session(stand_in.connect().expect("failed to connect"));
+            thread::spawn(move || {
+                let set = ORSet::new("elements", "history");
+                let count = Counter::new("count", "history");
+                let last = LWWReg::new("last", "history");
+                for j in 0..10u64 {
+                    let t = session.start_transaction().expect("failed to start");
+                    session
+                        .mult_read_in_transaction(std::slice::from_ref(&set), &t)
+                        .expect("failed to read");
+                    let updates = [set.add(&[i * 100 + j]), count.inc(1), last.set(i * 100 + j)];
+                    session
+                        .mult_update_in_transaction(&updates, &t)
+                        .expect("failed to update");
+                    session.commit_transaction(&t).expect("failed to commit");
+                }
+                session
+                    .static_read_objects(std::slice::from_ref(&count))
+                    .expect("failed to read");
+                session
+                    .static_read_objects(std::slice::from_ref(&last))
+                    .expect("failed to read");
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert!(recorder.take_error().is_none(), "history not fully written");
+    drop(recorder);
+
+    let recorded = History::load(&path).expect("failed to load history");
+    println!(
+        "{} transactions recorded in {}",
+        recorded.transactions.len(),
+        path.display()
+    );
+    let anomalies = history::check(&recorded);
+    assert!(anomalies.is_empty(), "{:?}", anomalies);
+
+    // Antidote promises causal consistency, concurrent transactions may
+    // well not be serializable
+    let report = consistency::check(&recorded);
+    assert!(report.satisfies(Level::Causal));
+
+    let doctored = History::read(ANOMALIES.as_bytes()).expect("invalid history");
+    for anomaly in history::check(&doctored) {
+        println!("{}", anomaly);
+    }
+    let report = consistency::check(&doctored);
+    for &level in &Level::ALL {
+        if let Some(violation) = report.counterexample(level) {
+            println!("not {}: {}", level, violation);
+        }
+    }
+}
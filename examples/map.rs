@@ -1,33 +1,62 @@
 extern crate antidotedb;
 
-use antidotedb::crdt::{Counter, LWWReg, ORSet, RRMap};
-use antidotedb::crdt::{CounterT, MapT, RegT, SetT};
-use antidotedb::crdt::{Object, ObjectReset};
+use antidotedb::crdt::{number_name, Counter, LWWReg, MapKey, ORSet, RRMap};
+use antidotedb::crdt::{MapT, Object, ObjectReset, ReadT};
 use antidotedb::AntidoteDB;
 
 fn main() {
-    let mut db = AntidoteDB::connect("localhost", 8087);
-    let txn = db.start_transaction(None);
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+    let txn = db.start_transaction(None).expect("failed to start");
 
     let map = RRMap::new("123", "rrmap_test");
 
-    let nestedupdates = [
-        (Counter::map_key("counter"), Counter::inc_op(2)),
-        (ORSet::map_key("orset"), ORSet::add_op(&[2, 1])),
-        (LWWReg::map_key("lwwreg"), LWWReg::set_op(3)),
+    // typed keys only offer the operations of their CRDT
+    let counter = MapKey::<Counter>::new("counter");
+    let orset = MapKey::<ORSet>::new("orset");
+    let lwwreg = MapKey::<LWWReg>::new("lwwreg");
+    let nested = MapKey::<RRMap>::new("nested");
+
+    let nestedupdates = vec![
+        counter.inc(2),
+        orset.add(&[2, 1]),
+        lwwreg.set(3),
+        nested.update(
+            vec![MapKey::<Counter>::new("counter").inc_one()],
+            Vec::new(),
+        ),
     ];
 
     db.mult_update_in_transaction(&[map.reset()], &txn)
         .expect("failed to reset");
 
-    db.mult_update_in_transaction(&[map.update(&nestedupdates, &[])], &txn)
+    db.mult_update_in_transaction(&[map.apply(nestedupdates, Vec::new())], &txn)
         .expect("failed to update");
 
+    db.mult_update_in_transaction(&[map.apply(Vec::new(), vec![lwwreg.remove()])], &txn)
+        .expect("failed to remove");
+
     let values = db
-        .mult_read_in_transaction(&[map.clone()], &txn)
+        .mult_read_in_transaction(std::slice::from_ref(&map), &txn)
         .expect("failed to read");
 
-    println!("{:?}", values);
+    for v in values {
+        let view = RRMap::decode(&v);
+
+        println!("{:?}", view.get::<Counter>("counter"));
+        println!(
+            "{:?}",
+            view.path(&["nested"])
+                .and_then(|nested| nested.get::<Counter>("counter"))
+        );
+
+        for entry in view.iter() {
+            println!(
+                "{} {}",
+                String::from_utf8_lossy(entry.key()),
+                number_name(entry.type_number())
+            );
+        }
+    }
 
     db.commit_transaction(&txn).expect("failed to commit");
 }
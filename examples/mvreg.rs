@@ -1,24 +1,25 @@
 extern crate antidotedb;
 
 use antidotedb::crdt::{MVReg, Object, ObjectReset, RegT};
+use antidotedb::resolve::Resolver;
 use antidotedb::AntidoteDB;
 
 use byteorder::{BigEndian, ReadBytesExt};
 use std::io::Cursor;
 
 fn main() {
-    let mut db = AntidoteDB::connect("localhost", 8087);
-    let txn = db.start_transaction(None);
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+    let txn = db.start_transaction(None).expect("failed to start");
 
     let reg = MVReg::new("123", "mvreg_test");
 
     let op = reg.set(2);
 
     db.mult_update_in_transaction(&[reg.reset(), op], &txn)
-        .expect("failed to update");;
+        .expect("failed to update");
 
     let values = db
-        .mult_read_in_transaction(&[reg.clone()], &txn)
+        .mult_read_in_transaction(std::slice::from_ref(&reg), &txn)
         .expect("failed to read");
 
     for v in values {
@@ -33,10 +34,12 @@ fn main() {
 
     let commit_time = db.commit_transaction(&txn).expect("failed to commit");
 
-    let txn2 = db.start_transaction(Some(&commit_time));
+    let txn2 = db
+        .start_transaction(Some(&commit_time))
+        .expect("failed to start");
 
     let values = db
-        .mult_read_in_transaction(&[reg.clone()], &txn2)
+        .mult_read_in_transaction(std::slice::from_ref(&reg), &txn2)
         .expect("failed to read");
 
     for v in values {
@@ -52,10 +55,10 @@ fn main() {
     let op = reg.set(23);
 
     db.mult_update_in_transaction(&[op], &txn2)
-        .expect("failed to update");;
+        .expect("failed to update");
 
     let values = db
-        .mult_read_in_transaction(&[reg.clone()], &txn2)
+        .mult_read_in_transaction(std::slice::from_ref(&reg), &txn2)
         .expect("failed to read");
 
     for v in values {
@@ -72,11 +75,13 @@ fn main() {
 
     db.abort_transaction(&txn2).expect("failed to abort");
 
-    let txn2 = db.start_transaction(Some(&commit_time));
+    let txn2 = db
+        .start_transaction(Some(&commit_time))
+        .expect("failed to start");
 
     let values = db
-        .mult_read_in_transaction(&[reg.clone()], &txn2)
-        .expect("failed to read");;
+        .mult_read_in_transaction(std::slice::from_ref(&reg), &txn2)
+        .expect("failed to read");
 
     for v in values {
         let reg_r: Vec<_> = v
@@ -88,5 +93,14 @@ fn main() {
         println!("{:?}", reg_r);
     }
 
+    // concurrent values, if any, are merged into the largest one
+    let resolver = Resolver::new();
+    let value = resolver
+        .resolve_with(&mut db, &reg, &txn2, |values| {
+            values.iter().max().cloned().unwrap_or_default()
+        })
+        .expect("failed to resolve");
+    println!("resolved {:?}, {:?}", value, resolver.stats());
+
     db.commit_transaction(&txn2).expect("failed to commit");
 }
@@ -0,0 +1,51 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{Counter, CounterT, MapKey, MapT, ORSet, Object, RRMap, SetT};
+use antidotedb::ops;
+use antidotedb::AntidoteDB;
+
+const FIXTURE: &str = r#"
+# visits and tags of the front page
+counter web/visits inc 3
+orset web/tags add {news, "front page", 0x00ff} remove {old}
+rrmap web/"user:1" map {
+    lwwreg name set "Ada",
+    rrmap address map {
+        lwwreg city set Lisbon,
+        counter moves inc,
+    },
+    remove orset drafts,
+}
+flag_ew web/enabled enable
+"#;
+
+fn main() {
+    let updates = ops::parse_updates(FIXTURE).expect("invalid fixture");
+
+    // printed text parses back to the same updates
+    let text = ops::print_updates(&updates);
+    print!("{}", text);
+    assert_eq!(ops::parse_updates(&text).unwrap(), updates);
+
+    // as do updates built by the crate
+    let user = RRMap::new("user:2", "web");
+    let built = vec![
+        Counter::new("visits", "web").inc(-2),
+        ORSet::new("tags", "web").add(&[1, 2]),
+        user.apply(
+            vec![MapKey::<Counter>::new("logins").inc_one()],
+            vec![MapKey::<ORSet>::new("drafts").remove()],
+        ),
+    ];
+    let text = ops::print_updates(&built);
+    print!("{}", text);
+    assert_eq!(ops::parse_updates(&text).unwrap(), built);
+
+    if let Err(e) = ops::parse_update("counter web/visits inc 3 inc 4") {
+        println!("{}", e);
+    }
+
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+    db.static_update_objects(&updates, None)
+        .expect("failed to update");
+}
@@ -0,0 +1,22 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{ORSet, Object};
+use antidotedb::value::Value;
+use antidotedb::AntidoteDB;
+
+fn main() {
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+
+    let tags = ORSet::new("tags", "reconcile_test");
+    let desired = Value::Set(vec![b"rust".to_vec(), b"crdt".to_vec()]);
+
+    let txn = db.start_transaction(None).expect("failed to start");
+
+    // adds and removes only the tags that differ, concurrent adds are kept
+    let ops = db
+        .reconcile_in_transaction(&tags, &desired, &txn)
+        .expect("failed to reconcile");
+    println!("{} operations written", ops.len());
+
+    db.commit_transaction(&txn).expect("failed to commit");
+}
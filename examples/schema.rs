@@ -0,0 +1,28 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{Counter, CounterT, LWWReg, Object, RegT};
+use antidotedb::schema::{Checked, Schema};
+use antidotedb::{AntidoteDB, Error};
+
+const SCHEMA: &str = r#"
+[buckets.schema_test]
+"visits/*" = "counter"
+"name/*" = "lwwreg"
+"#;
+
+fn main() {
+    let schema = Schema::from_toml(SCHEMA).expect("invalid schema");
+    let db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+    let mut db = Checked::new(db, schema);
+
+    let visits = Counter::new("visits/home", "schema_test");
+    db.static_update_objects(&[visits.inc_one()], None)
+        .expect("failed to update");
+
+    // a register under a counter key never reaches the server
+    let wrong = LWWReg::new("visits/home", "schema_test");
+    match db.static_update_objects(&[wrong.set(1)], None) {
+        Err(Error::Schema(e)) => println!("rejected: {}", e),
+        other => println!("unexpected: {:?}", other),
+    }
+}
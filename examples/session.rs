@@ -0,0 +1,59 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{CounterT, FatCounter, Object};
+use antidotedb::session::{CommitTime, Session};
+use antidotedb::AntidoteDB;
+
+fn main() {
+    let db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+    let mut session = Session::new(db);
+
+    let counter = FatCounter::new("123", "session_test");
+
+    let txn = session.start_transaction().expect("failed to start");
+    session
+        .mult_update_in_transaction(&[counter.inc(3)], &txn)
+        .expect("failed to update");
+    session.commit_transaction(&txn).expect("failed to commit");
+
+    // no commit time to thread around, the session starts after its last commit
+    let txn2 = session.start_transaction().expect("failed to start");
+
+    let values = session
+        .mult_read_in_transaction(std::slice::from_ref(&counter), &txn2)
+        .expect("failed to read");
+
+    for v in values {
+        println!("{:?}", v.get_counter().get_value());
+    }
+
+    session.commit_transaction(&txn2).expect("failed to commit");
+
+    session
+        .static_update_objects(&[counter.inc_one()])
+        .expect("failed to update");
+
+    let values = session
+        .static_read_objects(std::slice::from_ref(&counter))
+        .expect("failed to read");
+
+    for v in values {
+        println!("{:?}", v.get_counter().get_value());
+    }
+
+    // the token can be handed to another process, e.g. in an http header
+    let token = session.token().expect("no commit time observed").to_token();
+    println!("session token: {}", token);
+
+    let commit_time: CommitTime = token.parse().expect("corrupted token");
+    let db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+    let mut other = Session::with_token(db, commit_time);
+
+    let values = other
+        .static_read_objects(std::slice::from_ref(&counter))
+        .expect("failed to read");
+
+    for v in values {
+        println!("{:?}", v.get_counter().get_value());
+    }
+}
@@ -7,18 +7,18 @@ use byteorder::{BigEndian, ReadBytesExt};
 use std::io::Cursor;
 
 fn main() {
-    let mut db = AntidoteDB::connect("localhost", 8087);
-    let txn = db.start_transaction(None);
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+    let txn = db.start_transaction(None).expect("failed to start");
 
     // usage of RWSet is same as ORSet.
     let set = ORSet::new("123", "orset_test");
 
     db.mult_update_in_transaction(&[set.reset(), set.add(&[1, 2, 3])], &txn)
-        .expect("failed to update");;
+        .expect("failed to update");
 
     let values = db
-        .mult_read_in_transaction(&[set.clone()], &txn)
-        .expect("failed to read");;
+        .mult_read_in_transaction(std::slice::from_ref(&set), &txn)
+        .expect("failed to read");
 
     for v in values {
         let set_r: Vec<_> = v
@@ -32,7 +32,9 @@ fn main() {
 
     let commit_time = db.commit_transaction(&txn).expect("failed to commit");
 
-    let txn2 = db.start_transaction(Some(&commit_time));
+    let txn2 = db
+        .start_transaction(Some(&commit_time))
+        .expect("failed to start");
 
     let values = db
         .mult_read_in_transaction(&[set.clone(), set.clone()], &txn2)
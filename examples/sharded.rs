@@ -0,0 +1,30 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::FatCounter;
+use antidotedb::sharded::ShardedCounter;
+use antidotedb::AntidoteDB;
+
+fn main() {
+    let mut db = AntidoteDB::connect("localhost", 8087).expect("failed to connect");
+
+    let visits = ShardedCounter::<FatCounter>::new("visits", "sharded_test", 8);
+
+    // each increment lands on one of the 8 shards
+    let ops: Vec<_> = (0..100).map(|page| visits.inc_by(&page, 1)).collect();
+    db.static_update_objects(&ops, None)
+        .expect("failed to update");
+
+    let txn = db.start_transaction(None).expect("failed to start");
+    let visits = visits
+        .reshard_in_transaction(&mut db, &txn, 2)
+        .expect("failed to reshard");
+    let total = visits
+        .read_in_transaction(&mut db, &txn)
+        .expect("failed to read");
+    println!("{} visits over {} shards", total, visits.shards().len());
+    db.commit_transaction(&txn).expect("failed to commit");
+
+    // all shards in one transaction
+    db.static_update_objects(&visits.reset(), None)
+        .expect("failed to reset");
+}
@@ -0,0 +1,97 @@
+use super::antidote;
+use super::{AntidoteDB, Error};
+
+use super::codec::{self, RepeatedField};
+
+// DC administration, for deployment tooling.
+//
+// A DC is created from a list of erlang nodes. Its connection descriptor is
+// opaque to the client: fetch it from one DC and pass it to the others to
+// make them replicate from it.
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ConnectionDescriptor(Vec<u8>);
+
+impl ConnectionDescriptor {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl From<Vec<u8>> for ConnectionDescriptor {
+    fn from(bytes: Vec<u8>) -> Self {
+        ConnectionDescriptor(bytes)
+    }
+}
+
+impl AntidoteDB {
+    // nodes are erlang node names, e.g. "antidote@node1"
+    pub fn create_dc(&mut self, nodes: &[&str]) -> Result<(), Error> {
+        let mut create_dc = antidote::ApbCreateDC::new();
+        let nodes: Vec<_> = nodes.iter().map(|node| node.to_string()).collect();
+        create_dc.set_nodes(RepeatedField::from_vec(nodes));
+
+        self.send_message(antidote::MessageCode::apbCreateDC, create_dc)?;
+
+        let message = self
+            .read_response::<antidote::ApbCreateDCResp>(antidote::MessageCode::apbCreateDCResp)?;
+
+        if message.get_success() {
+            Ok(())
+        } else {
+            Err(AntidoteDB::rejected(
+                message.has_errorcode(),
+                message.get_errorcode(),
+            ))
+        }
+    }
+
+    pub fn get_connection_descriptor(&mut self) -> Result<ConnectionDescriptor, Error> {
+        let get_descriptor = antidote::ApbGetConnectionDescriptor::new();
+
+        self.send_message(
+            antidote::MessageCode::apbGetConnectionDescriptor,
+            get_descriptor,
+        )?;
+
+        let mut message = self.read_response::<antidote::ApbGetConnectionDescriptorResp>(
+            antidote::MessageCode::apbGetConnectionDescriptorResp,
+        )?;
+
+        if message.get_success() {
+            Ok(ConnectionDescriptor(codec::into_vec(
+                message.take_descriptor(),
+            )))
+        } else {
+            Err(AntidoteDB::rejected(
+                message.has_errorcode(),
+                message.get_errorcode(),
+            ))
+        }
+    }
+
+    pub fn connect_to_dcs(&mut self, descriptors: &[ConnectionDescriptor]) -> Result<(), Error> {
+        let mut connect = antidote::ApbConnectToDCs::new();
+        let descriptors: Vec<_> = descriptors.iter().map(|d| d.0.clone()).collect();
+        connect.set_descriptors(RepeatedField::from_vec(descriptors));
+
+        self.send_message(antidote::MessageCode::apbConnectToDCs, connect)?;
+
+        let message = self.read_response::<antidote::ApbConnectToDCsResp>(
+            antidote::MessageCode::apbConnectToDCsResp,
+        )?;
+
+        if message.get_success() {
+            Ok(())
+        } else {
+            Err(AntidoteDB::rejected(
+                message.has_errorcode(),
+                message.get_errorcode(),
+            ))
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use super::antidote;
+use super::codec::{self, Encoder};
+use super::crdt::Object;
+use super::{write_frame_with, write_object};
+use super::{AntidoteDB, Error, Transaction};
+
+use std::ops::Range;
+
+// Updates encoded as they are added, for writers sending many of them.
+//
+// Each push encodes its update into a buffer shared by the batch, taking the
+// key and bucket from the object without building an ApbBoundObject or
+// ApbUpdateOp, and sending the batch copies the encoded bytes into the
+// request. A batch can be cleared and refilled, reusing its buffer:
+//
+//     let mut batch = Batch::new();
+//     for counter in &counters {
+//         batch.push(counter, &Counter::inc_op(1));
+//     }
+//     db.static_update_batch(&batch, None)?;
+//     batch.clear();
+
+#[derive(Clone, Debug, Default)]
+pub struct Batch {
+    buffer: Vec<u8>,
+    // encoded ApbUpdateOp of each update, in buffer
+    ops: Vec<Range<usize>>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Batch::default()
+    }
+
+    pub fn with_capacity(updates: usize, bytes: usize) -> Self {
+        Batch {
+            buffer: Vec::with_capacity(bytes),
+            ops: Vec::with_capacity(updates),
+        }
+    }
+
+    // adds operation on object
+    pub fn push<T: Object>(&mut self, object: &T, operation: &antidote::ApbUpdateOperation) {
+        self.encode(|encoder| {
+            write_object(encoder, 1, object)?;
+            encoder.write_message(2, operation)
+        });
+    }
+
+    // adds an update built by the object traits
+    pub fn push_op(&mut self, op: &antidote::ApbUpdateOp) {
+        self.encode(|encoder| {
+            encoder.write_message(1, op.get_boundobject())?;
+            encoder.write_message(2, op.get_operation())
+        });
+    }
+
+    fn encode<F>(&mut self, write_fields: F)
+    where
+        F: FnOnce(&mut Encoder) -> Result<(), Error>,
+    {
+        let start = self.buffer.len();
+        // writing to a vector does not fail
+        codec::encode(&mut self.buffer, write_fields).expect("encoding an update");
+        self.ops.push(start..self.buffer.len());
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    // removes every update, keeping the memory for the next ones
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.ops.clear();
+    }
+
+    // the updates, decoded back from the buffer
+    pub(crate) fn updates(
+        &self,
+    ) -> impl Iterator<Item = Result<antidote::ApbUpdateOp, Error>> + '_ {
+        self.ops
+            .iter()
+            .map(move |op| codec::decode(self.buffer[op.clone()].to_vec()))
+    }
+
+    fn write_updates(&self, encoder: &mut Encoder, field: u32) -> Result<(), Error> {
+        for op in &self.ops {
+            encoder.write_encoded(field, &self.buffer[op.clone()])?;
+        }
+        Ok(())
+    }
+}
+
+impl AntidoteDB {
+    pub fn update_batch_in_transaction(
+        &mut self,
+        batch: &Batch,
+        t: &Transaction,
+    ) -> Result<(), Error> {
+        // ApbUpdateObjects
+        write_frame_with(
+            &mut self.socket,
+            antidote::MessageCode::apbUpdateObjects,
+            |encoder| {
+                batch.write_updates(encoder, 1)?;
+                encoder.write_bytes(2, &t.id)
+            },
+        )?;
+
+        self.read_operation_response()
+    }
+
+    pub fn static_update_batch(
+        &mut self,
+        batch: &Batch,
+        timestamp: Option<&Vec<u8>>,
+    ) -> Result<Vec<u8>, Error> {
+        // ApbStaticUpdateObjects
+        let transaction = AntidoteDB::new_transaction(timestamp);
+        write_frame_with(
+            &mut self.socket,
+            antidote::MessageCode::apbStaticUpdateObjects,
+            |encoder| {
+                encoder.write_message(1, &transaction)?;
+                batch.write_updates(encoder, 2)
+            },
+        )?;
+
+        self.read_commit_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::codec::RepeatedField;
+    use super::super::crdt::{Counter, CounterT, LWWReg, ORSet, RegT, SetT};
+    use super::*;
+
+    // ApbUpdateObjects as update_batch_in_transaction encodes it
+    fn encode_batch(batch: &Batch, id: &[u8]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        codec::encode(&mut buffer, |encoder| {
+            batch.write_updates(encoder, 1)?;
+            encoder.write_bytes(2, id)
+        })
+        .unwrap();
+        buffer
+    }
+
+    #[test]
+    fn batches_encode_as_update_messages() {
+        let counter = Counter::new("visits", "web");
+        let tags = ORSet::new("tags", "web");
+        let name = LWWReg::new("name", "users");
+        let ops = vec![
+            counter.inc(-3),
+            tags.add_values(&[b"a".to_vec(), Vec::new()]),
+            name.set_value(&b"ada".to_vec()),
+        ];
+
+        let mut request = antidote::ApbUpdateObjects::new();
+        request.set_updates(RepeatedField::from_vec(ops.clone()));
+        request.set_transaction_descriptor(b"txn".to_vec());
+        let mut expected = Vec::new();
+        codec::encode(&mut expected, |encoder| encoder.write_fields(&request)).unwrap();
+
+        let mut pushed = Batch::new();
+        pushed.push(&counter, &Counter::inc_op(-3));
+        pushed.push(&tags, &ORSet::add_values_op(&[b"a".to_vec(), Vec::new()]));
+        pushed.push(&name, &LWWReg::set_value_op(&b"ada".to_vec()));
+        let mut built = Batch::with_capacity(3, 64);
+        for op in &ops {
+            built.push_op(op);
+        }
+
+        for batch in &[pushed, built] {
+            let encoded = encode_batch(batch, b"txn");
+            assert_eq!(encoded, expected);
+            let decoded = codec::decode::<antidote::ApbUpdateObjects>(encoded).unwrap();
+            assert_eq!(decoded, request);
+        }
+    }
+
+    #[test]
+    fn cleared_batches_encode_only_new_updates() {
+        let counter = Counter::new("visits", "web");
+        let mut batch = Batch::new();
+        batch.push(&counter, &Counter::inc_op(1));
+        batch.clear();
+        assert!(batch.is_empty());
+        batch.push(&counter, &Counter::inc_op(2));
+
+        let decoded =
+            codec::decode::<antidote::ApbUpdateObjects>(encode_batch(&batch, b"")).unwrap();
+        assert_eq!(decoded.get_updates().len(), 1);
+        assert_eq!(decoded.get_updates()[0], counter.inc(2));
+    }
+}
@@ -0,0 +1,740 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{type_from_name, type_name, CRDT_type};
+use antidotedb::crdt::{Counter, CounterT, FlagEW, FlagT, LWWReg, MapT, ORSet, Object, RRMap};
+use antidotedb::crdt::{RegT, SetT};
+use antidotedb::dynamic::{self, bound_object};
+use antidotedb::proto::{ApbBoundObject, ApbUpdateOp, ApbUpdateOperation};
+#[cfg(feature = "stand-in")]
+use antidotedb::server::StandIn;
+use antidotedb::{AntidoteDB, Error, ErrorCode};
+
+use serde_json::json;
+
+use std::env;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+usage: antidote-bench [options]
+
+target:
+  --stand-in            run against an in-process stand-in server, needs
+                        the stand-in feature
+  --host <host>         node to run against, localhost by default
+  --port <port>         its protocol buffer port, 8087 by default
+  --bucket <bucket>     bucket of the keys, \"bench\" by default
+
+workload:
+  --workload <name>     a (50% reads, 50% updates), b (95% reads, 5% updates),
+                        c (reads only) or t (transactions only), a by default
+  --mix <mix>           weights of the operations instead, as read=90,txn=10
+  --txn-size <n>        objects read then updated by a transaction, 4 by default
+  --types <types>       CRDT types of the keys, as counter,orset, counter by
+                        default
+  --keys <n>            keys per type, 1000 by default
+  --distribution <d>    uniform or zipfian, zipfian by default
+  --theta <theta>       skew of the zipfian distribution, 0.99 by default
+  --interactive         run operations as interactive transactions instead of
+                        static ones
+  --seed <seed>         seed of the random choices, 1 by default
+
+run:
+  --threads <n>         concurrent clients, one connection each, 1 by default
+  --duration <secs>     length of the run, 10 by default
+  --ops <n>             operations per client, instead of a duration
+  --output <format>     text or json, text by default
+
+Static transactions read their objects in one request then update them in
+another, at the snapshot of the read.";
+
+const KINDS: [Kind; 3] = [Kind::Read, Kind::Update, Kind::Txn];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Read,
+    Update,
+    Txn,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Distribution {
+    Uniform,
+    Zipfian(f64),
+}
+
+#[derive(Clone, Debug)]
+struct Config {
+    host: String,
+    port: u16,
+    stand_in: bool,
+    bucket: String,
+    // weights of reads, updates and transactions
+    mix: [u32; 3],
+    txn_size: usize,
+    types: Vec<CRDT_type>,
+    keys: u64,
+    distribution: Distribution,
+    interactive: bool,
+    seed: u64,
+    threads: usize,
+    duration: Duration,
+    ops: Option<u64>,
+    json: bool,
+}
+
+// what a client saw, latencies in microseconds by kind
+#[derive(Debug, Default)]
+struct Stats {
+    latencies: [Vec<u64>; 3],
+    aborts: u64,
+    errors: u64,
+    last_error: Option<String>,
+}
+
+fn main() {
+    let config = match parse_args(env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("antidote-bench: {}\nsee antidote-bench --help", message);
+            process::exit(2);
+        }
+    };
+
+    // kept alive for the run
+    let mut _stand_in = None;
+    let address = if config.stand_in {
+        match start_stand_in() {
+            Ok((stand_in, address)) => {
+                _stand_in = Some(stand_in);
+                address
+            }
+            Err(message) => {
+                eprintln!("antidote-bench: {}", message);
+                process::exit(1);
+            }
+        }
+    } else {
+        format!("{}:{}", config.host, config.port)
+    };
+
+    let config = Arc::new(config);
+    let keys = Arc::new(KeyChooser::new(config.keys, config.distribution));
+    let start = Instant::now();
+    let clients: Vec<_> = (0..config.threads)
+        .map(|client| {
+            let config = config.clone();
+            let keys = keys.clone();
+            let address = address.clone();
+            thread::spawn(move || run_client(&config, &keys, &address, client as u64))
+        })
+        .collect();
+
+    let mut stats = Stats::default();
+    for client in clients {
+        match client.join().expect("client panicked") {
+            Ok(client) => stats.merge(client),
+            Err(e) => {
+                eprintln!("antidote-bench: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+
+    if config.json {
+        println!("{}", report_json(&config, &mut stats, elapsed));
+    } else {
+        print!("{}", report_text(&config, &mut stats, elapsed));
+    }
+}
+
+// a stand-in server and its address
+#[cfg(feature = "stand-in")]
+fn start_stand_in() -> Result<(impl Sized, String), String> {
+    let stand_in = StandIn::start().map_err(|e| e.to_string())?;
+    let address = stand_in.address();
+    Ok((stand_in, address))
+}
+
+#[cfg(not(feature = "stand-in"))]
+fn start_stand_in() -> Result<(impl Sized, String), String> {
+    Err::<((), String), _>("built without the stand-in feature".to_string())
+}
+
+fn parse_args(argv: Vec<String>) -> Result<Config, String> {
+    let mut config = Config {
+        host: "localhost".to_string(),
+        port: 8087,
+        stand_in: false,
+        bucket: "bench".to_string(),
+        mix: [50, 50, 0],
+        txn_size: 4,
+        types: vec![CRDT_type::COUNTER],
+        keys: 1000,
+        distribution: Distribution::Zipfian(0.99),
+        interactive: false,
+        seed: 1,
+        threads: 1,
+        duration: Duration::from_secs(10),
+        ops: None,
+        json: false,
+    };
+    let mut uniform = false;
+
+    let mut argv = argv.into_iter();
+    while let Some(arg) = argv.next() {
+        let mut value = || argv.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--stand-in" => config.stand_in = true,
+            "--host" => config.host = value()?,
+            "--port" => config.port = number(&arg, &value()?)?,
+            "--bucket" => config.bucket = value()?,
+            "--workload" => {
+                config.mix = match value()?.as_str() {
+                    "a" => [50, 50, 0],
+                    "b" => [95, 5, 0],
+                    "c" => [100, 0, 0],
+                    "t" => [0, 0, 100],
+                    other => return Err(format!("unknown workload {:?}", other)),
+                }
+            }
+            "--mix" => config.mix = parse_mix(&value()?)?,
+            "--txn-size" => config.txn_size = number(&arg, &value()?)?,
+            "--types" => {
+                config.types = value()?
+                    .split(',')
+                    .map(|name| type_from_name(name).ok_or(format!("unknown type {:?}", name)))
+                    .collect::<Result<_, _>>()?
+            }
+            "--keys" => config.keys = number(&arg, &value()?)?,
+            "--distribution" => match value()?.as_str() {
+                "uniform" => uniform = true,
+                "zipfian" => uniform = false,
+                other => return Err(format!("unknown distribution {:?}", other)),
+            },
+            "--theta" => {
+                let theta: f64 = number(&arg, &value()?)?;
+                if !(theta > 0.0 && theta < 1.0) {
+                    return Err("--theta must be between 0 and 1".to_string());
+                }
+                config.distribution = Distribution::Zipfian(theta);
+            }
+            "--interactive" => config.interactive = true,
+            "--seed" => config.seed = number(&arg, &value()?)?,
+            "--threads" => config.threads = number(&arg, &value()?)?,
+            "--duration" => config.duration = Duration::from_secs_f64(number(&arg, &value()?)?),
+            "--ops" => config.ops = Some(number(&arg, &value()?)?),
+            "--output" => {
+                config.json = match value()?.as_str() {
+                    "text" => false,
+                    "json" => true,
+                    other => return Err(format!("unknown output {:?}", other)),
+                }
+            }
+            other => return Err(format!("unknown argument {}", other)),
+        }
+    }
+
+    if uniform {
+        config.distribution = Distribution::Uniform;
+    }
+    if config.mix.iter().sum::<u32>() == 0 {
+        return Err("the mix has no operations".to_string());
+    }
+    if config.keys == 0 || config.threads == 0 || config.txn_size == 0 {
+        return Err("--keys, --threads and --txn-size must be positive".to_string());
+    }
+    Ok(config)
+}
+
+fn number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {} {:?}", option, value))
+}
+
+fn parse_mix(mix: &str) -> Result<[u32; 3], String> {
+    let mut weights = [0; 3];
+    for part in mix.split(',') {
+        let (kind, weight) = part
+            .split_once('=')
+            .ok_or_else(|| format!("expected <operation>=<weight>, found {:?}", part))?;
+        let i = match kind {
+            "read" => 0,
+            "update" => 1,
+            "txn" => 2,
+            other => return Err(format!("unknown operation {:?}", other)),
+        };
+        weights[i] = number("--mix", weight)?;
+    }
+    Ok(weights)
+}
+
+fn run_client(
+    config: &Config,
+    keys: &KeyChooser,
+    address: &str,
+    client: u64,
+) -> Result<Stats, Error> {
+    let mut db = AntidoteDB::connect_with_string(address)?;
+    let mut rng = Rng::new(config.seed.wrapping_add(client));
+    let mut stats = Stats::default();
+    let total: u32 = config.mix.iter().sum();
+    let deadline = Instant::now() + config.duration;
+
+    let mut done = 0;
+    loop {
+        match config.ops {
+            Some(ops) if done >= ops => break,
+            None if Instant::now() >= deadline => break,
+            _ => {}
+        }
+        done += 1;
+
+        let mut pick = (rng.next() % u64::from(total)) as u32;
+        let kind = KINDS
+            .iter()
+            .zip(config.mix.iter())
+            .find(|(_, weight)| {
+                let found = pick < **weight;
+                pick = pick.saturating_sub(**weight);
+                found
+            })
+            .map(|(kind, _)| *kind)
+            .unwrap();
+        let crdt_type = config.types[(rng.next() % config.types.len() as u64) as usize];
+        let count = if kind == Kind::Txn {
+            config.txn_size
+        } else {
+            1
+        };
+        let objects: Vec<ApbBoundObject> = (0..count)
+            .map(|_| {
+                let key = format!("{}:{}", type_name(crdt_type), keys.next(&mut rng));
+                bound_object(key.as_bytes(), crdt_type, config.bucket.as_bytes())
+            })
+            .collect();
+
+        let started = Instant::now();
+        match run_operation(&mut db, config, kind, crdt_type, &objects, &mut rng) {
+            Ok(()) => stats.latencies[kind as usize].push(started.elapsed().as_micros() as u64),
+            Err(Error::Rejected(ErrorCode::Aborted)) => stats.aborts += 1,
+            Err(e) => {
+                stats.errors += 1;
+                // a broken connection is opened again
+                let reconnect = e.is_io();
+                stats.last_error = Some(e.to_string());
+                if reconnect {
+                    db = AntidoteDB::connect_with_string(address)?;
+                }
+            }
+        }
+    }
+    Ok(stats)
+}
+
+fn run_operation(
+    db: &mut AntidoteDB,
+    config: &Config,
+    kind: Kind,
+    crdt_type: CRDT_type,
+    objects: &[ApbBoundObject],
+    rng: &mut Rng,
+) -> Result<(), Error> {
+    let updates: Vec<ApbUpdateOp> = match kind {
+        Kind::Read => Vec::new(),
+        Kind::Update | Kind::Txn => objects
+            .iter()
+            .map(|object| {
+                let mut update = ApbUpdateOp::new();
+                update.set_boundobject(object.clone());
+                update.set_operation(operation(crdt_type, rng));
+                update
+            })
+            .collect(),
+    };
+    let reads: Vec<_> = match kind {
+        Kind::Read | Kind::Txn => objects,
+        Kind::Update => &[],
+    }
+    .iter()
+    .map(|object| (object.get_key(), object.get_bucket()))
+    .collect();
+
+    if config.interactive {
+        let txn = db.start_transaction(None)?;
+        let result = dynamic::read(db, crdt_type, &reads, Some(&txn), None).and_then(|_| {
+            match updates.is_empty() {
+                true => Ok(()),
+                false => db.mult_update_in_transaction(&updates, &txn),
+            }
+        });
+        match result {
+            Ok(()) => db.commit_transaction(&txn).map(|_| ()),
+            Err(e) => {
+                let _ = db.abort_transaction(&txn);
+                Err(e)
+            }
+        }
+    } else {
+        let (_, snapshot) = dynamic::read(db, crdt_type, &reads, None, None)?;
+        if !updates.is_empty() {
+            db.static_update_objects(&updates, snapshot.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+// an update of an object of type crdt_type with a random value
+fn operation(crdt_type: CRDT_type, rng: &mut Rng) -> ApbUpdateOperation {
+    match crdt_type {
+        CRDT_type::COUNTER | CRDT_type::FATCOUNTER | CRDT_type::BCOUNTER => Counter::inc_op(1),
+        CRDT_type::LWWREG | CRDT_type::MVREG => LWWReg::set_op(rng.next()),
+        CRDT_type::ORSET | CRDT_type::RWSET => ORSet::add_op(&[rng.next() % 100]),
+        CRDT_type::FLAG_EW | CRDT_type::FLAG_DW => FlagEW::set_op(rng.next() & 1 == 0),
+        CRDT_type::GMAP | CRDT_type::RRMAP => {
+            let key = format!("field{}", rng.next() % 10);
+            RRMap::update_op(
+                vec![(Counter::map_key(&key), Counter::inc_op(1))],
+                Vec::new(),
+            )
+        }
+    }
+}
+
+// Key indexes, uniform or zipfian. Zipfian ranks are computed as YCSB's
+// ZipfianGenerator does, then scrambled by a hash so that the hot keys are
+// spread over the key space.
+struct KeyChooser {
+    keys: u64,
+    zipfian: Option<Zipfian>,
+}
+
+struct Zipfian {
+    theta: f64,
+    alpha: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+impl KeyChooser {
+    fn new(keys: u64, distribution: Distribution) -> Self {
+        let zipfian = match distribution {
+            Distribution::Uniform => None,
+            Distribution::Zipfian(theta) => {
+                let zeta = |n: u64| (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+                let zetan = zeta(keys);
+                let zeta2 = zeta(2.min(keys));
+                Some(Zipfian {
+                    theta,
+                    alpha: 1.0 / (1.0 - theta),
+                    zetan,
+                    eta: (1.0 - (2.0 / keys as f64).powf(1.0 - theta)) / (1.0 - zeta2 / zetan),
+                })
+            }
+        };
+        KeyChooser { keys, zipfian }
+    }
+
+    fn next(&self, rng: &mut Rng) -> u64 {
+        let zipfian = match &self.zipfian {
+            Some(zipfian) => zipfian,
+            None => return rng.next() % self.keys,
+        };
+
+        let u = rng.next_f64();
+        let uz = u * zipfian.zetan;
+        let rank = if uz < 1.0 {
+            0
+        } else if uz < 1.0 + 0.5f64.powf(zipfian.theta) {
+            1
+        } else {
+            (self.keys as f64 * (zipfian.eta * u - zipfian.eta + 1.0).powf(zipfian.alpha)) as u64
+        };
+        fnv(rank.min(self.keys - 1)) % self.keys
+    }
+}
+
+fn fnv(value: u64) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in value.to_le_bytes().iter() {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+// splitmix64, enough for picking keys and values
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Stats {
+    fn merge(&mut self, other: Stats) {
+        for (latencies, other) in self.latencies.iter_mut().zip(other.latencies) {
+            latencies.extend(other);
+        }
+        self.aborts += other.aborts;
+        self.errors += other.errors;
+        self.last_error = other.last_error.or(self.last_error.take());
+    }
+
+    fn completed(&self) -> u64 {
+        self.latencies.iter().map(|l| l.len() as u64).sum()
+    }
+
+    // sorts the latencies, for percentiles
+    fn sort(&mut self) {
+        for latencies in self.latencies.iter_mut() {
+            latencies.sort_unstable();
+        }
+    }
+}
+
+// count, mean, p50, p90, p99, p99.9 and max of sorted latencies
+fn summary(latencies: &[u64]) -> Option<[u64; 7]> {
+    if latencies.is_empty() {
+        return None;
+    }
+    // nearest rank, in thousandths so that 99.9 is exact
+    let percentile = |thousandths: usize| {
+        let rank = (thousandths * latencies.len()).div_ceil(1000);
+        latencies[rank.max(1) - 1]
+    };
+    Some([
+        latencies.len() as u64,
+        latencies.iter().sum::<u64>() / latencies.len() as u64,
+        percentile(500),
+        percentile(900),
+        percentile(990),
+        percentile(999),
+        latencies[latencies.len() - 1],
+    ])
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Read => "read",
+        Kind::Update => "update",
+        Kind::Txn => "txn",
+    }
+}
+
+fn distribution_name(distribution: Distribution) -> String {
+    match distribution {
+        Distribution::Uniform => "uniform".to_string(),
+        Distribution::Zipfian(theta) => format!("zipfian({})", theta),
+    }
+}
+
+// operations completed, aborted or failed, per second, and the abort rate
+fn rates(stats: &Stats, elapsed: Duration) -> (f64, f64) {
+    let attempted = stats.completed() + stats.aborts + stats.errors;
+    let throughput = stats.completed() as f64 / elapsed.as_secs_f64();
+    let abort_rate = if attempted == 0 {
+        0.0
+    } else {
+        stats.aborts as f64 / attempted as f64
+    };
+    (throughput, abort_rate)
+}
+
+fn report_text(config: &Config, stats: &mut Stats, elapsed: Duration) -> String {
+    stats.sort();
+    let (throughput, abort_rate) = rates(stats, elapsed);
+    let types: Vec<_> = config.types.iter().map(|t| type_name(*t)).collect();
+
+    let mut text = format!(
+        "workload     read {} update {} txn {} (txn size {}), {}\n",
+        config.mix[0],
+        config.mix[1],
+        config.mix[2],
+        config.txn_size,
+        if config.interactive {
+            "interactive"
+        } else {
+            "static"
+        }
+    );
+    text += &format!(
+        "keys         {} per type, {}, {}\n",
+        config.keys,
+        distribution_name(config.distribution),
+        types.join(",")
+    );
+    text += &format!("clients      {}\n", config.threads);
+    text += &format!("elapsed      {:.2} s\n", elapsed.as_secs_f64());
+    text += &format!(
+        "operations   {} ({:.1} ops/s)\n",
+        stats.completed(),
+        throughput
+    );
+    text += &format!(
+        "aborts       {} ({:.2}%)\n",
+        stats.aborts,
+        abort_rate * 100.0
+    );
+    text += &format!("errors       {}\n", stats.errors);
+    if let Some(e) = &stats.last_error {
+        text += &format!("last error   {}\n", e);
+    }
+
+    text += &format!(
+        "\n{:<12} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}\n",
+        "latency (us)", "count", "mean", "p50", "p90", "p99", "p99.9", "max"
+    );
+    let mut all: Vec<u64> = stats.latencies.concat();
+    all.sort_unstable();
+    let rows = KINDS
+        .iter()
+        .map(|kind| (kind_name(*kind), &stats.latencies[*kind as usize][..]))
+        .chain(Some(("all", &all[..])));
+    for (name, latencies) in rows {
+        if let Some(summary) = summary(latencies) {
+            text += &format!("{:<12}", name);
+            for value in summary.iter() {
+                text += &format!(" {:>9}", value);
+            }
+            text.push('\n');
+        }
+    }
+    text
+}
+
+fn report_json(config: &Config, stats: &mut Stats, elapsed: Duration) -> serde_json::Value {
+    stats.sort();
+    let (throughput, abort_rate) = rates(stats, elapsed);
+    let latency = |latencies: &[u64]| match summary(latencies) {
+        Some([count, mean, p50, p90, p99, p999, max]) => json!({
+            "count": count,
+            "mean": mean,
+            "p50": p50,
+            "p90": p90,
+            "p99": p99,
+            "p99.9": p999,
+            "max": max,
+        }),
+        None => json!(null),
+    };
+    let mut all: Vec<u64> = stats.latencies.concat();
+    all.sort_unstable();
+
+    json!({
+        "workload": {
+            "read": config.mix[0],
+            "update": config.mix[1],
+            "txn": config.mix[2],
+            "txn_size": config.txn_size,
+            "interactive": config.interactive,
+            "keys": config.keys,
+            "distribution": distribution_name(config.distribution),
+            "types": config.types.iter().map(|t| type_name(*t)).collect::<Vec<_>>(),
+        },
+        "clients": config.threads,
+        "elapsed_secs": elapsed.as_secs_f64(),
+        "operations": stats.completed(),
+        "throughput": throughput,
+        "aborts": stats.aborts,
+        "abort_rate": abort_rate,
+        "errors": stats.errors,
+        "last_error": stats.last_error,
+        "latency_us": {
+            "read": latency(&stats.latencies[0]),
+            "update": latency(&stats.latencies[1]),
+            "txn": latency(&stats.latencies[2]),
+            "all": latency(&all),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draws(keys: u64, distribution: Distribution, draws: usize) -> Vec<usize> {
+        let chooser = KeyChooser::new(keys, distribution);
+        let mut rng = Rng::new(7);
+        let mut counts = vec![0; keys as usize];
+        for _ in 0..draws {
+            counts[chooser.next(&mut rng) as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn rank_zero_dominates() {
+        let counts = draws(1000, Distribution::Zipfian(0.99), 100_000);
+        let hottest = (fnv(0) % 1000) as usize;
+        let second = (fnv(1) % 1000) as usize;
+        assert_eq!(
+            counts
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, n)| **n)
+                .unwrap()
+                .0,
+            hottest
+        );
+        // about 1/zeta(1000) of the draws, 13%, then half as many for rank 1
+        assert!(counts[hottest] > 11_000 && counts[hottest] < 15_000);
+        assert!(counts[second] > counts[hottest] / 3 && counts[second] < counts[hottest] * 2 / 3);
+
+        let uniform = draws(1000, Distribution::Uniform, 100_000);
+        assert!(uniform.iter().all(|n| *n < 200));
+    }
+
+    #[test]
+    fn indexes_stay_below_keys() {
+        for keys in [1, 2, 3, 10, 1000] {
+            for distribution in [
+                Distribution::Uniform,
+                Distribution::Zipfian(0.5),
+                Distribution::Zipfian(0.99),
+            ] {
+                let chooser = KeyChooser::new(keys, distribution);
+                let mut rng = Rng::new(keys);
+                for _ in 0..10_000 {
+                    assert!(chooser.next(&mut rng) < keys);
+                }
+            }
+        }
+        assert_eq!(draws(1, Distribution::Zipfian(0.99), 100), vec![100]);
+    }
+
+    #[test]
+    fn percentiles_of_sorted_latencies() {
+        let latencies: Vec<u64> = (1..=1000).collect();
+        assert_eq!(
+            summary(&latencies),
+            Some([1000, 500, 500, 900, 990, 999, 1000])
+        );
+        // ranks round up, and never go below the first
+        assert_eq!(summary(&[5]), Some([1, 5, 5, 5, 5, 5, 5]));
+        assert_eq!(summary(&[1, 2, 3]), Some([3, 2, 2, 3, 3, 3, 3]));
+        assert_eq!(summary(&[]), None);
+    }
+}
@@ -0,0 +1,132 @@
+extern crate antidotedb;
+
+use antidotedb::history::consistency::{self, Level, Report};
+use antidotedb::history::History;
+
+use serde_json::json;
+
+use std::collections::BTreeSet;
+use std::env;
+use std::io;
+use std::process;
+
+const USAGE: &str = "\
+usage: antidote-check [options] <history>
+
+Checks a history recorded by antidotedb::history::Recorder, - for standard
+input, against consistency levels from the weakest to the strongest:
+read-committed, read-atomic, causal and serializable. Each violated level is
+shown with its smallest counterexample, as transaction ids and keys.
+
+options:
+  --level <level>       level the history must satisfy, causal by default;
+                        the exit status is 1 when it does not
+  --all                 show every violation of each level instead
+  --output <format>     text or json, text by default";
+
+#[derive(Debug)]
+struct Config {
+    path: String,
+    level: Level,
+    all: bool,
+    json: bool,
+}
+
+fn main() {
+    let config = match parse_args(env::args().skip(1).collect()) {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("antidote-check: {}\nsee antidote-check --help", message);
+            process::exit(2);
+        }
+    };
+
+    let history = if config.path == "-" {
+        History::read(io::stdin().lock())
+    } else {
+        History::load(&config.path)
+    };
+    let history = match history {
+        Ok(history) => history,
+        Err(e) => {
+            eprintln!("antidote-check: {}: {}", config.path, e);
+            process::exit(1);
+        }
+    };
+
+    let report = consistency::check(&history);
+    if config.json {
+        let mut json = report.to_json();
+        json["transactions"] = json!(history.transactions.len());
+        println!("{}", json);
+    } else {
+        print!("{}", report_text(&config, &history, &report));
+    }
+    if !report.satisfies(config.level) {
+        process::exit(1);
+    }
+}
+
+fn parse_args(argv: Vec<String>) -> Result<Config, String> {
+    let mut path = None;
+    let mut config = Config {
+        path: String::new(),
+        level: Level::Causal,
+        all: false,
+        json: false,
+    };
+
+    let mut argv = argv.into_iter();
+    while let Some(arg) = argv.next() {
+        let mut value = || argv.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--level" => {
+                let name = value()?;
+                config.level =
+                    Level::from_name(&name).ok_or_else(|| format!("unknown level {:?}", name))?
+            }
+            "--all" => config.all = true,
+            "--output" => {
+                config.json = match value()?.as_str() {
+                    "text" => false,
+                    "json" => true,
+                    other => return Err(format!("unknown output {:?}", other)),
+                }
+            }
+            other if other.starts_with("--") => return Err(format!("unknown argument {}", other)),
+            _ if path.is_some() => return Err("expected one history".to_string()),
+            _ => path = Some(arg),
+        }
+    }
+
+    config.path = path.ok_or("no history given")?;
+    Ok(config)
+}
+
+fn report_text(config: &Config, history: &History, report: &Report) -> String {
+    let sessions: BTreeSet<u64> = history.transactions.iter().map(|t| t.session).collect();
+    let mut text = format!(
+        "{} transactions in {} sessions\n",
+        history.transactions.len(),
+        sessions.len()
+    );
+    for &level in &Level::ALL {
+        if report.satisfies(level) {
+            text.push_str(&format!("{:<16}ok\n", level.name()));
+            continue;
+        }
+        text.push_str(&format!("{:<16}violated\n", level.name()));
+        if config.all {
+            for violation in report.violations.iter().filter(|v| v.level() == level) {
+                text.push_str(&format!("  {}\n", violation));
+            }
+        } else if let Some(violation) = report.counterexample(level) {
+            text.push_str(&format!("  {}\n", violation));
+        }
+    }
+    text
+}
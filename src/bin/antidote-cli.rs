@@ -0,0 +1,745 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{type_from_name, type_name, CRDT_type};
+use antidotedb::crdt::{Counter, CounterT, FlagEW, FlagT, LWWReg, MapT, ORSet, ObjectReset};
+use antidotedb::crdt::{RRMap, RegT, SetT};
+use antidotedb::dynamic::{self, bound_object, from_hex, to_hex};
+use antidotedb::proto::{ApbMapKey, ApbReadObjectResp, ApbUpdateOp, ApbUpdateOperation};
+use antidotedb::session::CommitTime;
+use antidotedb::value::Value;
+use antidotedb::{AntidoteDB, Error};
+
+use serde_json::json;
+
+use std::env;
+use std::process;
+
+const USAGE: &str = "\
+usage: antidote-cli [options] read <type> <key>
+       antidote-cli [options] update <type> <key> <update>...
+       antidote-cli [options] reset <type> <key>
+
+types: counter fatcounter bcounter lwwreg mvreg orset rwset flag_ew flag_dw
+       gmap rrmap
+
+options:
+  --host <host>       node to connect to, localhost by default
+  --port <port>       its protocol buffer port, 8087 by default
+  --bucket <bucket>   bucket of the key, \"default\" by default
+  --at <token>        read or write after the commit of a token
+  --interactive       run an interactive transaction instead of a static one
+  --output <format>   tree or json, tree by default
+  --codec <codec>     register values and set elements as utf8, u64 or hex,
+                      utf8 by default
+
+updates:
+  counters            --inc <n>, --dec <n>
+  registers           --set <value>
+  sets                --add <value>,..., --remove <value>,...
+  flags               --enable, --disable
+  maps                --entry <type>:<key>[/<type>:<key>...] then an update of
+                      the entry, --remove <type>:<key>[/<type>:<key>...]
+
+Tokens are the commit times printed by earlier commands. On maps, --remove
+right after an --entry is the update of that entry, and removes an entry
+otherwise.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Command {
+    Read,
+    Update,
+    Reset,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Output {
+    Tree,
+    Json,
+}
+
+// how register values and set elements are written and shown
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Codec {
+    Utf8,
+    U64,
+    Hex,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Update {
+    Inc(i64),
+    Set(String),
+    Add(Vec<String>),
+    // set elements, or the path of a map entry
+    Remove(String),
+    Flag(bool),
+    Entry(String),
+}
+
+#[derive(Debug)]
+struct Args {
+    host: String,
+    port: u16,
+    bucket: String,
+    at: Option<Vec<u8>>,
+    interactive: bool,
+    output: Output,
+    codec: Codec,
+    command: Command,
+    crdt_type: CRDT_type,
+    key: String,
+    updates: Vec<Update>,
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("antidote-cli: {}\nsee antidote-cli --help", message);
+            process::exit(2);
+        }
+    };
+
+    if let Err(message) = run(&args) {
+        eprintln!("antidote-cli: {}", message);
+        process::exit(1);
+    }
+}
+
+fn parse_args(argv: Vec<String>) -> Result<Args, String> {
+    let mut host = "localhost".to_string();
+    let mut port = 8087;
+    let mut bucket = "default".to_string();
+    let mut at = None;
+    let mut interactive = false;
+    let mut output = Output::Tree;
+    let mut codec = Codec::Utf8;
+    let mut positional = Vec::new();
+    let mut updates = Vec::new();
+
+    let mut argv = argv.into_iter();
+    while let Some(arg) = argv.next() {
+        let mut value = |name: &str| argv.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--host" => host = value(&arg)?,
+            "--port" => {
+                port = value(&arg)?
+                    .parse()
+                    .map_err(|_| "invalid --port".to_string())?
+            }
+            "--bucket" => bucket = value(&arg)?,
+            "--at" => {
+                let time = CommitTime::from_token(&value(&arg)?)
+                    .map_err(|e| format!("invalid --at token: {}", e))?;
+                at = Some(time.into_bytes())
+            }
+            "--interactive" => interactive = true,
+            "--output" => {
+                output = match value(&arg)?.as_str() {
+                    "tree" => Output::Tree,
+                    "json" => Output::Json,
+                    other => return Err(format!("unknown output {:?}", other)),
+                }
+            }
+            "--codec" => {
+                codec = match value(&arg)?.as_str() {
+                    "utf8" => Codec::Utf8,
+                    "u64" => Codec::U64,
+                    "hex" => Codec::Hex,
+                    other => return Err(format!("unknown codec {:?}", other)),
+                }
+            }
+            "--inc" | "--dec" => updates.push(Update::Inc(parse_inc(&arg, &value(&arg)?)?)),
+            "--set" => updates.push(Update::Set(value(&arg)?)),
+            "--add" => updates.push(Update::Add(split_list(&value(&arg)?))),
+            "--enable" => updates.push(Update::Flag(true)),
+            "--disable" => updates.push(Update::Flag(false)),
+            "--entry" => updates.push(Update::Entry(value(&arg)?)),
+            "--remove" => updates.push(Update::Remove(value(&arg)?)),
+            option if option.starts_with("--") => return Err(format!("unknown option {}", option)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let (command, type_name, key) = match positional.as_slice() {
+        [command, type_name, key] => (command, type_name, key),
+        _ => return Err("expected a command, a type and a key".to_string()),
+    };
+    let command = match command.as_str() {
+        "read" => Command::Read,
+        "update" => Command::Update,
+        "reset" => Command::Reset,
+        other => return Err(format!("unknown command {:?}", other)),
+    };
+    let crdt_type =
+        type_from_name(type_name).ok_or_else(|| format!("unknown type {:?}", type_name))?;
+
+    match (command, updates.is_empty()) {
+        (Command::Update, true) => return Err("update needs at least one update".to_string()),
+        (Command::Read, false) | (Command::Reset, false) => {
+            return Err("updates are only given to update".to_string())
+        }
+        _ => {}
+    }
+
+    Ok(Args {
+        host,
+        port,
+        bucket,
+        at,
+        interactive,
+        output,
+        codec,
+        command,
+        crdt_type,
+        key: key.clone(),
+        updates,
+    })
+}
+
+// the increment of --inc or --dec
+fn parse_inc(option: &str, value: &str) -> Result<i64, String> {
+    let n: i64 = value
+        .parse()
+        .map_err(|_| format!("{} needs an integer", option))?;
+    match option {
+        "--dec" => n
+            .checked_neg()
+            .ok_or_else(|| format!("--dec {} is out of range", n)),
+        _ => Ok(n),
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').map(str::to_string).collect()
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    // operations are built before connecting, to report usage errors first
+    let operations = match args.command {
+        Command::Read => Vec::new(),
+        Command::Update => operations(args.crdt_type, &args.updates, args.codec)?,
+        Command::Reset => vec![reset(args.crdt_type)?],
+    };
+
+    let mut db = AntidoteDB::connect(&args.host, args.port).map_err(|e| e.to_string())?;
+    let object = bound_object(args.key.as_bytes(), args.crdt_type, args.bucket.as_bytes());
+
+    if args.command == Command::Read {
+        let (resp, token) = read(&mut db, args).map_err(|e| e.to_string())?;
+        print_read(args, &Value::decode(args.crdt_type, &resp), token);
+        return Ok(());
+    }
+
+    let ops: Vec<_> = operations
+        .into_iter()
+        .map(|operation| {
+            let mut op = ApbUpdateOp::new();
+            op.set_boundobject(object.clone());
+            op.set_operation(operation);
+            op
+        })
+        .collect();
+
+    let token = if args.interactive {
+        let txn = db
+            .start_transaction(args.at.as_ref())
+            .map_err(|e| e.to_string())?;
+        db.mult_update_in_transaction(&ops, &txn)
+            .map_err(|e| e.to_string())?;
+        db.commit_transaction(&txn)
+    } else {
+        db.static_update_objects(&ops, args.at.as_ref())
+    }
+    .map_err(|e| e.to_string())?;
+
+    match args.output {
+        Output::Tree => println!("committed {}", CommitTime::from(token)),
+        Output::Json => println!("{}", json!({ "token": CommitTime::from(token).to_token() })),
+    }
+    Ok(())
+}
+
+fn map_key(key: &[u8], crdt_type: CRDT_type) -> ApbMapKey {
+    let mut map_key = ApbMapKey::new();
+    map_key.set_key(key.to_vec());
+    map_key.set_field_type(crdt_type);
+    map_key
+}
+
+// the value of the object and the token of the snapshot it was read at
+fn read(db: &mut AntidoteDB, args: &Args) -> Result<(ApbReadObjectResp, Vec<u8>), Error> {
+    let (key, bucket) = (args.key.as_bytes(), args.bucket.as_bytes());
+    if args.interactive {
+        let txn = db.start_transaction(args.at.as_ref())?;
+        let (value, _) = dynamic::read_one(db, args.crdt_type, key, bucket, Some(&txn), None)?;
+        Ok((value, db.commit_transaction(&txn)?))
+    } else {
+        let (value, token) =
+            dynamic::read_one(db, args.crdt_type, key, bucket, None, args.at.as_ref())?;
+        Ok((value, token.unwrap_or_default()))
+    }
+}
+
+fn print_read(args: &Args, value: &Value, token: Vec<u8>) {
+    let token = CommitTime::from(token);
+    let codec = args.codec;
+    match args.output {
+        Output::Tree => {
+            print!("{}", value.tree_with(&|bytes| codec.show(bytes)));
+            println!("at {}", token);
+        }
+        Output::Json => {
+            let output = json!({
+                "bucket": args.bucket,
+                "key": args.key,
+                "type": type_name(args.crdt_type),
+                "value": value.to_json_with(&|bytes| codec.json(bytes)),
+                "token": token.to_token(),
+            });
+            println!("{}", output);
+        }
+    }
+}
+
+fn reset(crdt_type: CRDT_type) -> Result<ApbUpdateOperation, String> {
+    match crdt_type {
+        CRDT_type::FATCOUNTER
+        | CRDT_type::MVREG
+        | CRDT_type::ORSET
+        | CRDT_type::RWSET
+        | CRDT_type::FLAG_EW
+        | CRDT_type::FLAG_DW
+        | CRDT_type::RRMAP => Ok(RRMap::reset_op()),
+        _ => Err(format!("{} cannot be reset", type_name(crdt_type))),
+    }
+}
+
+// the operations of the updates of an object of type crdt_type
+fn operations(
+    crdt_type: CRDT_type,
+    updates: &[Update],
+    codec: Codec,
+) -> Result<Vec<ApbUpdateOperation>, String> {
+    match crdt_type {
+        CRDT_type::GMAP | CRDT_type::RRMAP => Ok(vec![map_operation(updates, codec)?]),
+        _ => updates
+            .iter()
+            .map(|update| operation(crdt_type, update, codec))
+            .collect(),
+    }
+}
+
+fn operation(
+    crdt_type: CRDT_type,
+    update: &Update,
+    codec: Codec,
+) -> Result<ApbUpdateOperation, String> {
+    let operation = match (crdt_type, update) {
+        (CRDT_type::COUNTER, Update::Inc(n))
+        | (CRDT_type::FATCOUNTER, Update::Inc(n))
+        | (CRDT_type::BCOUNTER, Update::Inc(n)) => Counter::inc_op(*n),
+        (CRDT_type::LWWREG, Update::Set(value)) | (CRDT_type::MVREG, Update::Set(value)) => {
+            LWWReg::set_value_op(&codec.parse(value)?)
+        }
+        (CRDT_type::ORSET, Update::Add(values)) | (CRDT_type::RWSET, Update::Add(values)) => {
+            ORSet::add_values_op(&codec.parse_all(values)?)
+        }
+        (CRDT_type::ORSET, Update::Remove(values)) | (CRDT_type::RWSET, Update::Remove(values)) => {
+            ORSet::rem_values_op(&codec.parse_all(&split_list(values))?)
+        }
+        (CRDT_type::FLAG_EW, Update::Flag(value)) | (CRDT_type::FLAG_DW, Update::Flag(value)) => {
+            FlagEW::set_op(*value)
+        }
+        (crdt_type, update) => {
+            return Err(format!(
+                "{} does not apply to {}",
+                update.option(),
+                type_name(crdt_type)
+            ))
+        }
+    };
+    Ok(operation)
+}
+
+// A map update from --entry and --remove options. Each --entry path is
+// followed by the update of the entry it names, which may be a --remove of
+// set elements; any other --remove is the path of an entry to remove.
+fn map_operation(updates: &[Update], codec: Codec) -> Result<ApbUpdateOperation, String> {
+    let mut entries = Vec::new();
+    let mut removes = Vec::new();
+
+    let mut updates = updates.iter();
+    while let Some(update) = updates.next() {
+        match update {
+            Update::Entry(path) => {
+                let path = parse_path(path)?;
+                let update = updates
+                    .next()
+                    .filter(|update| !matches!(update, Update::Entry(_)))
+                    .ok_or_else(|| format!("--entry {} needs an update", show_path(&path)))?;
+                let (key, crdt_type) = path.last().unwrap();
+                let operation = match crdt_type {
+                    CRDT_type::GMAP | CRDT_type::RRMAP => {
+                        return Err("nested maps are updated through their entries".to_string())
+                    }
+                    _ => operation(*crdt_type, update, codec)?,
+                };
+                entries.push((
+                    path[..path.len() - 1].to_vec(),
+                    map_key(key, *crdt_type),
+                    operation,
+                ));
+            }
+            Update::Remove(path) => {
+                let path = parse_path(path)?;
+                let (key, crdt_type) = path.last().unwrap();
+                removes.push((path[..path.len() - 1].to_vec(), map_key(key, *crdt_type)));
+            }
+            update => return Err(format!("{} needs an --entry on maps", update.option())),
+        }
+    }
+
+    Ok(nested_map_operation(&[], &entries, &removes))
+}
+
+type Path = Vec<(Vec<u8>, CRDT_type)>;
+
+// the update of the map at prefix, with the entries and removes below it
+fn nested_map_operation(
+    prefix: &[(Vec<u8>, CRDT_type)],
+    entries: &[(Path, ApbMapKey, ApbUpdateOperation)],
+    removes: &[(Path, ApbMapKey)],
+) -> ApbUpdateOperation {
+    let depth = prefix.len();
+    let mut updates = Vec::new();
+    let mut nested: Vec<&(Vec<u8>, CRDT_type)> = Vec::new();
+
+    for (path, key, operation) in entries
+        .iter()
+        .filter(|(path, _, _)| path.starts_with(prefix))
+    {
+        if path.len() == depth {
+            updates.push((key.clone(), operation.clone()));
+        } else if !nested.contains(&&path[depth]) {
+            nested.push(&path[depth]);
+        }
+    }
+    for (path, _) in removes.iter().filter(|(path, _)| path.starts_with(prefix)) {
+        if path.len() > depth && !nested.contains(&&path[depth]) {
+            nested.push(&path[depth]);
+        }
+    }
+
+    for (key, crdt_type) in nested {
+        let mut inner = prefix.to_vec();
+        inner.push((key.clone(), *crdt_type));
+        let operation = nested_map_operation(&inner, entries, removes);
+        updates.push((map_key(key, *crdt_type), operation));
+    }
+
+    let removed: Vec<_> = removes
+        .iter()
+        .filter(|(path, _)| path.as_slice() == prefix)
+        .map(|(_, key)| key.clone())
+        .collect();
+
+    RRMap::update_op(updates, removed)
+}
+
+// type:key segments separated by /, every segment but the last naming a map
+fn parse_path(path: &str) -> Result<Path, String> {
+    let segments: Vec<_> = path
+        .split('/')
+        .map(|segment| {
+            let (type_name, key) = segment
+                .split_once(':')
+                .ok_or_else(|| format!("expected <type>:<key>, found {:?}", segment))?;
+            let crdt_type =
+                type_from_name(type_name).ok_or_else(|| format!("unknown type {:?}", type_name))?;
+            Ok((key.as_bytes().to_vec(), crdt_type))
+        })
+        .collect::<Result<_, String>>()?;
+
+    let inner = &segments[..segments.len() - 1];
+    if let Some((_, crdt_type)) = inner
+        .iter()
+        .find(|(_, crdt_type)| !matches!(crdt_type, CRDT_type::GMAP | CRDT_type::RRMAP))
+    {
+        return Err(format!(
+            "{} in {:?} is not a map",
+            type_name(*crdt_type),
+            path
+        ));
+    }
+    Ok(segments)
+}
+
+fn show_path(path: &[(Vec<u8>, CRDT_type)]) -> String {
+    let segments: Vec<_> = path
+        .iter()
+        .map(|(key, crdt_type)| {
+            format!("{}:{}", type_name(*crdt_type), String::from_utf8_lossy(key))
+        })
+        .collect();
+    segments.join("/")
+}
+
+impl Update {
+    fn option(&self) -> &'static str {
+        match self {
+            Update::Inc(_) => "--inc/--dec",
+            Update::Set(_) => "--set",
+            Update::Add(_) => "--add",
+            Update::Flag(true) => "--enable",
+            Update::Flag(false) => "--disable",
+            Update::Entry(_) => "--entry",
+            Update::Remove(_) => "--remove",
+        }
+    }
+}
+
+impl Codec {
+    fn parse(self, text: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Codec::Utf8 => Ok(text.as_bytes().to_vec()),
+            Codec::U64 => text
+                .parse::<u64>()
+                .map(|n| n.to_be_bytes().to_vec())
+                .map_err(|_| format!("{:?} is not a u64", text)),
+            Codec::Hex => from_hex(text.strip_prefix("0x").unwrap_or(text))
+                .ok_or_else(|| format!("{:?} is not hex", text)),
+        }
+    }
+
+    fn parse_all(self, texts: &[String]) -> Result<Vec<Vec<u8>>, String> {
+        texts.iter().map(|text| self.parse(text)).collect()
+    }
+
+    // bytes that do not decode are shown as hex
+    fn show(self, bytes: &[u8]) -> String {
+        match (self, bytes.len()) {
+            (Codec::U64, 8) => self.decode_u64(bytes).to_string(),
+            (Codec::Utf8, _) => Value::bytes(bytes).to_string(),
+            _ => format!("0x{}", to_hex(bytes)),
+        }
+    }
+
+    fn json(self, bytes: &[u8]) -> serde_json::Value {
+        match (self, bytes.len()) {
+            (Codec::U64, 8) => json!(self.decode_u64(bytes)),
+            (Codec::Utf8, _) => match String::from_utf8(bytes.to_vec()) {
+                Ok(text) => json!(text),
+                Err(_) => json!({ "hex": to_hex(bytes) }),
+            },
+            _ => json!({ "hex": to_hex(bytes) }),
+        }
+    }
+
+    fn decode_u64(self, bytes: &[u8]) -> u64 {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(bytes);
+        u64::from_be_bytes(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Args, String> {
+        parse_args(line.split_whitespace().map(str::to_string).collect())
+    }
+
+    fn key(key: &str) -> Vec<u8> {
+        key.as_bytes().to_vec()
+    }
+
+    #[test]
+    fn options_and_positionals() {
+        let args =
+            args("--host db --port 9000 --bucket b --output json --codec u64 read counter k")
+                .unwrap();
+        assert_eq!(args.host, "db");
+        assert_eq!(args.port, 9000);
+        assert_eq!(args.bucket, "b");
+        assert_eq!(args.output, Output::Json);
+        assert_eq!(args.codec, Codec::U64);
+        assert_eq!(args.command, Command::Read);
+        assert_eq!(args.crdt_type, CRDT_type::COUNTER);
+        assert_eq!(args.key, "k");
+        assert!(!args.interactive);
+        assert_eq!(args.at, None);
+
+        let token = CommitTime::from(vec![0, 0, 0, 0, 0, 0, 0, 3]).to_token();
+        let at = parse_args(vec![
+            "--at".to_string(),
+            token,
+            "read".to_string(),
+            "mvreg".to_string(),
+            "k".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(at.at, Some(vec![0, 0, 0, 0, 0, 0, 0, 3]));
+    }
+
+    #[test]
+    fn usage_errors() {
+        let error = |line: &str| args(line).unwrap_err();
+        assert_eq!(
+            error("read counter"),
+            "expected a command, a type and a key"
+        );
+        assert_eq!(error("write counter k"), "unknown command \"write\"");
+        assert_eq!(error("read tree k"), "unknown type \"tree\"");
+        assert_eq!(
+            error("update counter k"),
+            "update needs at least one update"
+        );
+        assert_eq!(
+            error("read counter k --inc 1"),
+            "updates are only given to update"
+        );
+        assert_eq!(error("read counter k --port"), "--port needs a value");
+        assert_eq!(error("--port x read counter k"), "invalid --port");
+        assert_eq!(
+            error("--output xml read counter k"),
+            "unknown output \"xml\""
+        );
+        assert_eq!(
+            error("--verbose read counter k"),
+            "unknown option --verbose"
+        );
+        assert!(error("--at 123 read counter k").starts_with("invalid --at token"));
+    }
+
+    #[test]
+    fn increments_and_decrements() {
+        assert_eq!(parse_inc("--inc", "-3"), Ok(-3));
+        assert_eq!(parse_inc("--dec", "3"), Ok(-3));
+        assert_eq!(parse_inc("--dec", &i64::MAX.to_string()), Ok(-i64::MAX));
+        assert_eq!(
+            parse_inc("--dec", &i64::MIN.to_string()),
+            Err(format!("--dec {} is out of range", i64::MIN))
+        );
+        assert_eq!(
+            parse_inc("--inc", "1.5"),
+            Err("--inc needs an integer".to_string())
+        );
+
+        let args = args("update counter k --inc 2 --dec 5").unwrap();
+        assert_eq!(args.updates, vec![Update::Inc(2), Update::Inc(-5)]);
+    }
+
+    #[test]
+    fn paths_name_nested_entries() {
+        assert_eq!(
+            parse_path("rrmap:user/gmap:address/lwwreg:city"),
+            Ok(vec![
+                (key("user"), CRDT_type::RRMAP),
+                (key("address"), CRDT_type::GMAP),
+                (key("city"), CRDT_type::LWWREG),
+            ])
+        );
+        // keys may hold colons, and be empty
+        assert_eq!(
+            parse_path("counter:a:b"),
+            Ok(vec![(key("a:b"), CRDT_type::COUNTER)])
+        );
+        assert_eq!(parse_path("orset:"), Ok(vec![(key(""), CRDT_type::ORSET)]));
+
+        assert_eq!(
+            parse_path("counter:n/lwwreg:x"),
+            Err("counter in \"counter:n/lwwreg:x\" is not a map".to_string())
+        );
+        assert_eq!(
+            parse_path("name"),
+            Err("expected <type>:<key>, found \"name\"".to_string())
+        );
+        assert_eq!(
+            parse_path("rrmap:a/tree:b"),
+            Err("unknown type \"tree\"".to_string())
+        );
+        let path = parse_path("rrmap:a/counter:b").unwrap();
+        assert_eq!(show_path(&path), "rrmap:a/counter:b");
+    }
+
+    #[test]
+    fn values_parse_by_codec() {
+        assert_eq!(Codec::Utf8.parse("0x01"), Ok(key("0x01")));
+        assert_eq!(Codec::U64.parse("258"), Ok(vec![0, 0, 0, 0, 0, 0, 1, 2]));
+        assert_eq!(
+            Codec::U64.parse("-1"),
+            Err("\"-1\" is not a u64".to_string())
+        );
+        assert_eq!(Codec::Hex.parse("0x00ff"), Ok(vec![0x00, 0xff]));
+        assert_eq!(Codec::Hex.parse("00ff"), Ok(vec![0x00, 0xff]));
+        assert_eq!(
+            Codec::Hex.parse("0f0"),
+            Err("\"0f0\" is not hex".to_string())
+        );
+        assert_eq!(
+            Codec::Utf8.parse_all(&split_list("a,,b")),
+            Ok(vec![key("a"), key(""), key("b")])
+        );
+
+        assert_eq!(Codec::U64.show(&[0, 0, 0, 0, 0, 0, 1, 2]), "258");
+        assert_eq!(Codec::U64.show(&[1]), "0x01");
+        assert_eq!(Codec::Utf8.show(b"a"), "\"a\"");
+        assert_eq!(Codec::Hex.show(b"a"), "0x61");
+        assert_eq!(Codec::Utf8.json(&[0xff]), json!({ "hex": "ff" }));
+        assert_eq!(Codec::U64.json(&[0, 0, 0, 0, 0, 0, 0, 7]), json!(7));
+    }
+
+    #[test]
+    fn map_updates_nest_by_path() {
+        let updates = args(
+            "update rrmap k --entry rrmap:user/lwwreg:name --set ada \
+             --entry rrmap:user/orset:tags --remove a,b --remove rrmap:user/counter:n \
+             --remove flag_ew:on",
+        )
+        .unwrap()
+        .updates;
+        let operation = map_operation(&updates, Codec::Utf8).unwrap();
+        let mapop = operation.get_mapop();
+        assert_eq!(mapop.get_removedKeys().len(), 1);
+        assert_eq!(mapop.get_removedKeys()[0].get_key(), b"on");
+
+        let user = &mapop.get_updates()[0];
+        assert_eq!(user.get_key().get_key(), b"user");
+        let inner = user.get_update().get_mapop();
+        let keys: Vec<_> = inner
+            .get_updates()
+            .iter()
+            .map(|update| update.get_key().get_key().to_vec())
+            .collect();
+        assert_eq!(keys, vec![key("name"), key("tags")]);
+        assert_eq!(
+            inner.get_updates()[1]
+                .get_update()
+                .get_setop()
+                .get_rems()
+                .len(),
+            2
+        );
+        assert_eq!(inner.get_removedKeys()[0].get_key(), b"n");
+
+        let error = |updates: &[Update]| map_operation(updates, Codec::Utf8).unwrap_err();
+        assert_eq!(
+            error(&[Update::Set("x".to_string())]),
+            "--set needs an --entry on maps"
+        );
+        assert_eq!(
+            error(&[Update::Entry("lwwreg:a".to_string())]),
+            "--entry lwwreg:a needs an update"
+        );
+        assert_eq!(
+            error(&[Update::Entry("counter:a".to_string()), Update::Flag(true)]),
+            "--enable does not apply to counter"
+        );
+    }
+}
@@ -0,0 +1,792 @@
+extern crate antidotedb;
+extern crate rustyline;
+
+use antidotedb::crdt::{type_from_name, type_name, CRDT_type};
+use antidotedb::crdt::{Counter, CounterT, FlagEW, FlagT, LWWReg, ORSet, ObjectReset, RRMap};
+use antidotedb::crdt::{RegT, SetT};
+use antidotedb::dynamic::{self, bound_object, from_hex};
+use antidotedb::proto::{ApbUpdateOp, ApbUpdateOperation};
+use antidotedb::session::CommitTime;
+use antidotedb::value::Value;
+use antidotedb::{AntidoteDB, Transaction};
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "usage: antidote-repl [--host <host>] [--port <port>] [--bucket <bucket>]";
+
+const HELP: &str = "\
+begin [at <token>]           start a transaction, after the last commit or token
+commit                       commit it and show its token
+abort                        abort it
+read [type] <object>         read an object
+inc [type] <object> [n]      increment a counter, by 1 by default
+dec [type] <object> [n]      decrement a counter
+set [type] <object> <value>  assign a register
+add [type] <object> <value>...
+remove [type] <object> <value>...
+                             add to or remove from a set
+enable [type] <object>       enable or disable a flag
+disable [type] <object>
+reset [type] <object>        reset an object
+history                      show the statements typed so far
+help, quit
+
+Objects are <bucket>/<key>, or <key> in the bucket given at start. Values are
+\"quoted\" strings with \\\" \\\\ \\n \\t \\xNN escapes, 0x hex bytes or bare words.
+Types default to counter, lwwreg, orset and flag_ew, or to the type an object
+was last used with. Statements outside a transaction commit on their own.";
+
+const COMMANDS: &[&str] = &[
+    "abort", "add", "begin", "commit", "dec", "disable", "enable", "help", "history", "inc",
+    "quit", "read", "remove", "reset", "set",
+];
+
+const TYPES: &[&str] = &[
+    "bcounter",
+    "counter",
+    "fatcounter",
+    "flag_dw",
+    "flag_ew",
+    "gmap",
+    "lwwreg",
+    "mvreg",
+    "orset",
+    "rrmap",
+    "rwset",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Quoted(Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Statement {
+    Begin(Option<Vec<u8>>),
+    Commit,
+    Abort,
+    Read(Target),
+    Update(Target, Op),
+    History,
+    Help,
+    Quit,
+}
+
+// an object and its type, if given
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Target {
+    crdt_type: Option<CRDT_type>,
+    bucket: Vec<u8>,
+    key: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Op {
+    Inc(i64),
+    Set(Vec<u8>),
+    Add(Vec<Vec<u8>>),
+    Remove(Vec<Vec<u8>>),
+    Flag(bool),
+    Reset,
+}
+
+struct Repl {
+    db: AntidoteDB,
+    bucket: String,
+    txn: Option<Transaction>,
+    // token of the last commit, later statements run after it
+    last: Option<Vec<u8>>,
+    // type each object was last used with, by a statement that succeeded
+    types: HashMap<(Vec<u8>, Vec<u8>), CRDT_type>,
+}
+
+// completes statements and CRDT type names
+struct Completion;
+
+fn main() {
+    let mut host = "localhost".to_string();
+    let mut port = 8087;
+    let mut bucket = "default".to_string();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next();
+        match (arg.as_str(), value) {
+            ("--host", Some(value)) => host = value,
+            ("--port", Some(value)) => match value.parse() {
+                Ok(value) => port = value,
+                Err(_) => usage(),
+            },
+            ("--bucket", Some(value)) => bucket = value,
+            _ => usage(),
+        }
+    }
+
+    let db = match AntidoteDB::connect(&host, port) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("antidote-repl: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut editor: Editor<Completion, DefaultHistory> = match Editor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("antidote-repl: {}", e);
+            process::exit(1);
+        }
+    };
+    editor.set_helper(Some(Completion));
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+
+    let mut repl = Repl {
+        db,
+        bucket,
+        txn: None,
+        last: None,
+        types: HashMap::new(),
+    };
+
+    loop {
+        let prompt = if repl.txn.is_some() {
+            "antidote(txn)> "
+        } else {
+            "antidote> "
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("antidote-repl: {}", e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+
+        let statement = match parse(&line, &repl.bucket) {
+            Ok(statement) => statement,
+            Err(message) => {
+                println!("error: {}", message);
+                continue;
+            }
+        };
+        match statement {
+            Statement::Quit => break,
+            Statement::Help => println!("{}", HELP),
+            Statement::History => {
+                for (i, line) in editor.history().iter().enumerate() {
+                    println!("{:5}  {}", i + 1, line);
+                }
+            }
+            statement => {
+                if let Err(message) = repl.execute(statement) {
+                    println!("error: {}", message);
+                }
+            }
+        }
+    }
+
+    if let Some(txn) = repl.txn.take() {
+        let _ = repl.db.abort_transaction(&txn);
+    }
+    if let Some(path) = &history {
+        let _ = editor.save_history(path);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".antidote_history"))
+}
+
+impl Repl {
+    fn execute(&mut self, statement: Statement) -> Result<(), String> {
+        match statement {
+            Statement::Begin(at) => {
+                if self.txn.is_some() {
+                    return Err("already in a transaction".to_string());
+                }
+                let at = at.or_else(|| self.last.clone());
+                let txn = self
+                    .db
+                    .start_transaction(at.as_ref())
+                    .map_err(|e| e.to_string())?;
+                self.txn = Some(txn);
+            }
+            Statement::Commit => {
+                let txn = self.txn.take().ok_or("not in a transaction")?;
+                let token = self
+                    .db
+                    .commit_transaction(&txn)
+                    .map_err(|e| e.to_string())?;
+                println!("committed {}", CommitTime::from(token.clone()));
+                self.last = Some(token);
+            }
+            Statement::Abort => {
+                let txn = self.txn.take().ok_or("not in a transaction")?;
+                self.db.abort_transaction(&txn).map_err(|e| e.to_string())?;
+                println!("aborted");
+            }
+            Statement::Read(target) => {
+                let crdt_type = self.crdt_type(&target, None)?;
+                let (resp, token) = dynamic::read_one(
+                    &mut self.db,
+                    crdt_type,
+                    &target.key,
+                    &target.bucket,
+                    self.txn.as_ref(),
+                    self.last.as_ref(),
+                )
+                .map_err(|e| e.to_string())?;
+                self.remember(&target, crdt_type);
+                print!("{}", Value::decode(crdt_type, &resp).tree());
+                if let Some(token) = token {
+                    println!("at {}", CommitTime::from(token.clone()));
+                    self.last = Some(token);
+                }
+            }
+            Statement::Update(target, op) => {
+                let crdt_type = self.crdt_type(&target, op.default_type())?;
+                let mut update = ApbUpdateOp::new();
+                update.set_boundobject(bound_object(&target.key, crdt_type, &target.bucket));
+                update.set_operation(operation(crdt_type, op)?);
+
+                match &self.txn {
+                    Some(txn) => {
+                        self.db
+                            .mult_update_in_transaction(&[update], txn)
+                            .map_err(|e| e.to_string())?;
+                        self.remember(&target, crdt_type);
+                        println!("ok");
+                    }
+                    None => {
+                        let token = self
+                            .db
+                            .static_update_objects(&[update], self.last.as_ref())
+                            .map_err(|e| e.to_string())?;
+                        self.remember(&target, crdt_type);
+                        println!("committed {}", CommitTime::from(token.clone()));
+                        self.last = Some(token);
+                    }
+                }
+            }
+            Statement::History | Statement::Help | Statement::Quit => {}
+        }
+        Ok(())
+    }
+
+    // the type given, else the one the object was last used with, else the
+    // default of the operation
+    fn crdt_type(&self, target: &Target, default: Option<CRDT_type>) -> Result<CRDT_type, String> {
+        let object = (target.bucket.clone(), target.key.clone());
+        target
+            .crdt_type
+            .or_else(|| self.types.get(&object).cloned())
+            .or(default)
+            .ok_or_else(|| {
+                format!(
+                    "type of {0} unknown, give it as in \"read orset {0}\"",
+                    show_object(target)
+                )
+            })
+    }
+
+    fn remember(&mut self, target: &Target, crdt_type: CRDT_type) {
+        let object = (target.bucket.clone(), target.key.clone());
+        self.types.insert(object, crdt_type);
+    }
+}
+
+impl Op {
+    fn default_type(&self) -> Option<CRDT_type> {
+        match self {
+            Op::Inc(_) => Some(CRDT_type::COUNTER),
+            Op::Set(_) => Some(CRDT_type::LWWREG),
+            Op::Add(_) | Op::Remove(_) => Some(CRDT_type::ORSET),
+            Op::Flag(_) => Some(CRDT_type::FLAG_EW),
+            Op::Reset => None,
+        }
+    }
+}
+
+fn operation(crdt_type: CRDT_type, op: Op) -> Result<ApbUpdateOperation, String> {
+    use self::CRDT_type::*;
+
+    let operation = match (crdt_type, op) {
+        (COUNTER, Op::Inc(n)) | (FATCOUNTER, Op::Inc(n)) | (BCOUNTER, Op::Inc(n)) => {
+            Counter::inc_op(n)
+        }
+        (LWWREG, Op::Set(value)) | (MVREG, Op::Set(value)) => LWWReg::set_value_op(&value),
+        (ORSET, Op::Add(values)) | (RWSET, Op::Add(values)) => ORSet::add_values_op(&values),
+        (ORSET, Op::Remove(values)) | (RWSET, Op::Remove(values)) => ORSet::rem_values_op(&values),
+        (FLAG_EW, Op::Flag(value)) | (FLAG_DW, Op::Flag(value)) => FlagEW::set_op(value),
+        (FATCOUNTER, Op::Reset)
+        | (MVREG, Op::Reset)
+        | (ORSET, Op::Reset)
+        | (RWSET, Op::Reset)
+        | (FLAG_EW, Op::Reset)
+        | (FLAG_DW, Op::Reset)
+        | (RRMAP, Op::Reset) => RRMap::reset_op(),
+        (crdt_type, op) => {
+            return Err(format!(
+                "{} does not apply to {}",
+                op.name(),
+                type_name(crdt_type)
+            ))
+        }
+    };
+    Ok(operation)
+}
+
+impl Op {
+    fn name(&self) -> &'static str {
+        match self {
+            Op::Inc(_) => "inc",
+            Op::Set(_) => "set",
+            Op::Add(_) => "add",
+            Op::Remove(_) => "remove",
+            Op::Flag(true) => "enable",
+            Op::Flag(false) => "disable",
+            Op::Reset => "reset",
+        }
+    }
+}
+
+fn parse(line: &str, bucket: &str) -> Result<Statement, String> {
+    let tokens = tokenize(line)?;
+    let mut tokens = tokens.into_iter();
+    let command = match tokens.next() {
+        Some(Token::Word(command)) => command,
+        _ => return Err("expected a command".to_string()),
+    };
+    let rest: Vec<Token> = tokens.collect();
+
+    let statement = match command.as_str() {
+        "begin" => match rest.as_slice() {
+            [] => Statement::Begin(None),
+            [Token::Word(at), Token::Word(token)] if at == "at" => {
+                let time = CommitTime::from_token(token).map_err(|e| e.to_string())?;
+                Statement::Begin(Some(time.into_bytes()))
+            }
+            _ => return Err("expected begin [at <token>]".to_string()),
+        },
+        "commit" | "abort" | "history" | "help" | "quit" | "exit" if !rest.is_empty() => {
+            return Err(format!("{} takes no arguments", command))
+        }
+        "commit" => Statement::Commit,
+        "abort" => Statement::Abort,
+        "history" => Statement::History,
+        "help" => Statement::Help,
+        "quit" | "exit" => Statement::Quit,
+        "read" => {
+            let (target, values) = target(&rest, bucket)?;
+            no_values(&command, &values)?;
+            Statement::Read(target)
+        }
+        "inc" | "dec" => {
+            let (target, values) = target(&rest, bucket)?;
+            let n: i64 = match values.as_slice() {
+                [] => 1,
+                [Token::Word(n)] => n
+                    .parse()
+                    .map_err(|_| format!("{:?} is not an integer", n))?,
+                _ => return Err(format!("expected {} [type] <object> [n]", command)),
+            };
+            let n = match command.as_str() {
+                "inc" => n,
+                _ => n
+                    .checked_neg()
+                    .ok_or_else(|| format!("dec {} is out of range", n))?,
+            };
+            Statement::Update(target, Op::Inc(n))
+        }
+        "set" => {
+            let (target, values) = target(&rest, bucket)?;
+            match values.as_slice() {
+                [value] => Statement::Update(target, Op::Set(literal(value)?)),
+                _ => return Err("expected set [type] <object> <value>".to_string()),
+            }
+        }
+        "add" | "remove" => {
+            let (target, values) = target(&rest, bucket)?;
+            if values.is_empty() {
+                return Err(format!("expected {} [type] <object> <value>...", command));
+            }
+            let values = values.iter().map(literal).collect::<Result<_, _>>()?;
+            let op = if command == "add" {
+                Op::Add(values)
+            } else {
+                Op::Remove(values)
+            };
+            Statement::Update(target, op)
+        }
+        "enable" | "disable" | "reset" => {
+            let (target, values) = target(&rest, bucket)?;
+            no_values(&command, &values)?;
+            let op = match command.as_str() {
+                "enable" => Op::Flag(true),
+                "disable" => Op::Flag(false),
+                _ => Op::Reset,
+            };
+            Statement::Update(target, op)
+        }
+        _ => return Err(format!("unknown command {:?}, see help", command)),
+    };
+    Ok(statement)
+}
+
+// [type] <object>, then the remaining tokens
+fn target(tokens: &[Token], bucket: &str) -> Result<(Target, Vec<Token>), String> {
+    let (crdt_type, tokens) = match tokens.first() {
+        Some(Token::Word(name)) if type_from_name(name).is_some() && tokens.len() > 1 => {
+            (type_from_name(name), &tokens[1..])
+        }
+        _ => (None, tokens),
+    };
+    let object = match tokens.first() {
+        Some(Token::Word(object)) => object,
+        _ => return Err("expected an object".to_string()),
+    };
+    let (bucket, key) = match object.split_once('/') {
+        Some((bucket, key)) => (bucket, key),
+        None => (bucket, object.as_str()),
+    };
+    let target = Target {
+        crdt_type,
+        bucket: bucket.as_bytes().to_vec(),
+        key: key.as_bytes().to_vec(),
+    };
+    Ok((target, tokens[1..].to_vec()))
+}
+
+fn no_values(command: &str, values: &[Token]) -> Result<(), String> {
+    if values.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("expected {} [type] <object>", command))
+    }
+}
+
+fn literal(token: &Token) -> Result<Vec<u8>, String> {
+    match token {
+        Token::Quoted(bytes) => Ok(bytes.clone()),
+        Token::Word(word) if word.starts_with("0x") => {
+            from_hex(&word[2..]).ok_or_else(|| format!("{:?} is not hex", word))
+        }
+        Token::Word(word) => Ok(word.as_bytes().to_vec()),
+    }
+}
+
+// words separated by whitespace and "quoted" strings
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut bytes = Vec::new();
+            loop {
+                match chars.next() {
+                    None => return Err("unterminated string".to_string()),
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => bytes.push(b'\n'),
+                        Some('t') => bytes.push(b'\t'),
+                        Some('x') => {
+                            let hex: String = chars.by_ref().take(2).collect();
+                            let byte = u8::from_str_radix(&hex, 16)
+                                .map_err(|_| format!("invalid escape \\x{}", hex))?;
+                            bytes.push(byte);
+                        }
+                        Some(c @ '"') | Some(c @ '\\') => bytes.push(c as u8),
+                        Some(c) => return Err(format!("invalid escape \\{}", c)),
+                        None => return Err("unterminated string".to_string()),
+                    },
+                    Some(c) => {
+                        let mut buf = [0; 4];
+                        bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    }
+                }
+            }
+            tokens.push(Token::Quoted(bytes));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+fn show_object(target: &Target) -> String {
+    format!(
+        "{}/{}",
+        String::from_utf8_lossy(&target.bucket),
+        String::from_utf8_lossy(&target.key)
+    )
+}
+
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let prefix = &line[start..pos];
+        let words = if line[..start].trim().is_empty() {
+            COMMANDS
+        } else if line[..start].trim() == "begin" {
+            &["at"]
+        } else {
+            TYPES
+        };
+        let candidates = words
+            .iter()
+            .filter(|word| word.starts_with(prefix))
+            .map(|word| word.to_string())
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use antidotedb::server::StandIn;
+
+    fn target(crdt_type: Option<CRDT_type>, bucket: &str, key: &str) -> Target {
+        Target {
+            crdt_type,
+            bucket: bucket.as_bytes().to_vec(),
+            key: key.as_bytes().to_vec(),
+        }
+    }
+
+    fn word(word: &str) -> Token {
+        Token::Word(word.to_string())
+    }
+
+    #[test]
+    fn words_and_quoted_strings() {
+        assert_eq!(
+            tokenize("  add s \"a b\"c  \"\" ").unwrap(),
+            vec![
+                word("add"),
+                word("s"),
+                Token::Quoted(b"a b".to_vec()),
+                word("c"),
+                Token::Quoted(Vec::new()),
+            ]
+        );
+        assert_eq!(
+            tokenize(r#""\"\\\n\t\x00\xfFé""#).unwrap(),
+            vec![Token::Quoted(b"\"\\\n\t\x00\xff\xc3\xa9".to_vec())]
+        );
+        assert_eq!(tokenize("\"open").unwrap_err(), "unterminated string");
+        assert_eq!(tokenize("\"open\\").unwrap_err(), "unterminated string");
+        assert_eq!(tokenize(r#""\q""#).unwrap_err(), "invalid escape \\q");
+        assert_eq!(tokenize(r#""\xg0""#).unwrap_err(), "invalid escape \\xg0");
+        assert_eq!(tokenize(r#""\x4""#).unwrap_err(), "invalid escape \\x4\"");
+    }
+
+    #[test]
+    fn objects_take_the_bucket_before_a_slash() {
+        assert_eq!(
+            parse("read k", "default"),
+            Ok(Statement::Read(target(None, "default", "k")))
+        );
+        assert_eq!(
+            parse("read users/ada", "default"),
+            Ok(Statement::Read(target(None, "users", "ada")))
+        );
+        // only the first slash splits
+        assert_eq!(
+            parse("read orset a/b/c", "default"),
+            Ok(Statement::Read(target(Some(CRDT_type::ORSET), "a", "b/c")))
+        );
+        // a type name alone is the object
+        assert_eq!(
+            parse("read orset", "default"),
+            Ok(Statement::Read(target(None, "default", "orset")))
+        );
+        assert_eq!(parse("read", "default").unwrap_err(), "expected an object");
+        assert_eq!(
+            parse("read \"k\"", "default").unwrap_err(),
+            "expected an object"
+        );
+    }
+
+    #[test]
+    fn statements() {
+        let counter = || target(Some(CRDT_type::COUNTER), "b", "n");
+        assert_eq!(
+            parse("inc counter n", "b"),
+            Ok(Statement::Update(counter(), Op::Inc(1)))
+        );
+        assert_eq!(
+            parse("dec counter n 3", "b"),
+            Ok(Statement::Update(counter(), Op::Inc(-3)))
+        );
+        assert_eq!(
+            parse(&format!("dec counter n {}", i64::MIN), "b").unwrap_err(),
+            format!("dec {} is out of range", i64::MIN)
+        );
+        assert_eq!(
+            parse("inc n x", "b").unwrap_err(),
+            "\"x\" is not an integer"
+        );
+        assert_eq!(
+            parse("set r 0x0aff", "b"),
+            Ok(Statement::Update(
+                target(None, "b", "r"),
+                Op::Set(vec![0x0a, 0xff])
+            ))
+        );
+        assert_eq!(
+            parse("set r \"0x0a\"", "b"),
+            Ok(Statement::Update(
+                target(None, "b", "r"),
+                Op::Set(b"0x0a".to_vec())
+            ))
+        );
+        assert_eq!(parse("set r 0xf", "b").unwrap_err(), "\"0xf\" is not hex");
+        assert_eq!(
+            parse("remove rwset s a \"b c\"", "b"),
+            Ok(Statement::Update(
+                target(Some(CRDT_type::RWSET), "b", "s"),
+                Op::Remove(vec![b"a".to_vec(), b"b c".to_vec()])
+            ))
+        );
+        assert_eq!(
+            parse("add s", "b").unwrap_err(),
+            "expected add [type] <object> <value>..."
+        );
+        assert_eq!(
+            parse("disable f", "b"),
+            Ok(Statement::Update(target(None, "b", "f"), Op::Flag(false)))
+        );
+        assert_eq!(
+            parse("reset f now", "b").unwrap_err(),
+            "expected reset [type] <object>"
+        );
+
+        let token = CommitTime::from(vec![0, 0, 0, 0, 0, 0, 0, 9]).to_token();
+        assert_eq!(
+            parse(&format!("begin at {}", token), "b"),
+            Ok(Statement::Begin(Some(vec![0, 0, 0, 0, 0, 0, 0, 9])))
+        );
+        assert_eq!(parse("begin", "b"), Ok(Statement::Begin(None)));
+        assert_eq!(
+            parse("begin now", "b").unwrap_err(),
+            "expected begin [at <token>]"
+        );
+        assert_eq!(parse("exit", "b"), Ok(Statement::Quit));
+        assert_eq!(
+            parse("commit now", "b").unwrap_err(),
+            "commit takes no arguments"
+        );
+        assert_eq!(parse("\"read\" k", "b").unwrap_err(), "expected a command");
+        assert_eq!(
+            parse("get k", "b").unwrap_err(),
+            "unknown command \"get\", see help"
+        );
+    }
+
+    #[test]
+    fn types_are_remembered_after_success() {
+        let stand_in = StandIn::start().unwrap();
+        let mut repl = Repl {
+            db: stand_in.connect().unwrap(),
+            bucket: "b".to_string(),
+            txn: None,
+            last: None,
+            types: HashMap::new(),
+        };
+        let run = |repl: &mut Repl, line: &str| {
+            let statement = parse(line, "b").unwrap();
+            repl.execute(statement)
+        };
+        let unknown = "type of b/k unknown, give it as in \"read orset b/k\"";
+
+        // refused by the operation, then by the stand-in, for a time it has
+        // not reached
+        assert!(run(&mut repl, "set counter k x").is_err());
+        repl.last = Some(100u64.to_be_bytes().to_vec());
+        assert!(run(&mut repl, "inc counter k").is_err());
+        assert!(run(&mut repl, "read counter k").is_err());
+        assert!(repl.types.is_empty());
+        assert_eq!(run(&mut repl, "read k"), Err(unknown.to_string()));
+
+        repl.last = None;
+        run(&mut repl, "inc counter k").unwrap();
+        run(&mut repl, "read k").unwrap();
+        // the remembered type goes before the default of the operation
+        assert_eq!(
+            run(&mut repl, "set k x"),
+            Err("set does not apply to counter".to_string())
+        );
+        // and a type given goes before the remembered one
+        run(&mut repl, "read lwwreg k").unwrap();
+        assert_eq!(
+            repl.types.get(&(b"b".to_vec(), b"k".to_vec())),
+            Some(&CRDT_type::LWWREG)
+        );
+
+        // statements in a transaction count once they succeed, even if the
+        // transaction is aborted
+        run(&mut repl, "begin").unwrap();
+        run(&mut repl, "add s a").unwrap();
+        run(&mut repl, "abort").unwrap();
+        assert_eq!(
+            repl.types.get(&(b"b".to_vec(), b"s".to_vec())),
+            Some(&CRDT_type::ORSET)
+        );
+    }
+}
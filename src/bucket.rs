@@ -0,0 +1,275 @@
+use super::antidote;
+use super::codec;
+use super::crdt::{BCounter, Counter, FatCounter, FlagDW, FlagEW, GMap, LWWReg, MVReg, RRMap};
+use super::crdt::{Element, ORSet, Object, RWSet, ReadT, SetT};
+use super::AntidoteDB;
+
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+// A bucket, handing out the objects stored in it:
+//
+//     let users = db.bucket("users").with_prefix("tenant42/");
+//     let visits = users.counter("visits");            // key "tenant42/visits"
+//     let tags = users.orset::<String>("tags");
+//
+// Prefixes nest, with_prefix appends to the current one. Buckets, prefixes
+// and keys are bytes, anything AsRef<[u8]> can be used. The objects share
+// the bucket name with the bucket.
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Bucket {
+    name: Arc<[u8]>,
+    prefix: Vec<u8>,
+}
+
+impl Bucket {
+    pub fn new(name: impl AsRef<[u8]>) -> Self {
+        Bucket {
+            name: Arc::from(name.as_ref()),
+            prefix: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    pub fn with_prefix(&self, prefix: impl AsRef<[u8]>) -> Self {
+        let mut bucket = self.clone();
+        bucket.prefix.extend_from_slice(prefix.as_ref());
+        bucket
+    }
+
+    // the stored key of key, with the prefix
+    pub fn key(&self, key: impl AsRef<[u8]>) -> Vec<u8> {
+        let mut stored = self.prefix.clone();
+        stored.extend_from_slice(key.as_ref());
+        stored
+    }
+
+    pub fn object<C: Object>(&self, key: impl AsRef<[u8]>) -> C {
+        C::new_in_bucket(&self.key(key), &self.name)
+    }
+
+    pub fn counter(&self, key: impl AsRef<[u8]>) -> Counter {
+        self.object(key)
+    }
+
+    pub fn fat_counter(&self, key: impl AsRef<[u8]>) -> FatCounter {
+        self.object(key)
+    }
+
+    pub fn bcounter(&self, key: impl AsRef<[u8]>) -> BCounter {
+        self.object(key)
+    }
+
+    pub fn lwwreg(&self, key: impl AsRef<[u8]>) -> LWWReg {
+        self.object(key)
+    }
+
+    pub fn mvreg(&self, key: impl AsRef<[u8]>) -> MVReg {
+        self.object(key)
+    }
+
+    pub fn orset<E: Element>(&self, key: impl AsRef<[u8]>) -> Set<ORSet, E> {
+        self.object(key)
+    }
+
+    pub fn rwset<E: Element>(&self, key: impl AsRef<[u8]>) -> Set<RWSet, E> {
+        self.object(key)
+    }
+
+    pub fn flag_ew(&self, key: impl AsRef<[u8]>) -> FlagEW {
+        self.object(key)
+    }
+
+    pub fn flag_dw(&self, key: impl AsRef<[u8]>) -> FlagDW {
+        self.object(key)
+    }
+
+    pub fn gmap(&self, key: impl AsRef<[u8]>) -> GMap {
+        self.object(key)
+    }
+
+    pub fn rrmap(&self, key: impl AsRef<[u8]>) -> RRMap {
+        self.object(key)
+    }
+}
+
+impl AntidoteDB {
+    pub fn bucket(&self, name: impl AsRef<[u8]>) -> Bucket {
+        Bucket::new(name)
+    }
+}
+
+// A set of elements of type E, e.g. Set<ORSet, String>
+pub struct Set<C: SetT, E: Element> {
+    set: C,
+    element: PhantomData<E>,
+}
+
+impl<C: SetT, E: Element> Set<C, E> {
+    pub fn inner(&self) -> &C {
+        &self.set
+    }
+
+    pub fn add(&self, values: &[E]) -> antidote::ApbUpdateOp {
+        self.set.add_values(values)
+    }
+
+    pub fn rem(&self, values: &[E]) -> antidote::ApbUpdateOp {
+        self.set.rem_values(values)
+    }
+}
+
+impl<C: SetT, E: Element> Object for Set<C, E> {
+    fn new_bytes(key: &[u8], bucket: &[u8]) -> Self {
+        Set {
+            set: C::new_bytes(key, bucket),
+            element: PhantomData,
+        }
+    }
+
+    fn new_in_bucket(key: &[u8], bucket: &Arc<[u8]>) -> Self {
+        Set {
+            set: C::new_in_bucket(key, bucket),
+            element: PhantomData,
+        }
+    }
+
+    fn get_object(&self) -> antidote::ApbBoundObject {
+        self.set.get_object()
+    }
+
+    fn object_parts(&self) -> (Cow<'_, [u8]>, i32, Cow<'_, [u8]>) {
+        self.set.object_parts()
+    }
+
+    fn type_number() -> i32 {
+        C::type_number()
+    }
+}
+
+// None if an element does not decode to E
+impl<C, E> ReadT for Set<C, E>
+where
+    C: SetT + ReadT<Value = Vec<codec::Bytes>>,
+    E: Element,
+{
+    type Value = Option<Vec<E>>;
+
+    fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+        C::decode(resp)
+            .iter()
+            .map(|bytes| E::from_bytes(bytes))
+            .collect()
+    }
+}
+
+impl<C: SetT + Clone, E: Element> Clone for Set<C, E> {
+    fn clone(&self) -> Self {
+        Set {
+            set: self.set.clone(),
+            element: PhantomData,
+        }
+    }
+}
+
+impl<C: SetT + fmt::Debug, E: Element> fmt::Debug for Set<C, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Set").field("set", &self.set).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::crdt::SetT;
+    use super::*;
+
+    fn set_resp(elements: &[&'static str]) -> antidote::ApbReadObjectResp {
+        let mut set = antidote::ApbGetSetResp::new();
+        set.set_value(
+            elements
+                .iter()
+                .map(|s| codec::Bytes::from(s.as_bytes()))
+                .collect(),
+        );
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_set(set);
+        resp
+    }
+
+    #[test]
+    fn prefixes_nest() {
+        let bucket = Bucket::new("users");
+        assert_eq!(bucket.key("ada"), b"ada");
+
+        let tenant = bucket.with_prefix("tenant42/");
+        let team = tenant.with_prefix("team7/");
+        assert_eq!(bucket.prefix(), b"");
+        assert_eq!(tenant.key("ada"), b"tenant42/ada");
+        assert_eq!(team.prefix(), b"tenant42/team7/");
+        assert_eq!(team.key(b"\xff"), b"tenant42/team7/\xff");
+        assert_eq!(team.name(), b"users");
+        assert_ne!(team, tenant);
+        assert_eq!(team, Bucket::new(b"users").with_prefix("tenant42/team7/"));
+
+        let visits = team.counter("visits");
+        assert_eq!(visits.key(), b"tenant42/team7/visits");
+        assert_eq!(visits.bucket(), b"users");
+        let tags = team.orset::<String>("tags");
+        let object = tags.get_object();
+        assert_eq!(object.get_key(), b"tenant42/team7/tags");
+        assert_eq!(object.get_bucket(), b"users");
+        assert_eq!(object.get_field_type(), antidote::CRDT_type::ORSET);
+    }
+
+    #[test]
+    fn objects_share_the_bucket_name() {
+        let bucket = Bucket::new("users").with_prefix("p/");
+        let counter = bucket.counter("a");
+        let reg = bucket.lwwreg("b");
+        let set = bucket.rwset::<u64>("c");
+        assert_eq!(counter.bucket().as_ptr(), bucket.name().as_ptr());
+        assert_eq!(reg.bucket().as_ptr(), bucket.name().as_ptr());
+        assert_eq!(set.inner().bucket().as_ptr(), bucket.name().as_ptr());
+        assert_eq!(
+            set.inner().get_object().get_field_type(),
+            antidote::CRDT_type::RWSET
+        );
+    }
+
+    #[test]
+    fn typed_sets_decode_every_element_or_none() {
+        type Names = Set<ORSet, String>;
+        type Numbers = Set<ORSet, u64>;
+
+        assert_eq!(
+            Names::decode(&set_resp(&["ada", "bob"])),
+            Some(vec!["ada".to_string(), "bob".to_string()])
+        );
+        assert_eq!(Names::decode(&set_resp(&[])), Some(Vec::new()));
+        assert_eq!(
+            Numbers::decode(&set_resp(&["\0\0\0\0\0\0\0\x07"])),
+            Some(vec![7])
+        );
+        // one element of the wrong length is enough
+        assert_eq!(
+            Numbers::decode(&set_resp(&["\0\0\0\0\0\0\0\x07", "seven"])),
+            None
+        );
+
+        let tags = Bucket::new("b").orset::<u64>("tags");
+        assert_eq!(
+            tags.add(&[1]).get_operation(),
+            &ORSet::add_values_op(&[1u64])
+        );
+    }
+}
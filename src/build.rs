@@ -1,13 +1,557 @@
+extern crate protobuf;
 extern crate protobuf_codegen_pure;
 
+use std::env;
+use std::path::PathBuf;
+
+// The messages are generated in OUT_DIR/antidote.rs, for the backend picked
+// by the features, and included by the antidote module.
+
 fn main() {
-    protobuf_codegen_pure::run(protobuf_codegen_pure::Args {
-        out_dir: "src/",
-        input: &["protos/antidote.proto"],
-        includes: &["protos"],
-        customize: protobuf_codegen_pure::Customize {
-            ..Default::default()
-        },
-    })
-    .expect("protoc");
+    println!("cargo:rerun-if-changed=protos/antidote.proto");
+    println!("cargo:rerun-if-changed=src/build.rs");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR"));
+    #[cfg(feature = "prost")]
+    prost_backend::codegen(&out_dir);
+    #[cfg(not(feature = "prost"))]
+    rust_protobuf_backend::codegen(&out_dir);
+}
+
+#[cfg(not(feature = "prost"))]
+mod rust_protobuf_backend {
+    use std::fs;
+    use std::path::Path;
+
+    pub(super) fn codegen(out_dir: &Path) {
+        protobuf_codegen_pure::Codegen::new()
+            .out_dir(out_dir)
+            .input("protos/antidote.proto")
+            .include("protos")
+            .run()
+            .expect("protoc");
+
+        // inner attributes cannot be included, the module has them instead
+        let path = out_dir.join("antidote.rs");
+        let code = fs::read_to_string(&path).expect("read antidote.rs");
+        let code: Vec<_> = code
+            .lines()
+            .filter(|line| !line.starts_with("#![") && !line.starts_with("//!"))
+            .collect();
+        fs::write(&path, code.join("\n")).expect("write antidote.rs");
+    }
+}
+
+// The prost backend: prost-build generates the messages, with bytes::Bytes
+// for bytes fields, from the descriptors parsed for rust-protobuf, so protoc
+// is not needed. The enums rust-protobuf generates, and its accessors, are
+// written after them, so the client code is the same for both.
+//
+// Enum fields are kept as i32, numbers missing from the enum are kept as they
+// are, and required fields are not checked, by rust-protobuf either. Required
+// scalars are plain values under prost, their has_ accessors always true.
+#[cfg(feature = "prost")]
+mod prost_backend {
+    use prost::Message as _;
+    use protobuf::descriptor::{
+        DescriptorProto, EnumDescriptorProto, FieldDescriptorProto,
+        FieldDescriptorProto_Label as Label, FieldDescriptorProto_Type as Type,
+    };
+    use protobuf::Message as _;
+
+    use std::fmt::Write;
+    use std::fs;
+    use std::path::Path;
+
+    pub(super) fn codegen(out_dir: &Path) {
+        let parsed = protobuf_codegen_pure::parse_and_typecheck(
+            &[Path::new("protos")],
+            &[Path::new("protos/antidote.proto")],
+        )
+        .expect("protoc");
+
+        let mut files = prost_types::FileDescriptorSet::default();
+        for file in &parsed.file_descriptors {
+            let bytes = file.write_to_bytes().expect("encode descriptor");
+            let file =
+                prost_types::FileDescriptorProto::decode(&bytes[..]).expect("decode descriptor");
+            files.file.push(file);
+        }
+
+        let mut config = prost_build::Config::new();
+        config.out_dir(out_dir).bytes(["."]);
+        let mut code = String::new();
+        writeln!(code).unwrap();
+        writeln!(code, "// accessors, generated by src/build.rs").unwrap();
+        writeln!(code).unwrap();
+        writeln!(code, "use crate::codec::{{ProtobufEnum, RepeatedField}};").unwrap();
+        writeln!(code, "use std::convert::TryFrom;").unwrap();
+        writeln!(code, "use std::sync::OnceLock;").unwrap();
+
+        let file = &parsed.file_descriptors[0];
+        let package = file.get_package();
+        let scope = Scope {
+            proto: format!(".{}.", package),
+            rust: String::new(),
+            module: String::new(),
+        };
+        for enum_type in file.get_enum_type() {
+            write_enum(&mut config, &mut code, &scope, enum_type);
+        }
+        for message in file.get_message_type() {
+            write_message(&mut config, &mut code, &scope, message);
+        }
+
+        config.compile_fds(files).expect("prost-build");
+
+        let generated = out_dir.join(format!("{}.rs", heck::ToSnakeCase::to_snake_case(package)));
+        let mut messages = fs::read_to_string(&generated).expect("read prost-build output");
+        fs::remove_file(&generated).expect("remove prost-build output");
+        messages.push_str(&code);
+        fs::write(out_dir.join("antidote.rs"), messages).expect("write antidote.rs");
+    }
+
+    // where a message or enum is declared: its proto name prefix, the prefix of
+    // its rust-protobuf name, nested names joined by _, and the module prost-build
+    // puts it in
+    struct Scope {
+        proto: String,
+        rust: String,
+        module: String,
+    }
+
+    impl Scope {
+        fn nested(&self, message: &DescriptorProto) -> Scope {
+            let name = message.get_name();
+            Scope {
+                proto: format!("{}{}.", self.proto, name),
+                rust: format!("{}{}_", self.rust, name),
+                module: format!(
+                    "{}{}::",
+                    self.module,
+                    heck::ToSnakeCase::to_snake_case(name)
+                ),
+            }
+        }
+    }
+
+    // Enums are written with their rust-protobuf names and variants, and prost
+    // uses them where the messages name them.
+    fn write_enum(
+        config: &mut prost_build::Config,
+        code: &mut String,
+        scope: &Scope,
+        enum_type: &EnumDescriptorProto,
+    ) {
+        let name = format!("{}{}", scope.rust, enum_type.get_name());
+        let values = enum_type.get_value();
+        config.extern_path(
+            format!("{}{}", scope.proto, enum_type.get_name()),
+            format!("crate::antidote::{}", name),
+        );
+
+        writeln!(code).unwrap();
+        writeln!(code, "#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]").unwrap();
+        writeln!(code, "pub enum {} {{", name).unwrap();
+        for value in values {
+            writeln!(code, "    {} = {},", value.get_name(), value.get_number()).unwrap();
+        }
+        writeln!(code, "}}").unwrap();
+
+        writeln!(code).unwrap();
+        writeln!(code, "impl ProtobufEnum for {} {{", name).unwrap();
+        writeln!(code, "    fn value(&self) -> i32 {{").unwrap();
+        writeln!(code, "        *self as i32").unwrap();
+        writeln!(code, "    }}").unwrap();
+        writeln!(code).unwrap();
+        writeln!(code, "    fn from_i32(value: i32) -> Option<{}> {{", name).unwrap();
+        writeln!(code, "        match value {{").unwrap();
+        for value in values {
+            writeln!(
+                code,
+                "            {} => Some({}::{}),",
+                value.get_number(),
+                name,
+                value.get_name()
+            )
+            .unwrap();
+        }
+        writeln!(code, "            _ => None,").unwrap();
+        writeln!(code, "        }}").unwrap();
+        writeln!(code, "    }}").unwrap();
+        writeln!(code).unwrap();
+        writeln!(code, "    fn values() -> &'static [{}] {{", name).unwrap();
+        write!(code, "        &[").unwrap();
+        for value in values {
+            write!(code, "{}::{}, ", name, value.get_name()).unwrap();
+        }
+        writeln!(code, "]").unwrap();
+        writeln!(code, "    }}").unwrap();
+        writeln!(code, "}}").unwrap();
+
+        // for the getters prost generates
+        writeln!(code).unwrap();
+        writeln!(code, "impl TryFrom<i32> for {} {{", name).unwrap();
+        writeln!(code, "    type Error = i32;").unwrap();
+        writeln!(code).unwrap();
+        writeln!(code, "    fn try_from(value: i32) -> Result<Self, i32> {{").unwrap();
+        writeln!(code, "        {}::from_i32(value).ok_or(value)", name).unwrap();
+        writeln!(code, "    }}").unwrap();
+        writeln!(code, "}}").unwrap();
+
+        writeln!(code).unwrap();
+        writeln!(code, "impl Default for {} {{", name).unwrap();
+        writeln!(code, "    fn default() -> Self {{").unwrap();
+        writeln!(code, "        {}::{}", name, values[0].get_name()).unwrap();
+        writeln!(code, "    }}").unwrap();
+        writeln!(code, "}}").unwrap();
+    }
+
+    fn write_message(
+        config: &mut prost_build::Config,
+        code: &mut String,
+        scope: &Scope,
+        message: &DescriptorProto,
+    ) {
+        let name = format!("{}{}", scope.rust, message.get_name());
+        let prost_name = format!(
+            "{}{}",
+            scope.module,
+            heck::ToUpperCamelCase::to_upper_camel_case(message.get_name())
+        );
+
+        writeln!(code).unwrap();
+        if prost_name != name {
+            writeln!(code, "pub type {} = {};", name, prost_name).unwrap();
+            writeln!(code).unwrap();
+        }
+        writeln!(code, "impl {} {{", prost_name).unwrap();
+        writeln!(code, "    pub fn new() -> {} {{", name).unwrap();
+        writeln!(code, "        Default::default()").unwrap();
+        writeln!(code, "    }}").unwrap();
+        writeln!(code).unwrap();
+        writeln!(
+            code,
+            "    pub fn default_instance() -> &'static {} {{",
+            name
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "        static INSTANCE: OnceLock<{}> = OnceLock::new();",
+            name
+        )
+        .unwrap();
+        writeln!(code, "        INSTANCE.get_or_init({}::new)", name).unwrap();
+        writeln!(code, "    }}").unwrap();
+        for field in message.get_field() {
+            write_accessors(code, &scope.proto, field);
+        }
+        writeln!(code, "}}").unwrap();
+
+        let nested = scope.nested(message);
+        for enum_type in message.get_enum_type() {
+            write_enum(config, code, &nested, enum_type);
+        }
+        for message in message.get_nested_type() {
+            write_message(config, code, &nested, message);
+        }
+    }
+
+    // rust-protobuf's name of a field in its accessors
+    fn rust_field(name: &str) -> String {
+        match name {
+            "type" | "ref" | "box" | "match" | "mod" | "fn" | "impl" | "struct" | "self"
+            | "use" | "in" | "loop" | "move" | "crate" | "where" => format!("field_{}", name),
+            _ => name.to_string(),
+        }
+    }
+
+    // prost-build's name of a field in its struct
+    fn prost_field(name: &str) -> String {
+        let name = heck::ToSnakeCase::to_snake_case(name);
+        match keyword(&name) {
+            true => format!("r#{}", name),
+            false => name,
+        }
+    }
+
+    fn keyword(name: &str) -> bool {
+        matches!(
+            name,
+            "type"
+                | "ref"
+                | "box"
+                | "match"
+                | "mod"
+                | "fn"
+                | "impl"
+                | "struct"
+                | "use"
+                | "in"
+                | "loop"
+                | "move"
+                | "where"
+                | "as"
+                | "break"
+                | "const"
+                | "continue"
+                | "else"
+                | "enum"
+                | "extern"
+                | "false"
+                | "for"
+                | "if"
+                | "let"
+                | "mut"
+                | "pub"
+                | "return"
+                | "static"
+                | "trait"
+                | "true"
+                | "unsafe"
+                | "while"
+                | "dyn"
+                | "abstract"
+                | "become"
+                | "do"
+                | "final"
+                | "macro"
+                | "override"
+                | "priv"
+                | "typeof"
+                | "unsized"
+                | "virtual"
+                | "yield"
+                | "async"
+                | "await"
+                | "try"
+        )
+    }
+
+    // Rust type of a message or enum, nested names joined by _ as rust-protobuf
+    // does
+    fn rust_type(package: &str, type_name: &str) -> String {
+        type_name.trim_start_matches(package).replace('.', "_")
+    }
+
+    // Rust type of the values of a field, enums being i32
+    fn value_type(package: &str, field: &FieldDescriptorProto) -> String {
+        let rust = match field.get_field_type() {
+            Type::TYPE_DOUBLE => "f64",
+            Type::TYPE_FLOAT => "f32",
+            Type::TYPE_INT64 | Type::TYPE_SFIXED64 | Type::TYPE_SINT64 => "i64",
+            Type::TYPE_UINT64 | Type::TYPE_FIXED64 => "u64",
+            Type::TYPE_INT32 | Type::TYPE_SFIXED32 | Type::TYPE_SINT32 | Type::TYPE_ENUM => "i32",
+            Type::TYPE_UINT32 | Type::TYPE_FIXED32 => "u32",
+            Type::TYPE_BOOL => "bool",
+            Type::TYPE_STRING => "String",
+            Type::TYPE_BYTES => "::prost::bytes::Bytes",
+            Type::TYPE_MESSAGE => return rust_type(package, field.get_type_name()),
+            Type::TYPE_GROUP => panic!("groups are not supported"),
+        };
+        rust.to_string()
+    }
+
+    fn write_accessors(code: &mut String, package: &str, field: &FieldDescriptorProto) {
+        let f = &rust_field(field.get_name());
+        let p = &prost_field(field.get_name());
+        let rust = value_type(package, field);
+
+        writeln!(code).unwrap();
+
+        if field.get_label() == Label::LABEL_REPEATED {
+            if field.get_field_type() == Type::TYPE_ENUM {
+                panic!("repeated enums are not supported");
+            }
+            writeln!(code, "    pub fn get_{}(&self) -> &[{}] {{", f, rust).unwrap();
+            writeln!(code, "        &self.{}", p).unwrap();
+            writeln!(code, "    }}").unwrap();
+            writeln!(code).unwrap();
+            writeln!(code, "    pub fn clear_{}(&mut self) {{", f).unwrap();
+            writeln!(code, "        self.{}.clear();", p).unwrap();
+            writeln!(code, "    }}").unwrap();
+            writeln!(code).unwrap();
+            if field.get_field_type() == Type::TYPE_BYTES {
+                writeln!(
+                    code,
+                    "    pub fn set_{}<T: Into<{}>>(&mut self, v: RepeatedField<T>) {{",
+                    f, rust
+                )
+                .unwrap();
+                writeln!(
+                    code,
+                    "        self.{} = v.into_iter().map(Into::into).collect();",
+                    p
+                )
+                .unwrap();
+            } else {
+                writeln!(
+                    code,
+                    "    pub fn set_{}(&mut self, v: RepeatedField<{}>) {{",
+                    f, rust
+                )
+                .unwrap();
+                writeln!(code, "        self.{} = v.into_vec();", p).unwrap();
+            }
+            writeln!(code, "    }}").unwrap();
+            writeln!(code).unwrap();
+            writeln!(
+                code,
+                "    pub fn mut_{}(&mut self) -> &mut Vec<{}> {{",
+                f, rust
+            )
+            .unwrap();
+            writeln!(code, "        &mut self.{}", p).unwrap();
+            writeln!(code, "    }}").unwrap();
+            writeln!(code).unwrap();
+            writeln!(
+                code,
+                "    pub fn take_{}(&mut self) -> RepeatedField<{}> {{",
+                f, rust
+            )
+            .unwrap();
+            writeln!(
+                code,
+                "        RepeatedField::from_vec(std::mem::take(&mut self.{}))",
+                p
+            )
+            .unwrap();
+            writeln!(code, "    }}").unwrap();
+            return;
+        }
+
+        // required fields are plain values for prost
+        let optional = field.get_label() == Label::LABEL_OPTIONAL;
+
+        match field.get_field_type() {
+            Type::TYPE_BYTES => {
+                writeln!(code, "    pub fn get_{}(&self) -> &[u8] {{", f).unwrap();
+                match optional {
+                    true => writeln!(code, "        self.{}.as_deref().unwrap_or(&[])", p).unwrap(),
+                    false => writeln!(code, "        &self.{}", p).unwrap(),
+                }
+            }
+            Type::TYPE_STRING => {
+                writeln!(code, "    pub fn get_{}(&self) -> &str {{", f).unwrap();
+                match optional {
+                    true => {
+                        writeln!(code, "        self.{}.as_deref().unwrap_or(\"\")", p).unwrap()
+                    }
+                    false => writeln!(code, "        &self.{}", p).unwrap(),
+                }
+            }
+            Type::TYPE_MESSAGE => {
+                writeln!(code, "    pub fn get_{}(&self) -> &{} {{", f, rust).unwrap();
+                match optional {
+                    true => writeln!(
+                        code,
+                        "        self.{}.as_ref().unwrap_or_else(|| {}::default_instance())",
+                        p, rust
+                    )
+                    .unwrap(),
+                    false => writeln!(code, "        &self.{}", p).unwrap(),
+                }
+            }
+            Type::TYPE_ENUM => {
+                let enum_name = rust_type(package, field.get_type_name());
+                writeln!(code, "    pub fn get_{}(&self) -> {} {{", f, enum_name).unwrap();
+                let number = match optional {
+                    true => format!("self.{}.and_then({}::from_i32)", p, enum_name),
+                    false => format!("{}::from_i32(self.{})", enum_name, p),
+                };
+                writeln!(code, "        {}.unwrap_or_default()", number).unwrap();
+            }
+            _ => {
+                writeln!(code, "    pub fn get_{}(&self) -> {} {{", f, rust).unwrap();
+                match optional {
+                    true => writeln!(code, "        self.{}.unwrap_or_default()", p).unwrap(),
+                    false => writeln!(code, "        self.{}", p).unwrap(),
+                }
+            }
+        }
+        writeln!(code, "    }}").unwrap();
+        writeln!(code).unwrap();
+        writeln!(code, "    pub fn clear_{}(&mut self) {{", f).unwrap();
+        writeln!(code, "        self.{} = Default::default();", p).unwrap();
+        writeln!(code, "    }}").unwrap();
+        writeln!(code).unwrap();
+        writeln!(code, "    pub fn has_{}(&self) -> bool {{", f).unwrap();
+        match optional {
+            true => writeln!(code, "        self.{}.is_some()", p).unwrap(),
+            false => writeln!(code, "        true").unwrap(),
+        }
+        writeln!(code, "    }}").unwrap();
+        writeln!(code).unwrap();
+
+        let set = match optional {
+            true => "Some(v)",
+            false => "v",
+        };
+        match field.get_field_type() {
+            Type::TYPE_BYTES => {
+                writeln!(
+                    code,
+                    "    pub fn set_{}<T: Into<{}>>(&mut self, v: T) {{",
+                    f, rust
+                )
+                .unwrap();
+                writeln!(code, "        let v = v.into();").unwrap();
+                writeln!(code, "        self.{} = {};", p, set).unwrap();
+                writeln!(code, "    }}").unwrap();
+                writeln!(code).unwrap();
+                writeln!(code, "    pub fn take_{}(&mut self) -> {} {{", f, rust).unwrap();
+                match optional {
+                    true => {
+                        writeln!(code, "        self.{}.take().unwrap_or_default()", p).unwrap()
+                    }
+                    false => writeln!(code, "        std::mem::take(&mut self.{})", p).unwrap(),
+                }
+                writeln!(code, "    }}").unwrap();
+            }
+            Type::TYPE_STRING | Type::TYPE_MESSAGE => {
+                writeln!(code, "    pub fn set_{}(&mut self, v: {}) {{", f, rust).unwrap();
+                writeln!(code, "        self.{} = {};", p, set).unwrap();
+                writeln!(code, "    }}").unwrap();
+                writeln!(code).unwrap();
+                writeln!(code, "    pub fn mut_{}(&mut self) -> &mut {} {{", f, rust).unwrap();
+                match optional {
+                    true => writeln!(
+                        code,
+                        "        self.{}.get_or_insert_with(Default::default)",
+                        p
+                    )
+                    .unwrap(),
+                    false => writeln!(code, "        &mut self.{}", p).unwrap(),
+                }
+                writeln!(code, "    }}").unwrap();
+                writeln!(code).unwrap();
+                writeln!(code, "    pub fn take_{}(&mut self) -> {} {{", f, rust).unwrap();
+                match optional {
+                    true => {
+                        writeln!(code, "        self.{}.take().unwrap_or_default()", p).unwrap()
+                    }
+                    false => writeln!(code, "        std::mem::take(&mut self.{})", p).unwrap(),
+                }
+                writeln!(code, "    }}").unwrap();
+            }
+            Type::TYPE_ENUM => {
+                // prost generates set_ for the field's name, with the enum
+                if *f != p.trim_start_matches("r#") {
+                    let enum_name = rust_type(package, field.get_type_name());
+                    writeln!(code, "    pub fn set_{}(&mut self, v: {}) {{", f, enum_name).unwrap();
+                    match optional {
+                        true => writeln!(code, "        self.{} = Some(v as i32);", p).unwrap(),
+                        false => writeln!(code, "        self.{} = v as i32;", p).unwrap(),
+                    }
+                    writeln!(code, "    }}").unwrap();
+                }
+            }
+            _ => {
+                writeln!(code, "    pub fn set_{}(&mut self, v: {}) {{", f, rust).unwrap();
+                writeln!(code, "        self.{} = {};", p, set).unwrap();
+                writeln!(code, "    }}").unwrap();
+            }
+        }
+    }
 }
@@ -0,0 +1,607 @@
+use super::antidote;
+use super::crdt;
+use super::session::{CommitTime, Session, SessionError};
+use super::{AntidoteDB, Error, Transaction};
+
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Geo-replicated deployment: several data centers, each with several nodes.
+//
+// New transactions are routed to the local DC. When none of its nodes can be
+// reached the session fails over to the other DCs in the order they were
+// added, and keeps its commit time so the new DC still sees the causal past.
+// Once failed over, the local DC is tried again for new transactions after
+// the failback interval.
+//
+// Static reads can be hedged: if the serving node has not answered within
+// the hedge delay, the same read, at the same snapshot time, is sent to
+// another node, of the same DC first, and the first valid answer wins. The
+// losing connection still has an answer in flight, so it is closed rather
+// than reused; the session reconnects for its next operation.
+
+#[derive(Clone, Debug)]
+pub struct DataCenter {
+    name: String,
+    nodes: Vec<String>,
+}
+
+impl DataCenter {
+    // nodes are "host:port" addresses
+    pub fn new(name: &str, nodes: &[&str]) -> Self {
+        DataCenter {
+            name: name.to_string(),
+            nodes: nodes.iter().map(|node| node.to_string()).collect(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn nodes(&self) -> &[String] {
+        &self.nodes
+    }
+
+    fn connect(&self, timeout: Duration) -> Result<(usize, AntidoteDB), Error> {
+        let mut last_error = Error::Protocol(format!("no nodes in data center {}", self.name));
+        for node in 0..self.nodes.len() {
+            match AntidoteDB::connect_timeout(&self.nodes[node], timeout) {
+                Ok(db) => return Ok((node, db)),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Cluster {
+    // the local DC comes first
+    dcs: Vec<DataCenter>,
+    connect_timeout: Duration,
+    failback_interval: Duration,
+    hedge_delay: Option<Duration>,
+}
+
+impl Cluster {
+    pub fn new(local: DataCenter) -> Self {
+        Cluster {
+            dcs: vec![local],
+            connect_timeout: Duration::from_secs(1),
+            failback_interval: Duration::from_secs(30),
+            hedge_delay: None,
+        }
+    }
+
+    pub fn with_dc(mut self, dc: DataCenter) -> Self {
+        self.dcs.push(dc);
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn with_failback_interval(mut self, interval: Duration) -> Self {
+        self.failback_interval = interval;
+        self
+    }
+
+    // opt in to hedged static reads
+    pub fn with_hedged_reads(mut self, delay: Duration) -> Self {
+        self.hedge_delay = Some(delay);
+        self
+    }
+
+    pub fn local(&self) -> &DataCenter {
+        &self.dcs[0]
+    }
+
+    pub fn dcs(&self) -> &[DataCenter] {
+        &self.dcs
+    }
+
+    // connect to the first reachable DC, local first
+    pub fn connect(&self) -> Result<(usize, AntidoteDB), Error> {
+        self.locate().map(|(dc, _, db)| (dc, db))
+    }
+
+    // like connect, also telling which node of the DC was reached
+    fn locate(&self) -> Result<(usize, usize, AntidoteDB), Error> {
+        let mut last_error = None;
+        for dc in 0..self.dcs.len() {
+            match self.dcs[dc].connect(self.connect_timeout) {
+                Ok((node, db)) => return Ok((dc, node, db)),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.expect("cluster without data centers"))
+    }
+
+    // connect to a node other than `node` of `dc`, trying the rest of `dc`
+    // before the other DCs
+    fn connect_other(&self, dc: usize, node: usize) -> Result<AntidoteDB, Error> {
+        let nodes = &self.dcs[dc].nodes;
+        let mut last_error = Error::Protocol("no other node to connect to".to_string());
+        for i in 1..nodes.len() {
+            let other = &nodes[(node + i) % nodes.len()];
+            match AntidoteDB::connect_timeout(other, self.connect_timeout) {
+                Ok(db) => return Ok(db),
+                Err(e) => last_error = e,
+            }
+        }
+        for other in (0..self.dcs.len()).filter(|&other| other != dc) {
+            match self.dcs[other].connect(self.connect_timeout) {
+                Ok((_, db)) => return Ok(db),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+// A causal session over a cluster.
+//
+// Mirrors `Session`. A connection error drops the connection; the transaction
+// it belonged to is lost and the error is returned. Its later operations fail
+// rather than reach another node, which does not know the transaction.
+// Starting a transaction and static reads have no effect on the database, so
+// they are retried once on another connection. Static updates are not, as
+// they may have been applied before the connection broke. The session does
+// not fail back to the local DC while a transaction is open.
+
+#[derive(Debug)]
+pub struct ClusterSession {
+    cluster: Cluster,
+    session: Session,
+    // DC and node serving the session, None after a connection error
+    serving: Option<(usize, usize)>,
+    failed_over_at: Option<Instant>,
+}
+
+impl ClusterSession {
+    pub fn connect(cluster: Cluster) -> Result<Self, Error> {
+        let (dc, node, db) = cluster.locate()?;
+        Ok(ClusterSession {
+            session: Session::new(db),
+            serving: Some((dc, node)),
+            failed_over_at: if dc == 0 { None } else { Some(Instant::now()) },
+            cluster,
+        })
+    }
+
+    pub fn with_token(cluster: Cluster, token: CommitTime) -> Result<Self, Error> {
+        let mut session = ClusterSession::connect(cluster)?;
+        session.session.observe(token);
+        Ok(session)
+    }
+
+    pub fn cluster(&self) -> &Cluster {
+        &self.cluster
+    }
+
+    // the DC currently serving the session
+    pub fn current_dc(&self) -> Option<&DataCenter> {
+        self.serving.map(|(dc, _)| &self.cluster.dcs[dc])
+    }
+
+    pub fn token(&self) -> Option<&CommitTime> {
+        self.session.token()
+    }
+
+    pub fn observe(&mut self, commit_time: CommitTime) {
+        self.session.observe(commit_time)
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.session.in_transaction()
+    }
+
+    pub fn start_transaction(&mut self) -> Result<Transaction, Error> {
+        self.failback();
+        self.run(true, |session| session.start_transaction())
+    }
+
+    pub fn mult_read_in_transaction<T>(
+        &mut self,
+        objects: &[T],
+        t: &Transaction,
+    ) -> Result<Vec<antidote::ApbReadObjectResp>, Error>
+    where
+        T: crdt::Object,
+    {
+        self.run_in_transaction(|session| session.mult_read_in_transaction(objects, t))
+    }
+
+    pub fn mult_update_in_transaction(
+        &mut self,
+        apb_operations: &[antidote::ApbUpdateOp],
+        t: &Transaction,
+    ) -> Result<(), Error> {
+        self.run_in_transaction(|session| session.mult_update_in_transaction(apb_operations, t))
+    }
+
+    pub fn commit_transaction(&mut self, t: &Transaction) -> Result<CommitTime, Error> {
+        self.run_in_transaction(|session| session.commit_transaction(t))
+    }
+
+    pub fn abort_transaction(&mut self, t: &Transaction) -> Result<(), Error> {
+        self.run_in_transaction(|session| session.abort_transaction(t))
+    }
+
+    pub fn static_update_objects(
+        &mut self,
+        apb_operations: &[antidote::ApbUpdateOp],
+    ) -> Result<CommitTime, Error> {
+        self.failback();
+        self.run(false, |session| {
+            session.static_update_objects(apb_operations)
+        })
+    }
+
+    pub fn static_read_objects<T>(
+        &mut self,
+        objects: &[T],
+    ) -> Result<Vec<antidote::ApbReadObjectResp>, Error>
+    where
+        T: crdt::Object,
+    {
+        self.failback();
+        match self.cluster.hedge_delay {
+            Some(delay) => {
+                self.session.check_idle()?;
+                let apb_objects = objects.iter().map(|x| x.get_object()).collect();
+                self.hedged_static_read(apb_objects, delay)
+            }
+            None => self.run(true, |session| session.static_read_objects(objects)),
+        }
+    }
+
+    fn run<R, F>(&mut self, retry: bool, mut f: F) -> Result<R, Error>
+    where
+        F: FnMut(&mut Session) -> Result<R, Error>,
+    {
+        let mut attempts = if retry { 2 } else { 1 };
+        loop {
+            attempts -= 1;
+            self.ensure_connected()?;
+            match f(&mut self.session) {
+                Err(ref e) if e.is_io() && attempts > 0 => self.serving = None,
+                Err(e) => {
+                    if e.is_io() {
+                        self.serving = None;
+                    }
+                    return Err(e);
+                }
+                Ok(r) => return Ok(r),
+            }
+        }
+    }
+
+    // the connection of an open transaction is never replaced, once it is
+    // gone the transaction is too
+    fn run_in_transaction<R, F>(&mut self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut Session) -> Result<R, Error>,
+    {
+        if self.serving.is_none() {
+            return Err(Error::Session(SessionError::NotOpen));
+        }
+        let result = f(&mut self.session);
+        if matches!(result, Err(ref e) if e.is_io()) {
+            self.serving = None;
+        }
+        result
+    }
+
+    fn hedged_static_read(
+        &mut self,
+        apb_objects: Vec<antidote::ApbBoundObject>,
+        delay: Duration,
+    ) -> Result<Vec<antidote::ApbReadObjectResp>, Error> {
+        self.ensure_connected()?;
+        let (dc, node) = self.serving.expect("connected session");
+        let timestamp = self.session.token().map(|t| t.as_bytes().to_vec());
+
+        let primary = match self.session.connection().try_clone() {
+            Ok(db) => db,
+            Err(e) => {
+                self.serving = None;
+                return Err(e);
+            }
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        spawn_static_read(
+            PRIMARY,
+            move || Ok(primary),
+            apb_objects.clone(),
+            timestamp.clone(),
+            sender.clone(),
+        );
+
+        let mut pending = vec![PRIMARY];
+        let mut last_error = None;
+
+        match receiver.recv_timeout(delay) {
+            Ok((_, Ok(read))) => return Ok(self.finish_hedged_read(read)),
+            Ok((_, Err(e))) => {
+                if e.is_io() {
+                    self.serving = None;
+                }
+                pending.clear();
+                last_error = Some(e);
+            }
+            Err(_) => {}
+        }
+
+        // the hedge connects from its own thread, so a slow connect does not
+        // hold back an answer from the primary
+        let hedge = Arc::new(Mutex::new(Hedge::Connecting));
+        let cluster = self.cluster.clone();
+        let hedge_state = hedge.clone();
+        spawn_static_read(
+            HEDGE,
+            move || {
+                let db = cluster.connect_other(dc, node)?;
+                let mut state = hedge_state.lock().unwrap();
+                if let Hedge::Cancelled = *state {
+                    return Err(Error::Protocol("hedged read already answered".to_string()));
+                }
+                *state = Hedge::Connected(db.try_clone()?);
+                Ok(db)
+            },
+            apb_objects,
+            timestamp,
+            sender,
+        );
+        pending.push(HEDGE);
+
+        let mut winner = None;
+        while winner.is_none() && !pending.is_empty() {
+            let (from, result) = match receiver.recv() {
+                Ok(answer) => answer,
+                Err(_) => break,
+            };
+            pending.retain(|&p| p != from);
+            match result {
+                Ok(read) => winner = Some(read),
+                Err(e) => {
+                    if from == PRIMARY && e.is_io() {
+                        self.serving = None;
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        // close whatever is still waiting for an answer
+        for p in pending {
+            if p == PRIMARY {
+                self.session.connection().shutdown();
+                self.serving = None;
+            } else {
+                let mut state = hedge.lock().unwrap();
+                if let Hedge::Connected(ref db) = *state {
+                    db.shutdown();
+                }
+                *state = Hedge::Cancelled;
+            }
+        }
+
+        match winner {
+            Some(read) => Ok(self.finish_hedged_read(read)),
+            None => Err(last_error.expect("hedged read without answer")),
+        }
+    }
+
+    fn finish_hedged_read(
+        &mut self,
+        (values, commit_time): (Vec<antidote::ApbReadObjectResp>, Vec<u8>),
+    ) -> Vec<antidote::ApbReadObjectResp> {
+        self.session.observe(CommitTime::from(commit_time));
+        values
+    }
+
+    fn ensure_connected(&mut self) -> Result<(), Error> {
+        if self.serving.is_none() {
+            let (dc, node, db) = self.cluster.locate()?;
+            self.switch_to(dc, node, db);
+        }
+        Ok(())
+    }
+
+    // move back to the local DC if the failback interval has passed
+    fn failback(&mut self) {
+        let due = match self.failed_over_at {
+            Some(at) => at.elapsed() >= self.cluster.failback_interval,
+            None => false,
+        };
+        if due && self.serving.is_some() && !self.session.in_transaction() {
+            match self.cluster.dcs[0].connect(self.cluster.connect_timeout) {
+                Ok((node, db)) => self.switch_to(0, node, db),
+                Err(_) => self.failed_over_at = Some(Instant::now()),
+            }
+        }
+    }
+
+    fn switch_to(&mut self, dc: usize, node: usize, db: AntidoteDB) {
+        if dc == 0 {
+            self.failed_over_at = None;
+        } else if self.serving.map(|(dc, _)| dc) != Some(dc) {
+            self.failed_over_at = Some(Instant::now());
+        }
+        self.session.reconnect(db);
+        self.serving = Some((dc, node));
+    }
+}
+
+// connection of the hedge of a static read, shared with its thread
+enum Hedge {
+    Connecting,
+    Connected(AntidoteDB),
+    Cancelled,
+}
+
+const PRIMARY: usize = 0;
+const HEDGE: usize = 1;
+
+type StaticRead = (Vec<antidote::ApbReadObjectResp>, Vec<u8>);
+
+fn spawn_static_read<F>(
+    from: usize,
+    connect: F,
+    apb_objects: Vec<antidote::ApbBoundObject>,
+    timestamp: Option<Vec<u8>>,
+    sender: mpsc::Sender<(usize, Result<StaticRead, Error>)>,
+) where
+    F: FnOnce() -> Result<AntidoteDB, Error> + Send + 'static,
+{
+    thread::spawn(move || {
+        let result = connect()
+            .and_then(|mut db| db.static_read_bound_objects(apb_objects, timestamp.as_ref()));
+        // the receiver is gone once another answer won
+        let _ = sender.send((from, result));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{Counter, CounterT, Object, ReadT};
+    use crate::server::StandIn;
+
+    fn cluster(local: &StandIn, remote: &StandIn) -> Cluster {
+        Cluster::new(DataCenter::new("local", &[&local.address()]))
+            .with_dc(DataCenter::new("remote", &[&remote.address()]))
+    }
+
+    // a local DC of a slow node, which serves the session, and a fast one
+    fn hedged(slow: &StandIn, fast: &StandIn) -> Cluster {
+        Cluster::new(DataCenter::new(
+            "local",
+            &[&slow.address(), &fast.address()],
+        ))
+        .with_hedged_reads(Duration::from_millis(100))
+    }
+
+    // sets the counter on the node, in as many commits as its value
+    fn set(stand_in: &StandIn, counter: &Counter, value: i64) {
+        let mut db = stand_in.connect().unwrap();
+        for _ in 0..value {
+            db.static_update_objects(&[counter.inc(1)], None).unwrap();
+        }
+    }
+
+    fn read(session: &mut ClusterSession, counter: &Counter) -> i32 {
+        let values = session
+            .static_read_objects(std::slice::from_ref(counter))
+            .unwrap();
+        Counter::decode(&values[0])
+    }
+
+    #[test]
+    fn hedge_is_sent_after_the_delay() {
+        let slow = StandIn::start().unwrap();
+        let fast = StandIn::start().unwrap();
+        let counter = Counter::new("hedge", "cluster");
+        set(&slow, &counter, 1);
+        set(&fast, &counter, 2);
+
+        // answered within the delay, no hedge
+        let mut session = ClusterSession::connect(hedged(&slow, &fast)).unwrap();
+        assert_eq!(read(&mut session, &counter), 1);
+
+        slow.delay_static_reads(Duration::from_secs(2));
+        let started = Instant::now();
+        assert_eq!(read(&mut session, &counter), 2);
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn first_answer_moves_the_commit_time() {
+        let slow = StandIn::start().unwrap();
+        let fast = StandIn::start().unwrap();
+        let counter = Counter::new("first", "cluster");
+        set(&slow, &counter, 1);
+        set(&fast, &counter, 5);
+
+        slow.delay_static_reads(Duration::from_secs(2));
+        let mut session = ClusterSession::connect(hedged(&slow, &fast)).unwrap();
+        assert!(session.token().is_none());
+        assert_eq!(read(&mut session, &counter), 5);
+        // the snapshot of the fast node, after its five updates
+        let token = session.token().unwrap();
+        assert_eq!(token.as_bytes(), &5u64.to_be_bytes()[..]);
+    }
+
+    #[test]
+    fn losing_connection_is_not_reused() {
+        let slow = StandIn::start().unwrap();
+        let fast = StandIn::start().unwrap();
+        let first = Counter::new("first", "cluster");
+        let second = Counter::new("second", "cluster");
+        // the slow node is ahead, so it can serve the session again
+        set(&slow, &first, 1);
+        set(&slow, &second, 7);
+        set(&fast, &first, 2);
+
+        slow.delay_static_reads(Duration::from_millis(500));
+        let mut session = ClusterSession::connect(hedged(&slow, &fast)).unwrap();
+        assert_eq!(read(&mut session, &first), 2);
+        assert!(session.serving.is_none());
+
+        // a reused connection would get the answer of the first read
+        slow.delay_static_reads(Duration::from_secs(0));
+        assert_eq!(read(&mut session, &second), 7);
+        assert_eq!(session.serving, Some((0, 0)));
+    }
+
+    #[test]
+    fn lost_transactions_are_not_sent_to_another_node() {
+        let local = StandIn::start().unwrap();
+        let remote = StandIn::start().unwrap();
+        let mut session = ClusterSession::connect(cluster(&local, &remote)).unwrap();
+        let counter = Counter::new("lost", "cluster");
+
+        let t = session.start_transaction().unwrap();
+        session.session.connection().shutdown();
+        let update = session.mult_update_in_transaction(&[counter.inc(1)], &t);
+        assert!(update.unwrap_err().is_io());
+        assert!(matches!(
+            session.commit_transaction(&t),
+            Err(Error::Session(SessionError::NotOpen))
+        ));
+
+        // the next transaction gets a new connection
+        let t = session.start_transaction().unwrap();
+        session.commit_transaction(&t).unwrap();
+    }
+
+    #[test]
+    fn no_failback_while_a_transaction_is_open() {
+        let local = StandIn::start().unwrap();
+        let remote = StandIn::start().unwrap();
+        let mut session = ClusterSession::connect(cluster(&local, &remote)).unwrap();
+        session.switch_to(1, 0, remote.connect().unwrap());
+        assert_eq!(session.current_dc().unwrap().name(), "remote");
+
+        let t = session.start_transaction().unwrap();
+        session.cluster.failback_interval = Duration::from_secs(0);
+        assert!(matches!(
+            session.static_read_objects(&[Counter::new("failback", "cluster")]),
+            Err(Error::Session(SessionError::TransactionOpen))
+        ));
+        assert_eq!(session.current_dc().unwrap().name(), "remote");
+        session.commit_transaction(&t).unwrap();
+
+        session.start_transaction().unwrap();
+        assert_eq!(session.current_dc().unwrap().name(), "local");
+    }
+}
@@ -0,0 +1,25 @@
+// The protocol buffers backend, rust-protobuf by default or prost with the
+// prost feature. Both generate the messages of protos/antidote.proto in the
+// antidote module, with the same accessors; the rest of their differences,
+// encoding and decoding, are kept here.
+//
+// Under prost, bytes fields are bytes::Bytes sliced from the received frame,
+// so reading them does not copy. The accessors of bytes fields have the same
+// types for both backends once written with Bytes, Vec<u8> for rust-protobuf:
+// get_ borrows a single field as &[u8] and a repeated one as &[Bytes], take_
+// moves out Bytes. The typed reads of registers and sets return Bytes as
+// well; the helpers below turn them into the vectors the rest of the API
+// takes.
+
+#[cfg(not(any(feature = "protobuf", feature = "prost")))]
+compile_error!("one of the protobuf and prost features must be enabled");
+
+#[cfg(not(feature = "prost"))]
+mod rust_protobuf;
+#[cfg(not(feature = "prost"))]
+pub use self::rust_protobuf::*;
+
+#[cfg(feature = "prost")]
+mod prost_bytes;
+#[cfg(feature = "prost")]
+pub use self::prost_bytes::*;
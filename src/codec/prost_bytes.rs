@@ -0,0 +1,227 @@
+use super::super::crdt::Element;
+use super::super::{antidote, Error};
+
+use prost::encoding::{self, encode_key, encode_varint, encoded_len_varint, key_len, WireType};
+
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+use std::slice;
+use std::sync::Arc;
+use std::vec;
+
+pub use bytes::Bytes;
+
+pub trait Message: prost::Message + Default + 'static {}
+
+impl<M: prost::Message + Default + 'static> Message for M {}
+
+// rust-protobuf's trait of the generated enums
+pub trait ProtobufEnum: Copy + Sized + 'static {
+    fn value(&self) -> i32;
+
+    fn from_i32(value: i32) -> Option<Self>;
+
+    fn values() -> &'static [Self];
+}
+
+// The type repeated fields are set and taken as, a vector as rust-protobuf's
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RepeatedField<T>(Vec<T>);
+
+impl<T> RepeatedField<T> {
+    pub fn new() -> Self {
+        RepeatedField(Vec::new())
+    }
+
+    pub fn from_vec(values: Vec<T>) -> Self {
+        RepeatedField(values)
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.0
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.0.push(value)
+    }
+}
+
+impl<T: Clone> RepeatedField<T> {
+    pub fn from_slice(values: &[T]) -> Self {
+        RepeatedField(values.to_vec())
+    }
+}
+
+impl<T> Deref for RepeatedField<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for RepeatedField<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.0
+    }
+}
+
+impl<T> From<Vec<T>> for RepeatedField<T> {
+    fn from(values: Vec<T>) -> Self {
+        RepeatedField(values)
+    }
+}
+
+impl<T> FromIterator<T> for RepeatedField<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        RepeatedField(iter.into_iter().collect())
+    }
+}
+
+impl<T> IntoIterator for RepeatedField<T> {
+    type Item = T;
+    type IntoIter = vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a RepeatedField<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+pub struct Encoder<'a> {
+    buffer: &'a mut Vec<u8>,
+}
+
+// appends what write_fields encodes to buffer
+pub(crate) fn encode<F>(buffer: &mut Vec<u8>, write_fields: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut Encoder) -> Result<(), Error>,
+{
+    write_fields(&mut Encoder { buffer })
+}
+
+// bytes fields of the message are slices of bytes
+pub(crate) fn decode<M: Message>(bytes: Vec<u8>) -> Result<M, Error> {
+    M::decode(Bytes::from(bytes)).map_err(|e| Error::Protocol(e.to_string()))
+}
+
+impl<'a> Encoder<'a> {
+    // the fields of message, required fields are not checked
+    pub(crate) fn write_fields<M: Message>(&mut self, message: &M) -> Result<(), Error> {
+        message.encode_raw(self.buffer);
+        Ok(())
+    }
+
+    pub(crate) fn write_message<M: Message>(
+        &mut self,
+        field: u32,
+        message: &M,
+    ) -> Result<(), Error> {
+        encoding::message::encode(field, message, self.buffer);
+        Ok(())
+    }
+
+    pub(crate) fn write_bytes(&mut self, field: u32, bytes: &[u8]) -> Result<(), Error> {
+        self.write_encoded(field, bytes)
+    }
+
+    // a message field already encoded
+    pub(crate) fn write_encoded(&mut self, field: u32, encoded: &[u8]) -> Result<(), Error> {
+        encode_key(field, WireType::LengthDelimited, self.buffer);
+        encode_varint(encoded.len() as u64, self.buffer);
+        self.buffer.extend_from_slice(encoded);
+        Ok(())
+    }
+
+    // an ApbBoundObject field
+    pub(crate) fn write_object(
+        &mut self,
+        field: u32,
+        key: &[u8],
+        type_number: i32,
+        bucket: &[u8],
+    ) -> Result<(), Error> {
+        let size = key_len(1)
+            + encoded_len_varint(key.len() as u64)
+            + key.len()
+            + key_len(2)
+            + encoded_len_varint(i64::from(type_number) as u64)
+            + key_len(3)
+            + encoded_len_varint(bucket.len() as u64)
+            + bucket.len();
+
+        encode_key(field, WireType::LengthDelimited, self.buffer);
+        encode_varint(size as u64, self.buffer);
+        self.write_encoded(1, key)?;
+        encoding::int32::encode(2, &type_number, self.buffer);
+        self.write_encoded(3, bucket)
+    }
+}
+
+// Messages with a CRDT type, 2 in both ApbBoundObject and ApbMapKey. The
+// number is kept as is, whether CRDT_type has it or not.
+pub trait TypedMessage {
+    fn set_type_number(&mut self, number: i32);
+
+    // the number, also when CRDT_type does not have it; get_field_type gives
+    // the default type instead
+    fn type_number(&self) -> i32;
+}
+
+impl TypedMessage for antidote::ApbBoundObject {
+    fn set_type_number(&mut self, number: i32) {
+        self.r#type = number;
+    }
+
+    fn type_number(&self) -> i32 {
+        self.r#type
+    }
+}
+
+impl TypedMessage for antidote::ApbMapKey {
+    fn set_type_number(&mut self, number: i32) {
+        self.r#type = number;
+    }
+
+    fn type_number(&self) -> i32 {
+        self.r#type
+    }
+}
+
+impl Element for Bytes {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Bytes::copy_from_slice(bytes))
+    }
+}
+
+// a bytes field of bytes kept by an object, sharing them
+pub(crate) fn shared(bytes: &Arc<[u8]>) -> Bytes {
+    Bytes::from_owner(bytes.clone())
+}
+
+// the value of a register read, sliced from the received frame
+pub(crate) fn reg_value(reg: &antidote::ApbGetRegResp) -> Bytes {
+    reg.value.clone()
+}
+
+// a bytes field taken from a message, for the API returning vectors
+pub(crate) fn into_vec(bytes: Bytes) -> Vec<u8> {
+    Vec::from(bytes)
+}
+
+// values of a repeated bytes field
+pub(crate) fn to_vecs(values: &[Bytes]) -> Vec<Vec<u8>> {
+    values.iter().map(|value| value.to_vec()).collect()
+}
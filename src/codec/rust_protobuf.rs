@@ -0,0 +1,172 @@
+use super::super::{antidote, Error};
+
+use protobuf::rt;
+use protobuf::wire_format::WireType;
+use protobuf::{CodedOutputStream, Message as _};
+
+use std::sync::Arc;
+
+pub use protobuf::{ProtobufEnum, RepeatedField};
+
+pub type Bytes = Vec<u8>;
+
+pub trait Message: protobuf::Message {}
+
+impl<M: protobuf::Message> Message for M {}
+
+fn protocol(e: protobuf::ProtobufError) -> Error {
+    Error::Protocol(e.to_string())
+}
+
+pub struct Encoder<'a> {
+    output: CodedOutputStream<'a>,
+}
+
+// appends what write_fields encodes to buffer
+pub(crate) fn encode<F>(buffer: &mut Vec<u8>, write_fields: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut Encoder) -> Result<(), Error>,
+{
+    let mut encoder = Encoder {
+        output: CodedOutputStream::vec(buffer),
+    };
+    write_fields(&mut encoder)?;
+    encoder.output.flush().map_err(protocol)
+}
+
+// required fields are not checked, as under prost: the type of a custom kind
+// is parsed as an unknown field
+pub(crate) fn decode<M: Message>(bytes: Vec<u8>) -> Result<M, Error> {
+    let mut message = M::new();
+    message.merge_from_bytes(&bytes).map_err(protocol)?;
+    Ok(message)
+}
+
+impl<'a> Encoder<'a> {
+    // the fields of message, required fields are not checked
+    pub(crate) fn write_fields<M: Message>(&mut self, message: &M) -> Result<(), Error> {
+        message.compute_size();
+        message
+            .write_to_with_cached_sizes(&mut self.output)
+            .map_err(protocol)
+    }
+
+    pub(crate) fn write_message<M: Message>(
+        &mut self,
+        field: u32,
+        message: &M,
+    ) -> Result<(), Error> {
+        self.output
+            .write_tag(field, WireType::WireTypeLengthDelimited)
+            .and_then(|_| self.output.write_raw_varint32(message.compute_size()))
+            .and_then(|_| message.write_to_with_cached_sizes(&mut self.output))
+            .map_err(protocol)
+    }
+
+    pub(crate) fn write_bytes(&mut self, field: u32, bytes: &[u8]) -> Result<(), Error> {
+        self.output.write_bytes(field, bytes).map_err(protocol)
+    }
+
+    // a message field already encoded
+    pub(crate) fn write_encoded(&mut self, field: u32, encoded: &[u8]) -> Result<(), Error> {
+        self.output
+            .write_tag(field, WireType::WireTypeLengthDelimited)
+            .and_then(|_| self.output.write_raw_varint32(encoded.len() as u32))
+            .and_then(|_| self.output.write_raw_bytes(encoded))
+            .map_err(protocol)
+    }
+
+    // an ApbBoundObject field
+    pub(crate) fn write_object(
+        &mut self,
+        field: u32,
+        key: &[u8],
+        type_number: i32,
+        bucket: &[u8],
+    ) -> Result<(), Error> {
+        let size = rt::bytes_size(1, key)
+            + rt::value_size(2, type_number, WireType::WireTypeVarint)
+            + rt::bytes_size(3, bucket);
+
+        let output = &mut self.output;
+        output
+            .write_tag(field, WireType::WireTypeLengthDelimited)
+            .and_then(|_| output.write_raw_varint32(size))
+            .and_then(|_| output.write_bytes(1, key))
+            .and_then(|_| output.write_int32(2, type_number))
+            .and_then(|_| output.write_bytes(3, bucket))
+            .map_err(protocol)
+    }
+}
+
+// Messages with a CRDT type, 2 in both ApbBoundObject and ApbMapKey. Numbers
+// missing from CRDT_type are written, and parsed, as an unknown field.
+pub trait TypedMessage {
+    fn set_type_number(&mut self, number: i32);
+
+    // the number, also when CRDT_type does not have it; get_field_type gives
+    // the default type instead
+    fn type_number(&self) -> i32;
+}
+
+impl TypedMessage for antidote::ApbBoundObject {
+    fn set_type_number(&mut self, number: i32) {
+        match antidote::CRDT_type::from_i32(number) {
+            Some(crdt_type) => self.set_field_type(crdt_type),
+            None => self
+                .mut_unknown_fields()
+                .add_varint(2, i64::from(number) as u64),
+        }
+    }
+
+    fn type_number(&self) -> i32 {
+        match unknown_type_number(self.get_unknown_fields()) {
+            Some(number) if !self.has_field_type() => number,
+            _ => self.get_field_type().value(),
+        }
+    }
+}
+
+impl TypedMessage for antidote::ApbMapKey {
+    fn set_type_number(&mut self, number: i32) {
+        match antidote::CRDT_type::from_i32(number) {
+            Some(crdt_type) => self.set_field_type(crdt_type),
+            None => self
+                .mut_unknown_fields()
+                .add_varint(2, i64::from(number) as u64),
+        }
+    }
+
+    fn type_number(&self) -> i32 {
+        match unknown_type_number(self.get_unknown_fields()) {
+            Some(number) if !self.has_field_type() => number,
+            _ => self.get_field_type().value(),
+        }
+    }
+}
+
+fn unknown_type_number(fields: &protobuf::UnknownFields) -> Option<i32> {
+    let values = fields.get(2)?;
+    values.varint.last().map(|&number| number as i32)
+}
+
+// a bytes field of bytes kept by an object; rust-protobuf fields own their
+// bytes, so these are copied
+pub(crate) fn shared(bytes: &Arc<[u8]>) -> Vec<u8> {
+    bytes.to_vec()
+}
+
+// the value of a register read
+pub(crate) fn reg_value(reg: &antidote::ApbGetRegResp) -> Bytes {
+    reg.get_value().to_vec()
+}
+
+// a bytes field taken from a message, for the API returning vectors
+pub(crate) fn into_vec(bytes: Bytes) -> Vec<u8> {
+    bytes
+}
+
+// values of a repeated bytes field
+pub(crate) fn to_vecs(values: &[Bytes]) -> Vec<Vec<u8>> {
+    values.to_vec()
+}
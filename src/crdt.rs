@@ -1,41 +1,193 @@
 use super::antidote;
+use super::codec::{self, ProtobufEnum, RepeatedField, TypedMessage};
+use super::Error;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+pub use super::antidote::CRDT_type;
+
+// the name of a CRDT type, as used in the derive attributes, e.g. "orset"
+pub fn type_name(crdt_type: CRDT_type) -> &'static str {
+    match crdt_type {
+        CRDT_type::COUNTER => "counter",
+        CRDT_type::FATCOUNTER => "fatcounter",
+        CRDT_type::BCOUNTER => "bcounter",
+        CRDT_type::LWWREG => "lwwreg",
+        CRDT_type::MVREG => "mvreg",
+        CRDT_type::ORSET => "orset",
+        CRDT_type::RWSET => "rwset",
+        CRDT_type::FLAG_EW => "flag_ew",
+        CRDT_type::FLAG_DW => "flag_dw",
+        CRDT_type::GMAP => "gmap",
+        CRDT_type::RRMAP => "rrmap",
+    }
+}
+
+// the name of a type number, type_name for CRDT_type, type:<number> for
+// custom kinds
+pub fn number_name(number: i32) -> Cow<'static, str> {
+    match CRDT_type::from_i32(number) {
+        Some(crdt_type) => Cow::Borrowed(type_name(crdt_type)),
+        None => Cow::Owned(format!("type:{}", number)),
+    }
+}
+
+// the type number of a name given by number_name
+pub fn number_from_name(name: &str) -> Option<i32> {
+    match name.strip_prefix("type:") {
+        Some(number) => number.parse().ok(),
+        None => type_from_name(name).map(|crdt_type| crdt_type as i32),
+    }
+}
+
+// the CRDT type of a name given by type_name
+pub fn type_from_name(name: &str) -> Option<CRDT_type> {
+    match name {
+        "counter" => Some(CRDT_type::COUNTER),
+        "fatcounter" => Some(CRDT_type::FATCOUNTER),
+        "bcounter" => Some(CRDT_type::BCOUNTER),
+        "lwwreg" => Some(CRDT_type::LWWREG),
+        "mvreg" => Some(CRDT_type::MVREG),
+        "orset" => Some(CRDT_type::ORSET),
+        "rwset" => Some(CRDT_type::RWSET),
+        "flag_ew" => Some(CRDT_type::FLAG_EW),
+        "flag_dw" => Some(CRDT_type::FLAG_DW),
+        "gmap" => Some(CRDT_type::GMAP),
+        "rrmap" => Some(CRDT_type::RRMAP),
+        _ => None,
+    }
+}
 
-use byteorder::{BigEndian, WriteBytesExt};
-use protobuf::RepeatedField;
+pub trait Object: Sized {
+    fn new(key: &str, bucket: &str) -> Self {
+        Self::new_bytes(key.as_bytes(), bucket.as_bytes())
+    }
 
-pub trait Object {
-    fn new(key: &str, bucket: &str) -> Self;
+    // keys and buckets need not be UTF-8
+    fn new_bytes(key: &[u8], bucket: &[u8]) -> Self;
 
-    fn get_crdt_type() -> antidote::CRDT_type;
+    // objects that keep their bucket apart share it, the others copy it
+    fn new_in_bucket(key: &[u8], bucket: &Arc<[u8]>) -> Self {
+        Self::new_bytes(key, bucket)
+    }
+
+    // the CRDT_type number sent for the object
+    fn type_number() -> i32;
+
+    // None for custom kinds that CRDT_type does not have, see CrdtKind
+    fn get_crdt_type() -> Option<antidote::CRDT_type> {
+        CRDT_type::from_i32(Self::type_number())
+    }
 
     fn get_object(&self) -> antidote::ApbBoundObject;
 
+    // key, type number and bucket, borrowed from objects that keep them
+    // apart, so requests are encoded without building an ApbBoundObject
+    fn object_parts(&self) -> (Cow<'_, [u8]>, i32, Cow<'_, [u8]>) {
+        let mut object = self.get_object();
+        (
+            Cow::Owned(codec::into_vec(object.take_key())),
+            Self::type_number(),
+            Cow::Owned(codec::into_vec(object.take_bucket())),
+        )
+    }
+
     fn new_object(key: &str, bucket: &str) -> antidote::ApbBoundObject {
+        Self::new_object_bytes(key.as_bytes(), bucket.as_bytes())
+    }
+
+    fn new_object_bytes(key: &[u8], bucket: &[u8]) -> antidote::ApbBoundObject {
         let mut object = antidote::ApbBoundObject::new();
-        object.set_key(key.as_bytes().to_vec());
-        object.set_field_type(Self::get_crdt_type());
-        object.set_bucket(bucket.as_bytes().to_vec());
+        object.set_key(key.to_vec());
+        object.set_type_number(Self::type_number());
+        object.set_bucket(bucket.to_vec());
         object
     }
 
     fn map_key(key: &str) -> antidote::ApbMapKey {
         let mut crdt_key = antidote::ApbMapKey::new();
         crdt_key.set_key(key.as_bytes().to_vec());
-        crdt_key.set_field_type(Self::get_crdt_type());
+        crdt_key.set_type_number(Self::type_number());
         crdt_key
     }
 }
 
+// Register values and set elements
+// u64 is written big endian, as the u64 operations below do
+
+pub trait Element: Sized {
+    fn to_bytes(&self) -> Vec<u8>;
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl Element for u64 {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() == 8 {
+            Some(BigEndian::read_u64(bytes))
+        } else {
+            None
+        }
+    }
+}
+
+impl Element for i64 {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() == 8 {
+            Some(BigEndian::read_i64(bytes))
+        } else {
+            None
+        }
+    }
+}
+
+impl Element for String {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl Element for Vec<u8> {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
 // CRDT traits
 
 pub trait ObjectReset: Object {
-    fn reset(&self) -> antidote::ApbUpdateOp {
+    fn reset_op() -> antidote::ApbUpdateOperation {
         let mut update_op = antidote::ApbUpdateOperation::new();
         update_op.set_resetop(antidote::ApbCrdtReset::new());
 
+        update_op
+    }
+
+    fn reset(&self) -> antidote::ApbUpdateOp {
         let mut update_op_obj = antidote::ApbUpdateOp::new();
         update_op_obj.set_boundobject(self.get_object());
-        update_op_obj.set_operation(update_op);
+        update_op_obj.set_operation(Self::reset_op());
 
         update_op_obj
     }
@@ -43,10 +195,16 @@ pub trait ObjectReset: Object {
 
 pub trait RegT: Object {
     fn set_op(value: u64) -> antidote::ApbUpdateOperation {
+        Self::set_value_op(&value)
+    }
+
+    fn set(&self, value: u64) -> antidote::ApbUpdateOp {
+        self.set_value(&value)
+    }
+
+    fn set_value_op<E: Element>(value: &E) -> antidote::ApbUpdateOperation {
         let mut set_value = antidote::ApbRegUpdate::new();
-        let mut buffer = Vec::<u8>::with_capacity(8);
-        buffer.write_u64::<BigEndian>(value).unwrap();
-        set_value.set_value(buffer);
+        set_value.set_value(value.to_bytes());
 
         let mut update_op = antidote::ApbUpdateOperation::new();
         update_op.set_regop(set_value);
@@ -54,10 +212,10 @@ pub trait RegT: Object {
         update_op
     }
 
-    fn set(&self, value: u64) -> antidote::ApbUpdateOp {
+    fn set_value<E: Element>(&self, value: &E) -> antidote::ApbUpdateOp {
         let mut update_op_obj = antidote::ApbUpdateOp::new();
         update_op_obj.set_boundobject(self.get_object());
-        update_op_obj.set_operation(Self::set_op(value));
+        update_op_obj.set_operation(Self::set_value_op(value));
 
         update_op_obj
     }
@@ -83,6 +241,15 @@ pub trait CounterT: Object {
         update_op_obj
     }
 
+    // Error::Overflow for i64::MIN, whose negation does not fit the increment
+    fn dec_op(val: i64) -> Result<antidote::ApbUpdateOperation, Error> {
+        Ok(Self::inc_op(negate(val)?))
+    }
+
+    fn dec(&self, val: i64) -> Result<antidote::ApbUpdateOp, Error> {
+        Ok(self.inc(negate(val)?))
+    }
+
     fn inc_one(&self) -> antidote::ApbUpdateOp {
         self.inc(1)
     }
@@ -90,22 +257,43 @@ pub trait CounterT: Object {
     fn dec_one(&self) -> antidote::ApbUpdateOp {
         self.inc(-1)
     }
+
+    fn value(resp: &antidote::ApbReadObjectResp) -> i32 {
+        resp.get_counter().get_value()
+    }
+}
+
+pub(crate) fn negate(val: i64) -> Result<i64, Error> {
+    val.checked_neg().ok_or(Error::Overflow(val))
 }
 
 pub trait SetT: Object {
     fn add_op(values: &[u64]) -> antidote::ApbUpdateOperation {
+        Self::add_values_op(values)
+    }
+
+    fn add(&self, values: &[u64]) -> antidote::ApbUpdateOp {
+        self.add_values(values)
+    }
+
+    fn rem_op(values: &[u64]) -> antidote::ApbUpdateOperation {
+        Self::rem_values_op(values)
+    }
+
+    fn rem(&self, values: &[u64]) -> antidote::ApbUpdateOp {
+        self.rem_values(values)
+    }
+
+    fn add_values_op<'a, E, I>(values: I) -> antidote::ApbUpdateOperation
+    where
+        E: Element + 'a,
+        I: IntoIterator<Item = &'a E>,
+    {
         let mut add_op = antidote::ApbSetUpdate::new();
         add_op.set_optype(antidote::ApbSetUpdate_SetOpType::ADD);
 
-        let value_bytes: Vec<_> = values
-            .iter()
-            .map(|&val| {
-                let mut buffer = Vec::<u8>::with_capacity(8);
-                buffer.write_u64::<BigEndian>(val).unwrap();
-                buffer
-            })
-            .collect();
-        add_op.set_adds(RepeatedField::from_slice(&value_bytes));
+        let value_bytes: Vec<_> = values.into_iter().map(Element::to_bytes).collect();
+        add_op.set_adds(RepeatedField::from_vec(value_bytes));
 
         let mut update_op = antidote::ApbUpdateOperation::new();
         update_op.set_setop(add_op);
@@ -113,27 +301,28 @@ pub trait SetT: Object {
         update_op
     }
 
-    fn add(&self, values: &[u64]) -> antidote::ApbUpdateOp {
+    fn add_values<'a, E, I>(&self, values: I) -> antidote::ApbUpdateOp
+    where
+        E: Element + 'a,
+        I: IntoIterator<Item = &'a E>,
+    {
         let mut update_op_obj = antidote::ApbUpdateOp::new();
         update_op_obj.set_boundobject(self.get_object());
-        update_op_obj.set_operation(Self::add_op(values));
+        update_op_obj.set_operation(Self::add_values_op(values));
 
         update_op_obj
     }
 
-    fn rem_op(values: &[u64]) -> antidote::ApbUpdateOperation {
+    fn rem_values_op<'a, E, I>(values: I) -> antidote::ApbUpdateOperation
+    where
+        E: Element + 'a,
+        I: IntoIterator<Item = &'a E>,
+    {
         let mut rem_op = antidote::ApbSetUpdate::new();
         rem_op.set_optype(antidote::ApbSetUpdate_SetOpType::REMOVE);
 
-        let value_bytes: Vec<_> = values
-            .iter()
-            .map(|&val| {
-                let mut buffer = Vec::<u8>::with_capacity(8);
-                buffer.write_u64::<BigEndian>(val).unwrap();
-                buffer
-            })
-            .collect();
-        rem_op.set_rems(RepeatedField::from_slice(&value_bytes));
+        let value_bytes: Vec<_> = values.into_iter().map(Element::to_bytes).collect();
+        rem_op.set_rems(RepeatedField::from_vec(value_bytes));
 
         let mut update_op = antidote::ApbUpdateOperation::new();
         update_op.set_setop(rem_op);
@@ -141,10 +330,14 @@ pub trait SetT: Object {
         update_op
     }
 
-    fn rem(&self, values: &[u64]) -> antidote::ApbUpdateOp {
+    fn rem_values<'a, E, I>(&self, values: I) -> antidote::ApbUpdateOp
+    where
+        E: Element + 'a,
+        I: IntoIterator<Item = &'a E>,
+    {
         let mut update_op_obj = antidote::ApbUpdateOp::new();
         update_op_obj.set_boundobject(self.get_object());
-        update_op_obj.set_operation(Self::rem_op(values));
+        update_op_obj.set_operation(Self::rem_values_op(values));
 
         update_op_obj
     }
@@ -188,22 +381,22 @@ pub trait MapT: Object {
     }
 
     fn update_op(
-        updates: &[(antidote::ApbMapKey, antidote::ApbUpdateOperation)],
-        removes: &[antidote::ApbMapKey],
+        updates: Vec<(antidote::ApbMapKey, antidote::ApbUpdateOperation)>,
+        removes: Vec<antidote::ApbMapKey>,
     ) -> antidote::ApbUpdateOperation {
         let update_ops: Vec<_> = updates
-            .iter()
+            .into_iter()
             .map(|(key, op)| {
                 let mut nestedop = antidote::ApbMapNestedUpdate::new();
-                nestedop.set_key(key.clone());
-                nestedop.set_update(op.clone());
+                nestedop.set_key(key);
+                nestedop.set_update(op);
                 nestedop
             })
             .collect();
 
         let mut map_update = antidote::ApbMapUpdate::new();
-        map_update.set_updates(RepeatedField::from_slice(&update_ops));
-        map_update.set_removedKeys(RepeatedField::from_slice(removes));
+        map_update.set_updates(RepeatedField::from_vec(update_ops));
+        map_update.set_removedKeys(RepeatedField::from_vec(removes));
 
         let mut update_op = antidote::ApbUpdateOperation::new();
         update_op.set_mapop(map_update);
@@ -213,8 +406,8 @@ pub trait MapT: Object {
 
     fn update(
         &self,
-        updates: &[(antidote::ApbMapKey, antidote::ApbUpdateOperation)],
-        removes: &[antidote::ApbMapKey],
+        updates: Vec<(antidote::ApbMapKey, antidote::ApbUpdateOperation)>,
+        removes: Vec<antidote::ApbMapKey>,
     ) -> antidote::ApbUpdateOp {
         let mut update_op_obj = antidote::ApbUpdateOp::new();
         update_op_obj.set_boundobject(self.get_object());
@@ -222,269 +415,672 @@ pub trait MapT: Object {
 
         update_op_obj
     }
-}
 
-// Antidote CRDTs
+    // like update_op, with updates and removals built from typed keys
+    fn apply_op(updates: Vec<MapUpdate>, removes: Vec<MapRemove>) -> antidote::ApbUpdateOperation {
+        let update_ops: Vec<_> = updates.into_iter().map(|update| update.0).collect();
+        let removed_keys: Vec<_> = removes.into_iter().map(|remove| remove.0).collect();
 
-// Last writer wins register
+        let mut map_update = antidote::ApbMapUpdate::new();
+        map_update.set_updates(RepeatedField::from_vec(update_ops));
+        map_update.set_removedKeys(RepeatedField::from_vec(removed_keys));
 
-#[derive(Clone, Debug)]
-pub struct LWWReg {
-    object: antidote::ApbBoundObject,
-}
+        let mut update_op = antidote::ApbUpdateOperation::new();
+        update_op.set_mapop(map_update);
 
-impl Object for LWWReg {
-    fn new(key: &str, bucket: &str) -> Self {
-        Self {
-            object: Self::new_object(key, bucket),
-        }
+        update_op
     }
 
-    fn get_object(&self) -> antidote::ApbBoundObject {
-        self.object.clone()
-    }
+    fn apply(&self, updates: Vec<MapUpdate>, removes: Vec<MapRemove>) -> antidote::ApbUpdateOp {
+        let mut update_op_obj = antidote::ApbUpdateOp::new();
+        update_op_obj.set_boundobject(self.get_object());
+        update_op_obj.set_operation(Self::apply_op(updates, removes));
 
-    fn get_crdt_type() -> antidote::CRDT_type {
-        antidote::CRDT_type::LWWREG
+        update_op_obj
     }
 }
 
-impl RegT for LWWReg {}
+// Typed reads
 
-// Multi value register
+pub trait ReadT: Object {
+    type Value;
 
-#[derive(Clone, Debug)]
-pub struct MVReg {
-    object: antidote::ApbBoundObject,
+    fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value;
 }
 
-impl Object for MVReg {
-    fn new(key: &str, bucket: &str) -> Self {
-        Self {
-            object: Self::new_object(key, bucket),
+// Typed map keys
+// a key knows the CRDT stored under it, so only that CRDT's operations can be
+// nested under it, e.g. MapKey::<Counter>::new("visits").inc(1)
+
+pub struct MapKey<C: Object> {
+    key: antidote::ApbMapKey,
+    crdt: PhantomData<C>,
+}
+
+// nested update of a map entry, built from a typed key
+#[derive(Clone, Debug)]
+pub struct MapUpdate(antidote::ApbMapNestedUpdate);
+
+// removal of a map entry, built from a typed key
+#[derive(Clone, Debug)]
+pub struct MapRemove(antidote::ApbMapKey);
+
+impl<C: Object> MapKey<C> {
+    pub fn new(key: &str) -> Self {
+        MapKey {
+            key: C::map_key(key),
+            crdt: PhantomData,
         }
     }
 
-    fn get_object(&self) -> antidote::ApbBoundObject {
-        self.object.clone()
+    pub fn get_key(&self) -> antidote::ApbMapKey {
+        self.key.clone()
     }
 
-    fn get_crdt_type() -> antidote::CRDT_type {
-        antidote::CRDT_type::MVREG
+    pub fn remove(&self) -> MapRemove {
+        MapRemove(self.key.clone())
+    }
+
+    fn nested(&self, op: antidote::ApbUpdateOperation) -> MapUpdate {
+        let mut nestedop = antidote::ApbMapNestedUpdate::new();
+        nestedop.set_key(self.key.clone());
+        nestedop.set_update(op);
+        MapUpdate(nestedop)
     }
 }
-impl RegT for MVReg {}
 
-impl ObjectReset for MVReg {}
+// derived impls would require C: Clone and C: Debug
+impl<C: Object> Clone for MapKey<C> {
+    fn clone(&self) -> Self {
+        MapKey {
+            key: self.key.clone(),
+            crdt: PhantomData,
+        }
+    }
+}
 
-// Counter
+impl<C: Object> fmt::Debug for MapKey<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MapKey").field("key", &self.key).finish()
+    }
+}
 
-#[derive(Clone, Debug)]
-pub struct Counter {
-    object: antidote::ApbBoundObject,
+impl<C: ObjectReset> MapKey<C> {
+    pub fn reset(&self) -> MapUpdate {
+        self.nested(C::reset_op())
+    }
 }
 
-impl Object for Counter {
-    fn new(key: &str, bucket: &str) -> Self {
-        Self {
-            object: Self::new_object(key, bucket),
-        }
+impl<C: RegT> MapKey<C> {
+    pub fn set(&self, value: u64) -> MapUpdate {
+        self.nested(C::set_op(value))
     }
 
-    fn get_object(&self) -> antidote::ApbBoundObject {
-        self.object.clone()
+    pub fn set_value<E: Element>(&self, value: &E) -> MapUpdate {
+        self.nested(C::set_value_op(value))
     }
+}
 
-    fn get_crdt_type() -> antidote::CRDT_type {
-        antidote::CRDT_type::COUNTER
+impl<C: CounterT> MapKey<C> {
+    pub fn inc(&self, val: i64) -> MapUpdate {
+        self.nested(C::inc_op(val))
+    }
+
+    pub fn dec(&self, val: i64) -> Result<MapUpdate, Error> {
+        Ok(self.nested(C::dec_op(val)?))
+    }
+
+    pub fn inc_one(&self) -> MapUpdate {
+        self.inc(1)
+    }
+
+    pub fn dec_one(&self) -> MapUpdate {
+        self.inc(-1)
     }
 }
 
-impl CounterT for Counter {}
+impl<C: SetT> MapKey<C> {
+    pub fn add(&self, values: &[u64]) -> MapUpdate {
+        self.nested(C::add_op(values))
+    }
+
+    pub fn rem(&self, values: &[u64]) -> MapUpdate {
+        self.nested(C::rem_op(values))
+    }
 
-// Counter with reset
+    pub fn add_values<'a, E, I>(&self, values: I) -> MapUpdate
+    where
+        E: Element + 'a,
+        I: IntoIterator<Item = &'a E>,
+    {
+        self.nested(C::add_values_op(values))
+    }
 
-#[derive(Clone, Debug)]
-pub struct FatCounter {
-    object: antidote::ApbBoundObject,
+    pub fn rem_values<'a, E, I>(&self, values: I) -> MapUpdate
+    where
+        E: Element + 'a,
+        I: IntoIterator<Item = &'a E>,
+    {
+        self.nested(C::rem_values_op(values))
+    }
 }
 
-impl Object for FatCounter {
-    fn new(key: &str, bucket: &str) -> Self {
-        Self {
-            object: Self::new_object(key, bucket),
-        }
+// named apart from RegT's set, as a CRDT could be both a register and a flag
+impl<C: FlagT> MapKey<C> {
+    pub fn set_flag(&self, val: bool) -> MapUpdate {
+        self.nested(<C as FlagT>::set_op(val))
     }
 
-    fn get_object(&self) -> antidote::ApbBoundObject {
-        self.object.clone()
+    pub fn enable(&self) -> MapUpdate {
+        self.set_flag(true)
     }
 
-    fn get_crdt_type() -> antidote::CRDT_type {
-        antidote::CRDT_type::FATCOUNTER
+    pub fn disable(&self) -> MapUpdate {
+        self.set_flag(false)
     }
 }
 
-impl CounterT for FatCounter {}
+impl<C: MapT> MapKey<C> {
+    // nested map
+    pub fn update(&self, updates: Vec<MapUpdate>, removes: Vec<MapRemove>) -> MapUpdate {
+        self.nested(C::apply_op(updates, removes))
+    }
+}
 
-impl ObjectReset for FatCounter {}
+// Antidote CRDTs
+//
+// Every object is an Obj<K>, K being a marker type for its kind of CRDT, e.g.
+// LWWReg is Obj<kind::LWWReg>. The kind gives the CRDT_type number Antidote
+// knows the CRDT by and decodes its reads; the operations come from the
+// traits implemented for Obj<K>, RegT for registers and so on.
+//
+// Kinds this crate does not know can be defined downstream, with their own
+// operations:
+//
+//     struct Mine;
+//
+//     impl CrdtKind for Mine {
+//         const TYPE: i32 = 42;
+//         type Value = i32;
+//
+//         fn decode(resp: &proto::ApbReadObjectResp) -> i32 {
+//             resp.get_counter().get_value()
+//         }
+//     }
+//
+//     trait MineT {
+//         fn bump(&self) -> proto::ApbUpdateOp;
+//     }
+//
+//     impl MineT for Obj<Mine> {
+//         fn bump(&self) -> proto::ApbUpdateOp {
+//             let mut op = proto::ApbUpdateOperation::new();
+//             ...
+//             self.update(op)
+//         }
+//     }
+//
+// A number missing from CRDT_type is sent as is. Such a kind has no CRDT_type,
+// get_crdt_type is None for it, and the readers of dynamic values do not
+// look into it: value::Value keeps its map entries as encoded Custom values,
+// and reconcile refuses it and only keeps or removes such entries. The
+// number of a received message is in TypedMessage::type_number, as
+// get_field_type gives the default type for it. Its name, in the text format
+// of the ops module and in errors, is type:<number>.
+
+pub trait CrdtKind {
+    const TYPE: i32;
+
+    type Value;
+
+    fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value;
+}
 
-// Add wins set
+// key and bucket are shared between the clones of an object, and under prost
+// with the messages built for it
+pub struct Obj<K: CrdtKind> {
+    key: Arc<[u8]>,
+    bucket: Arc<[u8]>,
+    kind: PhantomData<K>,
+}
 
-#[derive(Clone, Debug)]
-pub struct ORSet {
-    object: antidote::ApbBoundObject,
+impl<K: CrdtKind> Obj<K> {
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub fn bucket(&self) -> &[u8] {
+        &self.bucket
+    }
+
+    // binds an operation built for the kind to this object
+    pub fn update(&self, operation: antidote::ApbUpdateOperation) -> antidote::ApbUpdateOp {
+        let mut update_op_obj = antidote::ApbUpdateOp::new();
+        update_op_obj.set_boundobject(self.get_object());
+        update_op_obj.set_operation(operation);
+
+        update_op_obj
+    }
 }
 
-impl Object for ORSet {
-    fn new(key: &str, bucket: &str) -> Self {
-        Self {
-            object: Self::new_object(key, bucket),
+impl<K: CrdtKind> Object for Obj<K> {
+    fn new_bytes(key: &[u8], bucket: &[u8]) -> Self {
+        Obj {
+            key: Arc::from(key),
+            bucket: Arc::from(bucket),
+            kind: PhantomData,
+        }
+    }
+
+    fn new_in_bucket(key: &[u8], bucket: &Arc<[u8]>) -> Self {
+        Obj {
+            key: Arc::from(key),
+            bucket: bucket.clone(),
+            kind: PhantomData,
         }
     }
 
     fn get_object(&self) -> antidote::ApbBoundObject {
-        self.object.clone()
+        let mut object = antidote::ApbBoundObject::new();
+        object.set_key(codec::shared(&self.key));
+        object.set_type_number(K::TYPE);
+        object.set_bucket(codec::shared(&self.bucket));
+        object
     }
 
-    fn get_crdt_type() -> antidote::CRDT_type {
-        antidote::CRDT_type::ORSET
+    fn object_parts(&self) -> (Cow<'_, [u8]>, i32, Cow<'_, [u8]>) {
+        (
+            Cow::Borrowed(&self.key),
+            K::TYPE,
+            Cow::Borrowed(&self.bucket),
+        )
+    }
+
+    fn type_number() -> i32 {
+        K::TYPE
     }
 }
 
-impl SetT for ORSet {}
+impl<K: CrdtKind> ReadT for Obj<K> {
+    type Value = K::Value;
 
-impl ObjectReset for ORSet {}
+    fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+        K::decode(resp)
+    }
+}
 
-// Remove wins set
+impl<K: CrdtKind> Clone for Obj<K> {
+    fn clone(&self) -> Self {
+        Obj {
+            key: self.key.clone(),
+            bucket: self.bucket.clone(),
+            kind: PhantomData,
+        }
+    }
+}
 
-#[derive(Clone, Debug)]
-pub struct RWSet {
-    object: antidote::ApbBoundObject,
+impl<K: CrdtKind> fmt::Debug for Obj<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Obj")
+            .field("key", &self.key)
+            .field("type", &K::TYPE)
+            .field("bucket", &self.bucket)
+            .finish()
+    }
 }
 
-impl Object for RWSet {
-    fn new(key: &str, bucket: &str) -> Self {
-        Self {
-            object: Self::new_object(key, bucket),
+// the built-in kinds
+pub mod kind {
+    use super::super::antidote;
+    use super::super::codec;
+    use super::super::view::MapView;
+    use super::{CRDT_type, CrdtKind};
+
+    // Last writer wins register
+    #[derive(Clone, Copy, Debug)]
+    pub struct LWWReg;
+
+    impl CrdtKind for LWWReg {
+        const TYPE: i32 = CRDT_type::LWWREG as i32;
+
+        type Value = codec::Bytes;
+
+        fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+            codec::reg_value(resp.get_reg())
         }
     }
 
-    fn get_object(&self) -> antidote::ApbBoundObject {
-        self.object.clone()
+    // Multi value register
+    #[derive(Clone, Copy, Debug)]
+    pub struct MVReg;
+
+    impl CrdtKind for MVReg {
+        const TYPE: i32 = CRDT_type::MVREG as i32;
+
+        type Value = Vec<codec::Bytes>;
+
+        fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+            resp.get_mvreg().get_values().to_vec()
+        }
     }
 
-    fn get_crdt_type() -> antidote::CRDT_type {
-        antidote::CRDT_type::RWSET
+    // Counter
+    #[derive(Clone, Copy, Debug)]
+    pub struct Counter;
+
+    impl CrdtKind for Counter {
+        const TYPE: i32 = CRDT_type::COUNTER as i32;
+
+        type Value = i32;
+
+        fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+            resp.get_counter().get_value()
+        }
     }
-}
 
-impl SetT for RWSet {}
+    // Counter with reset
+    #[derive(Clone, Copy, Debug)]
+    pub struct FatCounter;
 
-impl ObjectReset for RWSet {}
+    impl CrdtKind for FatCounter {
+        const TYPE: i32 = CRDT_type::FATCOUNTER as i32;
 
-// Enable wins flag
+        type Value = i32;
 
-#[derive(Clone, Debug)]
-pub struct FlagEW {
-    object: antidote::ApbBoundObject,
-}
+        fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+            resp.get_counter().get_value()
+        }
+    }
 
-impl Object for FlagEW {
-    fn new(key: &str, bucket: &str) -> Self {
-        Self {
-            object: Self::new_object(key, bucket),
+    // Bounded counter, never goes below zero
+    // a decrement fails with ErrorCode::NoPermissions when the replica lacks
+    // rights
+    #[derive(Clone, Copy, Debug)]
+    pub struct BCounter;
+
+    impl CrdtKind for BCounter {
+        const TYPE: i32 = CRDT_type::BCOUNTER as i32;
+
+        type Value = i32;
+
+        fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+            resp.get_counter().get_value()
         }
     }
 
-    fn get_object(&self) -> antidote::ApbBoundObject {
-        self.object.clone()
+    // Add wins set
+    #[derive(Clone, Copy, Debug)]
+    pub struct ORSet;
+
+    impl CrdtKind for ORSet {
+        const TYPE: i32 = CRDT_type::ORSET as i32;
+
+        type Value = Vec<codec::Bytes>;
+
+        fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+            resp.get_set().get_value().to_vec()
+        }
     }
 
-    fn get_crdt_type() -> antidote::CRDT_type {
-        antidote::CRDT_type::FLAG_EW
+    // Remove wins set
+    #[derive(Clone, Copy, Debug)]
+    pub struct RWSet;
+
+    impl CrdtKind for RWSet {
+        const TYPE: i32 = CRDT_type::RWSET as i32;
+
+        type Value = Vec<codec::Bytes>;
+
+        fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+            resp.get_set().get_value().to_vec()
+        }
     }
-}
 
-impl FlagT for FlagEW {}
+    // Enable wins flag
+    #[derive(Clone, Copy, Debug)]
+    pub struct FlagEW;
 
-impl ObjectReset for FlagEW {}
+    impl CrdtKind for FlagEW {
+        const TYPE: i32 = CRDT_type::FLAG_EW as i32;
 
-// Disable wins flag
+        type Value = bool;
 
-#[derive(Clone, Debug)]
-pub struct FlagDW {
-    object: antidote::ApbBoundObject,
-}
+        fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+            resp.get_flag().get_value()
+        }
+    }
 
-impl Object for FlagDW {
-    fn new(key: &str, bucket: &str) -> Self {
-        Self {
-            object: Self::new_object(key, bucket),
+    // Disable wins flag
+    #[derive(Clone, Copy, Debug)]
+    pub struct FlagDW;
+
+    impl CrdtKind for FlagDW {
+        const TYPE: i32 = CRDT_type::FLAG_DW as i32;
+
+        type Value = bool;
+
+        fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+            resp.get_flag().get_value()
         }
     }
 
-    fn get_object(&self) -> antidote::ApbBoundObject {
-        self.object.clone()
+    // Grow only map
+    #[derive(Clone, Copy, Debug)]
+    pub struct GMap;
+
+    impl CrdtKind for GMap {
+        const TYPE: i32 = CRDT_type::GMAP as i32;
+
+        type Value = MapView<'static>;
+
+        fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+            MapView::new(resp.get_map()).into_owned()
+        }
     }
 
-    fn get_crdt_type() -> antidote::CRDT_type {
-        antidote::CRDT_type::FLAG_DW
+    // Recursive remove map
+    #[derive(Clone, Copy, Debug)]
+    pub struct RRMap;
+
+    impl CrdtKind for RRMap {
+        const TYPE: i32 = CRDT_type::RRMAP as i32;
+
+        type Value = MapView<'static>;
+
+        fn decode(resp: &antidote::ApbReadObjectResp) -> Self::Value {
+            MapView::new(resp.get_map()).into_owned()
+        }
     }
 }
 
+pub type LWWReg = Obj<kind::LWWReg>;
+pub type MVReg = Obj<kind::MVReg>;
+pub type Counter = Obj<kind::Counter>;
+pub type FatCounter = Obj<kind::FatCounter>;
+pub type BCounter = Obj<kind::BCounter>;
+pub type ORSet = Obj<kind::ORSet>;
+pub type RWSet = Obj<kind::RWSet>;
+pub type FlagEW = Obj<kind::FlagEW>;
+pub type FlagDW = Obj<kind::FlagDW>;
+pub type GMap = Obj<kind::GMap>;
+pub type RRMap = Obj<kind::RRMap>;
+
+impl RegT for LWWReg {}
+
+impl RegT for MVReg {}
+
+impl ObjectReset for MVReg {}
+
+impl CounterT for Counter {}
+
+impl CounterT for FatCounter {}
+
+impl ObjectReset for FatCounter {}
+
+impl CounterT for BCounter {}
+
+impl SetT for ORSet {}
+
+impl ObjectReset for ORSet {}
+
+impl SetT for RWSet {}
+
+impl ObjectReset for RWSet {}
+
+impl FlagT for FlagEW {}
+
+impl ObjectReset for FlagEW {}
+
 impl FlagT for FlagDW {}
 
 impl ObjectReset for FlagDW {}
 
-// Grow only map
+impl MapT for GMap {}
 
-#[derive(Clone, Debug)]
-pub struct GMap {
-    object: antidote::ApbBoundObject,
-}
+impl MapT for RRMap {}
 
-impl Object for GMap {
-    fn new(key: &str, bucket: &str) -> Self {
-        Self {
-            object: Self::new_object(key, bucket),
-        }
+impl ObjectReset for RRMap {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{codec, ops, reconcile, value::Value};
+    use super::*;
+
+    #[test]
+    fn decrements_negate_the_increment() {
+        let stock = BCounter::new("stock", "crdt");
+        assert_eq!(
+            stock
+                .dec(i64::MAX)
+                .unwrap()
+                .get_operation()
+                .get_counterop()
+                .get_inc(),
+            -i64::MAX
+        );
+        assert_eq!(BCounter::dec_op(3).unwrap().get_counterop().get_inc(), -3);
     }
 
-    fn get_object(&self) -> antidote::ApbBoundObject {
-        self.object.clone()
+    #[test]
+    fn decrement_by_min_is_an_error() {
+        assert!(matches!(
+            BCounter::new("stock", "crdt").dec(i64::MIN),
+            Err(Error::Overflow(i64::MIN))
+        ));
+        assert!(matches!(
+            MapKey::<Counter>::new("n").dec(i64::MIN),
+            Err(Error::Overflow(i64::MIN))
+        ));
+        assert_eq!(
+            Error::Overflow(i64::MIN).to_string(),
+            "cannot decrement by -9223372036854775808, the increment would overflow"
+        );
     }
 
-    fn get_crdt_type() -> antidote::CRDT_type {
-        antidote::CRDT_type::GMAP
+    #[cfg(feature = "prost")]
+    #[test]
+    fn bound_objects_share_key_and_bucket() {
+        let visits = Counter::new("visits", "web");
+        let op = visits.clone().inc(1);
+        let object = op.get_boundobject();
+        assert_eq!(object.get_key().as_ptr(), visits.key().as_ptr());
+        assert_eq!(object.get_bucket().as_ptr(), visits.bucket().as_ptr());
     }
-}
 
-impl MapT for GMap {}
+    #[cfg(feature = "prost")]
+    #[test]
+    fn register_reads_share_the_frame() {
+        let mut reg = antidote::ApbGetRegResp::new();
+        reg.set_value(b"value".to_vec());
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_reg(reg);
+        let resp = round_trip(&resp);
+
+        let value = LWWReg::decode(&resp);
+        assert_eq!(value, b"value"[..]);
+        assert_eq!(value.as_ptr(), resp.get_reg().get_value().as_ptr());
+    }
 
-// Recursive remove map
+    struct Mine;
 
-#[derive(Clone, Debug)]
-pub struct RRMap {
-    object: antidote::ApbBoundObject,
-}
+    impl CrdtKind for Mine {
+        const TYPE: i32 = 42;
+        type Value = i32;
 
-impl Object for RRMap {
-    fn new(key: &str, bucket: &str) -> Self {
-        Self {
-            object: Self::new_object(key, bucket),
+        fn decode(resp: &antidote::ApbReadObjectResp) -> i32 {
+            resp.get_counter().get_value()
         }
     }
 
-    fn get_object(&self) -> antidote::ApbBoundObject {
-        self.object.clone()
+    fn round_trip<M: codec::Message>(message: &M) -> M {
+        let mut buffer = Vec::new();
+        codec::encode(&mut buffer, |encoder| encoder.write_fields(message)).unwrap();
+        codec::decode(buffer).unwrap()
     }
 
-    fn get_crdt_type() -> antidote::CRDT_type {
-        antidote::CRDT_type::RRMAP
+    #[test]
+    fn custom_kinds_have_no_crdt_type() {
+        let mine = Obj::<Mine>::new("mine", "crdt");
+        assert_eq!(Obj::<Mine>::get_crdt_type(), None);
+        assert_eq!(Obj::<Mine>::type_number(), 42);
+        assert_eq!(number_name(42), "type:42");
+        assert_eq!(number_from_name("type:42"), Some(42));
+        assert_eq!(number_from_name("orset"), Some(CRDT_type::ORSET.value()));
+
+        // the number survives the wire, though CRDT_type has no value for it
+        let object = round_trip(&mine.get_object());
+        assert_eq!(object.type_number(), 42);
+        assert_eq!(round_trip(&Obj::<Mine>::map_key("mine")).type_number(), 42);
     }
-}
 
-impl MapT for RRMap {}
+    #[test]
+    fn custom_kinds_print_and_parse() {
+        let mut operation = antidote::ApbUpdateOperation::new();
+        let mut counterop = antidote::ApbCounterUpdate::new();
+        counterop.set_inc(2);
+        operation.set_counterop(counterop);
+        let update = Obj::<Mine>::new("mine", "crdt").update(operation);
+
+        let text = ops::print_update(&update);
+        assert_eq!(text, "type:42 crdt/mine inc 2");
+        let parsed = ops::parse_update(&text).unwrap();
+        assert_eq!(parsed.get_boundobject().type_number(), 42);
+        assert_eq!(ops::print_update(&parsed), text);
+    }
 
-impl ObjectReset for RRMap {}
+    #[test]
+    fn custom_kinds_are_kept_opaque_in_values() {
+        let mine = Obj::<Mine>::new("mine", "crdt");
+        let current = Value::Counter(1);
+        assert!(matches!(
+            reconcile::reconcile_object(&mine, &current, &Value::Counter(2)),
+            Err(reconcile::ReconcileError::Custom(42))
+        ));
+
+        let mut counter = antidote::ApbGetCounterResp::new();
+        counter.set_value(7);
+        let mut value = antidote::ApbReadObjectResp::new();
+        value.set_counter(counter);
+        let mut entry = antidote::ApbMapEntry::new();
+        entry.set_key(Obj::<Mine>::map_key("mine"));
+        entry.set_value(value.clone());
+        let mut map = antidote::ApbGetMapResp::new();
+        map.set_entries(RepeatedField::from_vec(vec![entry]));
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_map(map);
+
+        let resp = round_trip(&resp);
+        let bytes = match Value::decode(CRDT_type::RRMAP, &resp) {
+            Value::Map(mut entries) => match entries.pop() {
+                Some((key, 42, Value::Custom(42, bytes))) if entries.is_empty() => {
+                    assert_eq!(key, b"mine");
+                    bytes
+                }
+                entry => panic!("unexpected entry {:?}", entry),
+            },
+            value => panic!("unexpected value {:?}", value),
+        };
+        let decoded = codec::decode::<antidote::ApbReadObjectResp>(bytes).unwrap();
+        assert_eq!(decoded.get_counter().get_value(), 7);
+    }
+}
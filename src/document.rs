@@ -0,0 +1,297 @@
+use super::antidote;
+use super::crdt::{LWWReg, MVReg, ORSet, Object, RRMap};
+use super::crdt::{MapKey, MapRemove, MapT, MapUpdate, ObjectReset};
+use super::view::{MapEntry, MapView};
+
+use serde_json::{Map, Value};
+
+use std::error;
+use std::fmt;
+
+// JSON documents stored as nested RRMaps.
+//
+// A document is a JSON object. Each of its fields becomes a map entry:
+//
+//     object             RRMap, recursively
+//     array of scalars   ORSet, one element per distinct item
+//     scalars            LWWReg, or MVReg with Registers::MultiValue
+//
+// Scalars, including set elements, are stored as their JSON text, so the
+// string "42" and the number 42 stay apart. Booleans are scalars too: a
+// disabled flag is a bottom value, which RRMap drops, so false would read
+// back as a missing field. Arrays lose their order and
+// duplicates, and may not hold objects or arrays. Empty objects and arrays
+// are not stored, so they read back as missing fields.
+//
+// write_ops replaces the whole document. patch_ops applies a JSON merge
+// patch (RFC 7396): fields present in the patch are replaced, null removes a
+// field, and every other field is left alone.
+//
+// Concurrent edits merge field by field:
+//
+// - writes to different fields, at any depth, are all kept.
+// - two writes of the same scalar: the last one wins with LWWReg. With MVReg
+//   both are kept, and the field reads back as an array of the values.
+// - two writes of the same array: the items of both are kept. Replacing an
+//   array removes only the items the writer had seen.
+// - removing a field, or replacing the document, concurrently with an update
+//   inside it keeps the update: the field comes back holding only what the
+//   concurrent writer added.
+// - writes of different kinds of value to the same field are all kept, since
+//   each kind is a different map entry. The field reads back as the first of
+//   object, array, scalar that it holds.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Registers {
+    LastWriterWins,
+    MultiValue,
+}
+
+#[derive(Clone, Debug)]
+pub struct Document {
+    map: RRMap,
+    registers: Registers,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DocumentError {
+    // documents, and the values given to write_ops and patch_ops, are objects
+    NotAnObject,
+    // an array holding an object or an array, at the given path
+    NestedArray(String),
+    // a map entry, at the given path, that is not part of a document
+    Decode(String),
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DocumentError::NotAnObject => write!(f, "a document must be a JSON object"),
+            DocumentError::NestedArray(path) => {
+                write!(f, "array at {:?} holds objects or arrays", path)
+            }
+            DocumentError::Decode(path) => write!(f, "cannot decode document field {:?}", path),
+        }
+    }
+}
+
+impl error::Error for DocumentError {}
+
+impl Document {
+    pub fn new(key: &str, bucket: &str) -> Self {
+        Document {
+            map: RRMap::new(key, bucket),
+            registers: Registers::LastWriterWins,
+        }
+    }
+
+    // how scalars are written, last writer wins by default
+    pub fn with_registers(mut self, registers: Registers) -> Self {
+        self.registers = registers;
+        self
+    }
+
+    // the map holding the document, to read it
+    pub fn object(&self) -> &RRMap {
+        &self.map
+    }
+
+    pub fn write_ops(&self, json: &Value) -> Result<Vec<antidote::ApbUpdateOp>, DocumentError> {
+        let object = json.as_object().ok_or(DocumentError::NotAnObject)?;
+        let mut ops = Vec::new();
+        ops.push(self.map.reset());
+
+        let updates = self.writes(object, "")?;
+        if !updates.is_empty() {
+            ops.push(self.map.apply(updates, Vec::new()));
+        }
+
+        Ok(ops)
+    }
+
+    pub fn patch_ops(&self, json: &Value) -> Result<Vec<antidote::ApbUpdateOp>, DocumentError> {
+        let object = json.as_object().ok_or(DocumentError::NotAnObject)?;
+        let mut ops = Vec::new();
+
+        // stale entries go first, in an op of their own, so that arrays can be
+        // reset and then filled again in the same transaction
+        let (updates, removes) = self.clears(object);
+        if !updates.is_empty() || !removes.is_empty() {
+            ops.push(self.map.apply(updates, removes));
+        }
+
+        let updates = self.writes(object, "")?;
+        if !updates.is_empty() {
+            ops.push(self.map.apply(updates, Vec::new()));
+        }
+
+        Ok(ops)
+    }
+
+    pub fn read(resp: &antidote::ApbReadObjectResp) -> Result<Value, DocumentError> {
+        Document::from_view(&MapView::new(resp.get_map()))
+    }
+
+    pub fn from_view(view: &MapView) -> Result<Value, DocumentError> {
+        read_object(view, "")
+    }
+
+    fn register(&self) -> i32 {
+        match self.registers {
+            Registers::LastWriterWins => LWWReg::type_number(),
+            Registers::MultiValue => MVReg::type_number(),
+        }
+    }
+
+    // updates and removals clearing what each field of a patch replaces
+    fn clears(&self, object: &Map<String, Value>) -> (Vec<MapUpdate>, Vec<MapRemove>) {
+        let mut updates = Vec::new();
+        let mut removes = Vec::new();
+
+        for (key, value) in object {
+            match value {
+                Value::Null => removes.extend(removes_except(key, None)),
+                Value::Object(nested) => {
+                    removes.extend(removes_except(key, Some(RRMap::type_number())));
+                    let (nested_updates, nested_removes) = self.clears(nested);
+                    if !nested_updates.is_empty() || !nested_removes.is_empty() {
+                        let key = MapKey::<RRMap>::new(key);
+                        updates.push(key.update(nested_updates, nested_removes));
+                    }
+                }
+                Value::Array(_) => {
+                    removes.extend(removes_except(key, Some(ORSet::type_number())));
+                    updates.push(MapKey::<ORSet>::new(key).reset());
+                }
+                _ => removes.extend(removes_except(key, Some(self.register()))),
+            }
+        }
+
+        (updates, removes)
+    }
+
+    fn writes(
+        &self,
+        object: &Map<String, Value>,
+        path: &str,
+    ) -> Result<Vec<MapUpdate>, DocumentError> {
+        let mut updates = Vec::new();
+
+        for (key, value) in object {
+            let path = join(path, key);
+            match value {
+                Value::Null => {}
+                Value::Object(nested) => {
+                    let nested = self.writes(nested, &path)?;
+                    if !nested.is_empty() {
+                        updates.push(MapKey::<RRMap>::new(key).update(nested, Vec::new()));
+                    }
+                }
+                Value::Array(items) => {
+                    if items.iter().any(|item| item.is_object() || item.is_array()) {
+                        return Err(DocumentError::NestedArray(path));
+                    }
+                    if !items.is_empty() {
+                        let items: Vec<_> = items.iter().map(encode).collect();
+                        updates.push(MapKey::<ORSet>::new(key).add_values(&items));
+                    }
+                }
+                _ => {
+                    let value = encode(value);
+                    updates.push(match self.registers {
+                        Registers::LastWriterWins => MapKey::<LWWReg>::new(key).set_value(&value),
+                        Registers::MultiValue => MapKey::<MVReg>::new(key).set_value(&value),
+                    });
+                }
+            }
+        }
+
+        Ok(updates)
+    }
+}
+
+fn encode(value: &Value) -> Vec<u8> {
+    value.to_string().into_bytes()
+}
+
+fn decode(bytes: &[u8], path: &str) -> Result<Value, DocumentError> {
+    serde_json::from_slice(bytes).map_err(|_| DocumentError::Decode(path.to_string()))
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+// removals of every entry a document may hold under key, but the one of type
+// number keep
+fn removes_except(key: &str, keep: Option<i32>) -> Vec<MapRemove> {
+    let mut removes = Vec::new();
+    remove_unless::<RRMap>(&mut removes, key, keep);
+    remove_unless::<ORSet>(&mut removes, key, keep);
+    remove_unless::<LWWReg>(&mut removes, key, keep);
+    remove_unless::<MVReg>(&mut removes, key, keep);
+    removes
+}
+
+fn remove_unless<C: Object>(removes: &mut Vec<MapRemove>, key: &str, keep: Option<i32>) {
+    if keep != Some(C::type_number()) {
+        removes.push(MapKey::<C>::new(key).remove());
+    }
+}
+
+fn read_object(view: &MapView, path: &str) -> Result<Value, DocumentError> {
+    let mut object = Map::new();
+
+    for entry in view.iter() {
+        let key =
+            std::str::from_utf8(entry.key()).map_err(|_| DocumentError::Decode(join(path, "?")))?;
+        if object.contains_key(key) {
+            continue;
+        }
+
+        let path = join(path, key);
+        let entries: Vec<_> = view.iter().filter(|e| e.key() == entry.key()).collect();
+        if let Some(value) = read_field(&entries, &path)? {
+            object.insert(key.to_string(), value);
+        }
+    }
+
+    Ok(Value::Object(object))
+}
+
+// the value of a field, from the entries stored under its key
+fn read_field(entries: &[MapEntry], path: &str) -> Result<Option<Value>, DocumentError> {
+    let map = entries.iter().find(|e| e.is::<RRMap>());
+    if let Some(nested) = map.and_then(|e| e.map()) {
+        return read_object(&nested, path).map(Some);
+    }
+    if let Some(items) = entries.iter().find_map(|e| e.get::<ORSet>()) {
+        let items = items
+            .iter()
+            .map(|item| decode(item, path))
+            .collect::<Result<_, _>>()?;
+        return Ok(Some(Value::Array(items)));
+    }
+    if let Some(value) = entries.iter().find_map(|e| e.get::<LWWReg>()) {
+        return decode(&value, path).map(Some);
+    }
+    if let Some(mut values) = entries.iter().find_map(|e| e.get::<MVReg>()) {
+        return match values.len() {
+            0 => Ok(None),
+            1 => decode(&values.remove(0), path).map(Some),
+            _ => {
+                let values = values
+                    .iter()
+                    .map(|value| decode(value, path))
+                    .collect::<Result<_, _>>()?;
+                Ok(Some(Value::Array(values)))
+            }
+        };
+    }
+
+    Err(DocumentError::Decode(path.to_string()))
+}
@@ -0,0 +1,117 @@
+use super::antidote::{ApbBoundObject, ApbReadObjectResp, CRDT_type};
+use super::crdt::{BCounter, Counter, FatCounter, FlagDW, FlagEW, GMap, LWWReg, MVReg};
+use super::crdt::{ORSet, Object, RRMap, RWSet};
+use super::{AntidoteDB, Error, Transaction};
+
+use std::fmt::Write;
+
+// Objects whose type is only known at run time, named by a CRDT_type read
+// from a command line or a statement, and the hex text of their bytes. The
+// typed reads of crdt are picked by that type, for the binaries and the text
+// formats that do not know the types they read.
+
+pub fn bound_object(key: &[u8], crdt_type: CRDT_type, bucket: &[u8]) -> ApbBoundObject {
+    let mut object = ApbBoundObject::new();
+    object.set_key(key.to_vec());
+    object.set_field_type(crdt_type);
+    object.set_bucket(bucket.to_vec());
+    object
+}
+
+// Reads the objects, given as key and bucket, as objects of crdt_type, in the
+// transaction or statically when there is none. The token is the snapshot
+// time of static reads. No objects, no request.
+pub fn read(
+    db: &mut AntidoteDB,
+    crdt_type: CRDT_type,
+    objects: &[(&[u8], &[u8])],
+    txn: Option<&Transaction>,
+    at: Option<&Vec<u8>>,
+) -> Result<(Vec<ApbReadObjectResp>, Option<Vec<u8>>), Error> {
+    if objects.is_empty() {
+        return Ok((Vec::new(), None));
+    }
+    match crdt_type {
+        CRDT_type::COUNTER => read_as::<Counter>(db, objects, txn, at),
+        CRDT_type::FATCOUNTER => read_as::<FatCounter>(db, objects, txn, at),
+        CRDT_type::BCOUNTER => read_as::<BCounter>(db, objects, txn, at),
+        CRDT_type::LWWREG => read_as::<LWWReg>(db, objects, txn, at),
+        CRDT_type::MVREG => read_as::<MVReg>(db, objects, txn, at),
+        CRDT_type::ORSET => read_as::<ORSet>(db, objects, txn, at),
+        CRDT_type::RWSET => read_as::<RWSet>(db, objects, txn, at),
+        CRDT_type::FLAG_EW => read_as::<FlagEW>(db, objects, txn, at),
+        CRDT_type::FLAG_DW => read_as::<FlagDW>(db, objects, txn, at),
+        CRDT_type::GMAP => read_as::<GMap>(db, objects, txn, at),
+        CRDT_type::RRMAP => read_as::<RRMap>(db, objects, txn, at),
+    }
+}
+
+// the value of a single object, see read
+pub fn read_one(
+    db: &mut AntidoteDB,
+    crdt_type: CRDT_type,
+    key: &[u8],
+    bucket: &[u8],
+    txn: Option<&Transaction>,
+    at: Option<&Vec<u8>>,
+) -> Result<(ApbReadObjectResp, Option<Vec<u8>>), Error> {
+    let (mut values, token) = read(db, crdt_type, &[(key, bucket)], txn, at)?;
+    let value = values
+        .pop()
+        .ok_or_else(|| Error::Protocol("empty read response".to_string()))?;
+    Ok((value, token))
+}
+
+fn read_as<O: Object>(
+    db: &mut AntidoteDB,
+    objects: &[(&[u8], &[u8])],
+    txn: Option<&Transaction>,
+    at: Option<&Vec<u8>>,
+) -> Result<(Vec<ApbReadObjectResp>, Option<Vec<u8>>), Error> {
+    let objects: Vec<O> = objects
+        .iter()
+        .map(|(key, bucket)| O::new_bytes(key, bucket))
+        .collect();
+    match txn {
+        Some(txn) => Ok((db.mult_read_in_transaction(&objects, txn)?, None)),
+        None => {
+            let (values, token) = db.static_read_objects(&objects, at)?;
+            Ok((values, Some(token)))
+        }
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(hex, "{:02x}", byte).unwrap();
+    }
+    hex
+}
+
+// None unless the text is pairs of hex digits, without a 0x prefix; an odd
+// digit left over has no pair to get
+pub fn from_hex(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trips() {
+        let bytes = vec![0x00, 0x7f, 0x80, 0xff];
+        assert_eq!(to_hex(&bytes), "007f80ff");
+        assert_eq!(from_hex("007f80ff"), Some(bytes));
+        assert_eq!(from_hex("007F80FF"), from_hex("007f80ff"));
+        assert_eq!(from_hex(""), Some(Vec::new()));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+        // multi-byte characters are not digits, and do not panic
+        assert_eq!(from_hex("é"), None);
+    }
+}
@@ -0,0 +1,671 @@
+use super::antidote;
+use super::crdt::{self, CRDT_type};
+use super::ops;
+use super::session::{CommitTime, Session};
+use super::value::Value;
+use super::{AntidoteDB, Error, Transaction};
+
+use super::codec::{ProtobufEnum, TypedMessage};
+
+use serde_json::json;
+
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// Histories of transactions, recorded for isolation and consistency checking.
+//
+// A Recorder hands out RecordedSessions, causal sessions that log each
+// transaction they run: its reads with the values they observed, its
+// updates, the snapshot it started from, its commit time and its outcome.
+// Sessions may run on several threads against several nodes, the recorder
+// writes one transaction at a time as it ends.
+//
+// The file is JSON lines, a header then a transaction per line:
+//
+//     {"format":"antidote-history","version":2}
+//     {"commit_time":"v1.AAAAAAAAAAGa4W_S",
+//      "ops":[{"read":"orset h/tags","value":["a"]},
+//             {"update":"orset h/tags add {b}"}],
+//      "outcome":"committed","session":0,"snapshot":null,"static":false,
+//      "txn":1}
+//
+// Transaction ids grow in the order transactions start, so the transactions
+// of a session are in session order by id. Objects and updates are written
+// in the ops text format, values as Value::to_json gives them, times as
+// CommitTime tokens. The outcome is committed, aborted, or unknown when the
+// connection failed before the commit was acknowledged. A failed transaction
+// also has the "error" it failed with. Version 1 wrote times as hex.
+//
+// check looks for anomalies in a history, see check.rs, and consistency
+// decides which consistency levels it satisfies.
+
+mod check;
+pub mod consistency;
+
+pub use self::check::{check, Anomaly, AnomalyKind};
+
+const FORMAT: &str = "antidote-history";
+const VERSION: u64 = 2;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    // in the order they ended
+    pub transactions: Vec<Txn>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Txn {
+    pub id: u64,
+    pub session: u64,
+    // a static read or update rather than an interactive transaction
+    pub is_static: bool,
+    // the timestamp the transaction was started with
+    pub snapshot: Option<Vec<u8>>,
+    pub commit_time: Option<Vec<u8>>,
+    pub outcome: Outcome,
+    pub error: Option<String>,
+    pub ops: Vec<Op>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Committed,
+    Aborted,
+    // the transaction may or may not have committed
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    Read(antidote::ApbBoundObject, Value),
+    Update(antidote::ApbUpdateOp),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryError {
+    // 0 when reading failed
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl error::Error for HistoryError {}
+
+impl Outcome {
+    fn name(self) -> &'static str {
+        match self {
+            Outcome::Committed => "committed",
+            Outcome::Aborted => "aborted",
+            Outcome::Unknown => "unknown",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "committed" => Some(Outcome::Committed),
+            "aborted" => Some(Outcome::Aborted),
+            "unknown" => Some(Outcome::Unknown),
+            _ => None,
+        }
+    }
+
+    // the outcome of a transaction whose commit failed with e
+    fn of_error(e: &Error) -> Self {
+        if e.is_io() {
+            Outcome::Unknown
+        } else {
+            Outcome::Aborted
+        }
+    }
+}
+
+impl History {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, HistoryError> {
+        let file = File::open(path).map_err(|e| HistoryError {
+            line: 0,
+            message: e.to_string(),
+        })?;
+        History::read(BufReader::new(file))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Self, HistoryError> {
+        let mut history = History::default();
+        let mut header = false;
+        for (i, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| HistoryError {
+                line: 0,
+                message: e.to_string(),
+            })?;
+            let error = |message: String| HistoryError {
+                line: i + 1,
+                message,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let json: serde_json::Value =
+                serde_json::from_str(&line).map_err(|e| error(e.to_string()))?;
+            if !header {
+                if json["format"] != FORMAT {
+                    return Err(error("not an antidote history".to_string()));
+                }
+                if json["version"] != VERSION {
+                    return Err(error(format!(
+                        "unsupported version {}, expected {}",
+                        json["version"], VERSION
+                    )));
+                }
+                header = true;
+            } else {
+                history
+                    .transactions
+                    .push(Txn::from_json(&json).map_err(error)?);
+            }
+        }
+        if !header {
+            return Err(HistoryError {
+                line: 0,
+                message: "empty history".to_string(),
+            });
+        }
+        Ok(history)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", header())?;
+        for txn in &self.transactions {
+            writeln!(writer, "{}", txn.to_json())?;
+        }
+        writer.flush()
+    }
+
+    pub fn transaction(&self, id: u64) -> Option<&Txn> {
+        self.transactions.iter().find(|txn| txn.id == id)
+    }
+}
+
+fn header() -> serde_json::Value {
+    json!({ "format": FORMAT, "version": VERSION })
+}
+
+impl Txn {
+    pub fn reads(&self) -> impl Iterator<Item = (&antidote::ApbBoundObject, &Value)> {
+        self.ops.iter().filter_map(|op| match op {
+            Op::Read(object, value) => Some((object, value)),
+            Op::Update(_) => None,
+        })
+    }
+
+    pub fn updates(&self) -> impl Iterator<Item = &antidote::ApbUpdateOp> {
+        self.ops.iter().filter_map(|op| match op {
+            Op::Update(update) => Some(update),
+            Op::Read(..) => None,
+        })
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let ops: Vec<_> = self
+            .ops
+            .iter()
+            .map(|op| match op {
+                Op::Read(object, value) => json!({
+                    "read": ops::print_object(object),
+                    "value": value.to_json(),
+                }),
+                Op::Update(update) => json!({ "update": ops::print_update(update) }),
+            })
+            .collect();
+        let mut json = json!({
+            "txn": self.id,
+            "session": self.session,
+            "static": self.is_static,
+            "snapshot": self.snapshot.as_deref().map(token),
+            "commit_time": self.commit_time.as_deref().map(token),
+            "outcome": self.outcome.name(),
+            "ops": ops,
+        });
+        if let Some(e) = &self.error {
+            json["error"] = json!(e);
+        }
+        json
+    }
+
+    pub fn from_json(json: &serde_json::Value) -> Result<Self, String> {
+        let field = |name: &str| {
+            json.get(name)
+                .ok_or_else(|| format!("transaction without {:?}", name))
+        };
+        let time = |name: &str| -> Result<Option<Vec<u8>>, String> {
+            match field(name)? {
+                serde_json::Value::Null => Ok(None),
+                time => time
+                    .as_str()
+                    .ok_or_else(|| format!("{:?} is not a string", name))?
+                    .parse::<CommitTime>()
+                    .map(|time| Some(time.into_bytes()))
+                    .map_err(|e| format!("{:?}: {}", name, e)),
+            }
+        };
+        let id = field("txn")?.as_u64().ok_or("\"txn\" is not a number")?;
+        let session = field("session")?
+            .as_u64()
+            .ok_or("\"session\" is not a number")?;
+        let is_static = field("static")?
+            .as_bool()
+            .ok_or("\"static\" is not a boolean")?;
+        let outcome = field("outcome")?
+            .as_str()
+            .and_then(Outcome::from_name)
+            .ok_or("\"outcome\" is not committed, aborted or unknown")?;
+        let error = match json.get("error") {
+            Some(e) => Some(e.as_str().ok_or("\"error\" is not a string")?.to_string()),
+            None => None,
+        };
+        let ops = field("ops")?
+            .as_array()
+            .ok_or("\"ops\" is not an array")?
+            .iter()
+            .map(op_from_json)
+            .collect::<Result<_, _>>()?;
+        Ok(Txn {
+            id,
+            session,
+            is_static,
+            snapshot: time("snapshot")?,
+            commit_time: time("commit_time")?,
+            outcome,
+            error,
+            ops,
+        })
+    }
+}
+
+fn token(time: &[u8]) -> String {
+    CommitTime::from(time.to_vec()).to_token()
+}
+
+fn op_from_json(json: &serde_json::Value) -> Result<Op, String> {
+    if let Some(update) = json.get("update") {
+        let text = update.as_str().ok_or("\"update\" is not a string")?;
+        let update = ops::parse_update(text).map_err(|e| format!("update {}", e))?;
+        return Ok(Op::Update(update));
+    }
+    let text = json
+        .get("read")
+        .and_then(serde_json::Value::as_str)
+        .ok_or("operation is neither a read nor an update")?;
+    let object = ops::parse_object(text).map_err(|e| format!("read {}", e))?;
+    let crdt_type = CRDT_type::from_i32(object.type_number())
+        .ok_or_else(|| format!("read of {} of a custom kind", text))?;
+    let value = json
+        .get("value")
+        .and_then(|value| Value::from_json(crdt_type, value))
+        .ok_or_else(|| {
+            format!(
+                "read of {} without a {} value",
+                text,
+                crdt::type_name(crdt_type)
+            )
+        })?;
+    Ok(Op::Read(object, value))
+}
+
+// Writes the transactions of its sessions, clones share the same output.
+#[derive(Clone)]
+pub struct Recorder {
+    sink: Arc<Mutex<Sink>>,
+}
+
+struct Sink {
+    out: Box<dyn Write + Send>,
+    sessions: u64,
+    transactions: u64,
+    // the first write that failed since take_error
+    error: Option<io::Error>,
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Recorder").finish()
+    }
+}
+
+impl Recorder {
+    pub fn new<W: Write + Send + 'static>(out: W) -> io::Result<Self> {
+        let mut out: Box<dyn Write + Send> = Box::new(out);
+        writeln!(out, "{}", header())?;
+        out.flush()?;
+        Ok(Recorder {
+            sink: Arc::new(Mutex::new(Sink {
+                out,
+                sessions: 0,
+                transactions: 0,
+                error: None,
+            })),
+        })
+    }
+
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Recorder::new(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn session(&self, db: AntidoteDB) -> RecordedSession {
+        self.recorded(Session::new(db))
+    }
+
+    // record the transactions of a session already under way
+    pub fn recorded(&self, session: Session) -> RecordedSession {
+        let id = {
+            let mut sink = self.lock();
+            sink.sessions += 1;
+            sink.sessions - 1
+        };
+        RecordedSession {
+            session,
+            recorder: self.clone(),
+            id,
+            open: None,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Sink> {
+        // a panic while writing leaves at worst a torn line
+        self.sink.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn next_transaction(&self) -> u64 {
+        let mut sink = self.lock();
+        sink.transactions += 1;
+        sink.transactions
+    }
+
+    // The first write that failed since the last call. The history misses
+    // the transaction it was writing, whose outcome its session returned all
+    // the same.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.lock().error.take()
+    }
+
+    fn write(&self, txn: &Txn) {
+        let mut sink = self.lock();
+        let written = writeln!(sink.out, "{}", txn.to_json()).and_then(|_| sink.out.flush());
+        if let Err(e) = written {
+            sink.error.get_or_insert(e);
+        }
+    }
+}
+
+// A Session that records its transactions. Operations the session refuses,
+// as when a transaction is open, are not sent and not recorded. A transaction
+// left open by reconnect or into_inner is recorded as unknown. Failures to
+// write the history are kept by the Recorder, operations return what the
+// node answered.
+#[derive(Debug)]
+pub struct RecordedSession {
+    session: Session,
+    recorder: Recorder,
+    id: u64,
+    open: Option<Txn>,
+}
+
+impl RecordedSession {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn token(&self) -> Option<&CommitTime> {
+        self.session.token()
+    }
+
+    pub fn start_transaction(&mut self) -> Result<Transaction, Error> {
+        self.session.check_idle()?;
+        let mut txn = self.new_txn(false);
+        match self.session.start_transaction() {
+            Ok(t) => {
+                self.open = Some(txn);
+                Ok(t)
+            }
+            Err(e) => {
+                txn.outcome = Outcome::Aborted;
+                txn.error = Some(e.to_string());
+                self.recorder.write(&txn);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn mult_read_in_transaction<T>(
+        &mut self,
+        objects: &[T],
+        t: &Transaction,
+    ) -> Result<Vec<antidote::ApbReadObjectResp>, Error>
+    where
+        T: crdt::Object,
+    {
+        self.session.check_open(t)?;
+        let result = self.session.mult_read_in_transaction(objects, t);
+        if let Some(txn) = &mut self.open {
+            match &result {
+                Ok(values) => txn.ops.extend(read_ops(objects, values)),
+                Err(e) => txn.error = Some(e.to_string()),
+            }
+        }
+        result
+    }
+
+    pub fn mult_update_in_transaction(
+        &mut self,
+        apb_operations: &[antidote::ApbUpdateOp],
+        t: &Transaction,
+    ) -> Result<(), Error> {
+        self.session.check_open(t)?;
+        let result = self.session.mult_update_in_transaction(apb_operations, t);
+        if let Some(txn) = &mut self.open {
+            match &result {
+                Ok(()) => txn
+                    .ops
+                    .extend(apb_operations.iter().cloned().map(Op::Update)),
+                Err(e) => txn.error = Some(e.to_string()),
+            }
+        }
+        result
+    }
+
+    pub fn commit_transaction(&mut self, t: &Transaction) -> Result<CommitTime, Error> {
+        self.session.check_open(t)?;
+        let result = self.session.commit_transaction(t);
+        if let Some(mut txn) = self.open.take() {
+            match &result {
+                Ok(commit_time) => {
+                    txn.outcome = Outcome::Committed;
+                    txn.commit_time = Some(commit_time.as_bytes().to_vec());
+                }
+                Err(e) => {
+                    txn.outcome = Outcome::of_error(e);
+                    txn.error = Some(e.to_string());
+                }
+            }
+            self.recorder.write(&txn);
+        }
+        result
+    }
+
+    pub fn abort_transaction(&mut self, t: &Transaction) -> Result<(), Error> {
+        self.session.check_open(t)?;
+        let result = self.session.abort_transaction(t);
+        if let Some(mut txn) = self.open.take() {
+            txn.outcome = Outcome::Aborted;
+            self.recorder.write(&txn);
+        }
+        result
+    }
+
+    pub fn static_update_objects(
+        &mut self,
+        apb_operations: &[antidote::ApbUpdateOp],
+    ) -> Result<CommitTime, Error> {
+        self.session.check_idle()?;
+        let mut txn = self.new_txn(true);
+        txn.ops = apb_operations.iter().cloned().map(Op::Update).collect();
+        let result = self.session.static_update_objects(apb_operations);
+        match &result {
+            Ok(commit_time) => {
+                txn.outcome = Outcome::Committed;
+                txn.commit_time = Some(commit_time.as_bytes().to_vec());
+            }
+            Err(e) => {
+                txn.outcome = Outcome::of_error(e);
+                txn.error = Some(e.to_string());
+            }
+        }
+        self.recorder.write(&txn);
+        result
+    }
+
+    pub fn static_read_objects<T>(
+        &mut self,
+        objects: &[T],
+    ) -> Result<Vec<antidote::ApbReadObjectResp>, Error>
+    where
+        T: crdt::Object,
+    {
+        self.session.check_idle()?;
+        let mut txn = self.new_txn(true);
+        let result = self.session.static_read_objects(objects);
+        match &result {
+            Ok(values) => {
+                txn.outcome = Outcome::Committed;
+                txn.commit_time = self.session.token().map(|t| t.as_bytes().to_vec());
+                txn.ops = read_ops(objects, values).collect();
+            }
+            Err(e) => {
+                txn.outcome = Outcome::Aborted;
+                txn.error = Some(e.to_string());
+            }
+        }
+        self.recorder.write(&txn);
+        result
+    }
+
+    pub fn reconnect(&mut self, db: AntidoteDB) {
+        self.leave_open("left open by a reconnect");
+        self.session.reconnect(db);
+    }
+
+    pub fn into_inner(mut self) -> Session {
+        self.leave_open("no longer recorded");
+        self.session
+    }
+
+    fn leave_open(&mut self, reason: &str) {
+        if let Some(mut txn) = self.open.take() {
+            txn.outcome = Outcome::Unknown;
+            txn.error = Some(reason.to_string());
+            self.recorder.write(&txn);
+        }
+    }
+
+    fn new_txn(&self, is_static: bool) -> Txn {
+        Txn {
+            id: self.recorder.next_transaction(),
+            session: self.id,
+            is_static,
+            snapshot: self.session.token().map(|t| t.as_bytes().to_vec()),
+            commit_time: None,
+            outcome: Outcome::Aborted,
+            error: None,
+            ops: Vec::new(),
+        }
+    }
+}
+
+fn read_ops<'a, T: crdt::Object>(
+    objects: &'a [T],
+    values: &'a [antidote::ApbReadObjectResp],
+) -> impl Iterator<Item = Op> + 'a {
+    objects.iter().zip(values).filter_map(|(object, resp)| {
+        // reads of custom kinds are not recorded, a Value cannot hold them
+        let crdt_type = T::get_crdt_type()?;
+        Some(Op::Read(
+            object.get_object(),
+            Value::decode(crdt_type, resp),
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::crdt::{Counter, CounterT, Object};
+    use super::super::server::StandIn;
+    use super::*;
+
+    // shares what was written, or fails every write after the header
+    #[derive(Clone)]
+    struct Shared(Arc<Mutex<Vec<u8>>>, bool);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut written = self.0.lock().unwrap();
+            if self.1 && written.contains(&b'\n') {
+                return Err(io::Error::other("disk full"));
+            }
+            written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn refused_operations_are_not_recorded() {
+        let stand_in = StandIn::start().unwrap();
+        let out = Shared(Arc::default(), false);
+        let recorder = Recorder::new(out.clone()).unwrap();
+        let mut session = recorder.session(stand_in.connect().unwrap());
+        let counter = Counter::new("c", "h");
+
+        let t = session.start_transaction().unwrap();
+        assert!(session.start_transaction().is_err());
+        assert!(session.static_update_objects(&[counter.inc(1)]).is_err());
+        session
+            .mult_update_in_transaction(&[counter.inc(1)], &t)
+            .unwrap();
+        let commit_time = session.commit_transaction(&t).unwrap();
+        assert!(session.commit_transaction(&t).is_err());
+        assert!(recorder.take_error().is_none());
+
+        let history = History::read(&out.0.lock().unwrap()[..]).unwrap();
+        assert_eq!(history.transactions.len(), 1);
+        let txn = &history.transactions[0];
+        assert_eq!(txn.outcome, Outcome::Committed);
+        assert_eq!(txn.commit_time.as_deref(), Some(commit_time.as_bytes()));
+        assert_eq!(txn.updates().count(), 1);
+    }
+
+    #[test]
+    fn write_failures_leave_outcomes_alone() {
+        let stand_in = StandIn::start().unwrap();
+        let recorder = Recorder::new(Shared(Arc::default(), true)).unwrap();
+        let mut session = recorder.session(stand_in.connect().unwrap());
+        let counter = Counter::new("c", "h");
+
+        assert!(session.static_update_objects(&[counter.inc(1)]).is_ok());
+        assert_eq!(recorder.take_error().unwrap().to_string(), "disk full");
+        assert!(recorder.take_error().is_none());
+    }
+}
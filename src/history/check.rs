@@ -0,0 +1,844 @@
+use super::super::antidote;
+use super::super::codec::{self, ProtobufEnum, TypedMessage};
+use super::super::crdt::{type_name, CRDT_type};
+use super::super::ops;
+use super::super::value::Value;
+use super::{History, Op, Outcome, Txn};
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+// Anomalies of a history, found offline from what its transactions read.
+//
+// Which transaction a read observed is inferred from values written once:
+// set elements and register values. A transaction must see what is in its
+// causal past: the committed transactions before it in its session, the
+// transactions it observed, and theirs in turn. Each read of an object is
+// checked against the updates of that past:
+//
+// - a set holds the elements added there, unless a remove that saw the add
+//   (for orsets, any remove for rwsets) may have taken them out,
+// - a register holds no value overwritten there,
+// - a counter sums the increments there plus some of the concurrent ones,
+// - an enable-wins flag is enabled after an enable no disable saw.
+//
+// A missing update is a lost update when it comes from the session itself,
+// a fractured read when the transaction observed its writer elsewhere, and a
+// causality violation otherwise. Values only aborted transactions wrote are
+// aborted reads.
+//
+// Objects are assumed to be empty when recording starts, use fresh keys.
+// Map entries are checked as objects of their own. Objects that were reset
+// or removed from a map are not checked, nor are disable-wins flags.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AnomalyKind {
+    LostUpdate,
+    CausalityViolation,
+    FracturedRead,
+    AbortedRead,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    // the reading transaction
+    pub txn: u64,
+    // the transaction whose update is missing or should not be seen, if known
+    pub writer: Option<u64>,
+    pub object: antidote::ApbBoundObject,
+    // map entries from object down to the one read, empty for the object
+    pub path: Vec<(Vec<u8>, CRDT_type)>,
+    pub detail: String,
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AnomalyKind::LostUpdate => "lost update",
+            AnomalyKind::CausalityViolation => "causality violation",
+            AnomalyKind::FracturedRead => "fractured read",
+            AnomalyKind::AbortedRead => "aborted read",
+        })
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in txn {}", self.kind, self.txn)?;
+        if let Some(writer) = self.writer {
+            write!(f, " of txn {}", writer)?;
+        }
+        write!(f, " at {}", ops::print_object(&self.object))?;
+        for (key, crdt_type) in &self.path {
+            write!(f, " > {}/{}", Value::bytes(key), type_name(*crdt_type))?;
+        }
+        write!(f, ": {}", self.detail)
+    }
+}
+
+pub fn check(history: &History) -> Vec<Anomaly> {
+    let analysis = Analysis::new(history);
+    let mut anomalies: Vec<Anomaly> = analysis
+        .anomalies()
+        .iter()
+        .map(|found| analysis.anomaly_of(found))
+        .collect();
+    anomalies.sort_by_key(|a| (a.txn, a.kind, a.writer));
+    anomalies.dedup();
+    anomalies
+}
+
+// An object, or an entry of a map object, checked on its own.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct Cell {
+    pub(crate) bucket: Vec<u8>,
+    pub(crate) key: Vec<u8>,
+    pub(crate) root: i32,
+    pub(crate) path: Vec<(Vec<u8>, i32)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Effect {
+    Inc(i64),
+    Add(Vec<u8>),
+    Remove(Vec<u8>),
+    Assign(Vec<u8>),
+    Flag(bool),
+    Reset,
+}
+
+// an effect of the update at ops[op] of transactions[txn]
+#[derive(Clone, Debug)]
+pub(crate) struct Write {
+    pub(crate) txn: usize,
+    pub(crate) op: usize,
+    pub(crate) cell: Cell,
+    pub(crate) effect: Effect,
+}
+
+// the value a read at ops[op] of transactions[txn] gave for cell
+#[derive(Clone, Debug)]
+pub(crate) struct Read {
+    pub(crate) txn: usize,
+    pub(crate) op: usize,
+    pub(crate) cell: Cell,
+    pub(crate) value: Value,
+}
+
+// an anomaly, with transactions by index
+#[derive(Clone, Debug)]
+pub(crate) struct Found {
+    pub(crate) kind: AnomalyKind,
+    pub(crate) reader: usize,
+    pub(crate) writer: Option<usize>,
+    pub(crate) cell: Cell,
+    pub(crate) detail: String,
+}
+
+// reads[read] holds value, written by transactions[writer]
+#[derive(Clone, Debug)]
+pub(crate) struct Observation {
+    pub(crate) writer: usize,
+    pub(crate) reader: usize,
+    pub(crate) read: usize,
+    pub(crate) value: Vec<u8>,
+}
+
+// Transactions are numbered by their index in the history.
+#[derive(Debug)]
+pub(crate) struct Analysis<'a> {
+    pub(crate) transactions: &'a [Txn],
+    pub(crate) writes: Vec<Write>,
+    // indices in writes, by cell
+    pub(crate) by_cell: HashMap<Cell, Vec<usize>>,
+    pub(crate) reads: Vec<Read>,
+    // inferred from values written once
+    pub(crate) observations: Vec<Observation>,
+    // the last committed transaction before each one in its session
+    pub(crate) session_pred: Vec<Option<usize>>,
+    // causal past of each transaction, as bitsets over transactions
+    pub(crate) past: Vec<Vec<u64>>,
+    aborted_reads: Vec<Found>,
+}
+
+impl<'a> Analysis<'a> {
+    pub(crate) fn new(history: &'a History) -> Self {
+        let transactions = &history.transactions[..];
+        let mut analysis = Analysis {
+            transactions,
+            writes: Vec::new(),
+            by_cell: HashMap::new(),
+            reads: Vec::new(),
+            observations: Vec::new(),
+            session_pred: vec![None; transactions.len()],
+            past: Vec::new(),
+            aborted_reads: Vec::new(),
+        };
+
+        for (txn, t) in transactions.iter().enumerate() {
+            for (op, o) in t.ops.iter().enumerate() {
+                if let Op::Update(update) = o {
+                    let object = update.get_boundobject();
+                    let cell = Cell::object(object);
+                    flatten_operation(&mut analysis.writes, txn, op, cell, update.get_operation());
+                }
+            }
+        }
+        for (i, write) in analysis.writes.iter().enumerate() {
+            analysis
+                .by_cell
+                .entry(write.cell.clone())
+                .or_default()
+                .push(i);
+        }
+
+        // the cells written under each object, so map reads are checked for
+        // entries they do not hold
+        let mut under: HashMap<(Vec<u8>, Vec<u8>, i32), BTreeSet<Cell>> = HashMap::new();
+        for cell in analysis.by_cell.keys() {
+            under
+                .entry((cell.bucket.clone(), cell.key.clone(), cell.root))
+                .or_default()
+                .insert(cell.clone());
+        }
+        for (txn, t) in transactions.iter().enumerate() {
+            for (op, o) in t.ops.iter().enumerate() {
+                if let Op::Read(object, value) = o {
+                    let root = Cell::object(object);
+                    let id = (root.bucket.clone(), root.key.clone(), root.root);
+                    for cell in under.get(&id).into_iter().flatten() {
+                        let value = match cell.crdt_type() {
+                            Some(CRDT_type::GMAP) | Some(CRDT_type::RRMAP) | None => continue,
+                            Some(crdt_type) => entry_value(value, &cell.path)
+                                .cloned()
+                                .unwrap_or_else(|| empty(crdt_type)),
+                        };
+                        analysis.reads.push(Read {
+                            txn,
+                            op,
+                            cell: cell.clone(),
+                            value,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut last: HashMap<u64, usize> = HashMap::new();
+        let mut order: Vec<usize> = (0..transactions.len()).collect();
+        order.sort_by_key(|&txn| transactions[txn].id);
+        for txn in order {
+            let t = &transactions[txn];
+            analysis.session_pred[txn] = last.get(&t.session).cloned();
+            if t.outcome == Outcome::Committed {
+                last.insert(t.session, txn);
+            }
+        }
+
+        analysis.observe();
+        analysis.past = analysis.causal_pasts();
+        analysis
+    }
+
+    pub(crate) fn visible(&self, txn: usize) -> bool {
+        self.transactions[txn].outcome != Outcome::Aborted
+    }
+
+    // whether u is in the causal past of t
+    pub(crate) fn in_past(&self, t: usize, u: usize) -> bool {
+        self.past[t][u / 64] & (1 << (u % 64)) != 0
+    }
+
+    pub(crate) fn observed(&self, t: usize) -> impl Iterator<Item = usize> + '_ {
+        self.observations
+            .iter()
+            .filter(move |o| o.reader == t)
+            .map(|o| o.writer)
+    }
+
+    // writes of cell, in the order of the history
+    pub(crate) fn writes_of(&self, cell: &Cell) -> impl Iterator<Item = &Write> {
+        self.by_cell
+            .get(cell)
+            .into_iter()
+            .flatten()
+            .map(move |&i| &self.writes[i])
+    }
+
+    fn observe(&mut self) {
+        for (r, read) in self.reads.iter().enumerate() {
+            let values: Vec<&Vec<u8>> = match &read.value {
+                Value::Set(values) | Value::MultiRegister(values) => values.iter().collect(),
+                Value::Register(value) if !value.is_empty() => vec![value],
+                _ => continue,
+            };
+            for value in values {
+                let writers: Vec<&Write> = self
+                    .writes_of(&read.cell)
+                    .filter(|w| match &w.effect {
+                        Effect::Add(e) | Effect::Assign(e) => e == value,
+                        _ => false,
+                    })
+                    .collect();
+                if writers.iter().any(|w| w.txn == read.txn && w.op < read.op) {
+                    continue;
+                }
+                let others: BTreeSet<usize> = writers
+                    .iter()
+                    .map(|w| w.txn)
+                    .filter(|&txn| txn != read.txn)
+                    .collect();
+                let visible: Vec<usize> = others
+                    .iter()
+                    .cloned()
+                    .filter(|&txn| self.visible(txn))
+                    .collect();
+                if visible.len() == 1 {
+                    self.observations.push(Observation {
+                        writer: visible[0],
+                        reader: read.txn,
+                        read: r,
+                        value: value.clone(),
+                    });
+                } else if visible.is_empty() {
+                    if let Some(&writer) = others.iter().next() {
+                        let detail = format!(
+                            "reads {} written by an aborted transaction",
+                            Value::bytes(value)
+                        );
+                        let anomaly =
+                            self.anomaly(AnomalyKind::AbortedRead, read, Some(writer), detail);
+                        self.aborted_reads.push(anomaly);
+                    }
+                }
+            }
+        }
+    }
+
+    // the transactions each one depends on, through its session and the
+    // transactions it observed
+    fn causal_pasts(&self) -> Vec<Vec<u64>> {
+        let n = self.transactions.len();
+        let mut preds: Vec<Vec<usize>> = vec![Vec::new(); n];
+        for (txn, pred) in self.session_pred.iter().enumerate() {
+            preds[txn].extend(*pred);
+        }
+        for o in &self.observations {
+            preds[o.reader].push(o.writer);
+        }
+
+        let words = n.div_ceil(64);
+        let mut pasts = Vec::with_capacity(n);
+        let mut stack = Vec::new();
+        for txn in 0..n {
+            let mut past = vec![0u64; words];
+            stack.extend(preds[txn].iter().cloned());
+            while let Some(u) = stack.pop() {
+                if past[u / 64] & (1 << (u % 64)) == 0 {
+                    past[u / 64] |= 1 << (u % 64);
+                    stack.extend(preds[u].iter().cloned());
+                }
+            }
+            pasts.push(past);
+        }
+        pasts
+    }
+
+    // how the update of writer can be missing from a read of reader
+    fn kind(&self, reader: usize, writer: usize) -> AnomalyKind {
+        let (r, w) = (&self.transactions[reader], &self.transactions[writer]);
+        if reader == writer || r.session == w.session {
+            AnomalyKind::LostUpdate
+        } else if self.observed(reader).any(|txn| txn == writer) {
+            AnomalyKind::FracturedRead
+        } else {
+            AnomalyKind::CausalityViolation
+        }
+    }
+
+    pub(crate) fn anomalies(&self) -> Vec<Found> {
+        let mut anomalies = self.aborted_reads.clone();
+        for read in &self.reads {
+            self.check_read(read, &mut anomalies);
+        }
+        anomalies
+    }
+
+    pub(crate) fn anomaly_of(&self, found: &Found) -> Anomaly {
+        Anomaly {
+            kind: found.kind,
+            txn: self.transactions[found.reader].id,
+            writer: found.writer.map(|w| self.transactions[w].id),
+            object: found.cell.bound_object(),
+            path: found
+                .cell
+                .path
+                .iter()
+                .filter_map(|(key, type_number)| {
+                    Some((key.clone(), CRDT_type::from_i32(*type_number)?))
+                })
+                .collect(),
+            detail: found.detail.clone(),
+        }
+    }
+
+    fn anomaly(
+        &self,
+        kind: AnomalyKind,
+        read: &Read,
+        writer: Option<usize>,
+        detail: String,
+    ) -> Found {
+        Found {
+            kind,
+            reader: read.txn,
+            writer,
+            cell: read.cell.clone(),
+            detail,
+        }
+    }
+
+    fn missing(&self, read: &Read, writer: usize, detail: String) -> Found {
+        self.anomaly(self.kind(read.txn, writer), read, Some(writer), detail)
+    }
+
+    // whether the cell or a map it is in was reset by a transaction that may
+    // have committed
+    pub(crate) fn was_reset(&self, cell: &Cell) -> bool {
+        (0..=cell.path.len()).any(|len| {
+            let outer = Cell {
+                path: cell.path[..len].to_vec(),
+                ..cell.clone()
+            };
+            self.writes_of(&outer)
+                .any(|w| w.effect == Effect::Reset && self.visible(w.txn))
+        })
+    }
+
+    // writes of the cell of read it must see, and the ones it may see
+    pub(crate) fn split(&self, read: &Read) -> (Vec<&Write>, Vec<&Write>) {
+        let mut past = Vec::new();
+        let mut concurrent = Vec::new();
+        for w in self.writes_of(&read.cell) {
+            if w.txn == read.txn {
+                if w.op < read.op {
+                    past.push(w);
+                }
+            } else if self.visible(w.txn) {
+                if self.in_past(read.txn, w.txn) {
+                    past.push(w);
+                } else {
+                    concurrent.push(w);
+                }
+            }
+        }
+        (past, concurrent)
+    }
+
+    fn check_read(&self, read: &Read, anomalies: &mut Vec<Found>) {
+        if self.was_reset(&read.cell) {
+            return;
+        }
+        let (past, concurrent) = self.split(read);
+        match (read.cell.crdt_type(), &read.value) {
+            (Some(crdt_type), Value::Set(values)) => {
+                let present: BTreeSet<&Vec<u8>> = values.iter().collect();
+                let remove_wins = crdt_type == CRDT_type::RWSET;
+                self.check_set(read, &past, &concurrent, &present, remove_wins, anomalies);
+            }
+            (Some(CRDT_type::FLAG_EW), Value::Flag(enabled)) => {
+                let as_set = |w: &&Write| {
+                    let effect = match w.effect {
+                        Effect::Flag(true) => Effect::Add(Vec::new()),
+                        _ => Effect::Remove(Vec::new()),
+                    };
+                    Write {
+                        effect,
+                        ..(*w).clone()
+                    }
+                };
+                let past: Vec<Write> = past.iter().map(as_set).collect();
+                let concurrent: Vec<Write> = concurrent.iter().map(as_set).collect();
+                let present = if *enabled {
+                    vec![Vec::new()]
+                } else {
+                    Vec::new()
+                };
+                self.check_set(
+                    read,
+                    &past.iter().collect::<Vec<_>>(),
+                    &concurrent.iter().collect::<Vec<_>>(),
+                    &present.iter().collect(),
+                    false,
+                    anomalies,
+                );
+            }
+            (_, Value::Register(value)) => {
+                let values = if value.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![value]
+                };
+                self.check_register(read, &past, &concurrent, &values, anomalies);
+            }
+            (_, Value::MultiRegister(values)) => {
+                let values: Vec<&Vec<u8>> = values.iter().collect();
+                self.check_register(read, &past, &concurrent, &values, anomalies);
+            }
+            (_, Value::Counter(value)) => {
+                let sum = |writes: &[&Write], keep: fn(i64) -> bool| -> i64 {
+                    writes
+                        .iter()
+                        .filter_map(|w| match w.effect {
+                            Effect::Inc(n) if keep(n) => Some(n),
+                            _ => None,
+                        })
+                        .sum()
+                };
+                let known = sum(&past, |_| true);
+                let lowest = known + sum(&concurrent, |n| n < 0);
+                let highest = known + sum(&concurrent, |n| n > 0);
+                let detail = format!("reads {}, expected {} to {}", value, lowest, highest);
+                if *value < lowest {
+                    anomalies.push(self.anomaly(AnomalyKind::LostUpdate, read, None, detail));
+                } else if *value > highest {
+                    anomalies.push(self.anomaly(AnomalyKind::AbortedRead, read, None, detail));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_set(
+        &self,
+        read: &Read,
+        past: &[&Write],
+        concurrent: &[&Write],
+        present: &BTreeSet<&Vec<u8>>,
+        remove_wins: bool,
+        anomalies: &mut Vec<Found>,
+    ) {
+        for add in past {
+            let element = match &add.effect {
+                Effect::Add(element) if !present.contains(element) => element,
+                _ => continue,
+            };
+            let removed = past.iter().chain(concurrent).any(|w| match &w.effect {
+                Effect::Remove(e) if e == element => {
+                    remove_wins
+                        || (w.txn == add.txn && w.op > add.op)
+                        || (w.txn != add.txn && self.in_past(w.txn, add.txn))
+                }
+                _ => false,
+            });
+            if !removed {
+                let detail = if read.cell.crdt_type() == Some(CRDT_type::FLAG_EW) {
+                    "is not enabled".to_string()
+                } else {
+                    format!("{} is missing", Value::bytes(element))
+                };
+                anomalies.push(self.missing(read, add.txn, detail));
+            }
+        }
+    }
+
+    fn check_register(
+        &self,
+        read: &Read,
+        past: &[&Write],
+        concurrent: &[&Write],
+        values: &[&Vec<u8>],
+        anomalies: &mut Vec<Found>,
+    ) {
+        let assigns: Vec<&Write> = past
+            .iter()
+            .cloned()
+            .filter(|w| matches!(w.effect, Effect::Assign(_)))
+            .collect();
+        // whether w' overwrote w
+        let overwrites = |later: &Write, w: &Write| {
+            if later.txn == w.txn {
+                later.op > w.op
+            } else {
+                self.in_past(later.txn, w.txn)
+            }
+        };
+        let latest = assigns
+            .iter()
+            .find(|w| !assigns.iter().any(|later| overwrites(later, w)));
+        let latest = match latest {
+            Some(latest) => latest,
+            None => return,
+        };
+        if values.is_empty() {
+            anomalies.push(self.missing(read, latest.txn, "reads no value".to_string()));
+        }
+        for value in values {
+            let writers: Vec<&Write> = past
+                .iter()
+                .chain(concurrent)
+                .cloned()
+                .filter(|w| w.effect == Effect::Assign(value.to_vec()))
+                .collect();
+            let current = writers
+                .iter()
+                .any(|w| !assigns.iter().any(|later| overwrites(later, w)));
+            if !current {
+                let overwriter = writers
+                    .iter()
+                    .find_map(|w| assigns.iter().find(|later| overwrites(later, w)))
+                    .unwrap_or(latest);
+                let detail = format!(
+                    "reads {}, overwritten in its causal past",
+                    Value::bytes(value)
+                );
+                anomalies.push(self.missing(read, overwriter.txn, detail));
+            }
+        }
+    }
+}
+
+impl Cell {
+    fn object(object: &antidote::ApbBoundObject) -> Self {
+        Cell {
+            bucket: object.get_bucket().to_vec(),
+            key: object.get_key().to_vec(),
+            root: object.type_number(),
+            path: Vec::new(),
+        }
+    }
+
+    fn entry(&self, key: &[u8], type_number: i32) -> Self {
+        let mut entry = self.clone();
+        entry.path.push((key.to_vec(), type_number));
+        entry
+    }
+
+    pub(crate) fn crdt_type(&self) -> Option<CRDT_type> {
+        let type_number = self.path.last().map_or(self.root, |(_, t)| *t);
+        CRDT_type::from_i32(type_number)
+    }
+
+    // the object and entry in the style of Anomaly's Display
+    pub(crate) fn name(&self) -> String {
+        let mut name = ops::print_object(&self.bound_object());
+        for (key, type_number) in &self.path {
+            let crdt_type = CRDT_type::from_i32(*type_number).map_or("?", type_name);
+            name.push_str(&format!(" > {}/{}", Value::bytes(key), crdt_type));
+        }
+        name
+    }
+
+    pub(crate) fn bound_object(&self) -> antidote::ApbBoundObject {
+        let mut object = antidote::ApbBoundObject::new();
+        object.set_bucket(self.bucket.clone());
+        object.set_key(self.key.clone());
+        object.set_field_type(CRDT_type::from_i32(self.root).unwrap_or(CRDT_type::RRMAP));
+        object
+    }
+}
+
+fn flatten_operation(
+    writes: &mut Vec<Write>,
+    txn: usize,
+    op: usize,
+    cell: Cell,
+    operation: &antidote::ApbUpdateOperation,
+) {
+    let mut push = |cell: &Cell, effect| {
+        writes.push(Write {
+            txn,
+            op,
+            cell: cell.clone(),
+            effect,
+        })
+    };
+    if operation.has_resetop() {
+        push(&cell, Effect::Reset);
+    }
+    if operation.has_counterop() {
+        let counterop = operation.get_counterop();
+        let n = if counterop.has_inc() {
+            counterop.get_inc()
+        } else {
+            1
+        };
+        push(&cell, Effect::Inc(n));
+    }
+    if operation.has_regop() {
+        push(
+            &cell,
+            Effect::Assign(operation.get_regop().get_value().to_vec()),
+        );
+    }
+    if operation.has_setop() {
+        let setop = operation.get_setop();
+        for element in codec::to_vecs(setop.get_adds()) {
+            push(&cell, Effect::Add(element));
+        }
+        for element in codec::to_vecs(setop.get_rems()) {
+            push(&cell, Effect::Remove(element));
+        }
+    }
+    if operation.has_flagop() {
+        push(&cell, Effect::Flag(operation.get_flagop().get_value()));
+    }
+    if operation.has_mapop() {
+        let mapop = operation.get_mapop();
+        for key in mapop.get_removedKeys() {
+            let entry = cell.entry(key.get_key(), key.type_number());
+            push(&entry, Effect::Reset);
+        }
+        for update in mapop.get_updates() {
+            let key = update.get_key();
+            let entry = cell.entry(key.get_key(), key.type_number());
+            flatten_operation(writes, txn, op, entry, update.get_update());
+        }
+    }
+}
+
+// the value of the map entry at path, None if the map does not hold it
+fn entry_value<'v>(value: &'v Value, path: &[(Vec<u8>, i32)]) -> Option<&'v Value> {
+    match path.split_first() {
+        None => Some(value),
+        Some(((key, type_number), rest)) => match value {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(k, t, _)| k == key && t == type_number)
+                .and_then(|(_, _, value)| entry_value(value, rest)),
+            _ => None,
+        },
+    }
+}
+
+fn empty(crdt_type: CRDT_type) -> Value {
+    match crdt_type {
+        CRDT_type::COUNTER | CRDT_type::FATCOUNTER | CRDT_type::BCOUNTER => Value::Counter(0),
+        CRDT_type::LWWREG => Value::Register(Vec::new()),
+        CRDT_type::MVREG => Value::MultiRegister(Vec::new()),
+        CRDT_type::ORSET | CRDT_type::RWSET => Value::Set(Vec::new()),
+        CRDT_type::FLAG_EW | CRDT_type::FLAG_DW => Value::Flag(false),
+        CRDT_type::GMAP | CRDT_type::RRMAP => Value::Map(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a history of transactions given as session, outcome and ops
+    fn history(transactions: &[(u64, &str, &str)]) -> History {
+        let mut text = String::from("{\"format\":\"antidote-history\",\"version\":2}\n");
+        for (i, (session, outcome, ops)) in transactions.iter().enumerate() {
+            text.push_str(&format!(
+                "{{\"txn\":{},\"session\":{},\"static\":false,\"snapshot\":null,\
+                 \"commit_time\":null,\"outcome\":\"{}\",\"ops\":[{}]}}\n",
+                i + 1,
+                session,
+                outcome,
+                ops
+            ));
+        }
+        History::read(text.as_bytes()).unwrap()
+    }
+
+    fn kinds(history: &History) -> Vec<(AnomalyKind, u64, Option<u64>)> {
+        check(history)
+            .into_iter()
+            .map(|a| (a.kind, a.txn, a.writer))
+            .collect()
+    }
+
+    #[test]
+    fn consistent_histories_have_no_anomalies() {
+        let history = history(&[
+            (
+                0,
+                "committed",
+                r#"{"update":"orset h/s add {x}"},{"update":"counter h/c inc 2"}"#,
+            ),
+            (
+                0,
+                "committed",
+                r#"{"read":"orset h/s","value":["x"]},{"read":"counter h/c","value":2}"#,
+            ),
+            (
+                1,
+                "committed",
+                r#"{"read":"orset h/s","value":["x"]},{"update":"lwwreg h/r set v"}"#,
+            ),
+            (
+                2,
+                "committed",
+                r#"{"read":"lwwreg h/r","value":"v"},{"read":"orset h/s","value":["x"]}"#,
+            ),
+            (3, "aborted", r#"{"update":"orset h/s add {y}"}"#),
+            (3, "committed", r#"{"read":"orset h/s","value":[]}"#),
+        ]);
+        assert_eq!(kinds(&history), vec![]);
+    }
+
+    #[test]
+    fn sessions_see_their_own_updates() {
+        let history = history(&[
+            (0, "committed", r#"{"update":"counter h/c inc 2"}"#),
+            (0, "committed", r#"{"read":"counter h/c","value":0}"#),
+        ]);
+        assert_eq!(kinds(&history), vec![(AnomalyKind::LostUpdate, 2, None)]);
+    }
+
+    #[test]
+    fn transactions_are_seen_whole() {
+        let history = history(&[
+            (
+                0,
+                "committed",
+                r#"{"update":"orset h/a add {x}"},{"update":"orset h/b add {x}"}"#,
+            ),
+            (
+                1,
+                "committed",
+                r#"{"read":"orset h/a","value":["x"]},{"read":"orset h/b","value":[]}"#,
+            ),
+        ]);
+        assert_eq!(
+            kinds(&history),
+            vec![(AnomalyKind::FracturedRead, 2, Some(1))]
+        );
+    }
+
+    #[test]
+    fn observed_transactions_bring_their_past() {
+        // txn 3 sees txn 2, which saw txn 1, but misses the element of txn 1
+        let history = history(&[
+            (0, "committed", r#"{"update":"orset h/a add {x}"}"#),
+            (
+                1,
+                "committed",
+                r#"{"read":"orset h/a","value":["x"]},{"update":"lwwreg h/r set v"}"#,
+            ),
+            (
+                2,
+                "committed",
+                r#"{"read":"lwwreg h/r","value":"v"},{"read":"orset h/a","value":[]}"#,
+            ),
+        ]);
+        assert_eq!(
+            kinds(&history),
+            vec![(AnomalyKind::CausalityViolation, 3, Some(1))]
+        );
+    }
+
+    #[test]
+    fn aborted_updates_are_not_seen() {
+        let history = history(&[
+            (0, "aborted", r#"{"update":"orset h/a add {x}"}"#),
+            (1, "committed", r#"{"read":"orset h/a","value":["x"]}"#),
+        ]);
+        assert_eq!(
+            kinds(&history),
+            vec![(AnomalyKind::AbortedRead, 2, Some(1))]
+        );
+    }
+}
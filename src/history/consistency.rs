@@ -0,0 +1,886 @@
+use super::super::crdt::CRDT_type;
+use super::super::value::Value;
+use super::check::{Analysis, AnomalyKind, Cell, Effect, Found, Read, Write};
+use super::History;
+
+use serde_json::json;
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+
+// Consistency levels of a history, decided from its dependency graph.
+//
+// The graph has an edge per dependency between transactions:
+//
+// - so, session order, from the last committed transaction of a session to
+//   the next one,
+// - wr, write-read, from a transaction to one that observed its update:
+//   read a set element or register value only it wrote, or a counter sum
+//   that holds every increment outside the causal past of the read,
+// - rw, read-write, from a transaction to one whose update it missed: a
+//   set element it does not hold and nobody removed, or an increment outside
+//   the causal past its counter sum holds none of.
+//
+// Counter edges are only drawn when those increments all have one sign, so
+// that a sum holding all or none of them cannot be made up another way.
+//
+// The causal order is the transitive closure of so and wr. Levels, from the
+// weakest:
+//
+// - read-committed: no aborted or intermediate reads, no cycle of wr edges,
+// - read-atomic: and no fractured reads, a transaction sees all the updates
+//   of the transactions it observed,
+// - causal: transactional causal consistency, the one of Antidote, no lost
+//   updates or causality violations as history::check finds them and no
+//   cycle in the causal order,
+// - serializable: no cycle of so, wr and rw edges. The updates of CRDTs
+//   commute, so there are no write-write edges.
+//
+// Each violation comes with a counterexample, the few transactions that make
+// it by id, and the keys involved. Report::counterexample gives the smallest
+// one of a level.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    ReadCommitted,
+    ReadAtomic,
+    Causal,
+    Serializable,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    SessionOrder,
+    WriteRead,
+    ReadWrite,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub from: u64,
+    pub to: u64,
+    pub kind: EdgeKind,
+    // the object the dependency is on, none for session order
+    pub key: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ViolationKind {
+    AbortedRead,
+    // a value its writer overwrote before committing
+    IntermediateRead,
+    CyclicInformationFlow,
+    FracturedRead,
+    LostUpdate,
+    CausalityViolation,
+    CausalCycle,
+    SerializationCycle,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    // in the order of the dependencies between them
+    pub txns: Vec<u64>,
+    pub keys: Vec<String>,
+    pub detail: String,
+}
+
+#[derive(Debug)]
+pub struct Graph {
+    pub edges: Vec<Edge>,
+    index: HashMap<u64, usize>,
+    // causal past of each transaction, as bitsets over transactions
+    past: Vec<Vec<u64>>,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub graph: Graph,
+    // from the weakest level they break
+    pub violations: Vec<Violation>,
+}
+
+impl Level {
+    pub const ALL: [Level; 4] = [
+        Level::ReadCommitted,
+        Level::ReadAtomic,
+        Level::Causal,
+        Level::Serializable,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::ReadCommitted => "read-committed",
+            Level::ReadAtomic => "read-atomic",
+            Level::Causal => "causal",
+            Level::Serializable => "serializable",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Level::ALL
+            .iter()
+            .cloned()
+            .find(|level| level.name() == name)
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl EdgeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeKind::SessionOrder => "so",
+            EdgeKind::WriteRead => "wr",
+            EdgeKind::ReadWrite => "rw",
+        }
+    }
+}
+
+impl ViolationKind {
+    // the weakest level the violation breaks
+    pub fn level(self) -> Level {
+        match self {
+            ViolationKind::AbortedRead
+            | ViolationKind::IntermediateRead
+            | ViolationKind::CyclicInformationFlow => Level::ReadCommitted,
+            ViolationKind::FracturedRead => Level::ReadAtomic,
+            ViolationKind::LostUpdate
+            | ViolationKind::CausalityViolation
+            | ViolationKind::CausalCycle => Level::Causal,
+            ViolationKind::SerializationCycle => Level::Serializable,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ViolationKind::AbortedRead => "aborted read",
+            ViolationKind::IntermediateRead => "intermediate read",
+            ViolationKind::CyclicInformationFlow => "cyclic information flow",
+            ViolationKind::FracturedRead => "fractured read",
+            ViolationKind::LostUpdate => "lost update",
+            ViolationKind::CausalityViolation => "causality violation",
+            ViolationKind::CausalCycle => "causal cycle",
+            ViolationKind::SerializationCycle => "serialization cycle",
+        }
+    }
+}
+
+impl Violation {
+    pub fn level(&self) -> Level {
+        self.kind.level()
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "kind": self.kind.name(),
+            "level": self.level().name(),
+            "txns": self.txns,
+            "keys": self.keys,
+            "detail": self.detail,
+        })
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let txns: Vec<String> = self.txns.iter().map(u64::to_string).collect();
+        write!(f, "{}: txns {}", self.kind.name(), txns.join(", "))?;
+        if !self.keys.is_empty() {
+            write!(f, " on {}", self.keys.join(", "))?;
+        }
+        write!(f, ": {}", self.detail)
+    }
+}
+
+impl Graph {
+    pub fn edges_of(&self, kind: EdgeKind) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |edge| edge.kind == kind)
+    }
+
+    // whether transaction a is in the causal past of transaction b
+    pub fn causally_precedes(&self, a: u64, b: u64) -> bool {
+        match (self.index.get(&a), self.index.get(&b)) {
+            (Some(&a), Some(&b)) => self.past[b][a / 64] & (1 << (a % 64)) != 0,
+            _ => false,
+        }
+    }
+}
+
+impl Report {
+    pub fn satisfies(&self, level: Level) -> bool {
+        self.violations.iter().all(|v| v.level() > level)
+    }
+
+    // the smallest violation of level
+    pub fn counterexample(&self, level: Level) -> Option<&Violation> {
+        self.violations
+            .iter()
+            .filter(|v| v.level() <= level)
+            .min_by_key(|v| (v.txns.len(), v.keys.len()))
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let levels: Vec<_> = Level::ALL
+            .iter()
+            .map(|&level| {
+                json!({
+                    "level": level.name(),
+                    "satisfied": self.satisfies(level),
+                    "counterexample": self.counterexample(level).map(Violation::to_json),
+                })
+            })
+            .collect();
+        let violations: Vec<_> = self.violations.iter().map(Violation::to_json).collect();
+        json!({ "levels": levels, "violations": violations })
+    }
+}
+
+// an edge between transactions by index
+#[derive(Clone, Debug)]
+struct Dependency {
+    from: usize,
+    to: usize,
+    kind: EdgeKind,
+    cell: Option<Cell>,
+}
+
+pub fn check(history: &History) -> Report {
+    let analysis = Analysis::new(history);
+    let transactions = analysis.transactions;
+    let id = |txn: usize| transactions[txn].id;
+
+    let dependencies = dependencies(&analysis);
+    let past = causal_pasts(transactions.len(), &dependencies);
+    let mut violations = Vec::new();
+
+    for found in analysis.anomalies() {
+        violations.push(violation(&analysis, &dependencies, &found));
+    }
+    violations.extend(intermediate_reads(&analysis));
+
+    let cycles: [&[EdgeKind]; 3] = [
+        &[EdgeKind::WriteRead],
+        &[EdgeKind::SessionOrder, EdgeKind::WriteRead],
+        &[
+            EdgeKind::SessionOrder,
+            EdgeKind::WriteRead,
+            EdgeKind::ReadWrite,
+        ],
+    ];
+    for kinds in &cycles {
+        if let Some(cycle) = shortest_cycle(transactions.len(), &dependencies, kinds) {
+            let cycle: Vec<&Dependency> = cycle.iter().map(|&e| &dependencies[e]).collect();
+            let kind = if cycle.iter().any(|d| d.kind == EdgeKind::ReadWrite) {
+                ViolationKind::SerializationCycle
+            } else if cycle.iter().any(|d| d.kind == EdgeKind::SessionOrder) {
+                ViolationKind::CausalCycle
+            } else {
+                ViolationKind::CyclicInformationFlow
+            };
+            let mut detail = id(cycle[0].from).to_string();
+            for d in &cycle {
+                detail.push_str(&format!(" -{}-> {}", d.kind.name(), id(d.to)));
+            }
+            violations.push(Violation {
+                kind,
+                txns: cycle.iter().map(|d| id(d.from)).collect(),
+                keys: keys(cycle.iter().filter_map(|d| d.cell.as_ref())),
+                detail,
+            });
+        }
+    }
+
+    violations.sort_by_key(|v| (v.kind, v.txns.clone()));
+    violations.dedup_by(|a, b| a.kind == b.kind && a.txns == b.txns && a.keys == b.keys);
+
+    let graph = Graph {
+        edges: dependencies
+            .iter()
+            .map(|d| Edge {
+                from: id(d.from),
+                to: id(d.to),
+                kind: d.kind,
+                key: d.cell.as_ref().map(Cell::name),
+            })
+            .collect(),
+        index: transactions
+            .iter()
+            .enumerate()
+            .map(|(txn, t)| (t.id, txn))
+            .collect(),
+        past,
+    };
+    Report { graph, violations }
+}
+
+fn dependencies(analysis: &Analysis) -> Vec<Dependency> {
+    let mut dependencies = Vec::new();
+    let mut seen = HashSet::new();
+    let mut push = |from: usize, to: usize, kind: EdgeKind, cell: Option<&Cell>| {
+        if seen.insert((from, to, kind)) {
+            dependencies.push(Dependency {
+                from,
+                to,
+                kind,
+                cell: cell.cloned(),
+            });
+        }
+    };
+
+    for (txn, pred) in analysis.session_pred.iter().enumerate() {
+        if let Some(pred) = *pred {
+            push(pred, txn, EdgeKind::SessionOrder, None);
+        }
+    }
+    for o in &analysis.observations {
+        let cell = &analysis.reads[o.read].cell;
+        push(o.writer, o.reader, EdgeKind::WriteRead, Some(cell));
+    }
+
+    for read in &analysis.reads {
+        if analysis.was_reset(&read.cell) {
+            continue;
+        }
+        let (past, concurrent) = analysis.split(read);
+        match (read.cell.crdt_type(), &read.value) {
+            (_, Value::Counter(value)) => {
+                let increments: Vec<(usize, i64)> = concurrent
+                    .iter()
+                    .filter_map(|w| match w.effect {
+                        Effect::Inc(n) if n != 0 => Some((w.txn, n)),
+                        _ => None,
+                    })
+                    .collect();
+                // with increments of one sign, only none or all of them
+                // give the lowest or highest sum
+                let one_sign = increments.iter().all(|&(_, n)| n > 0)
+                    || increments.iter().all(|&(_, n)| n < 0);
+                if increments.is_empty() || !one_sign {
+                    continue;
+                }
+                let known: i64 = past
+                    .iter()
+                    .filter_map(|w| match w.effect {
+                        Effect::Inc(n) => Some(n),
+                        _ => None,
+                    })
+                    .sum();
+                let all: i64 = increments.iter().map(|&(_, n)| n).sum();
+                for &(txn, _) in &increments {
+                    if *value == known {
+                        push(read.txn, txn, EdgeKind::ReadWrite, Some(&read.cell));
+                    } else if *value == known + all {
+                        push(txn, read.txn, EdgeKind::WriteRead, Some(&read.cell));
+                    }
+                }
+            }
+            (Some(CRDT_type::ORSET), Value::Set(values))
+            | (Some(CRDT_type::RWSET), Value::Set(values)) => {
+                for w in &concurrent {
+                    let element = match &w.effect {
+                        Effect::Add(element) if !values.contains(element) => element,
+                        _ => continue,
+                    };
+                    let removed = past
+                        .iter()
+                        .chain(&concurrent)
+                        .any(|r| r.effect == Effect::Remove(element.clone()));
+                    if !removed {
+                        push(read.txn, w.txn, EdgeKind::ReadWrite, Some(&read.cell));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    dependencies
+}
+
+fn causal_pasts(n: usize, dependencies: &[Dependency]) -> Vec<Vec<u64>> {
+    let mut preds: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for d in dependencies {
+        if d.kind != EdgeKind::ReadWrite {
+            preds[d.to].push(d.from);
+        }
+    }
+    let words = n.div_ceil(64);
+    let mut pasts = Vec::with_capacity(n);
+    let mut stack = Vec::new();
+    for txn in 0..n {
+        let mut past = vec![0u64; words];
+        stack.extend(preds[txn].iter().cloned());
+        while let Some(u) = stack.pop() {
+            if past[u / 64] & (1 << (u % 64)) == 0 {
+                past[u / 64] |= 1 << (u % 64);
+                stack.extend(preds[u].iter().cloned());
+            }
+        }
+        pasts.push(past);
+    }
+    pasts
+}
+
+fn violation(analysis: &Analysis, dependencies: &[Dependency], found: &Found) -> Violation {
+    let id = |txn: usize| analysis.transactions[txn].id;
+    let kind = match found.kind {
+        AnomalyKind::AbortedRead => ViolationKind::AbortedRead,
+        AnomalyKind::FracturedRead => ViolationKind::FracturedRead,
+        AnomalyKind::LostUpdate => ViolationKind::LostUpdate,
+        AnomalyKind::CausalityViolation => ViolationKind::CausalityViolation,
+    };
+    let mut txns = Vec::new();
+    let mut cells = vec![&found.cell];
+    match (found.kind, found.writer) {
+        (AnomalyKind::AbortedRead, writer) => {
+            txns.extend(writer);
+            txns.push(found.reader);
+        }
+        // the update of writer is missing, the path to the reader is why it
+        // should not be
+        (_, Some(writer)) if writer != found.reader => {
+            let kinds = [EdgeKind::SessionOrder, EdgeKind::WriteRead];
+            let n = analysis.transactions.len();
+            match shortest_path(n, dependencies, &kinds, writer, found.reader) {
+                Some(path) => {
+                    txns.push(writer);
+                    for &e in &path {
+                        txns.push(dependencies[e].to);
+                        cells.extend(dependencies[e].cell.as_ref());
+                    }
+                }
+                None => txns.extend([writer, found.reader]),
+            }
+        }
+        (_, Some(_)) => txns.push(found.reader),
+        // a counter sum, missing some of the increments of its causal past
+        (_, None) => {
+            let writers: BTreeSet<usize> = analysis
+                .writes_of(&found.cell)
+                .filter(|w| matches!(w.effect, Effect::Inc(_)))
+                .map(|w| w.txn)
+                .filter(|&txn| txn != found.reader && analysis.in_past(found.reader, txn))
+                .collect();
+            txns.extend(writers);
+            txns.push(found.reader);
+        }
+    }
+    Violation {
+        kind,
+        txns: txns.into_iter().map(id).collect(),
+        keys: keys(cells.into_iter()),
+        detail: found.detail.clone(),
+    }
+}
+
+// reads of a value its writer changed later in the same transaction
+fn intermediate_reads(analysis: &Analysis) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for o in &analysis.observations {
+        let read: &Read = &analysis.reads[o.read];
+        let writes: Vec<&Write> = analysis
+            .writes_of(&read.cell)
+            .filter(|w| w.txn == o.writer)
+            .collect();
+        let first = writes.iter().position(|w| match &w.effect {
+            Effect::Add(value) | Effect::Assign(value) => *value == o.value,
+            _ => false,
+        });
+        let overwritten = first.is_some_and(|first| {
+            writes[first + 1..].iter().any(|w| match &w.effect {
+                Effect::Assign(value) => *value != o.value,
+                Effect::Remove(value) => *value == o.value,
+                _ => false,
+            })
+        });
+        if overwritten {
+            violations.push(Violation {
+                kind: ViolationKind::IntermediateRead,
+                txns: vec![
+                    analysis.transactions[o.writer].id,
+                    analysis.transactions[o.reader].id,
+                ],
+                keys: vec![read.cell.name()],
+                detail: format!(
+                    "reads {}, which its writer changed before committing",
+                    Value::bytes(&o.value)
+                ),
+            });
+        }
+    }
+    violations
+}
+
+fn keys<'c>(cells: impl Iterator<Item = &'c Cell>) -> Vec<String> {
+    let mut keys = Vec::new();
+    for name in cells.map(Cell::name) {
+        if !keys.contains(&name) {
+            keys.push(name);
+        }
+    }
+    keys
+}
+
+// indices of the dependencies of the given kinds from each transaction
+fn adjacency(n: usize, dependencies: &[Dependency], kinds: &[EdgeKind]) -> Vec<Vec<usize>> {
+    let mut adjacency = vec![Vec::new(); n];
+    for (e, d) in dependencies.iter().enumerate() {
+        if kinds.contains(&d.kind) {
+            adjacency[d.from].push(e);
+        }
+    }
+    adjacency
+}
+
+fn shortest_path(
+    n: usize,
+    dependencies: &[Dependency],
+    kinds: &[EdgeKind],
+    from: usize,
+    to: usize,
+) -> Option<Vec<usize>> {
+    let adjacency = adjacency(n, dependencies, kinds);
+    bfs(dependencies, &adjacency, from, to, |_| true)
+}
+
+// the dependencies on a shortest path from `from` to `to`, over transactions
+// allowed by keep
+fn bfs(
+    dependencies: &[Dependency],
+    adjacency: &[Vec<usize>],
+    from: usize,
+    to: usize,
+    keep: impl Fn(usize) -> bool,
+) -> Option<Vec<usize>> {
+    let mut parent: HashMap<usize, usize> = HashMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(from);
+    while let Some(u) = queue.pop_front() {
+        for &e in &adjacency[u] {
+            let v = dependencies[e].to;
+            if v == to {
+                let mut path = vec![e];
+                let mut at = u;
+                while at != from {
+                    let e = parent[&at];
+                    path.push(e);
+                    at = dependencies[e].from;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if v != from && keep(v) && !parent.contains_key(&v) {
+                parent.insert(v, e);
+                queue.push_back(v);
+            }
+        }
+    }
+    None
+}
+
+// the dependencies of a shortest cycle, searched in each strongly connected
+// component of the graph
+fn shortest_cycle(n: usize, dependencies: &[Dependency], kinds: &[EdgeKind]) -> Option<Vec<usize>> {
+    let adjacency = adjacency(n, dependencies, kinds);
+    let successors: Vec<Vec<usize>> = adjacency
+        .iter()
+        .map(|edges| edges.iter().map(|&e| dependencies[e].to).collect())
+        .collect();
+    let component = components(&successors);
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for &c in &component {
+        *sizes.entry(c).or_default() += 1;
+    }
+
+    let mut best: Option<Vec<usize>> = None;
+    for u in 0..n {
+        if sizes[&component[u]] < 2 {
+            continue;
+        }
+        let cycle = bfs(dependencies, &adjacency, u, u, |v| {
+            component[v] == component[u]
+        });
+        if let Some(cycle) = cycle {
+            if best.as_ref().is_none_or(|best| cycle.len() < best.len()) {
+                best = Some(cycle);
+            }
+        }
+    }
+    best
+}
+
+// the strongly connected component of each node, named by one of its nodes
+fn components(successors: &[Vec<usize>]) -> Vec<usize> {
+    let n = successors.len();
+    // nodes by finishing time of a depth-first search
+    let mut order = Vec::with_capacity(n);
+    let mut visited = vec![false; n];
+    for start in 0..n {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut stack = vec![(start, 0)];
+        while let Some((u, i)) = stack.pop() {
+            if i < successors[u].len() {
+                stack.push((u, i + 1));
+                let v = successors[u][i];
+                if !visited[v] {
+                    visited[v] = true;
+                    stack.push((v, 0));
+                }
+            } else {
+                order.push(u);
+            }
+        }
+    }
+
+    let mut predecessors = vec![Vec::new(); n];
+    for (u, vs) in successors.iter().enumerate() {
+        for &v in vs {
+            predecessors[v].push(u);
+        }
+    }
+    let mut component = vec![usize::MAX; n];
+    for &root in order.iter().rev() {
+        if component[root] != usize::MAX {
+            continue;
+        }
+        component[root] = root;
+        let mut stack = vec![root];
+        while let Some(u) = stack.pop() {
+            for &v in &predecessors[u] {
+                if component[v] == usize::MAX {
+                    component[v] = root;
+                    stack.push(v);
+                }
+            }
+        }
+    }
+    component
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a history of transactions given as session and ops, committed unless
+    // their ops start with "aborted "
+    fn history(transactions: &[(u64, &str)]) -> History {
+        let mut text = String::from("{\"format\":\"antidote-history\",\"version\":2}\n");
+        for (i, (session, ops)) in transactions.iter().enumerate() {
+            let (outcome, ops) = match ops.strip_prefix("aborted ") {
+                Some(ops) => ("aborted", ops),
+                None => ("committed", *ops),
+            };
+            text.push_str(&format!(
+                "{{\"txn\":{},\"session\":{},\"static\":false,\"snapshot\":null,\
+                 \"commit_time\":null,\"outcome\":\"{}\",\"ops\":[{}]}}\n",
+                i + 1,
+                session,
+                outcome,
+                ops
+            ));
+        }
+        History::read(text.as_bytes()).unwrap()
+    }
+
+    // the kinds of the violations, and the weakest level they break
+    fn violations(history: &History) -> (Vec<ViolationKind>, Option<Level>) {
+        let report = check(history);
+        let kinds = report.violations.iter().map(|v| v.kind).collect();
+        let weakest = Level::ALL.iter().cloned().find(|&l| !report.satisfies(l));
+        (kinds, weakest)
+    }
+
+    #[test]
+    fn serial_histories_are_serializable() {
+        let history = history(&[
+            (
+                0,
+                r#"{"update":"orset h/s add {x}"},{"update":"counter h/c inc 2"}"#,
+            ),
+            (
+                0,
+                r#"{"read":"orset h/s","value":["x"]},{"read":"counter h/c","value":2}"#,
+            ),
+            (
+                1,
+                r#"{"read":"orset h/s","value":["x"]},{"update":"lwwreg h/r set v"}"#,
+            ),
+            (
+                2,
+                r#"{"read":"lwwreg h/r","value":"v"},{"read":"counter h/c","value":2}"#,
+            ),
+        ]);
+        assert_eq!(violations(&history), (vec![], None));
+    }
+
+    #[test]
+    fn aborted_reads() {
+        let history = history(&[
+            (0, r#"aborted {"update":"orset h/a add {x}"}"#),
+            (1, r#"{"read":"orset h/a","value":["x"]}"#),
+        ]);
+        assert_eq!(
+            violations(&history),
+            (vec![ViolationKind::AbortedRead], Some(Level::ReadCommitted))
+        );
+    }
+
+    #[test]
+    fn intermediate_reads() {
+        let history = history(&[
+            (
+                0,
+                r#"{"update":"lwwreg h/r set v"},{"update":"lwwreg h/r set w"}"#,
+            ),
+            (1, r#"{"read":"lwwreg h/r","value":"v"}"#),
+        ]);
+        let (kinds, weakest) = violations(&history);
+        assert!(kinds.contains(&ViolationKind::IntermediateRead));
+        assert_eq!(weakest, Some(Level::ReadCommitted));
+    }
+
+    #[test]
+    fn cyclic_information_flow() {
+        // each transaction reads what the other one wrote
+        let history = history(&[
+            (
+                0,
+                r#"{"update":"lwwreg h/a set x"},{"read":"lwwreg h/b","value":"y"}"#,
+            ),
+            (
+                1,
+                r#"{"update":"lwwreg h/b set y"},{"read":"lwwreg h/a","value":"x"}"#,
+            ),
+        ]);
+        let (kinds, weakest) = violations(&history);
+        assert!(kinds.contains(&ViolationKind::CyclicInformationFlow));
+        assert_eq!(weakest, Some(Level::ReadCommitted));
+    }
+
+    #[test]
+    fn fractured_reads() {
+        let history = history(&[
+            (
+                0,
+                r#"{"update":"orset h/a add {x}"},{"update":"orset h/b add {x}"}"#,
+            ),
+            (
+                1,
+                r#"{"read":"orset h/a","value":["x"]},{"read":"orset h/b","value":[]}"#,
+            ),
+        ]);
+        let (kinds, weakest) = violations(&history);
+        assert!(kinds.contains(&ViolationKind::FracturedRead));
+        assert_eq!(weakest, Some(Level::ReadAtomic));
+    }
+
+    #[test]
+    fn lost_updates() {
+        let history = history(&[
+            (0, r#"{"update":"counter h/c inc 2"}"#),
+            (0, r#"{"read":"counter h/c","value":0}"#),
+        ]);
+        let (kinds, weakest) = violations(&history);
+        assert!(kinds.contains(&ViolationKind::LostUpdate));
+        assert_eq!(weakest, Some(Level::Causal));
+    }
+
+    #[test]
+    fn causality_violations() {
+        // txn 3 sees txn 2, which saw txn 1, but misses the element of txn 1
+        let history = history(&[
+            (0, r#"{"update":"orset h/a add {x}"}"#),
+            (
+                1,
+                r#"{"read":"orset h/a","value":["x"]},{"update":"lwwreg h/r set v"}"#,
+            ),
+            (
+                2,
+                r#"{"read":"lwwreg h/r","value":"v"},{"read":"orset h/a","value":[]}"#,
+            ),
+        ]);
+        let (kinds, weakest) = violations(&history);
+        assert!(kinds.contains(&ViolationKind::CausalityViolation));
+        assert_eq!(weakest, Some(Level::Causal));
+    }
+
+    #[test]
+    fn causal_cycles() {
+        // txn 1 reads from txn 3, which read from txn 2, after txn 1 in its
+        // session
+        let history = history(&[
+            (0, r#"{"read":"lwwreg h/r","value":"v"}"#),
+            (0, r#"{"update":"lwwreg h/q set u"}"#),
+            (
+                1,
+                r#"{"read":"lwwreg h/q","value":"u"},{"update":"lwwreg h/r set v"}"#,
+            ),
+        ]);
+        let (kinds, weakest) = violations(&history);
+        assert!(kinds.contains(&ViolationKind::CausalCycle));
+        assert!(!kinds.contains(&ViolationKind::CyclicInformationFlow));
+        assert_eq!(weakest, Some(Level::Causal));
+    }
+
+    #[test]
+    fn write_skew_is_a_serialization_cycle() {
+        // each transaction misses the add of the other one
+        let history = history(&[
+            (
+                0,
+                r#"{"read":"orset h/a","value":[]},{"update":"orset h/b add {y}"}"#,
+            ),
+            (
+                1,
+                r#"{"read":"orset h/b","value":[]},{"update":"orset h/a add {x}"}"#,
+            ),
+        ]);
+        assert_eq!(
+            violations(&history),
+            (
+                vec![ViolationKind::SerializationCycle],
+                Some(Level::Serializable)
+            )
+        );
+    }
+
+    #[test]
+    fn counter_sums_of_all_or_none_of_the_increments_are_edges() {
+        let edges = |value: i64| {
+            let ops = format!(r#"{{"read":"counter h/c","value":{}}}"#, value);
+            let history = history(&[
+                (0, r#"{"update":"counter h/c inc 1"}"#),
+                (1, r#"{"update":"counter h/c inc 2"}"#),
+                (2, &ops),
+            ]);
+            let report = check(&history);
+            let mut edges: Vec<_> = report
+                .graph
+                .edges
+                .iter()
+                .map(|e| (e.from, e.to, e.kind))
+                .collect();
+            edges.sort_by_key(|&(from, to, _)| (from, to));
+            edges
+        };
+        assert_eq!(
+            edges(3),
+            vec![(1, 3, EdgeKind::WriteRead), (2, 3, EdgeKind::WriteRead)]
+        );
+        assert_eq!(
+            edges(0),
+            vec![(3, 1, EdgeKind::ReadWrite), (3, 2, EdgeKind::ReadWrite)]
+        );
+        // either increment alone, no telling which
+        assert_eq!(edges(1), vec![]);
+        assert_eq!(edges(2), vec![]);
+    }
+}
@@ -1,16 +1,51 @@
+#[cfg(all(feature = "protobuf", not(feature = "prost")))]
 extern crate protobuf;
 
+pub mod admin;
+pub mod batch;
+pub mod bucket;
+pub mod cluster;
 pub mod crdt;
+pub mod document;
+pub mod dynamic;
+pub mod history;
+pub mod ops;
+pub mod reconcile;
+pub mod record;
+pub mod resolve;
+pub mod schema;
+#[cfg(any(test, feature = "stand-in"))]
+pub mod server;
+pub mod session;
+pub mod sharded;
+pub mod value;
+pub mod view;
+
+// generated by src/build.rs
+#[allow(clippy::all, warnings)]
+mod antidote {
+    include!(concat!(env!("OUT_DIR"), "/antidote.rs"));
+}
+mod codec;
 
-mod antidote;
+// the protocol messages, for custom CRDT kinds, with the same accessors for
+// either backend
+pub mod proto {
+    pub use super::antidote::*;
+    pub use super::codec::{Bytes, ProtobufEnum, RepeatedField, TypedMessage};
+}
 
-use protobuf::{Message, ProtobufEnum, RepeatedField};
+use codec::{Encoder, Message, ProtobufEnum, RepeatedField};
 use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::Duration;
 
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
 
 use std::any::Any;
+use std::error;
+use std::fmt;
+use std::io;
 
 #[derive(Debug)]
 pub struct AntidoteDB {
@@ -22,36 +57,147 @@ pub struct Transaction {
     id: Vec<u8>,
 }
 
+#[derive(Debug)]
+pub enum Error {
+    // the connection failed, the node may be unreachable
+    Io(io::Error),
+    // the node answered with a message that does not fit the request
+    Protocol(String),
+    // the node sent an error response
+    Server { code: u32, message: String },
+    // the node could not execute the request
+    Rejected(ErrorCode),
+    // the desired value given to reconcile_in_transaction does not fit
+    Reconcile(reconcile::ReconcileError),
+    // the request does not follow the schema of a schema::Checked connection
+    Schema(schema::SchemaError),
+    // the operation does not fit the open transaction of a session::Session
+    Session(session::SessionError),
+    // a counter decrement by a value whose negation does not fit an i64
+    Overflow(i64),
+}
+
+// error codes of antidote's operation responses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    Unknown,
+    Timeout,
+    // e.g. a bounded counter decrement without enough rights
+    NoPermissions,
+    Aborted,
+    Other(u32),
+}
+
+impl From<u32> for ErrorCode {
+    fn from(code: u32) -> Self {
+        match code {
+            0 => ErrorCode::Unknown,
+            1 => ErrorCode::Timeout,
+            2 => ErrorCode::NoPermissions,
+            3 => ErrorCode::Aborted,
+            code => ErrorCode::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorCode::Unknown => f.write_str("unknown error"),
+            ErrorCode::Timeout => f.write_str("timeout"),
+            ErrorCode::NoPermissions => f.write_str("not enough rights for the operation"),
+            ErrorCode::Aborted => f.write_str("transaction aborted"),
+            ErrorCode::Other(code) => write!(f, "error code {}", code),
+        }
+    }
+}
+
+impl Error {
+    pub fn is_io(&self) -> bool {
+        matches!(self, Error::Io(_))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "connection error: {}", e),
+            Error::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            Error::Server { code, message } => write!(f, "server error {}: {}", code, message),
+            Error::Rejected(code) => write!(f, "request rejected: {}", code),
+            Error::Reconcile(e) => write!(f, "cannot reconcile: {}", e),
+            Error::Schema(e) => write!(f, "schema violation: {}", e),
+            Error::Session(e) => write!(f, "session error: {}", e),
+            Error::Overflow(val) => write!(
+                f,
+                "cannot decrement by {}, the increment would overflow",
+                val
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Reconcile(e) => Some(e),
+            Error::Schema(e) => Some(e),
+            Error::Session(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
 impl AntidoteDB {
-    pub fn connect(hostname: &str, port: u16) -> Self {
+    pub fn connect(hostname: &str, port: u16) -> Result<Self, Error> {
         Self::connect_with_string(&format!("{}:{}", hostname, port))
     }
 
-    pub fn connect_with_string(st: &str) -> Self {
-        AntidoteDB {
-            socket: TcpStream::connect(st).expect("error while creating tcp socket"),
-        }
+    pub fn connect_with_string(st: &str) -> Result<Self, Error> {
+        Ok(AntidoteDB {
+            socket: TcpStream::connect(st)?,
+        })
     }
 
-    pub fn start_transaction(&mut self, timestamp: Option<&Vec<u8>>) -> Transaction {
-        let mut transaction = antidote::ApbStartTransaction::new();
-        if let Some(v) = timestamp {
-            transaction.set_timestamp(v.clone());
+    // like connect_with_string, but gives up on each resolved address after timeout
+    pub fn connect_timeout(st: &str, timeout: Duration) -> Result<Self, Error> {
+        let mut last_error = None;
+        for addr in st.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(socket) => return Ok(AntidoteDB { socket }),
+                Err(e) => last_error = Some(e),
+            }
         }
-        transaction.set_properties(Default::default());
+        Err(Error::Io(last_error.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "address resolved to nothing")
+        })))
+    }
 
-        self.send_message(antidote::MessageCode::apbStartTransaction, transaction);
+    pub fn start_transaction(&mut self, timestamp: Option<&Vec<u8>>) -> Result<Transaction, Error> {
+        let transaction = AntidoteDB::new_transaction(timestamp);
 
-        let (code, message) = self.read_message();
-        let message = message
-            .downcast_ref::<antidote::ApbStartTransactionResp>()
-            .expect("Error to StartTransactionResp");
+        self.send_message(antidote::MessageCode::apbStartTransaction, transaction)?;
 
-        assert_eq!(code, antidote::MessageCode::apbStartTransactionResp);
-        assert!(message.get_success());
+        let message = self.read_response::<antidote::ApbStartTransactionResp>(
+            antidote::MessageCode::apbStartTransactionResp,
+        )?;
 
-        Transaction {
-            id: message.get_transaction_descriptor().to_vec(),
+        if message.get_success() {
+            Ok(Transaction {
+                id: message.get_transaction_descriptor().to_vec(),
+            })
+        } else {
+            Err(AntidoteDB::rejected(
+                message.has_errorcode(),
+                message.get_errorcode(),
+            ))
         }
     }
 
@@ -59,28 +205,33 @@ impl AntidoteDB {
         &mut self,
         objects: &[T],
         t: &Transaction,
-    ) -> Result<Vec<antidote::ApbReadObjectResp>, ()>
+    ) -> Result<Vec<antidote::ApbReadObjectResp>, Error>
     where
         T: crdt::Object,
     {
-        let mut read_objects = antidote::ApbReadObjects::new();
-        let apb_objects: Vec<_> = objects.iter().map(|x| x.get_object()).collect();
-        read_objects.set_boundobjects(RepeatedField::from_slice(&apb_objects));
-        read_objects.set_transaction_descriptor(t.id.clone());
-
-        self.send_message(antidote::MessageCode::apbReadObjects, read_objects);
-
-        let (code, message) = self.read_message();
-        let message = message
-            .downcast::<antidote::ApbReadObjectsResp>()
-            .expect("Error to ApbReadObjectsResp");
-
-        assert_eq!(code, antidote::MessageCode::apbReadObjectsResp);
+        // ApbReadObjects, encoded from the objects without copying them
+        write_frame_with(
+            &mut self.socket,
+            antidote::MessageCode::apbReadObjects,
+            |encoder| {
+                for object in objects {
+                    write_object(encoder, 1, object)?;
+                }
+                encoder.write_bytes(2, &t.id)
+            },
+        )?;
+
+        let mut message = self.read_response::<antidote::ApbReadObjectsResp>(
+            antidote::MessageCode::apbReadObjectsResp,
+        )?;
 
         if message.get_success() {
-            Ok(message.get_objects().to_vec())
+            Ok(message.take_objects().into_vec())
         } else {
-            Err(())
+            Err(AntidoteDB::rejected(
+                message.has_errorcode(),
+                message.get_errorcode(),
+            ))
         }
     }
 
@@ -88,159 +239,438 @@ impl AntidoteDB {
         &mut self,
         apb_operations: &[antidote::ApbUpdateOp],
         t: &Transaction,
-    ) -> Result<(), ()> {
-        let mut update_objects = antidote::ApbUpdateObjects::new();
-        update_objects.set_updates(RepeatedField::from_slice(apb_operations));
-        update_objects.set_transaction_descriptor(t.id.clone());
-
-        self.send_message(antidote::MessageCode::apbUpdateObjects, update_objects);
+    ) -> Result<(), Error> {
+        // ApbUpdateObjects, encoded from the operations without copying them
+        write_frame_with(
+            &mut self.socket,
+            antidote::MessageCode::apbUpdateObjects,
+            |encoder| {
+                for op in apb_operations {
+                    encoder.write_message(1, op)?;
+                }
+                encoder.write_bytes(2, &t.id)
+            },
+        )?;
+
+        self.read_operation_response()
+    }
 
-        let (code, message) = self.read_message();
-        let message = message
-            .downcast::<antidote::ApbOperationResp>()
-            .expect("error to downcast to ApbOperationResp");
+    pub(crate) fn read_operation_response(&mut self) -> Result<(), Error> {
+        let message = self
+            .read_response::<antidote::ApbOperationResp>(antidote::MessageCode::apbOperationResp)?;
 
-        assert_eq!(code, antidote::MessageCode::apbOperationResp);
         if message.get_success() {
             Ok(())
         } else {
-            Err(())
+            Err(AntidoteDB::rejected(
+                message.has_errorcode(),
+                message.get_errorcode(),
+            ))
         }
     }
 
-    pub fn commit_transaction(&mut self, t: &Transaction) -> Result<Vec<u8>, ()> {
+    pub fn commit_transaction(&mut self, t: &Transaction) -> Result<Vec<u8>, Error> {
         let mut commit = antidote::ApbCommitTransaction::new();
         commit.set_transaction_descriptor(t.id.clone());
 
-        self.send_message(antidote::MessageCode::apbCommitTransaction, commit);
+        self.send_message(antidote::MessageCode::apbCommitTransaction, commit)?;
 
-        let (_, message) = self.read_message();
-        let message = message
-            .downcast_ref::<antidote::ApbCommitResp>()
-            .expect("error to downcast ApbCommitResp");
+        let mut message =
+            self.read_response::<antidote::ApbCommitResp>(antidote::MessageCode::apbCommitResp)?;
 
-        Ok(message.get_commit_time().to_vec())
+        if message.get_success() {
+            Ok(codec::into_vec(message.take_commit_time()))
+        } else {
+            Err(AntidoteDB::rejected(
+                message.has_errorcode(),
+                message.get_errorcode(),
+            ))
+        }
     }
 
-    pub fn abort_transaction(&mut self, t: &Transaction) -> Result<(), ()> {
+    pub fn abort_transaction(&mut self, t: &Transaction) -> Result<(), Error> {
         let mut commit = antidote::ApbAbortTransaction::new();
         commit.set_transaction_descriptor(t.id.clone());
 
-        self.send_message(antidote::MessageCode::apbAbortTransaction, commit);
+        self.send_message(antidote::MessageCode::apbAbortTransaction, commit)?;
 
-        let (_, message) = self.read_message();
-        let message = message
-            .downcast_ref::<antidote::ApbOperationResp>()
-            .expect("error to downcast ApbOperationResp");
+        let message = self
+            .read_response::<antidote::ApbOperationResp>(antidote::MessageCode::apbOperationResp)?;
 
         if message.get_success() {
             Ok(())
         } else {
-            Err(())
+            Err(AntidoteDB::rejected(
+                message.has_errorcode(),
+                message.get_errorcode(),
+            ))
+        }
+    }
+
+    pub fn static_update_objects(
+        &mut self,
+        apb_operations: &[antidote::ApbUpdateOp],
+        timestamp: Option<&Vec<u8>>,
+    ) -> Result<Vec<u8>, Error> {
+        // ApbStaticUpdateObjects
+        let transaction = AntidoteDB::new_transaction(timestamp);
+        write_frame_with(
+            &mut self.socket,
+            antidote::MessageCode::apbStaticUpdateObjects,
+            |encoder| {
+                encoder.write_message(1, &transaction)?;
+                for op in apb_operations {
+                    encoder.write_message(2, op)?;
+                }
+                Ok(())
+            },
+        )?;
+
+        self.read_commit_response()
+    }
+
+    pub(crate) fn read_commit_response(&mut self) -> Result<Vec<u8>, Error> {
+        let mut message =
+            self.read_response::<antidote::ApbCommitResp>(antidote::MessageCode::apbCommitResp)?;
+
+        if message.get_success() {
+            Ok(codec::into_vec(message.take_commit_time()))
+        } else {
+            Err(AntidoteDB::rejected(
+                message.has_errorcode(),
+                message.get_errorcode(),
+            ))
         }
     }
 
-    fn send_message<T>(&mut self, code: antidote::MessageCode, message: T)
+    // returns the read values along with the snapshot time they were read at
+    pub fn static_read_objects<T>(
+        &mut self,
+        objects: &[T],
+        timestamp: Option<&Vec<u8>>,
+    ) -> Result<(Vec<antidote::ApbReadObjectResp>, Vec<u8>), Error>
     where
-        T: Message,
+        T: crdt::Object,
     {
-        let mut buffer = Vec::with_capacity(5);
+        // ApbStaticReadObjects
+        let transaction = AntidoteDB::new_transaction(timestamp);
+        write_frame_with(
+            &mut self.socket,
+            antidote::MessageCode::apbStaticReadObjects,
+            |encoder| {
+                encoder.write_message(1, &transaction)?;
+                for object in objects {
+                    write_object(encoder, 2, object)?;
+                }
+                Ok(())
+            },
+        )?;
+
+        self.read_static_read_response()
+    }
 
-        buffer
-            .write_i32::<BigEndian>(message.compute_size() as i32 + 1)
-            .unwrap();
+    pub(crate) fn static_read_bound_objects(
+        &mut self,
+        apb_objects: Vec<antidote::ApbBoundObject>,
+        timestamp: Option<&Vec<u8>>,
+    ) -> Result<(Vec<antidote::ApbReadObjectResp>, Vec<u8>), Error> {
+        let mut read_objects = antidote::ApbStaticReadObjects::new();
+        read_objects.set_transaction(AntidoteDB::new_transaction(timestamp));
+        read_objects.set_objects(RepeatedField::from_vec(apb_objects));
 
-        buffer.write_u8(code as u8).expect("couldn't write");
+        self.send_message(antidote::MessageCode::apbStaticReadObjects, read_objects)?;
 
-        buffer.extend(message.write_to_bytes().unwrap().drain(..));
+        self.read_static_read_response()
+    }
 
-        self.socket.write_all(&buffer).expect("couldn't write all");
+    fn read_static_read_response(
+        &mut self,
+    ) -> Result<(Vec<antidote::ApbReadObjectResp>, Vec<u8>), Error> {
+        let mut message = self.read_response::<antidote::ApbStaticReadObjectsResp>(
+            antidote::MessageCode::apbStaticReadObjectsResp,
+        )?;
+
+        let mut objects = message.take_objects();
+        let mut committime = message.take_committime();
+        if !objects.get_success() {
+            Err(AntidoteDB::rejected(
+                objects.has_errorcode(),
+                objects.get_errorcode(),
+            ))
+        } else if !committime.get_success() {
+            Err(AntidoteDB::rejected(
+                committime.has_errorcode(),
+                committime.get_errorcode(),
+            ))
+        } else {
+            Ok((
+                objects.take_objects().into_vec(),
+                codec::into_vec(committime.take_commit_time()),
+            ))
+        }
     }
 
-    fn read_message(&mut self) -> (antidote::MessageCode, Box<Any>) {
-        let msg_leng = self
-            .socket
-            .read_i32::<BigEndian>()
-            .expect("error to read message length");
-        let msg_code = antidote::MessageCode::from_i32(i32::from(
-            self.socket.read_u8().expect("error to read message code"),
-        ))
-        .expect("error to convert message code");
+    // a second handle on the same connection
+    pub(crate) fn try_clone(&self) -> Result<AntidoteDB, Error> {
+        Ok(AntidoteDB {
+            socket: self.socket.try_clone()?,
+        })
+    }
+
+    // close the connection, for every handle on it
+    pub(crate) fn shutdown(&self) {
+        let _ = self.socket.shutdown(Shutdown::Both);
+    }
+
+    fn new_transaction(timestamp: Option<&Vec<u8>>) -> antidote::ApbStartTransaction {
+        let mut transaction = antidote::ApbStartTransaction::new();
+        if let Some(v) = timestamp {
+            transaction.set_timestamp(v.clone());
+        }
+        transaction.set_properties(Default::default());
+        transaction
+    }
+
+    fn rejected(has_errorcode: bool, errorcode: u32) -> Error {
+        if has_errorcode {
+            Error::Rejected(ErrorCode::from(errorcode))
+        } else {
+            Error::Rejected(ErrorCode::Unknown)
+        }
+    }
+
+    fn send_message<T>(&mut self, code: antidote::MessageCode, message: T) -> Result<(), Error>
+    where
+        T: Message,
+    {
+        write_frame(&mut self.socket, code, &message)
+    }
+
+    fn read_message(&mut self) -> Result<(antidote::MessageCode, Box<dyn Any>), Error> {
+        let (raw_code, read_buffer) = read_frame(&mut self.socket)?;
+
+        let msg_code = antidote::MessageCode::from_i32(i32::from(raw_code))
+            .ok_or_else(|| Error::Protocol(format!("unknown message code {}", raw_code)))?;
 
-        let mut read_buffer = vec![0; msg_leng as usize - 1];
-        self.socket
-            .read_exact(&mut read_buffer)
-            .expect("couldn't read");
+        let message = AntidoteDB::parse_from_bytes(msg_code, read_buffer)
+            .ok_or_else(|| Error::Protocol(format!("cannot parse {:?}", msg_code)))?;
 
-        (
-            msg_code,
-            AntidoteDB::parse_from_bytes(msg_code, &read_buffer).unwrap(),
-        )
+        Ok((msg_code, message))
     }
 
-    fn parse_from_bytes(msgcode: antidote::MessageCode, bytes: &[u8]) -> Option<Box<Any>> {
+    // read a message, turning error responses and unexpected messages into errors
+    fn read_response<T>(&mut self, expected: antidote::MessageCode) -> Result<Box<T>, Error>
+    where
+        T: Message,
+    {
+        let (code, message) = self.read_message()?;
+
+        if code == expected {
+            message
+                .downcast::<T>()
+                .map_err(|_| Error::Protocol(format!("malformed {:?}", code)))
+        } else if code == antidote::MessageCode::apbErrorResp {
+            let message = message
+                .downcast::<antidote::ApbErrorResp>()
+                .map_err(|_| Error::Protocol(format!("malformed {:?}", code)))?;
+            Err(Error::Server {
+                code: message.get_errcode(),
+                message: String::from_utf8_lossy(message.get_errmsg()).into_owned(),
+            })
+        } else {
+            Err(Error::Protocol(format!(
+                "expected {:?}, received {:?}",
+                expected, code
+            )))
+        }
+    }
+
+    fn parse_from_bytes(msgcode: antidote::MessageCode, bytes: Vec<u8>) -> Option<Box<dyn Any>> {
         match msgcode {
             antidote::MessageCode::apbErrorResp => {
-                let mut msg = antidote::ApbErrorResp::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbErrorResp>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             antidote::MessageCode::apbGetRegResp => {
-                let mut msg = antidote::ApbGetRegResp::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbGetRegResp>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             antidote::MessageCode::apbCounterUpdate => {
-                let mut msg = antidote::ApbCounterUpdate::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbCounterUpdate>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             antidote::MessageCode::apbGetCounterResp => {
-                let mut msg = antidote::ApbGetCounterResp::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbGetCounterResp>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             antidote::MessageCode::apbOperationResp => {
-                let mut msg = antidote::ApbOperationResp::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbOperationResp>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             antidote::MessageCode::apbSetUpdate => {
-                let mut msg = antidote::ApbSetUpdate::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbSetUpdate>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             antidote::MessageCode::apbGetSetResp => {
-                let mut msg = antidote::ApbGetSetResp::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbGetSetResp>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             antidote::MessageCode::apbStartTransactionResp => {
-                let mut msg = antidote::ApbStartTransactionResp::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbStartTransactionResp>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             antidote::MessageCode::apbReadObjectResp => {
-                let mut msg = antidote::ApbReadObjectResp::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbReadObjectResp>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             antidote::MessageCode::apbReadObjectsResp => {
-                let mut msg = antidote::ApbReadObjectsResp::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbReadObjectsResp>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             antidote::MessageCode::apbCommitResp => {
-                let mut msg = antidote::ApbCommitResp::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbCommitResp>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             antidote::MessageCode::apbStaticReadObjectsResp => {
-                let mut msg = antidote::ApbStaticReadObjectsResp::new();
-                msg.merge_from_bytes(&bytes).expect("Cannot read message!");
+                let msg = codec::decode::<antidote::ApbStaticReadObjectsResp>(bytes).ok()?;
+                Some(Box::new(msg))
+            }
+            antidote::MessageCode::apbCreateDCResp => {
+                let msg = codec::decode::<antidote::ApbCreateDCResp>(bytes).ok()?;
+                Some(Box::new(msg))
+            }
+            antidote::MessageCode::apbConnectToDCsResp => {
+                let msg = codec::decode::<antidote::ApbConnectToDCsResp>(bytes).ok()?;
+                Some(Box::new(msg))
+            }
+            antidote::MessageCode::apbGetConnectionDescriptorResp => {
+                let msg = codec::decode::<antidote::ApbGetConnectionDescriptorResp>(bytes).ok()?;
                 Some(Box::new(msg))
             }
             _ => None,
         }
     }
 }
+
+// A frame is the length of the rest of the frame as a big endian i32, the
+// message code as one byte, and the encoded message.
+
+pub(crate) fn write_frame<W, T>(
+    writer: &mut W,
+    code: antidote::MessageCode,
+    message: &T,
+) -> Result<(), Error>
+where
+    W: Write,
+    T: Message,
+{
+    // required fields are not checked, objects of custom CRDT kinds carry
+    // their type as an unknown field
+    write_frame_with(writer, code, |encoder| encoder.write_fields(message))
+}
+
+// writes a frame whose message is encoded by write_fields, straight into the
+// frame buffer
+pub(crate) fn write_frame_with<W, F>(
+    writer: &mut W,
+    code: antidote::MessageCode,
+    write_fields: F,
+) -> Result<(), Error>
+where
+    W: Write,
+    F: FnOnce(&mut Encoder) -> Result<(), Error>,
+{
+    // length and code are filled in once the message is written
+    let mut buffer = vec![0; 5];
+    codec::encode(&mut buffer, write_fields)?;
+
+    let length = buffer.len() - 4;
+    BigEndian::write_i32(&mut buffer[..4], length as i32);
+    buffer[4] = code as u8;
+
+    writer.write_all(&buffer)?;
+
+    Ok(())
+}
+
+// an ApbBoundObject field, from the parts of object
+pub(crate) fn write_object<T>(encoder: &mut Encoder, field: u32, object: &T) -> Result<(), Error>
+where
+    T: crdt::Object,
+{
+    let (key, type_number, bucket) = object.object_parts();
+    encoder.write_object(field, &key, type_number, &bucket)
+}
+
+pub(crate) fn read_frame<R>(reader: &mut R) -> Result<(u8, Vec<u8>), Error>
+where
+    R: Read,
+{
+    let msg_leng = reader.read_i32::<BigEndian>()?;
+    if msg_leng < 1 {
+        return Err(Error::Protocol(format!(
+            "invalid message length {}",
+            msg_leng
+        )));
+    }
+    let raw_code = reader.read_u8()?;
+
+    let mut read_buffer = vec![0; msg_leng as usize - 1];
+    reader.read_exact(&mut read_buffer)?;
+
+    Ok((raw_code, read_buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crdt::{BCounter, CounterT, Object};
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn error_codes_map_to_their_variant() {
+        let codes: Vec<_> = (0..5).map(ErrorCode::from).collect();
+        assert_eq!(
+            codes,
+            vec![
+                ErrorCode::Unknown,
+                ErrorCode::Timeout,
+                ErrorCode::NoPermissions,
+                ErrorCode::Aborted,
+                ErrorCode::Other(4),
+            ]
+        );
+    }
+
+    // a node refusing a bounded counter decrement, as antidote does without
+    // enough rights
+    #[test]
+    fn refused_decrements_are_rejected_for_permissions() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let node = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (code, _) = read_frame(&mut stream).unwrap();
+            assert_eq!(code, antidote::MessageCode::apbStaticUpdateObjects as u8);
+            let mut response = antidote::ApbCommitResp::new();
+            response.set_success(false);
+            response.set_errorcode(2);
+            write_frame(&mut stream, antidote::MessageCode::apbCommitResp, &response).unwrap();
+        });
+
+        let mut db = AntidoteDB::connect_with_string(&address.to_string()).unwrap();
+        let stock = BCounter::new("stock", "lib");
+        let error = db
+            .static_update_objects(&[stock.dec(5).unwrap()], None)
+            .unwrap_err();
+        node.join().unwrap();
+
+        assert!(matches!(error, Error::Rejected(ErrorCode::NoPermissions)));
+        assert_eq!(
+            error.to_string(),
+            "request rejected: not enough rights for the operation"
+        );
+    }
+}
@@ -0,0 +1,755 @@
+use super::antidote;
+use super::codec::{self, RepeatedField, TypedMessage};
+use super::crdt::{number_from_name, number_name};
+use super::dynamic::from_hex;
+
+use std::error;
+use std::fmt;
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::{self, Chars};
+
+// Text format of update operations, for config files, runbooks and test
+// fixtures. An update names its object, by type, bucket and key, then gives
+// the operation:
+//
+//     counter web/visits inc 3
+//     orset web/tags add {a, "b c"} remove {0x00ff}
+//     lwwreg web/"user name" set "Ada"
+//     flag_ew web/enabled enable
+//     rrmap web/profile map {
+//         counter visits inc,
+//         rrmap address map {
+//             lwwreg city set Lisbon,
+//         },
+//         remove orset old,
+//     }
+//     mvreg web/status reset
+//
+// Operations are made of clauses, one per field of ApbUpdateOperation:
+// `inc [n]`, `add {...}` and `remove {...}` (the first one gives the optype
+// of the set update), `set <value>`, `map {...}`, `reset`, and `enable` or
+// `disable`. Map entries are `<type> <key> <clauses>` or `remove <type>
+// <key>`, separated by commas. Types are named as by crdt::number_name, so
+// custom kinds are type:<number>.
+//
+// Bytes are written as "strings" with \" \\ \n \t \r \0 and \xNN escapes, as
+// 0x hex, as integers, 8 bytes big endian like crdt::Element does for u64, or
+// as bare words of letters, digits and _ . : - not starting with a digit.
+// Comments run from # to the end of the line.
+//
+// print_* give the text of messages, which parses back to equal messages.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl error::Error for ParseError {}
+
+pub fn parse_updates(text: &str) -> Result<Vec<antidote::ApbUpdateOp>, ParseError> {
+    let mut parser = Parser::new(text)?;
+    let mut updates = Vec::new();
+    while !parser.at_end() {
+        updates.push(parser.update()?);
+    }
+    Ok(updates)
+}
+
+pub fn parse_update(text: &str) -> Result<antidote::ApbUpdateOp, ParseError> {
+    let mut parser = Parser::new(text)?;
+    let update = parser.update()?;
+    parser.end()?;
+    Ok(update)
+}
+
+pub fn parse_object(text: &str) -> Result<antidote::ApbBoundObject, ParseError> {
+    let mut parser = Parser::new(text)?;
+    let object = parser.object()?;
+    parser.end()?;
+    Ok(object)
+}
+
+pub fn parse_operation(text: &str) -> Result<antidote::ApbUpdateOperation, ParseError> {
+    let mut parser = Parser::new(text)?;
+    let operation = parser.operation()?;
+    parser.end()?;
+    Ok(operation)
+}
+
+// one update per line, nested maps over several lines
+pub fn print_updates(updates: &[antidote::ApbUpdateOp]) -> String {
+    let mut text = String::new();
+    for update in updates {
+        text.push_str(&print_update(update));
+        text.push('\n');
+    }
+    text
+}
+
+pub fn print_update(update: &antidote::ApbUpdateOp) -> String {
+    let mut text = print_object(update.get_boundobject());
+    write_operation(&mut text, update.get_operation(), "");
+    text
+}
+
+pub fn print_object(object: &antidote::ApbBoundObject) -> String {
+    format!(
+        "{} {}/{}",
+        number_name(object.type_number()),
+        Literal(object.get_bucket()),
+        Literal(object.get_key())
+    )
+}
+
+pub fn print_operation(operation: &antidote::ApbUpdateOperation) -> String {
+    let mut text = String::new();
+    write_operation(&mut text, operation, "");
+    text.trim_start().to_string()
+}
+
+// the clauses of the operation, each after a space, entries of maps on
+// their own lines indented past indent
+fn write_operation(text: &mut String, operation: &antidote::ApbUpdateOperation, indent: &str) {
+    if operation.has_counterop() {
+        let counterop = operation.get_counterop();
+        if counterop.has_inc() {
+            write!(text, " inc {}", counterop.get_inc()).unwrap();
+        } else {
+            text.push_str(" inc");
+        }
+    }
+    if operation.has_setop() {
+        let setop = operation.get_setop();
+        let (adds, rems) = (setop.get_adds(), setop.get_rems());
+        if setop.get_optype() == antidote::ApbSetUpdate_SetOpType::REMOVE {
+            write_set(text, "remove", rems);
+            if !adds.is_empty() {
+                write_set(text, "add", adds);
+            }
+        } else {
+            write_set(text, "add", adds);
+            if !rems.is_empty() {
+                write_set(text, "remove", rems);
+            }
+        }
+    }
+    if operation.has_regop() {
+        write!(text, " set {}", Literal(operation.get_regop().get_value())).unwrap();
+    }
+    if operation.has_mapop() {
+        let mapop = operation.get_mapop();
+        if mapop.get_updates().is_empty() && mapop.get_removedKeys().is_empty() {
+            text.push_str(" map {}");
+        } else {
+            let inner = format!("{}    ", indent);
+            text.push_str(" map {\n");
+            for update in mapop.get_updates() {
+                let key = update.get_key();
+                write!(
+                    text,
+                    "{}{} {}",
+                    inner,
+                    number_name(key.type_number()),
+                    Literal(key.get_key())
+                )
+                .unwrap();
+                write_operation(text, update.get_update(), &inner);
+                text.push_str(",\n");
+            }
+            for key in mapop.get_removedKeys() {
+                writeln!(
+                    text,
+                    "{}remove {} {},",
+                    inner,
+                    number_name(key.type_number()),
+                    Literal(key.get_key())
+                )
+                .unwrap();
+            }
+            write!(text, "{}}}", indent).unwrap();
+        }
+    }
+    if operation.has_resetop() {
+        text.push_str(" reset");
+    }
+    if operation.has_flagop() {
+        if operation.get_flagop().get_value() {
+            text.push_str(" enable");
+        } else {
+            text.push_str(" disable");
+        }
+    }
+}
+
+fn write_set(text: &mut String, clause: &str, values: &[codec::Bytes]) {
+    write!(text, " {} {{", clause).unwrap();
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            text.push_str(", ");
+        }
+        write!(text, "{}", Literal(value)).unwrap();
+    }
+    text.push('}');
+}
+
+// bytes as a bare word or string when they are text, as an integer when
+// they are 8 bytes of something else, as hex otherwise
+struct Literal<'a>(&'a [u8]);
+
+impl<'a> fmt::Display for Literal<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match str::from_utf8(self.0) {
+            Ok(s) if is_bare(s) => f.write_str(s),
+            Ok(s) if !s.chars().any(|c| c.is_control() && !"\n\t\r".contains(c)) => {
+                f.write_str("\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\t' => f.write_str("\\t")?,
+                        '\r' => f.write_str("\\r")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                f.write_str("\"")
+            }
+            _ if self.0.len() == 8 => {
+                let mut buf = [0; 8];
+                buf.copy_from_slice(self.0);
+                write!(f, "{}", u64::from_be_bytes(buf))
+            }
+            _ => {
+                f.write_str("0x")?;
+                for byte in self.0 {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn is_bare(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && s.chars().all(is_word_char)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_.:-".contains(c)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Int(String),
+    Bytes(Vec<u8>),
+    Punct(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{:?}", word),
+            Token::Int(int) => write!(f, "{}", int),
+            Token::Bytes(bytes) => write!(f, "{}", Literal(bytes)),
+            Token::Punct(c) => write!(f, "'{}'", c),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize, usize)>,
+    pos: usize,
+    // position of the end of the text, for errors there
+    end: (usize, usize),
+}
+
+// chars of the text, with the position of the next one
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+            self.column = 1;
+        } else if c.is_some() {
+            self.column += 1;
+        }
+        c
+    }
+
+    fn take_while(&mut self, word: &mut String, f: impl Fn(char) -> bool) {
+        while let Some(c) = self.peek().filter(|c| f(*c)) {
+            word.push(c);
+            self.next();
+        }
+    }
+
+    // the bytes of a string, after its opening quote
+    fn string(&mut self) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.next() {
+                None => return Err("unterminated string".to_string()),
+                Some('"') => return Ok(bytes),
+                Some('\\') => {
+                    let byte = match self.next() {
+                        Some('"') => b'"',
+                        Some('\\') => b'\\',
+                        Some('n') => b'\n',
+                        Some('t') => b'\t',
+                        Some('r') => b'\r',
+                        Some('0') => 0,
+                        Some('x') => {
+                            let hex: String = [self.next(), self.next()].iter().flatten().collect();
+                            u8::from_str_radix(&hex, 16)
+                                .map_err(|_| format!("invalid escape \\x{}", hex))?
+                        }
+                        Some(c) => return Err(format!("invalid escape \\{}", c)),
+                        None => return Err("unterminated string".to_string()),
+                    };
+                    bytes.push(byte);
+                }
+                Some(c) => {
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+    }
+
+    // skips whitespace and comments up to the next token
+    fn blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some('#') => while !matches!(self.next(), Some('\n') | None) {},
+                Some(c) if c.is_whitespace() => {
+                    self.next();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    // the token at the position, after blank, None at the end of the text
+    fn token(&mut self) -> Result<Option<Token>, String> {
+        let c = match self.next() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let mut word = c.to_string();
+        let token = if "{}/,".contains(c) {
+            Token::Punct(c)
+        } else if c == '"' {
+            Token::Bytes(self.string()?)
+        } else if c.is_ascii_digit() || c == '-' {
+            self.take_while(&mut word, |c| c.is_ascii_alphanumeric());
+            match word.strip_prefix("0x") {
+                Some(hex) => Token::Bytes(from_hex(hex).ok_or(format!("invalid hex {}", word))?),
+                None => Token::Int(word),
+            }
+        } else if is_word_char(c) {
+            self.take_while(&mut word, is_word_char);
+            Token::Word(word)
+        } else {
+            return Err(format!("unexpected character {:?}", c));
+        };
+        Ok(Some(token))
+    }
+}
+
+impl Parser {
+    fn new(text: &str) -> Result<Self, ParseError> {
+        let mut lexer = Lexer {
+            chars: text.chars().peekable(),
+            line: 1,
+            column: 1,
+        };
+        let mut tokens = Vec::new();
+        loop {
+            // errors are reported at the start of their token
+            lexer.blank();
+            let (line, column) = (lexer.line, lexer.column);
+            match lexer.token() {
+                Ok(Some(token)) => tokens.push((token, line, column)),
+                Ok(None) => break,
+                Err(message) => {
+                    return Err(ParseError {
+                        line,
+                        column,
+                        message,
+                    })
+                }
+            }
+        }
+
+        Ok(Parser {
+            tokens,
+            pos: 0,
+            end: (lexer.line, lexer.column),
+        })
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos == self.tokens.len()
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _, _)| token)
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Word(word)) => Some(word),
+            _ => None,
+        }
+    }
+
+    // an error at the current token
+    fn error(&self, message: String) -> ParseError {
+        let (line, column) = match self.tokens.get(self.pos) {
+            Some((_, line, column)) => (*line, *column),
+            None => self.end,
+        };
+        ParseError {
+            line,
+            column,
+            message,
+        }
+    }
+
+    fn expected(&self, what: &str) -> ParseError {
+        match self.peek() {
+            Some(token) => self.error(format!("expected {}, found {}", what, token)),
+            None => self.error(format!("expected {}, found the end", what)),
+        }
+    }
+
+    fn end(&self) -> Result<(), ParseError> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            Err(self.expected("the end"))
+        }
+    }
+
+    fn punct(&mut self, c: char) -> Result<(), ParseError> {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.expected(&format!("'{}'", c)))
+        }
+    }
+
+    fn update(&mut self) -> Result<antidote::ApbUpdateOp, ParseError> {
+        let mut update = antidote::ApbUpdateOp::new();
+        update.set_boundobject(self.object()?);
+        update.set_operation(self.operation()?);
+        Ok(update)
+    }
+
+    fn object(&mut self) -> Result<antidote::ApbBoundObject, ParseError> {
+        let type_number = self.type_number()?;
+        let bucket = self.literal()?;
+        self.punct('/')?;
+        let key = self.literal()?;
+
+        let mut object = antidote::ApbBoundObject::new();
+        object.set_key(key);
+        object.set_type_number(type_number);
+        object.set_bucket(bucket);
+        Ok(object)
+    }
+
+    fn map_key(&mut self) -> Result<antidote::ApbMapKey, ParseError> {
+        let type_number = self.type_number()?;
+        let mut key = antidote::ApbMapKey::new();
+        key.set_key(self.literal()?);
+        key.set_type_number(type_number);
+        Ok(key)
+    }
+
+    fn type_number(&mut self) -> Result<i32, ParseError> {
+        match self.peek_word().and_then(number_from_name) {
+            Some(type_number) => {
+                self.pos += 1;
+                Ok(type_number)
+            }
+            None => Err(self.expected("a CRDT type")),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Vec<u8>, ParseError> {
+        let bytes = match self.peek() {
+            Some(Token::Bytes(bytes)) => bytes.clone(),
+            Some(Token::Word(word)) => word.as_bytes().to_vec(),
+            Some(Token::Int(int)) => match int.parse::<u64>() {
+                Ok(n) => n.to_be_bytes().to_vec(),
+                Err(_) => return Err(self.error(format!("invalid integer {}", int))),
+            },
+            _ => return Err(self.expected("bytes")),
+        };
+        self.pos += 1;
+        Ok(bytes)
+    }
+
+    // the clauses up to the next update, entry or the end
+    fn operation(&mut self) -> Result<antidote::ApbUpdateOperation, ParseError> {
+        let mut operation = antidote::ApbUpdateOperation::new();
+        let mut clauses = Vec::new();
+
+        while let Some(clause) = self.peek_word().map(str::to_string) {
+            let field = match clause.as_str() {
+                "enable" | "disable" => "enable",
+                "inc" | "add" | "remove" | "set" | "map" | "reset" => &clause,
+                _ => break,
+            };
+            if clauses.contains(&field.to_string()) {
+                return Err(self.error(format!("duplicate {} clause", clause)));
+            }
+            clauses.push(field.to_string());
+            self.pos += 1;
+
+            match clause.as_str() {
+                "inc" => {
+                    let mut counterop = antidote::ApbCounterUpdate::new();
+                    if let Some(Token::Int(int)) = self.peek() {
+                        let inc = int
+                            .parse()
+                            .map_err(|_| self.error(format!("invalid increment {}", int)))?;
+                        counterop.set_inc(inc);
+                        self.pos += 1;
+                    }
+                    operation.set_counterop(counterop);
+                }
+                "add" | "remove" => {
+                    let values = self.set()?;
+                    // the first of add and remove gives the optype
+                    let first = !operation.has_setop();
+                    let setop = operation.mut_setop();
+                    if clause == "add" {
+                        if first {
+                            setop.set_optype(antidote::ApbSetUpdate_SetOpType::ADD);
+                        }
+                        setop.set_adds(values);
+                    } else {
+                        if first {
+                            setop.set_optype(antidote::ApbSetUpdate_SetOpType::REMOVE);
+                        }
+                        setop.set_rems(values);
+                    }
+                }
+                "set" => {
+                    let mut regop = antidote::ApbRegUpdate::new();
+                    regop.set_value(self.literal()?);
+                    operation.set_regop(regop);
+                }
+                "map" => {
+                    let mapop = self.map()?;
+                    operation.set_mapop(mapop);
+                }
+                "reset" => operation.set_resetop(antidote::ApbCrdtReset::new()),
+                _ => {
+                    let mut flagop = antidote::ApbFlagUpdate::new();
+                    flagop.set_value(clause == "enable");
+                    operation.set_flagop(flagop);
+                }
+            }
+        }
+        Ok(operation)
+    }
+
+    fn set(&mut self) -> Result<RepeatedField<Vec<u8>>, ParseError> {
+        self.punct('{')?;
+        let mut values = Vec::new();
+        while self.peek() != Some(&Token::Punct('}')) {
+            values.push(self.literal()?);
+            if self.peek() != Some(&Token::Punct('}')) {
+                self.punct(',')?;
+            }
+        }
+        self.pos += 1;
+        Ok(RepeatedField::from_vec(values))
+    }
+
+    fn map(&mut self) -> Result<antidote::ApbMapUpdate, ParseError> {
+        self.punct('{')?;
+        let mut updates = Vec::new();
+        let mut removes = Vec::new();
+        while self.peek() != Some(&Token::Punct('}')) {
+            if self.peek_word() == Some("remove") {
+                self.pos += 1;
+                removes.push(self.map_key()?);
+            } else {
+                let mut update = antidote::ApbMapNestedUpdate::new();
+                update.set_key(self.map_key()?);
+                update.set_update(self.operation()?);
+                updates.push(update);
+            }
+            if self.peek() != Some(&Token::Punct('}')) {
+                self.punct(',')?;
+            }
+        }
+        self.pos += 1;
+
+        let mut mapop = antidote::ApbMapUpdate::new();
+        mapop.set_updates(RepeatedField::from_vec(updates));
+        mapop.set_removedKeys(RepeatedField::from_vec(removes));
+        Ok(mapop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::crdt::{Counter, CounterT, FlagEW, FlagT, LWWReg, MapT, ORSet, Object};
+    use super::super::crdt::{ObjectReset, RRMap, RegT, SetT};
+    use super::*;
+
+    fn update<O: Object>(
+        key: &str,
+        operation: antidote::ApbUpdateOperation,
+    ) -> antidote::ApbUpdateOp {
+        let mut update = antidote::ApbUpdateOp::new();
+        update.set_boundobject(O::new_object(key, "web"));
+        update.set_operation(operation);
+        update
+    }
+
+    // the text parses back to the update, and prints the same again
+    fn assert_round_trip(update: &antidote::ApbUpdateOp) {
+        let text = print_update(update);
+        let parsed = parse_update(&text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+        assert_eq!(&parsed, update, "{}", text);
+        assert_eq!(print_update(&parsed), text);
+    }
+
+    #[test]
+    fn updates_print_and_parse_back() {
+        let mut both = ORSet::rem_values_op(&[b"x".to_vec()]);
+        let adds = ORSet::add_values_op(&[b"y".to_vec()])
+            .take_setop()
+            .take_adds();
+        both.mut_setop().set_adds(adds);
+        let mut bare_inc = antidote::ApbUpdateOperation::new();
+        bare_inc.set_counterop(antidote::ApbCounterUpdate::new());
+
+        let updates = vec![
+            update::<Counter>("visits", Counter::inc_op(3)),
+            update::<Counter>("visits", Counter::inc_op(i64::MIN)),
+            update::<Counter>("visits", bare_inc),
+            update::<ORSet>(
+                "tags",
+                ORSet::add_values_op(&[b"a".to_vec(), b"b c".to_vec()]),
+            ),
+            update::<ORSet>("tags", both),
+            update::<LWWReg>("user name", LWWReg::set_value_op(&b"Ada".to_vec())),
+            update::<FlagEW>("enabled", FlagEW::set_op(false)),
+            update::<RRMap>("profile", RRMap::reset_op()),
+            update::<RRMap>(
+                "profile",
+                RRMap::update_op(
+                    vec![
+                        (Counter::map_key("visits"), Counter::inc_op(1)),
+                        (
+                            RRMap::map_key("address"),
+                            RRMap::update_op(
+                                vec![(
+                                    LWWReg::map_key("city"),
+                                    LWWReg::set_value_op(&b"Lisbon".to_vec()),
+                                )],
+                                Vec::new(),
+                            ),
+                        ),
+                    ],
+                    vec![ORSet::map_key("old")],
+                ),
+            ),
+            update::<RRMap>("empty", RRMap::update_op(Vec::new(), Vec::new())),
+        ];
+        for update in &updates {
+            assert_round_trip(update);
+        }
+        assert_eq!(parse_updates(&print_updates(&updates)).unwrap(), updates);
+    }
+
+    #[test]
+    fn literals_print_and_parse_back() {
+        let values: Vec<Vec<u8>> = vec![
+            b"word".to_vec(),
+            b"a.b:c-d_e".to_vec(),
+            Vec::new(),
+            b"9 lives".to_vec(),
+            b"quote \" backslash \\ tab \t line \n return \r".to_vec(),
+            "caf\u{e9}".as_bytes().to_vec(),
+            42u64.to_be_bytes().to_vec(),
+            vec![0, 0xff],
+            b"nul \0".to_vec(),
+        ];
+        for value in values {
+            assert_round_trip(&update::<LWWReg>("r", LWWReg::set_value_op(&value)));
+        }
+        assert_eq!(
+            print_operation(&LWWReg::set_value_op(&vec![0x00, 0xff])),
+            "set 0x00ff"
+        );
+        assert_eq!(print_operation(&LWWReg::set_value_op(&7u64)), "set 7");
+    }
+
+    #[test]
+    fn the_documented_example_parses() {
+        let text = "
+            counter web/visits inc 3
+            orset web/tags add {a, \"b c\"} remove {0x00ff}
+            lwwreg web/\"user name\" set \"Ada\"
+            flag_ew web/enabled enable # a comment
+            rrmap web/profile map {
+                counter visits inc,
+                rrmap address map {
+                    lwwreg city set Lisbon,
+                },
+                remove orset old,
+            }
+            mvreg web/status reset
+        ";
+        let updates = parse_updates(text).unwrap();
+        assert_eq!(updates.len(), 6);
+        assert_eq!(parse_updates(&print_updates(&updates)).unwrap(), updates);
+        assert_eq!(
+            print_update(&updates[1]),
+            "orset web/tags add {a, \"b c\"} remove {0x00ff}"
+        );
+    }
+
+    #[test]
+    fn errors_give_their_position() {
+        let error = parse_update("counter web/visits\n  inc }").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 7));
+        assert!(parse_update("counter web/visits inc 1 extra").is_err());
+        assert!(parse_update("lwwreg web/r set \"open").is_err());
+        assert!(parse_update("orset web/tags add {0xabc}").is_err());
+        assert!(parse_update("nothing web/k inc").is_err());
+    }
+}
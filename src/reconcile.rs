@@ -0,0 +1,463 @@
+use super::antidote;
+use super::codec::{ProtobufEnum, TypedMessage};
+use super::crdt::{number_name, type_name, CRDT_type, Object};
+use super::crdt::{Counter, CounterT, FlagEW, FlagT, LWWReg, MVReg, MapT, ORSet, RRMap};
+use super::crdt::{ObjectReset, RegT, SetT};
+use super::value::Value;
+use super::{AntidoteDB, Error, Transaction};
+
+use std::error;
+use std::fmt;
+use std::slice;
+
+// Operations bringing an object from its current value to a desired one.
+//
+// Only what differs is written, so concurrent updates to the rest of the
+// object are kept: counters are incremented by the difference, set elements
+// added or removed one by one, registers and flags set when they change, and
+// maps updated entry by entry, removing the entries missing from the desired
+// value.
+//
+// Map entries are created only when their desired value differs from the
+// empty value of their type (0, false, no elements), registers are always
+// written. A multi-value register is written when it holds anything else than
+// the single desired value, which also resolves its conflicts.
+//
+// A set can need both an add and a remove, which Antidote takes as two
+// operations, so a set or a map can reconcile to two operations. They apply
+// in order, in the same transaction.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReconcileError {
+    // the desired value does not fit the CRDT type
+    Mismatch(CRDT_type),
+    // a desired multi-value register with several values
+    Conflict,
+    // entries cannot be removed from a GMap
+    Remove(Vec<u8>),
+    // a custom kind, by type number, whose value reconcile cannot change
+    Custom(i32),
+    // a counter whose distance to the desired value does not fit an increment
+    Overflow { current: i64, desired: i64 },
+}
+
+impl fmt::Display for ReconcileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReconcileError::Mismatch(crdt_type) => {
+                write!(f, "value does not fit a {}", type_name(*crdt_type))
+            }
+            ReconcileError::Conflict => write!(f, "a register cannot be set to several values"),
+            ReconcileError::Remove(key) => {
+                write!(f, "cannot remove {:?} from a gmap", Value::bytes(key))
+            }
+            ReconcileError::Custom(number) => {
+                write!(f, "{} is not a built-in CRDT type", number_name(*number))
+            }
+            ReconcileError::Overflow { current, desired } => write!(
+                f,
+                "a counter cannot move from {} to {} in one increment",
+                current, desired
+            ),
+        }
+    }
+}
+
+impl error::Error for ReconcileError {}
+
+pub fn reconcile(
+    crdt_type: CRDT_type,
+    current: &Value,
+    desired: &Value,
+) -> Result<Vec<antidote::ApbUpdateOperation>, ReconcileError> {
+    operations(crdt_type, Some(current), desired)
+}
+
+// like reconcile, bound to object
+pub fn reconcile_object<T: Object>(
+    object: &T,
+    current: &Value,
+    desired: &Value,
+) -> Result<Vec<antidote::ApbUpdateOp>, ReconcileError> {
+    let crdt_type = T::get_crdt_type().ok_or(ReconcileError::Custom(T::type_number()))?;
+    let ops = reconcile(crdt_type, current, desired)?;
+    Ok(ops
+        .into_iter()
+        .map(|op| {
+            let mut update_op_obj = antidote::ApbUpdateOp::new();
+            update_op_obj.set_boundobject(object.get_object());
+            update_op_obj.set_operation(op);
+            update_op_obj
+        })
+        .collect())
+}
+
+impl AntidoteDB {
+    // reads object, then writes what brings it to desired; returns the
+    // operations written
+    pub fn reconcile_in_transaction<T: Object>(
+        &mut self,
+        object: &T,
+        desired: &Value,
+        transaction: &Transaction,
+    ) -> Result<Vec<antidote::ApbUpdateOp>, Error> {
+        self.reconcile_checked(object, desired, transaction, |_| Ok(()))
+    }
+
+    // reconcile_in_transaction, with the operations passed to check before
+    // they are written
+    pub(crate) fn reconcile_checked<T, F>(
+        &mut self,
+        object: &T,
+        desired: &Value,
+        transaction: &Transaction,
+        check: F,
+    ) -> Result<Vec<antidote::ApbUpdateOp>, Error>
+    where
+        T: Object,
+        F: FnOnce(&[antidote::ApbUpdateOp]) -> Result<(), Error>,
+    {
+        let crdt_type = T::get_crdt_type()
+            .ok_or_else(|| Error::Reconcile(ReconcileError::Custom(T::type_number())))?;
+        let values = self.mult_read_in_transaction(slice::from_ref(object), transaction)?;
+        let resp = values
+            .first()
+            .ok_or_else(|| Error::Protocol("empty read response".to_string()))?;
+        let current = Value::decode(crdt_type, resp);
+
+        let ops = reconcile_object(object, &current, desired).map_err(Error::Reconcile)?;
+        check(&ops)?;
+        if !ops.is_empty() {
+            self.mult_update_in_transaction(&ops, transaction)?;
+        }
+
+        Ok(ops)
+    }
+}
+
+// current is None for a map entry that does not exist yet
+fn operations(
+    crdt_type: CRDT_type,
+    current: Option<&Value>,
+    desired: &Value,
+) -> Result<Vec<antidote::ApbUpdateOperation>, ReconcileError> {
+    let mismatch = || ReconcileError::Mismatch(crdt_type);
+
+    let ops = match crdt_type {
+        CRDT_type::COUNTER | CRDT_type::FATCOUNTER | CRDT_type::BCOUNTER => {
+            let current = match current {
+                Some(Value::Counter(value)) => *value,
+                None => 0,
+                Some(_) => return Err(mismatch()),
+            };
+            match desired {
+                Value::Counter(value) if *value != current => {
+                    let inc = value.checked_sub(current).ok_or(ReconcileError::Overflow {
+                        current,
+                        desired: *value,
+                    })?;
+                    vec![Counter::inc_op(inc)]
+                }
+                Value::Counter(_) => vec![],
+                _ => return Err(mismatch()),
+            }
+        }
+        CRDT_type::LWWREG => {
+            let current = match current {
+                Some(Value::Register(value)) => Some(value),
+                None => None,
+                Some(_) => return Err(mismatch()),
+            };
+            match desired {
+                Value::Register(value) if current != Some(value) => {
+                    vec![LWWReg::set_value_op(value)]
+                }
+                Value::Register(_) => vec![],
+                _ => return Err(mismatch()),
+            }
+        }
+        CRDT_type::MVREG => {
+            let current = match current {
+                Some(Value::MultiRegister(values)) => Some(values.as_slice()),
+                None => None,
+                Some(_) => return Err(mismatch()),
+            };
+            let desired = match desired {
+                Value::Register(value) => Some(value),
+                Value::MultiRegister(values) if values.len() > 1 => {
+                    return Err(ReconcileError::Conflict)
+                }
+                Value::MultiRegister(values) => values.first(),
+                _ => return Err(mismatch()),
+            };
+            match (current, desired) {
+                (Some(current), Some(value)) if current == slice::from_ref(value) => vec![],
+                (_, Some(value)) => vec![MVReg::set_value_op(value)],
+                (Some(current), None) if !current.is_empty() => vec![MVReg::reset_op()],
+                (_, None) => vec![],
+            }
+        }
+        CRDT_type::ORSET | CRDT_type::RWSET => {
+            let current: &[Vec<u8>] = match current {
+                Some(Value::Set(values)) => values,
+                None => &[],
+                Some(_) => return Err(mismatch()),
+            };
+            let desired = match desired {
+                Value::Set(values) => values,
+                _ => return Err(mismatch()),
+            };
+
+            let mut adds: Vec<&Vec<u8>> = Vec::new();
+            for value in desired {
+                if !current.contains(value) && !adds.contains(&value) {
+                    adds.push(value);
+                }
+            }
+            let rems: Vec<_> = current.iter().filter(|v| !desired.contains(v)).collect();
+
+            let mut ops = Vec::new();
+            if !adds.is_empty() {
+                ops.push(ORSet::add_values_op(adds));
+            }
+            if !rems.is_empty() {
+                ops.push(ORSet::rem_values_op(rems));
+            }
+            ops
+        }
+        CRDT_type::FLAG_EW | CRDT_type::FLAG_DW => {
+            let current = match current {
+                Some(Value::Flag(value)) => *value,
+                None => false,
+                Some(_) => return Err(mismatch()),
+            };
+            match desired {
+                Value::Flag(value) if *value != current => vec![FlagEW::set_op(*value)],
+                Value::Flag(_) => vec![],
+                _ => return Err(mismatch()),
+            }
+        }
+        CRDT_type::GMAP | CRDT_type::RRMAP => {
+            let current: &[(Vec<u8>, i32, Value)] = match current {
+                Some(Value::Map(entries)) => entries,
+                None => &[],
+                Some(_) => return Err(mismatch()),
+            };
+            let desired = match desired {
+                Value::Map(entries) => entries,
+                _ => return Err(mismatch()),
+            };
+            map_operations(crdt_type, current, desired)?
+        }
+    };
+
+    Ok(ops)
+}
+
+fn map_operations(
+    crdt_type: CRDT_type,
+    current: &[(Vec<u8>, i32, Value)],
+    desired: &[(Vec<u8>, i32, Value)],
+) -> Result<Vec<antidote::ApbUpdateOperation>, ReconcileError> {
+    // the i-th operation of every entry goes in the i-th map operation
+    let mut rounds: Vec<Vec<(antidote::ApbMapKey, antidote::ApbUpdateOperation)>> = Vec::new();
+    for (key, entry_type, value) in desired {
+        let current_value = current
+            .iter()
+            .find(|(k, t, _)| k == key && t == entry_type)
+            .map(|(_, _, v)| v);
+        // entries of custom kinds are kept as they are, not changed
+        let ops = match CRDT_type::from_i32(*entry_type) {
+            Some(crdt_type) => operations(crdt_type, current_value, value)?,
+            None if current_value == Some(value) => vec![],
+            None => return Err(ReconcileError::Custom(*entry_type)),
+        };
+        for (i, op) in ops.into_iter().enumerate() {
+            if rounds.len() == i {
+                rounds.push(Vec::new());
+            }
+            rounds[i].push((map_key(key, *entry_type), op));
+        }
+    }
+
+    let mut removes = Vec::new();
+    for (key, entry_type, _) in current {
+        if !desired.iter().any(|(k, t, _)| k == key && t == entry_type) {
+            if crdt_type == CRDT_type::GMAP {
+                return Err(ReconcileError::Remove(key.clone()));
+            }
+            removes.push(map_key(key, *entry_type));
+        }
+    }
+
+    if rounds.is_empty() && !removes.is_empty() {
+        rounds.push(Vec::new());
+    }
+
+    // removals go with the first operation
+    let mut removes = Some(removes);
+    Ok(rounds
+        .into_iter()
+        .map(|updates| RRMap::update_op(updates, removes.take().unwrap_or_default()))
+        .collect())
+}
+
+fn map_key(key: &[u8], type_number: i32) -> antidote::ApbMapKey {
+    let mut map_key = antidote::ApbMapKey::new();
+    map_key.set_key(key.to_vec());
+    map_key.set_type_number(type_number);
+    map_key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+
+    fn bytes(s: &str) -> Vec<u8> {
+        s.as_bytes().to_vec()
+    }
+
+    fn set(items: &[&str]) -> Value {
+        Value::Set(items.iter().map(|s| bytes(s)).collect())
+    }
+
+    fn entry(key: &str, crdt_type: CRDT_type, value: Value) -> (Vec<u8>, i32, Value) {
+        (bytes(key), crdt_type as i32, value)
+    }
+
+    #[test]
+    fn counters_move_by_the_difference() {
+        let ops = reconcile(CRDT_type::COUNTER, &Value::Counter(3), &Value::Counter(-2)).unwrap();
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].get_counterop().get_inc(), -5);
+
+        let ops = reconcile(CRDT_type::COUNTER, &Value::Counter(3), &Value::Counter(3)).unwrap();
+        assert!(ops.is_empty());
+    }
+
+    #[test]
+    fn sets_add_and_remove_what_differs() {
+        let ops = reconcile(CRDT_type::ORSET, &set(&["a", "b"]), &set(&["b", "c", "c"])).unwrap();
+        assert_eq!(ops.len(), 2);
+        assert_eq!(codec::to_vecs(ops[0].get_setop().get_adds()), [bytes("c")]);
+        assert_eq!(codec::to_vecs(ops[1].get_setop().get_rems()), [bytes("a")]);
+
+        let ops = reconcile(CRDT_type::ORSET, &set(&["a"]), &set(&["a"])).unwrap();
+        assert!(ops.is_empty());
+    }
+
+    #[test]
+    fn registers_are_written_when_they_differ() {
+        let a = Value::Register(bytes("a"));
+        assert!(reconcile(CRDT_type::LWWREG, &a, &a).unwrap().is_empty());
+
+        let b = Value::Register(bytes("b"));
+        let ops = reconcile(CRDT_type::LWWREG, &a, &b).unwrap();
+        assert_eq!(ops[0].get_regop().get_value(), b"b");
+
+        // a conflict is resolved even to one of its values
+        let conflict = Value::MultiRegister(vec![bytes("a"), bytes("b")]);
+        let ops = reconcile(CRDT_type::MVREG, &conflict, &a).unwrap();
+        assert_eq!(ops[0].get_regop().get_value(), b"a");
+        assert_eq!(
+            reconcile(CRDT_type::MVREG, &Value::MultiRegister(vec![]), &conflict),
+            Err(ReconcileError::Conflict)
+        );
+    }
+
+    #[test]
+    fn counters_too_far_apart_are_refused() {
+        let (min, max) = (Value::Counter(i64::MIN), Value::Counter(i64::MAX));
+        assert_eq!(
+            reconcile(CRDT_type::COUNTER, &min, &max),
+            Err(ReconcileError::Overflow {
+                current: i64::MIN,
+                desired: i64::MAX,
+            })
+        );
+        assert_eq!(
+            reconcile(CRDT_type::FATCOUNTER, &Value::Counter(1), &min),
+            Err(ReconcileError::Overflow {
+                current: 1,
+                desired: i64::MIN,
+            })
+        );
+        // the largest distances that fit
+        let ops = reconcile(CRDT_type::COUNTER, &Value::Counter(0), &max).unwrap();
+        assert_eq!(ops[0].get_counterop().get_inc(), i64::MAX);
+        let ops = reconcile(CRDT_type::COUNTER, &Value::Counter(0), &min).unwrap();
+        assert_eq!(ops[0].get_counterop().get_inc(), i64::MIN);
+
+        let entry = |value| Value::Map(vec![entry("n", CRDT_type::COUNTER, value)]);
+        assert!(matches!(
+            reconcile(
+                CRDT_type::RRMAP,
+                &entry(Value::Counter(i64::MAX)),
+                &entry(Value::Counter(-2))
+            ),
+            Err(ReconcileError::Overflow { .. })
+        ));
+    }
+
+    #[test]
+    fn mismatched_values_are_refused() {
+        assert_eq!(
+            reconcile(CRDT_type::COUNTER, &Value::Counter(0), &Value::Flag(true)),
+            Err(ReconcileError::Mismatch(CRDT_type::COUNTER))
+        );
+        let map = Value::Map(vec![entry("k", CRDT_type::COUNTER, Value::Counter(1))]);
+        assert_eq!(
+            reconcile(CRDT_type::GMAP, &map, &Value::Map(vec![])),
+            Err(ReconcileError::Remove(bytes("k")))
+        );
+    }
+
+    #[test]
+    fn maps_update_entries_in_rounds() {
+        let current = Value::Map(vec![
+            entry("tags", CRDT_type::ORSET, set(&["a"])),
+            entry("old", CRDT_type::LWWREG, Value::Register(bytes("x"))),
+            entry("n", CRDT_type::COUNTER, Value::Counter(1)),
+        ]);
+        let desired = Value::Map(vec![
+            entry("tags", CRDT_type::ORSET, set(&["b"])),
+            entry("n", CRDT_type::COUNTER, Value::Counter(1)),
+            entry("on", CRDT_type::FLAG_EW, Value::Flag(false)),
+        ]);
+        let ops = reconcile(CRDT_type::RRMAP, &current, &desired).unwrap();
+
+        // the set add, with the removal of old, then the set remove
+        assert_eq!(ops.len(), 2);
+        let first = ops[0].get_mapop();
+        assert_eq!(first.get_updates().len(), 1);
+        assert_eq!(first.get_updates()[0].get_key().get_key(), b"tags");
+        assert_eq!(first.get_removedKeys().len(), 1);
+        assert_eq!(first.get_removedKeys()[0].get_key(), b"old");
+        let second = ops[1].get_mapop();
+        assert_eq!(second.get_updates().len(), 1);
+        assert!(second.get_removedKeys().is_empty());
+
+        assert!(reconcile(CRDT_type::RRMAP, &desired, &desired)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn custom_entries_are_kept_or_removed() {
+        let mine = (bytes("mine"), 42, Value::Custom(42, vec![0x08, 0x01]));
+        let map = Value::Map(vec![mine.clone()]);
+        assert!(reconcile(CRDT_type::RRMAP, &map, &map).unwrap().is_empty());
+
+        let changed = Value::Map(vec![(bytes("mine"), 42, Value::Custom(42, vec![]))]);
+        assert_eq!(
+            reconcile(CRDT_type::RRMAP, &map, &changed),
+            Err(ReconcileError::Custom(42))
+        );
+
+        let ops = reconcile(CRDT_type::RRMAP, &map, &Value::Map(vec![])).unwrap();
+        let removed = &ops[0].get_mapop().get_removedKeys()[0];
+        assert_eq!(removed.get_key(), b"mine");
+        assert_eq!(removed.type_number(), 42);
+    }
+}
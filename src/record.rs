@@ -0,0 +1,140 @@
+use super::antidote;
+use super::codec;
+use super::crdt::{Element, MapT, MapUpdate, ReadT};
+use super::view::MapView;
+
+use std::error;
+use std::fmt;
+
+#[cfg(feature = "derive")]
+pub use antidotedb_derive::AntidoteMap;
+
+// Rust structs stored as RRMap or GMap entries, one entry per field.
+//
+// Usually derived with the `derive` feature:
+//
+//     #[derive(AntidoteMap, Default)]
+//     struct User {
+//         #[antidote(counter)]
+//         visits: i32,
+//         #[antidote(orset)]
+//         tags: Vec<String>,
+//         name: String,          // lwwreg unless told otherwise
+//         #[antidote(map)]
+//         address: Address,      // nested RRMap
+//     }
+//
+// Field attributes choose the CRDT: counter, fatcounter, bcounter, lwwreg,
+// mvreg, orset, rwset, flag_ew, flag_dw, map (RRMap) and gmap; `key = "..."`
+// renames the entry. The derive also generates a typed key per field, e.g.
+// `User::visits_key()`.
+//
+// Entries missing from the map read as the field's default. `updates` writes
+// every field except counters, which can only be incremented through their
+// key, and multi-value registers holding other than exactly one value.
+
+pub trait AntidoteMap: Sized {
+    fn from_view(view: &MapView) -> Result<Self, DecodeError>;
+
+    fn updates(&self) -> Vec<MapUpdate>;
+
+    fn read(resp: &antidote::ApbReadObjectResp) -> Result<Self, DecodeError> {
+        Self::from_view(&MapView::new(resp.get_map()))
+    }
+
+    fn write<M: MapT>(&self, map: &M) -> antidote::ApbUpdateOp {
+        map.apply(self.updates(), Vec::new())
+    }
+}
+
+// a map entry whose bytes do not decode to the field's type
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    pub key: String,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot decode map entry {:?}", self.key)
+    }
+}
+
+impl error::Error for DecodeError {}
+
+// Used by the generated code. Register and set values are codec::Bytes,
+// as the typed reads of crdt give them under either backend.
+
+#[doc(hidden)]
+pub mod __private {
+    use super::*;
+
+    use std::iter::FromIterator;
+
+    fn decode<E: Element>(key: &str, bytes: &[u8]) -> Result<E, DecodeError> {
+        E::from_bytes(bytes).ok_or_else(|| DecodeError {
+            key: key.to_string(),
+        })
+    }
+
+    pub fn counter<C, T>(view: &MapView, key: &str) -> T
+    where
+        C: ReadT<Value = i32>,
+        T: From<i32> + Default,
+    {
+        view.get::<C>(key).map(T::from).unwrap_or_default()
+    }
+
+    pub fn flag<C>(view: &MapView, key: &str) -> bool
+    where
+        C: ReadT<Value = bool>,
+    {
+        view.get::<C>(key).unwrap_or_default()
+    }
+
+    pub fn register<C, T>(view: &MapView, key: &str) -> Result<T, DecodeError>
+    where
+        C: ReadT<Value = codec::Bytes>,
+        T: Element + Default,
+    {
+        match view.get::<C>(key) {
+            Some(bytes) => decode(key, &bytes),
+            None => Ok(T::default()),
+        }
+    }
+
+    pub fn values<C, T, V>(view: &MapView, key: &str) -> Result<V, DecodeError>
+    where
+        C: ReadT<Value = Vec<codec::Bytes>>,
+        T: Element,
+        V: FromIterator<T> + Default,
+    {
+        match view.get::<C>(key) {
+            Some(values) => values.iter().map(|bytes| decode(key, bytes)).collect(),
+            None => Ok(V::default()),
+        }
+    }
+
+    // the nested view borrows from view, without decoding the entry as C
+    pub fn map<C, T>(view: &MapView, key: &str) -> Result<T, DecodeError>
+    where
+        C: ReadT<Value = MapView<'static>>,
+        T: AntidoteMap + Default,
+    {
+        match view.entry::<C>(key).and_then(|entry| entry.map()) {
+            Some(nested) => T::from_view(&nested),
+            None => Ok(T::default()),
+        }
+    }
+
+    pub fn single<'a, T, I>(values: I) -> Option<&'a T>
+    where
+        T: 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        let mut values = values.into_iter();
+        match (values.next(), values.next()) {
+            (Some(value), None) => Some(value),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use super::codec::Bytes;
+use super::crdt::{MVReg, ObjectReset, ReadT, RegT};
+use super::{AntidoteDB, Error, Transaction};
+
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Resolution of MVReg conflicts.
+//
+// resolve_with reads a register in a transaction and, when it holds several
+// concurrent values, merges them with the given function and writes the
+// result back, so later reads see a single value again:
+//
+//     let resolver = Resolver::new();
+//     let value = resolver.resolve_with(&mut db, &reg, &txn, |values| {
+//         values.iter().max().cloned().unwrap()
+//     })?;
+//
+// with_reset writes the merged value after resetting the register. Both the
+// reset and the write only remove the values the transaction read: values
+// written concurrently by other transactions survive either way, and come
+// back as a new conflict with the merged value.
+//
+// A resolver counts the reads and conflicts it went through, and can be
+// shared between threads to monitor contention over many registers.
+
+#[derive(Debug, Default)]
+pub struct Resolver {
+    reset: bool,
+    reads: AtomicU64,
+    conflicts: AtomicU64,
+    values: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConflictStats {
+    // registers read
+    pub reads: u64,
+    // reads that found more than one value
+    pub conflicts: u64,
+    // values merged by those reads
+    pub values: u64,
+}
+
+impl ConflictStats {
+    // fraction of the reads that found a conflict
+    pub fn conflict_rate(&self) -> f64 {
+        if self.reads == 0 {
+            0.0
+        } else {
+            self.conflicts as f64 / self.reads as f64
+        }
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver::default()
+    }
+
+    pub fn with_reset(mut self, reset: bool) -> Self {
+        self.reset = reset;
+        self
+    }
+
+    // the register's value, None if it was never set; merge is only called
+    // with two or more values
+    pub fn resolve_with<F>(
+        &self,
+        db: &mut AntidoteDB,
+        reg: &MVReg,
+        transaction: &Transaction,
+        merge: F,
+    ) -> Result<Option<Bytes>, Error>
+    where
+        F: FnOnce(&[Bytes]) -> Bytes,
+    {
+        let values = db.mult_read_in_transaction(slice::from_ref(reg), transaction)?;
+        let resp = values
+            .first()
+            .ok_or_else(|| Error::Protocol("empty read response".to_string()))?;
+        let mut values = MVReg::decode(resp);
+
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        if values.len() < 2 {
+            return Ok(values.pop());
+        }
+        self.conflicts.fetch_add(1, Ordering::Relaxed);
+        self.values
+            .fetch_add(values.len() as u64, Ordering::Relaxed);
+
+        let merged = merge(&values);
+
+        let mut ops = Vec::new();
+        if self.reset {
+            ops.push(reg.reset());
+        }
+        ops.push(reg.set_value(&merged));
+        db.mult_update_in_transaction(&ops, transaction)?;
+
+        Ok(Some(merged))
+    }
+
+    pub fn stats(&self) -> ConflictStats {
+        ConflictStats {
+            reads: self.reads.load(Ordering::Relaxed),
+            conflicts: self.conflicts.load(Ordering::Relaxed),
+            values: self.values.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::Object;
+    use crate::server::StandIn;
+
+    // two transactions of the same snapshot set the register, each to one
+    // of values
+    fn conflict(stand_in: &StandIn, reg: &MVReg, values: [&str; 2]) {
+        let mut first = stand_in.connect().unwrap();
+        let mut second = stand_in.connect().unwrap();
+        let t1 = first.start_transaction(None).unwrap();
+        let t2 = second.start_transaction(None).unwrap();
+        first
+            .mult_update_in_transaction(&[reg.set_value(&values[0].to_string())], &t1)
+            .unwrap();
+        second
+            .mult_update_in_transaction(&[reg.set_value(&values[1].to_string())], &t2)
+            .unwrap();
+        first.commit_transaction(&t1).unwrap();
+        second.commit_transaction(&t2).unwrap();
+    }
+
+    fn read(db: &mut AntidoteDB, reg: &MVReg, transaction: &Transaction) -> Vec<Bytes> {
+        let values = db
+            .mult_read_in_transaction(slice::from_ref(reg), transaction)
+            .unwrap();
+        MVReg::decode(&values[0])
+    }
+
+    fn bytes(s: &'static str) -> Bytes {
+        Bytes::from(s.as_bytes())
+    }
+
+    #[test]
+    fn merge_gets_every_value() {
+        let stand_in = StandIn::start().unwrap();
+        let mut db = stand_in.connect().unwrap();
+        let reg = MVReg::new("name", "resolve");
+        conflict(&stand_in, &reg, ["ada", "grace"]);
+
+        let resolver = Resolver::new();
+        let txn = db.start_transaction(None).unwrap();
+        let mut merged = Vec::new();
+        let value = resolver
+            .resolve_with(&mut db, &reg, &txn, |values| {
+                merged = values.to_vec();
+                values.iter().max().cloned().unwrap()
+            })
+            .unwrap();
+        db.commit_transaction(&txn).unwrap();
+
+        merged.sort();
+        assert_eq!(merged, vec![bytes("ada"), bytes("grace")]);
+        assert_eq!(value, Some(bytes("grace")));
+        assert_eq!(
+            resolver.stats(),
+            ConflictStats {
+                reads: 1,
+                conflicts: 1,
+                values: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn merged_value_is_written_in_the_transaction() {
+        let stand_in = StandIn::start().unwrap();
+        let mut db = stand_in.connect().unwrap();
+        let reg = MVReg::new("name", "resolve");
+        conflict(&stand_in, &reg, ["ada", "grace"]);
+
+        for &reset in &[false, true] {
+            let resolver = Resolver::new().with_reset(reset);
+            let merge = |_: &[Bytes]| bytes("merged");
+
+            // aborted, the conflict stays
+            let txn = db.start_transaction(None).unwrap();
+            resolver.resolve_with(&mut db, &reg, &txn, merge).unwrap();
+            assert_eq!(read(&mut db, &reg, &txn), vec![bytes("merged")]);
+            db.abort_transaction(&txn).unwrap();
+
+            let txn = db.start_transaction(None).unwrap();
+            assert_eq!(read(&mut db, &reg, &txn).len(), 2);
+            db.abort_transaction(&txn).unwrap();
+        }
+
+        let resolver = Resolver::new();
+        let txn = db.start_transaction(None).unwrap();
+        resolver
+            .resolve_with(&mut db, &reg, &txn, |_| bytes("merged"))
+            .unwrap();
+        db.commit_transaction(&txn).unwrap();
+
+        let txn = db.start_transaction(None).unwrap();
+        assert_eq!(read(&mut db, &reg, &txn), vec![bytes("merged")]);
+        db.commit_transaction(&txn).unwrap();
+    }
+
+    #[test]
+    fn single_values_are_not_rewritten() {
+        let stand_in = StandIn::start().unwrap();
+        let mut db = stand_in.connect().unwrap();
+        let reg = MVReg::new("name", "resolve");
+        let written = db
+            .static_update_objects(&[reg.set_value(&"ada".to_string())], None)
+            .unwrap();
+
+        let resolver = Resolver::new();
+        let txn = db.start_transaction(None).unwrap();
+        let value = resolver
+            .resolve_with(&mut db, &reg, &txn, |_| panic!("nothing to merge"))
+            .unwrap();
+        assert_eq!(value, Some(bytes("ada")));
+        // a transaction without updates commits at its snapshot
+        assert_eq!(db.commit_transaction(&txn).unwrap(), written);
+
+        let empty = MVReg::new("empty", "resolve");
+        let txn = db.start_transaction(None).unwrap();
+        let value = resolver
+            .resolve_with(&mut db, &empty, &txn, |_| panic!("nothing to merge"))
+            .unwrap();
+        assert_eq!(value, None);
+        assert_eq!(db.commit_transaction(&txn).unwrap(), written);
+        assert_eq!(resolver.stats().conflicts, 0);
+        assert_eq!(resolver.stats().reads, 2);
+    }
+}
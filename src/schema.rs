@@ -0,0 +1,632 @@
+use super::antidote;
+use super::batch::Batch;
+use super::codec::TypedMessage;
+use super::crdt::{number_name, type_from_name, type_name, CRDT_type, Object};
+use super::value::Value;
+use super::{AntidoteDB, Error, Transaction};
+
+use serde_json::Value as Json;
+
+use std::error;
+use std::fmt;
+use std::slice;
+
+// Expected CRDT types of the keys of each bucket.
+//
+// Antidote keeps objects of different types under the same key apart, so a
+// key written as a counter by one service and read as a register by another
+// goes unnoticed. A schema declares the type of every key of a bucket, by
+// pattern, and Checked rejects any request that does not follow it before
+// sending it. In TOML:
+//
+//     [buckets.users]
+//     "visits/*" = "counter"
+//
+//     [buckets.users."user/*"]
+//     type = "rrmap"
+//     entries = { name = "lwwreg", tags = "orset", "score_*" = "counter" }
+//
+// or the same structure in JSON. Types are named as by crdt::type_name. In a
+// pattern, * matches any bytes and ? a single byte; when several patterns
+// match a key, the longest one applies, and the first of equally long ones:
+// in the order of with_key, in byte order in TOML and JSON.
+//
+// Buckets that are not declared are not checked. In a declared bucket, or
+// in a map declared with entries, keys matching no pattern are rejected.
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Schema {
+    buckets: Vec<(Vec<u8>, Vec<Rule>)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Rule {
+    pattern: Vec<u8>,
+    crdt_type: CRDT_type,
+    // map entries, None when they are not checked
+    entries: Option<Vec<Rule>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemaError {
+    // the schema itself is malformed
+    Parse(String),
+    // key, then the map keys leading to the entry, matches no pattern
+    Undeclared {
+        bucket: Vec<u8>,
+        path: Vec<Vec<u8>>,
+    },
+    // key, then the map keys leading to the entry, has the wrong type, found
+    // being a type number as custom kinds are never declared
+    Mismatch {
+        bucket: Vec<u8>,
+        path: Vec<Vec<u8>>,
+        expected: CRDT_type,
+        found: i32,
+    },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaError::Parse(msg) => write!(f, "invalid schema: {}", msg),
+            SchemaError::Undeclared { bucket, path } => {
+                write!(f, "undeclared key ")?;
+                write_path(f, bucket, path)
+            }
+            SchemaError::Mismatch {
+                bucket,
+                path,
+                expected,
+                found,
+            } => {
+                write_path(f, bucket, path)?;
+                write!(
+                    f,
+                    " is a {}, not a {}",
+                    type_name(*expected),
+                    number_name(*found)
+                )
+            }
+        }
+    }
+}
+
+fn write_path(f: &mut fmt::Formatter, bucket: &[u8], path: &[Vec<u8>]) -> fmt::Result {
+    write!(f, "{}", Value::bytes(bucket))?;
+    for key in path {
+        write!(f, " / {}", Value::bytes(key))?;
+    }
+    Ok(())
+}
+
+impl error::Error for SchemaError {}
+
+impl Schema {
+    pub fn new() -> Self {
+        Schema::default()
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, SchemaError> {
+        let json: Json =
+            serde_json::from_str(text).map_err(|e| SchemaError::Parse(e.to_string()))?;
+        Schema::from_value(&json)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, SchemaError> {
+        let toml: toml::Value =
+            toml::from_str(text).map_err(|e| SchemaError::Parse(e.to_string()))?;
+        let json = serde_json::to_value(toml).map_err(|e| SchemaError::Parse(e.to_string()))?;
+        Schema::from_value(&json)
+    }
+
+    fn from_value(json: &Json) -> Result<Self, SchemaError> {
+        let buckets = json
+            .get("buckets")
+            .and_then(Json::as_object)
+            .ok_or_else(|| SchemaError::Parse("missing buckets table".to_string()))?;
+
+        let mut schema = Schema::new();
+        for (bucket, keys) in buckets {
+            schema
+                .buckets
+                .push((bucket.as_bytes().to_vec(), parse_rules(keys)?));
+        }
+        Ok(schema)
+    }
+
+    // declares key pattern of bucket as crdt_type; maps declared this way
+    // have their entries unchecked
+    pub fn with_key(mut self, bucket: &str, pattern: &str, crdt_type: CRDT_type) -> Self {
+        let rule = Rule {
+            pattern: pattern.as_bytes().to_vec(),
+            crdt_type,
+            entries: None,
+        };
+        match self
+            .buckets
+            .iter_mut()
+            .find(|(b, _)| b == bucket.as_bytes())
+        {
+            Some((_, rules)) => rules.push(rule),
+            None => self.buckets.push((bucket.as_bytes().to_vec(), vec![rule])),
+        }
+        self
+    }
+
+    pub fn check_object(&self, object: &antidote::ApbBoundObject) -> Result<(), SchemaError> {
+        self.check(object, None)
+    }
+
+    // checks the updated object, and the map keys of map updates
+    pub fn check_update(&self, op: &antidote::ApbUpdateOp) -> Result<(), SchemaError> {
+        self.check(op.get_boundobject(), Some(op.get_operation()))
+    }
+
+    fn check(
+        &self,
+        object: &antidote::ApbBoundObject,
+        operation: Option<&antidote::ApbUpdateOperation>,
+    ) -> Result<(), SchemaError> {
+        let bucket = object.get_bucket();
+        let rules = match self.buckets.iter().find(|(b, _)| b == bucket) {
+            Some((_, rules)) => rules,
+            None => return Ok(()),
+        };
+
+        let mut path = vec![object.get_key().to_vec()];
+        let rule = check_key(rules, bucket, &path, object.type_number())?;
+        match (operation, &rule.entries) {
+            (Some(operation), Some(entries)) => check_map(entries, bucket, &mut path, operation),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn parse_rules(keys: &Json) -> Result<Vec<Rule>, SchemaError> {
+    let keys = keys
+        .as_object()
+        .ok_or_else(|| SchemaError::Parse("keys must be a table".to_string()))?;
+
+    keys.iter()
+        .map(|(pattern, rule)| {
+            let (name, entries) = match rule {
+                Json::String(name) => (name.as_str(), None),
+                Json::Object(table) => {
+                    let name = table.get("type").and_then(Json::as_str).ok_or_else(|| {
+                        SchemaError::Parse(format!("missing type for {:?}", pattern))
+                    })?;
+                    let entries = match table.get("entries") {
+                        Some(entries) => Some(parse_rules(entries)?),
+                        None => None,
+                    };
+                    (name, entries)
+                }
+                _ => {
+                    return Err(SchemaError::Parse(format!(
+                        "invalid rule for {:?}",
+                        pattern
+                    )))
+                }
+            };
+            let crdt_type = type_from_name(name)
+                .ok_or_else(|| SchemaError::Parse(format!("unknown type {:?}", name)))?;
+
+            Ok(Rule {
+                pattern: pattern.as_bytes().to_vec(),
+                crdt_type,
+                entries,
+            })
+        })
+        .collect()
+}
+
+// the rule of the last key of path
+fn check_key<'a>(
+    rules: &'a [Rule],
+    bucket: &[u8],
+    path: &[Vec<u8>],
+    type_number: i32,
+) -> Result<&'a Rule, SchemaError> {
+    let key = path.last().map(Vec::as_slice).unwrap_or_default();
+    let rule = rules
+        .iter()
+        .filter(|rule| matches(&rule.pattern, key))
+        .fold(None, |longest: Option<&Rule>, rule| match longest {
+            Some(longest) if longest.pattern.len() >= rule.pattern.len() => Some(longest),
+            _ => Some(rule),
+        })
+        .ok_or_else(|| SchemaError::Undeclared {
+            bucket: bucket.to_vec(),
+            path: path.to_vec(),
+        })?;
+
+    if rule.crdt_type as i32 != type_number {
+        return Err(SchemaError::Mismatch {
+            bucket: bucket.to_vec(),
+            path: path.to_vec(),
+            expected: rule.crdt_type,
+            found: type_number,
+        });
+    }
+    Ok(rule)
+}
+
+fn check_map(
+    entries: &[Rule],
+    bucket: &[u8],
+    path: &mut Vec<Vec<u8>>,
+    operation: &antidote::ApbUpdateOperation,
+) -> Result<(), SchemaError> {
+    if !operation.has_mapop() {
+        return Ok(());
+    }
+    let mapop = operation.get_mapop();
+
+    for update in mapop.get_updates() {
+        let key = update.get_key();
+        path.push(key.get_key().to_vec());
+        let rule = check_key(entries, bucket, path, key.type_number())?;
+        if let Some(nested) = &rule.entries {
+            check_map(nested, bucket, path, update.get_update())?;
+        }
+        path.pop();
+    }
+
+    for key in mapop.get_removedKeys() {
+        path.push(key.get_key().to_vec());
+        check_key(entries, bucket, path, key.type_number())?;
+        path.pop();
+    }
+
+    Ok(())
+}
+
+// glob match, * for any bytes and ? for one
+fn matches(pattern: &[u8], key: &[u8]) -> bool {
+    let (mut p, mut k) = (0, 0);
+    // position after the last *, and the key position it was tried at
+    let mut star = None;
+
+    while k < key.len() {
+        // * before the literal test, a key may hold a * itself
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p + 1, k));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == key[k]) {
+            p += 1;
+            k += 1;
+        } else if let Some((after, tried)) = star {
+            p = after;
+            k = tried + 1;
+            star = Some((after, tried + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+// A connection that checks every object it reads or updates against a schema.
+//
+// Batches are decoded to be checked, and reconcile_in_transaction checks the
+// operations it computes before writing them. The connection given back by
+// into_inner is not checked, nor are the batches or reconciles sent on it.
+#[derive(Debug)]
+pub struct Checked {
+    db: AntidoteDB,
+    schema: Schema,
+}
+
+impl Checked {
+    pub fn new(db: AntidoteDB, schema: Schema) -> Self {
+        Checked { db, schema }
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn check_objects<T: Object>(&self, objects: &[T]) -> Result<(), Error> {
+        for object in objects {
+            self.schema
+                .check_object(&object.get_object())
+                .map_err(Error::Schema)?;
+        }
+        Ok(())
+    }
+
+    fn check_updates(&self, apb_operations: &[antidote::ApbUpdateOp]) -> Result<(), Error> {
+        check_updates(&self.schema, apb_operations)
+    }
+
+    fn check_batch(&self, batch: &Batch) -> Result<(), Error> {
+        for op in batch.updates() {
+            self.schema.check_update(&op?).map_err(Error::Schema)?;
+        }
+        Ok(())
+    }
+
+    pub fn start_transaction(&mut self, timestamp: Option<&Vec<u8>>) -> Result<Transaction, Error> {
+        self.db.start_transaction(timestamp)
+    }
+
+    pub fn mult_read_in_transaction<T: Object>(
+        &mut self,
+        objects: &[T],
+        t: &Transaction,
+    ) -> Result<Vec<antidote::ApbReadObjectResp>, Error> {
+        self.check_objects(objects)?;
+        self.db.mult_read_in_transaction(objects, t)
+    }
+
+    pub fn mult_update_in_transaction(
+        &mut self,
+        apb_operations: &[antidote::ApbUpdateOp],
+        t: &Transaction,
+    ) -> Result<(), Error> {
+        self.check_updates(apb_operations)?;
+        self.db.mult_update_in_transaction(apb_operations, t)
+    }
+
+    pub fn commit_transaction(&mut self, t: &Transaction) -> Result<Vec<u8>, Error> {
+        self.db.commit_transaction(t)
+    }
+
+    pub fn abort_transaction(&mut self, t: &Transaction) -> Result<(), Error> {
+        self.db.abort_transaction(t)
+    }
+
+    pub fn static_update_objects(
+        &mut self,
+        apb_operations: &[antidote::ApbUpdateOp],
+        timestamp: Option<&Vec<u8>>,
+    ) -> Result<Vec<u8>, Error> {
+        self.check_updates(apb_operations)?;
+        self.db.static_update_objects(apb_operations, timestamp)
+    }
+
+    pub fn static_read_objects<T: Object>(
+        &mut self,
+        objects: &[T],
+        timestamp: Option<&Vec<u8>>,
+    ) -> Result<(Vec<antidote::ApbReadObjectResp>, Vec<u8>), Error> {
+        self.check_objects(objects)?;
+        self.db.static_read_objects(objects, timestamp)
+    }
+
+    pub fn update_batch_in_transaction(
+        &mut self,
+        batch: &Batch,
+        t: &Transaction,
+    ) -> Result<(), Error> {
+        self.check_batch(batch)?;
+        self.db.update_batch_in_transaction(batch, t)
+    }
+
+    pub fn static_update_batch(
+        &mut self,
+        batch: &Batch,
+        timestamp: Option<&Vec<u8>>,
+    ) -> Result<Vec<u8>, Error> {
+        self.check_batch(batch)?;
+        self.db.static_update_batch(batch, timestamp)
+    }
+
+    pub fn reconcile_in_transaction<T: Object>(
+        &mut self,
+        object: &T,
+        desired: &Value,
+        transaction: &Transaction,
+    ) -> Result<Vec<antidote::ApbUpdateOp>, Error> {
+        self.check_objects(slice::from_ref(object))?;
+        let schema = &self.schema;
+        self.db
+            .reconcile_checked(object, desired, transaction, |ops| {
+                check_updates(schema, ops)
+            })
+    }
+
+    pub fn into_inner(self) -> AntidoteDB {
+        self.db
+    }
+}
+
+fn check_updates(schema: &Schema, apb_operations: &[antidote::ApbUpdateOp]) -> Result<(), Error> {
+    for op in apb_operations {
+        schema.check_update(op).map_err(Error::Schema)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{Counter, CounterT, LWWReg, MapKey, MapT, RRMap, RegT};
+    use crate::server::StandIn;
+
+    fn object<T: Object>(key: &str, bucket: &str) -> antidote::ApbBoundObject {
+        T::new(key, bucket).get_object()
+    }
+
+    fn mismatch(path: &[&str], expected: CRDT_type, found: CRDT_type) -> SchemaError {
+        SchemaError::Mismatch {
+            bucket: b"users".to_vec(),
+            path: path.iter().map(|key| key.as_bytes().to_vec()).collect(),
+            expected,
+            found: found as i32,
+        }
+    }
+
+    fn users() -> Schema {
+        Schema::from_toml(
+            r#"
+            [buckets.users]
+            "visits/*" = "counter"
+
+            [buckets.users."user/*"]
+            type = "rrmap"
+            entries = { name = "lwwreg", "score_*" = "counter" }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn checked_rejects_type_mismatches() {
+        let stand_in = StandIn::start().unwrap();
+        let mut db = Checked::new(stand_in.connect().unwrap(), users());
+
+        let visits = Counter::new("visits/1", "users");
+        db.static_update_objects(&[visits.inc(1)], None).unwrap();
+
+        let register = LWWReg::new("visits/1", "users");
+        let update = db.static_update_objects(&[register.set_value(&1u64)], None);
+        assert!(matches!(
+            update,
+            Err(Error::Schema(ref e))
+                if *e == mismatch(&["visits/1"], CRDT_type::COUNTER, CRDT_type::LWWREG)
+        ));
+        let read = db.static_read_objects(&[register], None);
+        assert!(matches!(
+            read,
+            Err(Error::Schema(SchemaError::Mismatch { .. }))
+        ));
+
+        let unknown = Counter::new("likes/1", "users");
+        assert!(matches!(
+            db.static_update_objects(&[unknown.inc(1)], None),
+            Err(Error::Schema(SchemaError::Undeclared { .. }))
+        ));
+        // undeclared buckets are not checked
+        let other = LWWReg::new("visits/1", "other");
+        db.static_update_objects(&[other.set_value(&1u64)], None)
+            .unwrap();
+    }
+
+    #[test]
+    fn longest_pattern_then_first_applies() {
+        let schema = Schema::new()
+            .with_key("users", "user/*", CRDT_type::RRMAP)
+            .with_key("users", "user/admin", CRDT_type::LWWREG)
+            .with_key("users", "a?", CRDT_type::COUNTER)
+            .with_key("users", "?b", CRDT_type::LWWREG);
+
+        assert!(schema
+            .check_object(&object::<LWWReg>("user/admin", "users"))
+            .is_ok());
+        assert!(schema
+            .check_object(&object::<RRMap>("user/ada", "users"))
+            .is_ok());
+        assert_eq!(
+            schema.check_object(&object::<RRMap>("user/admin", "users")),
+            Err(mismatch(
+                &["user/admin"],
+                CRDT_type::LWWREG,
+                CRDT_type::RRMAP
+            ))
+        );
+        // "ab" matches both patterns of two bytes, the first one applies
+        assert!(schema
+            .check_object(&object::<Counter>("ab", "users"))
+            .is_ok());
+        assert_eq!(
+            schema.check_object(&object::<LWWReg>("ab", "users")),
+            Err(mismatch(&["ab"], CRDT_type::COUNTER, CRDT_type::LWWREG))
+        );
+    }
+
+    #[test]
+    fn map_entries_are_checked() {
+        let user = RRMap::new("user/ada", "users");
+        let ok = user.apply(vec![MapKey::<Counter>::new("score_1").inc(1)], Vec::new());
+        assert!(users().check_update(&ok).is_ok());
+
+        let bad = user.apply(
+            vec![MapKey::<LWWReg>::new("score_1").set_value(&1u64)],
+            Vec::new(),
+        );
+        assert_eq!(
+            users().check_update(&bad),
+            Err(mismatch(
+                &["user/ada", "score_1"],
+                CRDT_type::COUNTER,
+                CRDT_type::LWWREG
+            ))
+        );
+    }
+
+    #[test]
+    fn batches_and_reconciles_are_checked() {
+        let stand_in = StandIn::start().unwrap();
+        let mut db = Checked::new(stand_in.connect().unwrap(), users());
+
+        let mut batch = Batch::new();
+        batch.push(&Counter::new("visits/1", "users"), &Counter::inc_op(1));
+        db.static_update_batch(&batch, None).unwrap();
+        batch.push(&Counter::new("likes/1", "users"), &Counter::inc_op(1));
+        assert!(matches!(
+            db.static_update_batch(&batch, None),
+            Err(Error::Schema(SchemaError::Undeclared { .. }))
+        ));
+
+        let user = RRMap::new("user/ada", "users");
+        let desired = Value::Map(vec![(
+            b"age".to_vec(),
+            CRDT_type::COUNTER as i32,
+            Value::Counter(36),
+        )]);
+        let txn = db.start_transaction(None).unwrap();
+        assert!(matches!(
+            db.reconcile_in_transaction(&user, &desired, &txn),
+            Err(Error::Schema(SchemaError::Undeclared { .. }))
+        ));
+        let desired = Value::Map(vec![(
+            b"score_1".to_vec(),
+            CRDT_type::COUNTER as i32,
+            Value::Counter(36),
+        )]);
+        assert_eq!(
+            db.reconcile_in_transaction(&user, &desired, &txn)
+                .unwrap()
+                .len(),
+            1
+        );
+        db.commit_transaction(&txn).unwrap();
+    }
+
+    #[test]
+    fn literals_and_question_marks() {
+        assert!(matches(b"user", b"user"));
+        assert!(!matches(b"user", b"users"));
+        assert!(!matches(b"users", b"user"));
+        assert!(matches(b"user?", b"user1"));
+        assert!(!matches(b"user?", b"user"));
+        assert!(matches(b"", b""));
+        assert!(!matches(b"", b"a"));
+    }
+
+    #[test]
+    fn stars_match_any_bytes() {
+        assert!(matches(b"*", b""));
+        assert!(matches(b"*", b"anything"));
+        assert!(matches(b"user:*", b"user:"));
+        assert!(matches(b"user:*", b"user:42"));
+        assert!(matches(b"*:name", b"user:42:name"));
+        assert!(matches(b"a*b*c", b"aXXbYYbZc"));
+        assert!(!matches(b"a*b*c", b"aXXbYY"));
+        assert!(matches(b"**a", b"ba"));
+        assert!(matches(b"*?", b"x"));
+        assert!(!matches(b"*?", b""));
+    }
+
+    #[test]
+    fn stars_in_keys() {
+        assert!(matches(b"*a", b"*ba"));
+        assert!(matches(b"*", b"*"));
+        assert!(matches(b"a*", b"a**"));
+        assert!(matches(b"?*b", b"**ab"));
+        assert!(!matches(b"*a", b"*b"));
+    }
+}
@@ -0,0 +1,358 @@
+use super::admin::ConnectionDescriptor;
+use super::antidote;
+use super::{read_frame, write_frame, AntidoteDB, Error};
+
+use super::codec::{self, ProtobufEnum, RepeatedField};
+
+use self::store::Store;
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// In-process stand-in for an Antidote node, to test clients and tooling
+// without a running cluster. It listens on a local port and speaks the
+// protocol buffer API, one thread per connection.
+//
+// It answers the DC management requests and records what it was asked to do.
+// Transactions, interactive and static, run against objects kept in memory,
+// see store.rs. Other requests get an error response.
+//
+// It is only built with the stand-in feature, and for the crate's own tests.
+
+mod store;
+
+#[derive(Debug)]
+pub struct StandIn {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    running: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    dc_nodes: Vec<String>,
+    connected_dcs: Vec<ConnectionDescriptor>,
+    store: Store,
+    static_read_delay: Duration,
+}
+
+impl StandIn {
+    pub fn start() -> Result<Self, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        {
+            let state = state.clone();
+            let running = running.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if !running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let state = state.clone();
+                        thread::spawn(move || serve(stream, address, &state));
+                    }
+                }
+            });
+        }
+
+        Ok(StandIn {
+            address,
+            state,
+            running,
+        })
+    }
+
+    pub fn address(&self) -> String {
+        self.address.to_string()
+    }
+
+    pub fn connect(&self) -> Result<AntidoteDB, Error> {
+        AntidoteDB::connect_with_string(&self.address())
+    }
+
+    // the descriptor this stand-in hands out
+    pub fn descriptor(&self) -> ConnectionDescriptor {
+        descriptor(self.address)
+    }
+
+    // nodes of the last create_dc request
+    pub fn dc_nodes(&self) -> Vec<String> {
+        self.state.lock().unwrap().dc_nodes.clone()
+    }
+
+    pub fn connected_dcs(&self) -> Vec<ConnectionDescriptor> {
+        self.state.lock().unwrap().connected_dcs.clone()
+    }
+
+    // answer static reads only after the delay, to stand in for a slow node
+    pub fn delay_static_reads(&self, delay: Duration) {
+        self.state.lock().unwrap().static_read_delay = delay;
+    }
+}
+
+impl Drop for StandIn {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        // wake up the accepting thread so that it sees the flag
+        let _ = TcpStream::connect(self.address);
+    }
+}
+
+fn descriptor(address: SocketAddr) -> ConnectionDescriptor {
+    ConnectionDescriptor::from(format!("stand-in@{}", address).into_bytes())
+}
+
+fn serve(mut stream: TcpStream, address: SocketAddr, state: &Mutex<State>) {
+    while let Ok((code, bytes)) = read_frame(&mut stream) {
+        let written = match antidote::MessageCode::from_i32(i32::from(code)) {
+            Some(code) => respond(&mut stream, code, bytes, address, state),
+            None => Err(Error::Protocol(format!("unknown message code {}", code))),
+        };
+        // malformed requests are answered, broken connections are closed
+        let written = match written {
+            Err(Error::Protocol(message)) => error_response(&mut stream, &message),
+            written => written,
+        };
+        if written.is_err() {
+            break;
+        }
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    code: antidote::MessageCode,
+    bytes: Vec<u8>,
+    address: SocketAddr,
+    state: &Mutex<State>,
+) -> Result<(), Error> {
+    match code {
+        antidote::MessageCode::apbCreateDC => {
+            let request = codec::decode::<antidote::ApbCreateDC>(bytes)?;
+            let mut response = antidote::ApbCreateDCResp::new();
+            if request.get_nodes().is_empty() {
+                response.set_success(false);
+                response.set_errorcode(0);
+            } else {
+                state.lock().unwrap().dc_nodes = request.get_nodes().to_vec();
+                response.set_success(true);
+            }
+            write_frame(stream, antidote::MessageCode::apbCreateDCResp, &response)
+        }
+        antidote::MessageCode::apbGetConnectionDescriptor => {
+            let mut response = antidote::ApbGetConnectionDescriptorResp::new();
+            response.set_success(true);
+            response.set_descriptor(descriptor(address).into_bytes());
+            write_frame(
+                stream,
+                antidote::MessageCode::apbGetConnectionDescriptorResp,
+                &response,
+            )
+        }
+        antidote::MessageCode::apbConnectToDCs => {
+            let request = codec::decode::<antidote::ApbConnectToDCs>(bytes)?;
+            let descriptors = codec::to_vecs(request.get_descriptors());
+            state
+                .lock()
+                .unwrap()
+                .connected_dcs
+                .extend(descriptors.into_iter().map(ConnectionDescriptor::from));
+            let mut response = antidote::ApbConnectToDCsResp::new();
+            response.set_success(true);
+            write_frame(
+                stream,
+                antidote::MessageCode::apbConnectToDCsResp,
+                &response,
+            )
+        }
+        antidote::MessageCode::apbStartTransaction => {
+            let request = codec::decode::<antidote::ApbStartTransaction>(bytes)?;
+            let descriptor = state
+                .lock()
+                .unwrap()
+                .store
+                .start(request.get_timestamp())
+                .map_err(Error::Protocol)?;
+            let mut response = antidote::ApbStartTransactionResp::new();
+            response.set_success(true);
+            response.set_transaction_descriptor(descriptor);
+            write_frame(
+                stream,
+                antidote::MessageCode::apbStartTransactionResp,
+                &response,
+            )
+        }
+        antidote::MessageCode::apbReadObjects => {
+            let request = codec::decode::<antidote::ApbReadObjects>(bytes)?;
+            let objects = state
+                .lock()
+                .unwrap()
+                .store
+                .read(
+                    request.get_transaction_descriptor(),
+                    request.get_boundobjects(),
+                )
+                .map_err(Error::Protocol)?;
+            let mut response = antidote::ApbReadObjectsResp::new();
+            response.set_success(true);
+            response.set_objects(RepeatedField::from_vec(objects));
+            write_frame(stream, antidote::MessageCode::apbReadObjectsResp, &response)
+        }
+        antidote::MessageCode::apbUpdateObjects => {
+            let request = codec::decode::<antidote::ApbUpdateObjects>(bytes)?;
+            state
+                .lock()
+                .unwrap()
+                .store
+                .update(request.get_transaction_descriptor(), request.get_updates())
+                .map_err(Error::Protocol)?;
+            operation_response(stream)
+        }
+        antidote::MessageCode::apbCommitTransaction => {
+            let request = codec::decode::<antidote::ApbCommitTransaction>(bytes)?;
+            let commit_time = state
+                .lock()
+                .unwrap()
+                .store
+                .commit(request.get_transaction_descriptor())
+                .map_err(Error::Protocol)?;
+            write_frame(
+                stream,
+                antidote::MessageCode::apbCommitResp,
+                &commit_response(commit_time),
+            )
+        }
+        antidote::MessageCode::apbAbortTransaction => {
+            let request = codec::decode::<antidote::ApbAbortTransaction>(bytes)?;
+            state
+                .lock()
+                .unwrap()
+                .store
+                .abort(request.get_transaction_descriptor())
+                .map_err(Error::Protocol)?;
+            operation_response(stream)
+        }
+        antidote::MessageCode::apbStaticUpdateObjects => {
+            let request = codec::decode::<antidote::ApbStaticUpdateObjects>(bytes)?;
+            let commit_time = {
+                let store = &mut state.lock().unwrap().store;
+                let timestamp = request.get_transaction().get_timestamp();
+                let descriptor = store.start(timestamp).map_err(Error::Protocol)?;
+                let updated = store.update(&descriptor, request.get_updates());
+                let commit_time = store.commit(&descriptor);
+                updated.and(commit_time).map_err(Error::Protocol)?
+            };
+            write_frame(
+                stream,
+                antidote::MessageCode::apbCommitResp,
+                &commit_response(commit_time),
+            )
+        }
+        antidote::MessageCode::apbStaticReadObjects => {
+            let request = codec::decode::<antidote::ApbStaticReadObjects>(bytes)?;
+            let delay = state.lock().unwrap().static_read_delay;
+            thread::sleep(delay);
+            let (objects, commit_time) = {
+                let store = &mut state.lock().unwrap().store;
+                let timestamp = request.get_transaction().get_timestamp();
+                let descriptor = store.start(timestamp).map_err(Error::Protocol)?;
+                let objects = store.read(&descriptor, request.get_objects());
+                let commit_time = store.commit(&descriptor);
+                (
+                    objects.map_err(Error::Protocol)?,
+                    commit_time.map_err(Error::Protocol)?,
+                )
+            };
+            let mut read = antidote::ApbReadObjectsResp::new();
+            read.set_success(true);
+            read.set_objects(RepeatedField::from_vec(objects));
+            let mut response = antidote::ApbStaticReadObjectsResp::new();
+            response.set_objects(read);
+            response.set_committime(commit_response(commit_time));
+            write_frame(
+                stream,
+                antidote::MessageCode::apbStaticReadObjectsResp,
+                &response,
+            )
+        }
+        code => Err(Error::Protocol(format!(
+            "{:?} is not supported by the stand-in",
+            code
+        ))),
+    }
+}
+
+fn operation_response(stream: &mut TcpStream) -> Result<(), Error> {
+    let mut response = antidote::ApbOperationResp::new();
+    response.set_success(true);
+    write_frame(stream, antidote::MessageCode::apbOperationResp, &response)
+}
+
+fn commit_response(commit_time: Vec<u8>) -> antidote::ApbCommitResp {
+    let mut response = antidote::ApbCommitResp::new();
+    response.set_success(true);
+    response.set_commit_time(commit_time);
+    response
+}
+
+fn error_response(stream: &mut TcpStream, message: &str) -> Result<(), Error> {
+    let mut response = antidote::ApbErrorResp::new();
+    response.set_errmsg(message.as_bytes().to_vec());
+    response.set_errcode(0);
+    write_frame(stream, antidote::MessageCode::apbErrorResp, &response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::crdt::{Counter, CounterT, Object};
+    use super::*;
+
+    #[test]
+    fn snapshots_start_at_or_after_their_timestamp() {
+        let stand_in = StandIn::start().unwrap();
+        let mut db = stand_in.connect().unwrap();
+        let counter = Counter::new("visits", "web");
+
+        let first = db.static_update_objects(&[counter.inc(1)], None).unwrap();
+        let second = db.static_update_objects(&[counter.inc(1)], None).unwrap();
+        // an older timestamp reads the latest commit
+        let (values, _) = db
+            .static_read_objects(std::slice::from_ref(&counter), Some(&first))
+            .unwrap();
+        assert_eq!(values[0].get_counter().get_value(), 2);
+
+        // a timestamp the stand-in has not reached, after two commits, is
+        // refused
+        let ahead = 3u64.to_be_bytes().to_vec();
+        assert!(matches!(
+            db.start_transaction(Some(&ahead)),
+            Err(Error::Server { .. })
+        ));
+        assert!(matches!(
+            db.static_read_objects(std::slice::from_ref(&counter), Some(&ahead)),
+            Err(Error::Server { .. })
+        ));
+        assert!(db.start_transaction(Some(&second)).is_ok());
+    }
+
+    #[test]
+    fn counters_past_32_bits_saturate() {
+        let stand_in = StandIn::start().unwrap();
+        let mut db = stand_in.connect().unwrap();
+        let up = Counter::new("up", "web");
+        let down = Counter::new("down", "web");
+
+        db.static_update_objects(&[up.inc(i64::MAX), up.inc(1), down.inc(i64::MIN)], None)
+            .unwrap();
+        let (values, _) = db.static_read_objects(&[up, down], None).unwrap();
+        assert_eq!(values[0].get_counter().get_value(), i32::MAX);
+        assert_eq!(values[1].get_counter().get_value(), i32::MIN);
+    }
+}
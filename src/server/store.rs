@@ -0,0 +1,366 @@
+use super::super::antidote::{self, CRDT_type};
+use super::super::codec::{self, ProtobufEnum, RepeatedField, TypedMessage};
+use super::super::crdt::type_name;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use std::collections::hash_map::Entry;
+use std::collections::{btree_map, BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+
+// Objects of the stand-in, kept in memory with a version per commit.
+//
+// Commits are numbered by a clock, sent as 8 bytes big endian commit times.
+// Transactions read the latest commit as of their start, which is at or after
+// the timestamp they are given, and their own updates, which are applied at
+// commit. A timestamp past the clock was not handed out by the stand-in and
+// is refused, where a node would wait for the commits of other DCs. CRDT
+// updates never conflict, so commits always succeed. A write to a multi-value
+// register replaces the values its transaction could read, values committed
+// after its snapshot are concurrent and kept next to it. Versions no snapshot
+// can read anymore are dropped.
+
+#[derive(Debug, Default)]
+pub(super) struct Store {
+    clock: u64,
+    // versions of each object, by commit time
+    objects: HashMap<ObjectId, Vec<(u64, Crdt)>>,
+    transactions: HashMap<u64, Txn>,
+    last_txn: u64,
+}
+
+// bucket, key and type number
+type ObjectId = (Vec<u8>, Vec<u8>, i32);
+
+#[derive(Debug)]
+struct Txn {
+    snapshot: u64,
+    updates: Vec<antidote::ApbUpdateOp>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Crdt {
+    Counter(i64),
+    Register(Vec<u8>),
+    // values with the time of their commit
+    MultiRegister(Vec<(u64, Vec<u8>)>),
+    Set(BTreeSet<Vec<u8>>),
+    Flag(bool),
+    Map(BTreeMap<(Vec<u8>, i32), Crdt>),
+}
+
+impl Store {
+    // the descriptor of a new transaction, reading after timestamp unless it
+    // is empty
+    pub(super) fn start(&mut self, timestamp: &[u8]) -> Result<Vec<u8>, String> {
+        if !timestamp.is_empty() {
+            let time = transaction_time(timestamp)?;
+            if time > self.clock {
+                return Err(format!(
+                    "snapshot time {} is past the last commit {}",
+                    time, self.clock
+                ));
+            }
+        }
+        self.last_txn += 1;
+        let txn = Txn {
+            snapshot: self.clock,
+            updates: Vec::new(),
+        };
+        self.transactions.insert(self.last_txn, txn);
+        Ok(time_bytes(self.last_txn))
+    }
+
+    pub(super) fn read(
+        &self,
+        descriptor: &[u8],
+        objects: &[antidote::ApbBoundObject],
+    ) -> Result<Vec<antidote::ApbReadObjectResp>, String> {
+        let txn = self.transaction(descriptor)?;
+        objects
+            .iter()
+            .map(|object| {
+                let id = object_id(object);
+                let mut crdt = self.version(&id, txn.snapshot)?;
+                for update in &txn.updates {
+                    if object_id(update.get_boundobject()) == id {
+                        crdt.apply(id.2, update.get_operation(), txn.snapshot, txn.snapshot)?;
+                    }
+                }
+                Ok(crdt.read())
+            })
+            .collect()
+    }
+
+    pub(super) fn update(
+        &mut self,
+        descriptor: &[u8],
+        updates: &[antidote::ApbUpdateOp],
+    ) -> Result<(), String> {
+        // updates are checked before they are buffered, commit cannot fail
+        for update in updates {
+            let id = object_id(update.get_boundobject());
+            let mut crdt = Crdt::new(id.2)?;
+            crdt.apply(id.2, update.get_operation(), 0, 0)?;
+        }
+        let id = transaction_id(descriptor)?;
+        let txn = self
+            .transactions
+            .get_mut(&id)
+            .ok_or("unknown transaction")?;
+        txn.updates.extend_from_slice(updates);
+        Ok(())
+    }
+
+    // the commit time
+    pub(super) fn commit(&mut self, descriptor: &[u8]) -> Result<Vec<u8>, String> {
+        let id = transaction_id(descriptor)?;
+        let txn = self.transactions.remove(&id).ok_or("unknown transaction")?;
+        if txn.updates.is_empty() {
+            return Ok(time_bytes(txn.snapshot));
+        }
+
+        self.clock += 1;
+        let mut written: HashMap<ObjectId, Crdt> = HashMap::new();
+        for update in &txn.updates {
+            let id = object_id(update.get_boundobject());
+            let type_number = id.2;
+            let crdt = match written.entry(id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let crdt = self.version(entry.key(), self.clock)?;
+                    entry.insert(crdt)
+                }
+            };
+            crdt.apply(
+                type_number,
+                update.get_operation(),
+                txn.snapshot,
+                self.clock,
+            )?;
+        }
+        let oldest = self
+            .transactions
+            .values()
+            .map(|txn| txn.snapshot)
+            .min()
+            .unwrap_or(self.clock);
+        for (id, crdt) in written {
+            let versions = self.objects.entry(id).or_default();
+            versions.push((self.clock, crdt));
+            // the latest version at or before oldest is the last one needed
+            let needed = versions
+                .iter()
+                .rposition(|(time, _)| *time <= oldest)
+                .unwrap_or(0);
+            versions.drain(..needed);
+        }
+        Ok(time_bytes(self.clock))
+    }
+
+    pub(super) fn abort(&mut self, descriptor: &[u8]) -> Result<(), String> {
+        let id = transaction_id(descriptor)?;
+        self.transactions
+            .remove(&id)
+            .map(|_| ())
+            .ok_or_else(|| "unknown transaction".to_string())
+    }
+
+    fn transaction(&self, descriptor: &[u8]) -> Result<&Txn, String> {
+        let id = transaction_id(descriptor)?;
+        self.transactions
+            .get(&id)
+            .ok_or_else(|| "unknown transaction".to_string())
+    }
+
+    // the object as of snapshot, empty if it was not written before
+    fn version(&self, id: &ObjectId, snapshot: u64) -> Result<Crdt, String> {
+        let version = self
+            .objects
+            .get(id)
+            .and_then(|versions| versions.iter().rev().find(|(time, _)| *time <= snapshot));
+        match version {
+            Some((_, crdt)) => Ok(crdt.clone()),
+            None => Crdt::new(id.2),
+        }
+    }
+}
+
+impl Crdt {
+    fn new(type_number: i32) -> Result<Self, String> {
+        let crdt = match CRDT_type::from_i32(type_number) {
+            Some(CRDT_type::COUNTER) | Some(CRDT_type::FATCOUNTER) | Some(CRDT_type::BCOUNTER) => {
+                Crdt::Counter(0)
+            }
+            Some(CRDT_type::LWWREG) => Crdt::Register(Vec::new()),
+            Some(CRDT_type::MVREG) => Crdt::MultiRegister(Vec::new()),
+            Some(CRDT_type::ORSET) | Some(CRDT_type::RWSET) => Crdt::Set(BTreeSet::new()),
+            Some(CRDT_type::FLAG_EW) | Some(CRDT_type::FLAG_DW) => Crdt::Flag(false),
+            Some(CRDT_type::GMAP) | Some(CRDT_type::RRMAP) => Crdt::Map(BTreeMap::new()),
+            None => return Err(format!("unknown CRDT type {}", type_number)),
+        };
+        Ok(crdt)
+    }
+
+    // the operation of a transaction reading snapshot, written at time; the
+    // values of multi-value registers written in between are concurrent
+    fn apply(
+        &mut self,
+        type_number: i32,
+        operation: &antidote::ApbUpdateOperation,
+        snapshot: u64,
+        time: u64,
+    ) -> Result<(), String> {
+        let concurrent = |written: u64| snapshot < written && written < time;
+        let mismatch = || match CRDT_type::from_i32(type_number) {
+            Some(crdt_type) => format!("operation does not apply to {}", type_name(crdt_type)),
+            None => format!("unknown CRDT type {}", type_number),
+        };
+        if operation.has_resetop() {
+            return match CRDT_type::from_i32(type_number) {
+                Some(CRDT_type::COUNTER)
+                | Some(CRDT_type::BCOUNTER)
+                | Some(CRDT_type::LWWREG)
+                | Some(CRDT_type::GMAP) => Err(mismatch()),
+                Some(CRDT_type::MVREG) => match self {
+                    Crdt::MultiRegister(values) => {
+                        values.retain(|(written, _)| concurrent(*written));
+                        Ok(())
+                    }
+                    _ => Err(mismatch()),
+                },
+                _ => {
+                    *self = Crdt::new(type_number)?;
+                    Ok(())
+                }
+            };
+        }
+
+        match self {
+            Crdt::Counter(value) if operation.has_counterop() => {
+                let counterop = operation.get_counterop();
+                let inc = if counterop.has_inc() {
+                    counterop.get_inc()
+                } else {
+                    1
+                };
+                *value = value.saturating_add(inc);
+            }
+            Crdt::Register(value) if operation.has_regop() => {
+                *value = operation.get_regop().get_value().to_vec();
+            }
+            Crdt::MultiRegister(values) if operation.has_regop() => {
+                values.retain(|(written, _)| concurrent(*written));
+                values.push((time, operation.get_regop().get_value().to_vec()));
+            }
+            Crdt::Set(values) if operation.has_setop() => {
+                let setop = operation.get_setop();
+                values.extend(codec::to_vecs(setop.get_adds()));
+                for value in setop.get_rems() {
+                    values.remove(&value[..]);
+                }
+            }
+            Crdt::Flag(value) if operation.has_flagop() => {
+                *value = operation.get_flagop().get_value();
+            }
+            Crdt::Map(entries) if operation.has_mapop() => {
+                let mapop = operation.get_mapop();
+                for update in mapop.get_updates() {
+                    let key = update.get_key();
+                    let type_number = key.type_number();
+                    let entry = match entries.entry((key.get_key().to_vec(), type_number)) {
+                        btree_map::Entry::Occupied(entry) => entry.into_mut(),
+                        btree_map::Entry::Vacant(entry) => entry.insert(Crdt::new(type_number)?),
+                    };
+                    entry.apply(type_number, update.get_update(), snapshot, time)?;
+                }
+                for key in mapop.get_removedKeys() {
+                    entries.remove(&(key.get_key().to_vec(), key.type_number()));
+                }
+            }
+            _ => return Err(mismatch()),
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> antidote::ApbReadObjectResp {
+        let mut resp = antidote::ApbReadObjectResp::new();
+        match self {
+            Crdt::Counter(value) => {
+                let mut counter = antidote::ApbGetCounterResp::new();
+                // the response has 32 bits, values past them saturate
+                let value =
+                    i32::try_from(*value).unwrap_or(if *value < 0 { i32::MIN } else { i32::MAX });
+                counter.set_value(value);
+                resp.set_counter(counter);
+            }
+            Crdt::Register(value) => {
+                let mut reg = antidote::ApbGetRegResp::new();
+                reg.set_value(value.clone());
+                resp.set_reg(reg);
+            }
+            Crdt::MultiRegister(values) => {
+                let mut mvreg = antidote::ApbGetMVRegResp::new();
+                let values = values.iter().map(|(_, value)| value.clone()).collect();
+                mvreg.set_values(RepeatedField::from_vec(values));
+                resp.set_mvreg(mvreg);
+            }
+            Crdt::Set(values) => {
+                let mut set = antidote::ApbGetSetResp::new();
+                set.set_value(RepeatedField::from_vec(values.iter().cloned().collect()));
+                resp.set_set(set);
+            }
+            Crdt::Flag(value) => {
+                let mut flag = antidote::ApbGetFlagResp::new();
+                flag.set_value(*value);
+                resp.set_flag(flag);
+            }
+            Crdt::Map(entries) => {
+                let mut map = antidote::ApbGetMapResp::new();
+                let entries = entries
+                    .iter()
+                    .filter_map(|((key, type_number), crdt)| {
+                        let mut map_key = antidote::ApbMapKey::new();
+                        map_key.set_key(key.clone());
+                        map_key.set_field_type(CRDT_type::from_i32(*type_number)?);
+                        let mut entry = antidote::ApbMapEntry::new();
+                        entry.set_key(map_key);
+                        entry.set_value(crdt.read());
+                        Some(entry)
+                    })
+                    .collect();
+                map.set_entries(entries);
+                resp.set_map(map);
+            }
+        }
+        resp
+    }
+}
+
+fn object_id(object: &antidote::ApbBoundObject) -> ObjectId {
+    (
+        object.get_bucket().to_vec(),
+        object.get_key().to_vec(),
+        object.type_number(),
+    )
+}
+
+fn transaction_id(descriptor: &[u8]) -> Result<u64, String> {
+    if descriptor.len() == 8 {
+        Ok(BigEndian::read_u64(descriptor))
+    } else {
+        Err("unknown transaction".to_string())
+    }
+}
+
+fn transaction_time(timestamp: &[u8]) -> Result<u64, String> {
+    if timestamp.len() == 8 {
+        Ok(BigEndian::read_u64(timestamp))
+    } else {
+        Err("invalid snapshot time".to_string())
+    }
+}
+
+fn time_bytes(time: u64) -> Vec<u8> {
+    time.to_be_bytes().to_vec()
+}
@@ -0,0 +1,354 @@
+use super::antidote;
+use super::crdt;
+use super::{AntidoteDB, Error, Transaction};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use byteorder::{BigEndian, ByteOrder};
+
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+// Commit time as a portable token.
+//
+// The token is `v1.` followed by the url-safe base64 (no padding) of the
+// commit time bytes and a big endian FNV-1a checksum of them. It can be
+// carried in HTTP headers and message metadata and parsed back by another
+// service to continue the same causal session.
+
+const TOKEN_PREFIX: &str = "v1.";
+const CHECKSUM_LEN: usize = 4;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CommitTime(Vec<u8>);
+
+impl CommitTime {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub fn to_token(&self) -> String {
+        let mut buffer = Vec::with_capacity(self.0.len() + CHECKSUM_LEN);
+        buffer.extend_from_slice(&self.0);
+        buffer.extend_from_slice(&checksum(&self.0).to_be_bytes());
+        format!("{}{}", TOKEN_PREFIX, URL_SAFE_NO_PAD.encode(&buffer))
+    }
+
+    pub fn from_token(token: &str) -> Result<Self, TokenError> {
+        let encoded = match token.strip_prefix(TOKEN_PREFIX) {
+            Some(encoded) => encoded,
+            None => return Err(TokenError::Version),
+        };
+        let mut buffer = URL_SAFE_NO_PAD
+            .decode(encoded)
+            .map_err(|_| TokenError::Encoding)?;
+        if buffer.len() <= CHECKSUM_LEN {
+            return Err(TokenError::Truncated);
+        }
+        let payload_len = buffer.len() - CHECKSUM_LEN;
+        if BigEndian::read_u32(&buffer[payload_len..]) != checksum(&buffer[..payload_len]) {
+            return Err(TokenError::Checksum);
+        }
+        buffer.truncate(payload_len);
+        Ok(CommitTime(buffer))
+    }
+
+    // only 8 byte clocks, as the stand-in's, are compared, Antidote's times
+    // are opaque; big endian bytes compare as the numbers do
+    fn is_before(&self, other: &CommitTime) -> bool {
+        self.0.len() == 8 && other.0.len() == 8 && self.0 < other.0
+    }
+}
+
+impl From<Vec<u8>> for CommitTime {
+    fn from(bytes: Vec<u8>) -> Self {
+        CommitTime(bytes)
+    }
+}
+
+impl fmt::Display for CommitTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_token())
+    }
+}
+
+impl FromStr for CommitTime {
+    type Err = TokenError;
+
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        CommitTime::from_token(token)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenError {
+    Version,
+    Encoding,
+    Truncated,
+    Checksum,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenError::Version => write!(f, "token does not start with {:?}", TOKEN_PREFIX),
+            TokenError::Encoding => f.write_str("token is not valid url-safe base64"),
+            TokenError::Truncated => f.write_str("token is too short to hold a commit time"),
+            TokenError::Checksum => f.write_str("token checksum does not match, it is corrupted"),
+        }
+    }
+}
+
+impl error::Error for TokenError {}
+
+// 32 bit FNV-1a
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &b| {
+        (hash ^ u32::from(b)).wrapping_mul(0x0100_0193)
+    })
+}
+
+// A causal session over a connection.
+//
+// The session remembers the latest commit time it has seen and uses it as
+// the snapshot lower bound of every transaction it starts, which gives
+// read-your-writes and monotonic reads across transactions.
+//
+// Antidote commit times are opaque to the client, so they cannot be compared
+// here. They do not need to be as long as the operations of a session do not
+// overlap: each one starts from the session's current time, so any time it
+// returns is at least as recent and the last observed one is the maximum. A
+// transaction that overlapped static operations would commit later from an
+// older snapshot, and its time could hide theirs. So while a transaction is
+// open the session refuses static operations and other transactions. Times
+// from elsewhere, given to with_token or observe, must not be older than the
+// session's, unless they are clocks observe can compare.
+
+#[derive(Debug)]
+pub struct Session {
+    db: AntidoteDB,
+    commit_time: Option<CommitTime>,
+    // descriptor of the open transaction
+    open: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionError {
+    // a transaction is open, commit or abort it first
+    TransactionOpen,
+    // the transaction is not the open one of the session, e.g. it was
+    // committed already or lost with its connection
+    NotOpen,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionError::TransactionOpen => {
+                f.write_str("the session has a transaction open, commit or abort it first")
+            }
+            SessionError::NotOpen => f.write_str("the transaction is not open in this session"),
+        }
+    }
+}
+
+impl error::Error for SessionError {}
+
+impl Session {
+    pub fn new(db: AntidoteDB) -> Self {
+        Session {
+            db,
+            commit_time: None,
+            open: None,
+        }
+    }
+
+    // resume a session from a token handed over by another process
+    pub fn with_token(db: AntidoteDB, token: CommitTime) -> Self {
+        Session {
+            db,
+            commit_time: Some(token),
+            open: None,
+        }
+    }
+
+    pub fn token(&self) -> Option<&CommitTime> {
+        self.commit_time.as_ref()
+    }
+
+    // Moves the session to commit_time. Times older than the current one are
+    // ignored where they can be compared, as 8 byte big endian clocks like
+    // the stand-in's; other times replace the current one, so they must not
+    // be older, e.g. a token from another process that this session has
+    // already moved past.
+    pub fn observe(&mut self, commit_time: CommitTime) {
+        if commit_time.0.is_empty() {
+            return;
+        }
+        match &self.commit_time {
+            Some(current) if commit_time.is_before(current) => {}
+            _ => self.commit_time = Some(commit_time),
+        }
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.open.is_some()
+    }
+
+    pub fn start_transaction(&mut self) -> Result<Transaction, Error> {
+        self.check_idle()?;
+        let timestamp = self.commit_time.as_ref().map(|t| &t.0);
+        let t = self.db.start_transaction(timestamp)?;
+        self.open = Some(t.id.clone());
+        Ok(t)
+    }
+
+    pub fn mult_read_in_transaction<T>(
+        &mut self,
+        objects: &[T],
+        t: &Transaction,
+    ) -> Result<Vec<antidote::ApbReadObjectResp>, Error>
+    where
+        T: crdt::Object,
+    {
+        self.check_open(t)?;
+        self.db.mult_read_in_transaction(objects, t)
+    }
+
+    pub fn mult_update_in_transaction(
+        &mut self,
+        apb_operations: &[antidote::ApbUpdateOp],
+        t: &Transaction,
+    ) -> Result<(), Error> {
+        self.check_open(t)?;
+        self.db.mult_update_in_transaction(apb_operations, t)
+    }
+
+    // the transaction is over whatever the outcome
+    pub fn commit_transaction(&mut self, t: &Transaction) -> Result<CommitTime, Error> {
+        self.check_open(t)?;
+        self.open = None;
+        let commit_time = CommitTime(self.db.commit_transaction(t)?);
+        self.observe(commit_time.clone());
+        Ok(commit_time)
+    }
+
+    pub fn abort_transaction(&mut self, t: &Transaction) -> Result<(), Error> {
+        self.check_open(t)?;
+        self.open = None;
+        self.db.abort_transaction(t)
+    }
+
+    pub fn static_update_objects(
+        &mut self,
+        apb_operations: &[antidote::ApbUpdateOp],
+    ) -> Result<CommitTime, Error> {
+        self.check_idle()?;
+        let timestamp = self.commit_time.as_ref().map(|t| &t.0);
+        let commit_time = CommitTime(self.db.static_update_objects(apb_operations, timestamp)?);
+        self.observe(commit_time.clone());
+        Ok(commit_time)
+    }
+
+    pub fn static_read_objects<T>(
+        &mut self,
+        objects: &[T],
+    ) -> Result<Vec<antidote::ApbReadObjectResp>, Error>
+    where
+        T: crdt::Object,
+    {
+        self.check_idle()?;
+        let timestamp = self.commit_time.as_ref().map(|t| &t.0);
+        let (values, commit_time) = self.db.static_read_objects(objects, timestamp)?;
+        self.observe(CommitTime(commit_time));
+        Ok(values)
+    }
+
+    // move the session to another connection, e.g. a node of another DC;
+    // an open transaction stays behind with the old connection
+    pub fn reconnect(&mut self, db: AntidoteDB) {
+        self.db = db;
+        self.open = None;
+    }
+
+    pub(crate) fn check_idle(&self) -> Result<(), Error> {
+        match self.open {
+            Some(_) => Err(Error::Session(SessionError::TransactionOpen)),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_open(&self, t: &Transaction) -> Result<(), Error> {
+        match self.open {
+            Some(ref id) if *id == t.id => Ok(()),
+            _ => Err(Error::Session(SessionError::NotOpen)),
+        }
+    }
+
+    pub(crate) fn connection(&self) -> &AntidoteDB {
+        &self.db
+    }
+
+    pub fn into_inner(self) -> AntidoteDB {
+        self.db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_round_trip() {
+        for bytes in [vec![0], vec![1, 2, 3], (0..=255).collect::<Vec<u8>>()] {
+            let time = CommitTime::from(bytes);
+            let token = time.to_token();
+            assert!(token.starts_with(TOKEN_PREFIX));
+            assert_eq!(token.parse::<CommitTime>(), Ok(time.clone()));
+            assert_eq!(time.to_string(), token);
+        }
+    }
+
+    #[test]
+    fn corrupted_tokens_are_rejected() {
+        let token = CommitTime::from(vec![1, 2, 3, 4, 5]).to_token();
+
+        assert_eq!(
+            CommitTime::from_token(&token[1..]),
+            Err(TokenError::Version)
+        );
+        assert_eq!(
+            CommitTime::from_token(&format!("{}!", token)),
+            Err(TokenError::Encoding)
+        );
+        assert_eq!(
+            CommitTime::from_token("v1.AAAA"),
+            Err(TokenError::Truncated)
+        );
+
+        // flip one payload character to another valid one
+        let mut chars: Vec<char> = token.chars().collect();
+        let i = TOKEN_PREFIX.len() + 1;
+        chars[i] = if chars[i] == 'A' { 'B' } else { 'A' };
+        let flipped: String = chars.into_iter().collect();
+        assert_eq!(CommitTime::from_token(&flipped), Err(TokenError::Checksum));
+    }
+
+    #[test]
+    fn only_clocks_are_compared() {
+        let clock = |n: u64| CommitTime::from(n.to_be_bytes().to_vec());
+        assert!(clock(1).is_before(&clock(256)));
+        assert!(!clock(256).is_before(&clock(1)));
+        assert!(!clock(1).is_before(&clock(1)));
+        // opaque times, as Antidote's, are never before another
+        let opaque = CommitTime::from(vec![131, 116, 0, 0, 0, 1]);
+        assert!(!opaque.is_before(&clock(1)));
+        assert!(!clock(1).is_before(&opaque));
+        assert!(!CommitTime::from(vec![0, 1]).is_before(&CommitTime::from(vec![0, 2])));
+    }
+}
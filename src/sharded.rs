@@ -0,0 +1,220 @@
+use super::antidote;
+use super::crdt::{self, Counter, CounterT, ObjectReset, ReadT};
+use super::{AntidoteDB, Error, Transaction};
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::thread;
+
+// Counter spread over several keys, for keys too hot for a single counter.
+//
+// A counter "visits" with 4 shards is stored as the counters "visits#0" to
+// "visits#3" of its bucket. Each increment goes to one shard, picked by the
+// calling thread or by a hash of anything else, and reads sum every shard in
+// one request.
+//
+// Every client of a counter must use the same number of shards. reshard
+// moves the value of the shards it drops into the ones it keeps, so the total
+// is unchanged; increments made to a dropped shard after that, by a client
+// still using the old count, are not counted until the count grows back.
+
+#[derive(Clone, Debug)]
+pub struct ShardedCounter<C = Counter> {
+    key: String,
+    bucket: String,
+    shards: Vec<C>,
+}
+
+impl<C> ShardedCounter<C>
+where
+    C: CounterT + ReadT<Value = i32>,
+{
+    // at least one shard is used
+    pub fn new(key: &str, bucket: &str, shards: usize) -> Self {
+        let shards = (0..shards.max(1))
+            .map(|i| C::new(&format!("{}#{}", key, i), bucket))
+            .collect();
+
+        ShardedCounter {
+            key: key.to_string(),
+            bucket: bucket.to_string(),
+            shards,
+        }
+    }
+
+    pub fn shards(&self) -> &[C] {
+        &self.shards
+    }
+
+    // increments the shard of the calling thread
+    pub fn inc(&self, val: i64) -> antidote::ApbUpdateOp {
+        self.inc_by(&thread::current().id(), val)
+    }
+
+    // increments the shard picked by hashing hint
+    pub fn inc_by<H: Hash + ?Sized>(&self, hint: &H, val: i64) -> antidote::ApbUpdateOp {
+        let mut hasher = DefaultHasher::new();
+        hint.hash(&mut hasher);
+        let shard = hasher.finish() % self.shards.len() as u64;
+
+        self.shards[shard as usize].inc(val)
+    }
+
+    // Error::Overflow for i64::MIN
+    pub fn dec(&self, val: i64) -> Result<antidote::ApbUpdateOp, Error> {
+        Ok(self.inc(crdt::negate(val)?))
+    }
+
+    // the total, from a read of all shards
+    pub fn sum(values: &[antidote::ApbReadObjectResp]) -> i64 {
+        values.iter().map(|v| i64::from(C::decode(v))).sum()
+    }
+
+    pub fn read_in_transaction(
+        &self,
+        db: &mut AntidoteDB,
+        transaction: &Transaction,
+    ) -> Result<i64, Error> {
+        let values = db.mult_read_in_transaction(&self.shards, transaction)?;
+        Ok(Self::sum(&values))
+    }
+
+    pub fn static_read(
+        &self,
+        db: &mut AntidoteDB,
+        timestamp: Option<&Vec<u8>>,
+    ) -> Result<(i64, Vec<u8>), Error> {
+        let (values, commit_time) = db.static_read_objects(&self.shards, timestamp)?;
+        Ok((Self::sum(&values), commit_time))
+    }
+
+    // brings every shard back to 0 by decrementing what the transaction
+    // reads, so increments concurrent with the reset are kept
+    pub fn reset_in_transaction(
+        &self,
+        db: &mut AntidoteDB,
+        transaction: &Transaction,
+    ) -> Result<(), Error> {
+        let values = db.mult_read_in_transaction(&self.shards, transaction)?;
+        let ops: Vec<_> = self
+            .shards
+            .iter()
+            .zip(values.iter())
+            .map(|(shard, value)| (shard, i64::from(C::decode(value))))
+            .filter(|(_, value)| *value != 0)
+            // read from i32 counters, the negation fits
+            .map(|(shard, value)| shard.inc(-value))
+            .collect();
+
+        if !ops.is_empty() {
+            db.mult_update_in_transaction(&ops, transaction)?;
+        }
+        Ok(())
+    }
+
+    // the counter with a new number of shards, the values of dropped shards
+    // are moved to the remaining ones
+    pub fn reshard_in_transaction(
+        &self,
+        db: &mut AntidoteDB,
+        transaction: &Transaction,
+        shards: usize,
+    ) -> Result<Self, Error> {
+        let resharded = ShardedCounter::new(&self.key, &self.bucket, shards);
+        let kept = resharded.shards.len();
+        if kept >= self.shards.len() {
+            return Ok(resharded);
+        }
+
+        let values = db.mult_read_in_transaction(&self.shards[kept..], transaction)?;
+        let mut ops = Vec::new();
+        for (i, value) in values.iter().enumerate() {
+            let value = i64::from(C::decode(value));
+            if value != 0 {
+                ops.push(self.shards[kept + i].inc(-value));
+                ops.push(resharded.shards[(kept + i) % kept].inc(value));
+            }
+        }
+
+        if !ops.is_empty() {
+            db.mult_update_in_transaction(&ops, transaction)?;
+        }
+        Ok(resharded)
+    }
+}
+
+impl<C> ShardedCounter<C>
+where
+    C: CounterT + ObjectReset,
+{
+    // resets every shard, in a single transaction when written together
+    pub fn reset(&self) -> Vec<antidote::ApbUpdateOp> {
+        self.shards.iter().map(|shard| shard.reset()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::StandIn;
+
+    #[test]
+    fn value_is_the_sum_of_the_shards() {
+        let stand_in = StandIn::start().unwrap();
+        let mut db = stand_in.connect().unwrap();
+        let visits = ShardedCounter::<Counter>::new("visits", "sharded", 4);
+
+        let mut ops: Vec<_> = (0..20).map(|i| visits.inc_by(&i, i)).collect();
+        ops.push(visits.dec(10).unwrap());
+        db.static_update_objects(&ops, None).unwrap();
+
+        let (total, _) = visits.static_read(&mut db, None).unwrap();
+        assert_eq!(total, (0..20).sum::<i64>() - 10);
+
+        let (values, _) = db.static_read_objects(visits.shards(), None).unwrap();
+        let shards: i64 = values.iter().map(|v| i64::from(Counter::decode(v))).sum();
+        assert_eq!(shards, total);
+
+        let txn = db.start_transaction(None).unwrap();
+        assert_eq!(visits.read_in_transaction(&mut db, &txn).unwrap(), total);
+        db.commit_transaction(&txn).unwrap();
+    }
+
+    #[test]
+    fn increments_spread_over_the_shards() {
+        let visits = ShardedCounter::<Counter>::new("visits", "sharded", 4);
+        let mut counts = vec![0; 4];
+        for i in 0..400 {
+            let op = visits.inc_by(&i, 1);
+            let key = op.get_boundobject().get_key().to_vec();
+            let shard = visits
+                .shards()
+                .iter()
+                .position(|shard| shard.key() == &key[..])
+                .unwrap();
+            counts[shard] += 1;
+        }
+        // no shard gets less than half its share
+        assert!(counts.iter().all(|&count| count >= 50), "{:?}", counts);
+
+        // the same hint, the same shard
+        assert_eq!(visits.inc_by("a", 1), visits.inc_by("a", 1));
+        assert_eq!(
+            ShardedCounter::<Counter>::new("one", "sharded", 0)
+                .shards()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn decrements_by_min_are_errors() {
+        let visits = ShardedCounter::<Counter>::new("visits", "sharded", 2);
+        let op = visits.dec(i64::MAX).unwrap();
+        assert_eq!(op.get_operation().get_counterop().get_inc(), -i64::MAX);
+        assert!(matches!(
+            visits.dec(i64::MIN),
+            Err(Error::Overflow(i64::MIN))
+        ));
+    }
+}
@@ -0,0 +1,566 @@
+use super::antidote;
+use super::codec::{self, ProtobufEnum, TypedMessage};
+use super::crdt::{number_from_name, number_name, CRDT_type};
+use super::dynamic::to_hex;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::json;
+
+use std::fmt;
+use std::str;
+
+// Dynamic value of any read, for code that does not know the types it reads.
+//
+// Counters of all kinds read as Counter, sets of both kinds as Set and so
+// on; map entries keep their key and type number next to their value. Entries
+// of custom kinds, see crdt::CrdtKind, are Custom values holding the type
+// number and the encoded response, as nothing here can read them. Bytes are
+// copied into vectors, under prost too, unlike the typed reads of crdt.
+//
+// Display renders a value on one line, bytes as strings when they are UTF-8
+// and as hex otherwise:
+//
+//     {"tags"/orset: {"a", "b"}, "visits"/counter: 3}
+//
+// to_json gives bytes as strings when they are UTF-8 and as {"base64": ...}
+// otherwise, and maps as arrays of {"key", "type", "value"} entries, which
+// from_json reads back given the type of the object. Custom values are
+// {"base64": ...} and shown as hex. tree renders a value over several lines:
+//
+//     map
+//     ├── "tags" (orset)
+//     │   ├── "a"
+//     │   └── "b"
+//     └── "visits" (counter): 3
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Counter(i64),
+    Register(Vec<u8>),
+    MultiRegister(Vec<Vec<u8>>),
+    Set(Vec<Vec<u8>>),
+    Flag(bool),
+    Map(Vec<(Vec<u8>, i32, Value)>),
+    Custom(i32, Vec<u8>),
+}
+
+impl Value {
+    // the value of a read, None if the response holds none
+    pub fn read(resp: &antidote::ApbReadObjectResp) -> Option<Self> {
+        let crdt_type = if resp.has_counter() {
+            CRDT_type::COUNTER
+        } else if resp.has_reg() {
+            CRDT_type::LWWREG
+        } else if resp.has_mvreg() {
+            CRDT_type::MVREG
+        } else if resp.has_set() {
+            CRDT_type::ORSET
+        } else if resp.has_flag() {
+            CRDT_type::FLAG_EW
+        } else if resp.has_map() {
+            CRDT_type::RRMAP
+        } else {
+            return None;
+        };
+
+        Some(Value::decode(crdt_type, resp))
+    }
+
+    // the value of a read of an object of type crdt_type
+    pub fn decode(crdt_type: CRDT_type, resp: &antidote::ApbReadObjectResp) -> Self {
+        match crdt_type {
+            CRDT_type::COUNTER | CRDT_type::FATCOUNTER | CRDT_type::BCOUNTER => {
+                Value::Counter(i64::from(resp.get_counter().get_value()))
+            }
+            CRDT_type::LWWREG => Value::Register(resp.get_reg().get_value().to_vec()),
+            CRDT_type::MVREG => Value::MultiRegister(codec::to_vecs(resp.get_mvreg().get_values())),
+            CRDT_type::ORSET | CRDT_type::RWSET => {
+                Value::Set(codec::to_vecs(resp.get_set().get_value()))
+            }
+            CRDT_type::FLAG_EW | CRDT_type::FLAG_DW => Value::Flag(resp.get_flag().get_value()),
+            CRDT_type::GMAP | CRDT_type::RRMAP => Value::Map(
+                resp.get_map()
+                    .get_entries()
+                    .iter()
+                    .map(|entry| {
+                        let key = entry.get_key();
+                        let number = key.type_number();
+                        let value = match CRDT_type::from_i32(number) {
+                            Some(crdt_type) => Value::decode(crdt_type, entry.get_value()),
+                            None => Value::Custom(number, encoded(entry.get_value())),
+                        };
+                        (key.get_key().to_vec(), number, value)
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        self.to_json_with(&bytes_json)
+    }
+
+    // with register values and set elements given by element, map keys are
+    // given as by to_json
+    pub fn to_json_with(&self, element: &dyn Fn(&[u8]) -> serde_json::Value) -> serde_json::Value {
+        match self {
+            Value::Counter(value) => json!(value),
+            Value::Register(value) => element(value),
+            Value::MultiRegister(values) | Value::Set(values) => {
+                values.iter().map(|value| element(value)).collect()
+            }
+            Value::Flag(value) => json!(value),
+            Value::Map(entries) => entries
+                .iter()
+                .map(|(key, number, value)| {
+                    json!({
+                        "key": bytes_json(key),
+                        "type": number_name(*number),
+                        "value": value.to_json_with(element),
+                    })
+                })
+                .collect(),
+            Value::Custom(_, bytes) => json!({ "base64": STANDARD.encode(bytes) }),
+        }
+    }
+
+    // the value to_json gave for an object of type crdt_type, None if json
+    // does not fit the type
+    pub fn from_json(crdt_type: CRDT_type, json: &serde_json::Value) -> Option<Self> {
+        let value = match crdt_type {
+            CRDT_type::COUNTER | CRDT_type::FATCOUNTER | CRDT_type::BCOUNTER => {
+                Value::Counter(json.as_i64()?)
+            }
+            CRDT_type::LWWREG => Value::Register(json_bytes(json)?),
+            CRDT_type::MVREG => Value::MultiRegister(json_list(json)?),
+            CRDT_type::ORSET | CRDT_type::RWSET => Value::Set(json_list(json)?),
+            CRDT_type::FLAG_EW | CRDT_type::FLAG_DW => Value::Flag(json.as_bool()?),
+            CRDT_type::GMAP | CRDT_type::RRMAP => Value::Map(
+                json.as_array()?
+                    .iter()
+                    .map(|entry| {
+                        let key = json_bytes(entry.get("key")?)?;
+                        let number = number_from_name(entry.get("type")?.as_str()?)?;
+                        let value = entry.get("value")?;
+                        let value = match CRDT_type::from_i32(number) {
+                            Some(crdt_type) => Value::from_json(crdt_type, value)?,
+                            None => Value::Custom(number, json_bytes(value)?),
+                        };
+                        Some((key, number, value))
+                    })
+                    .collect::<Option<_>>()?,
+            ),
+        };
+        Some(value)
+    }
+
+    pub fn tree(&self) -> Tree<'_> {
+        self.tree_with(&bytes_string)
+    }
+
+    // with register values and set elements shown by element
+    pub fn tree_with<'a>(&'a self, element: &'a dyn Fn(&[u8]) -> String) -> Tree<'a> {
+        Tree {
+            value: self,
+            element,
+        }
+    }
+
+    // the bytes of a register, set element or map key, for display
+    pub fn bytes(bytes: &[u8]) -> Bytes<'_> {
+        Bytes(bytes)
+    }
+
+    fn is_leaf(&self) -> bool {
+        matches!(
+            self,
+            Value::Counter(_) | Value::Register(_) | Value::Flag(_) | Value::Custom(..)
+        )
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Counter(value) => write!(f, "{}", value),
+            Value::Register(value) => write!(f, "{}", Bytes(value)),
+            Value::MultiRegister(values) => write_list(f, "[", values, "]"),
+            Value::Set(values) => write_list(f, "{", values, "}"),
+            Value::Flag(value) => write!(f, "{}", value),
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, number, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}/{}: {}", Bytes(key), number_name(*number), value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Custom(_, bytes) => write!(f, "0x{}", to_hex(bytes)),
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter, open: &str, values: &[Vec<u8>], close: &str) -> fmt::Result {
+    write!(f, "{}", open)?;
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", Bytes(value))?;
+    }
+    write!(f, "{}", close)
+}
+
+fn encoded(resp: &antidote::ApbReadObjectResp) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    codec::encode(&mut bytes, |encoder| encoder.write_fields(resp)).expect("encode into a vector");
+    bytes
+}
+
+fn bytes_string(bytes: &[u8]) -> String {
+    Bytes(bytes).to_string()
+}
+
+fn bytes_json(bytes: &[u8]) -> serde_json::Value {
+    match str::from_utf8(bytes) {
+        Ok(s) => json!(s),
+        Err(_) => json!({ "base64": STANDARD.encode(bytes) }),
+    }
+}
+
+fn json_bytes(json: &serde_json::Value) -> Option<Vec<u8>> {
+    match json {
+        serde_json::Value::String(s) => Some(s.clone().into_bytes()),
+        json => STANDARD.decode(json.get("base64")?.as_str()?).ok(),
+    }
+}
+
+fn json_list(json: &serde_json::Value) -> Option<Vec<Vec<u8>>> {
+    json.as_array()?.iter().map(json_bytes).collect()
+}
+
+// bytes as a quoted string when they are printable UTF-8, as hex otherwise
+#[derive(Clone, Copy, Debug)]
+pub struct Bytes<'a>(&'a [u8]);
+
+impl<'a> fmt::Display for Bytes<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match str::from_utf8(self.0) {
+            Ok(s) if !s.chars().any(char::is_control) => write!(f, "{:?}", s),
+            _ => {
+                write!(f, "0x")?;
+                for byte in self.0 {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// multi-line rendering of a value, see Value::tree
+#[derive(Clone, Copy)]
+pub struct Tree<'a> {
+    value: &'a Value,
+    element: &'a dyn Fn(&[u8]) -> String,
+}
+
+impl<'a> fmt::Debug for Tree<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Tree").field(self.value).finish()
+    }
+}
+
+impl<'a> fmt::Display for Tree<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            Value::MultiRegister(_) => writeln!(f, "registers")?,
+            Value::Set(_) => writeln!(f, "set")?,
+            Value::Map(_) => writeln!(f, "map")?,
+            value => return writeln!(f, "{}", self.leaf(value)),
+        }
+        self.write_children(f, self.value, "")
+    }
+}
+
+impl<'a> Tree<'a> {
+    fn leaf(&self, value: &Value) -> String {
+        match value {
+            Value::Register(value) => (self.element)(value),
+            value => value.to_string(),
+        }
+    }
+
+    fn write_children(&self, f: &mut fmt::Formatter, value: &Value, prefix: &str) -> fmt::Result {
+        match value {
+            Value::MultiRegister(values) | Value::Set(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    let (branch, _) = branches(i + 1 == values.len());
+                    writeln!(f, "{}{}{}", prefix, branch, (self.element)(value))?;
+                }
+            }
+            Value::Map(entries) => {
+                for (i, (key, number, value)) in entries.iter().enumerate() {
+                    let (branch, indent) = branches(i + 1 == entries.len());
+                    write!(
+                        f,
+                        "{}{}{} ({})",
+                        prefix,
+                        branch,
+                        Bytes(key),
+                        number_name(*number)
+                    )?;
+                    if value.is_leaf() {
+                        writeln!(f, ": {}", self.leaf(value))?;
+                    } else {
+                        writeln!(f)?;
+                        self.write_children(f, value, &format!("{}{}", prefix, indent))?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn branches(last: bool) -> (&'static str, &'static str) {
+    if last {
+        ("└── ", "    ")
+    } else {
+        ("├── ", "│   ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::RepeatedField;
+
+    fn bytes(s: &str) -> Vec<u8> {
+        s.as_bytes().to_vec()
+    }
+
+    fn elements(items: &[&'static str]) -> RepeatedField<codec::Bytes> {
+        RepeatedField::from_vec(
+            items
+                .iter()
+                .map(|s| codec::Bytes::from(s.as_bytes()))
+                .collect(),
+        )
+    }
+
+    fn entry(key: &str, crdt_type: CRDT_type, value: Value) -> (Vec<u8>, i32, Value) {
+        (bytes(key), crdt_type as i32, value)
+    }
+
+    // a user with a nested address and an entry of a custom kind
+    fn user() -> Value {
+        Value::Map(vec![
+            entry(
+                "address",
+                CRDT_type::RRMAP,
+                Value::Map(vec![entry(
+                    "city",
+                    CRDT_type::LWWREG,
+                    Value::Register(bytes("Paris")),
+                )]),
+            ),
+            entry("active", CRDT_type::FLAG_EW, Value::Flag(true)),
+            (bytes("mine"), 42, Value::Custom(42, vec![0x08, 0x07])),
+            entry(
+                "names",
+                CRDT_type::MVREG,
+                Value::MultiRegister(vec![bytes("Ada"), vec![0xff]]),
+            ),
+            entry("tags", CRDT_type::ORSET, Value::Set(vec![bytes("a")])),
+            entry("visits", CRDT_type::COUNTER, Value::Counter(3)),
+        ])
+    }
+
+    fn counter(value: i32) -> antidote::ApbReadObjectResp {
+        let mut counter = antidote::ApbGetCounterResp::new();
+        counter.set_value(value);
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_counter(counter);
+        resp
+    }
+
+    fn map_entry(
+        key: &str,
+        number: i32,
+        value: antidote::ApbReadObjectResp,
+    ) -> antidote::ApbMapEntry {
+        let mut map_key = antidote::ApbMapKey::new();
+        map_key.set_key(bytes(key));
+        map_key.set_type_number(number);
+        let mut entry = antidote::ApbMapEntry::new();
+        entry.set_key(map_key);
+        entry.set_value(value);
+        entry
+    }
+
+    fn map(entries: Vec<antidote::ApbMapEntry>) -> antidote::ApbReadObjectResp {
+        let mut map = antidote::ApbGetMapResp::new();
+        map.set_entries(RepeatedField::from_vec(entries));
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_map(map);
+        resp
+    }
+
+    #[test]
+    fn every_kind_decodes() {
+        for crdt_type in &[
+            CRDT_type::COUNTER,
+            CRDT_type::FATCOUNTER,
+            CRDT_type::BCOUNTER,
+        ] {
+            assert_eq!(Value::decode(*crdt_type, &counter(-4)), Value::Counter(-4));
+        }
+
+        let mut reg = antidote::ApbGetRegResp::new();
+        reg.set_value(bytes("x"));
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_reg(reg);
+        assert_eq!(
+            Value::decode(CRDT_type::LWWREG, &resp),
+            Value::Register(bytes("x"))
+        );
+
+        let mut mvreg = antidote::ApbGetMVRegResp::new();
+        mvreg.set_values(elements(&["x", "y"]));
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_mvreg(mvreg);
+        assert_eq!(
+            Value::decode(CRDT_type::MVREG, &resp),
+            Value::MultiRegister(vec![bytes("x"), bytes("y")])
+        );
+
+        let mut set = antidote::ApbGetSetResp::new();
+        set.set_value(elements(&["a"]));
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_set(set);
+        for crdt_type in &[CRDT_type::ORSET, CRDT_type::RWSET] {
+            assert_eq!(
+                Value::decode(*crdt_type, &resp),
+                Value::Set(vec![bytes("a")])
+            );
+        }
+
+        let mut flag = antidote::ApbGetFlagResp::new();
+        flag.set_value(true);
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_flag(flag);
+        for crdt_type in &[CRDT_type::FLAG_EW, CRDT_type::FLAG_DW] {
+            assert_eq!(Value::decode(*crdt_type, &resp), Value::Flag(true));
+        }
+    }
+
+    #[test]
+    fn nested_and_custom_map_entries_decode() {
+        let nested = map(vec![map_entry("n", CRDT_type::COUNTER as i32, counter(1))]);
+        let resp = map(vec![
+            map_entry("inner", CRDT_type::RRMAP as i32, nested),
+            map_entry("mine", 42, counter(7)),
+        ]);
+        for crdt_type in &[CRDT_type::GMAP, CRDT_type::RRMAP] {
+            assert_eq!(
+                Value::decode(*crdt_type, &resp),
+                Value::Map(vec![
+                    entry(
+                        "inner",
+                        CRDT_type::RRMAP,
+                        Value::Map(vec![entry("n", CRDT_type::COUNTER, Value::Counter(1))]),
+                    ),
+                    // the counter field holding the zigzag encoded 7
+                    (
+                        bytes("mine"),
+                        42,
+                        Value::Custom(42, vec![0x0a, 0x02, 0x08, 0x0e])
+                    ),
+                ])
+            );
+        }
+        assert_eq!(
+            Value::read(&resp),
+            Some(Value::decode(CRDT_type::RRMAP, &resp))
+        );
+        assert_eq!(Value::read(&antidote::ApbReadObjectResp::new()), None);
+    }
+
+    #[test]
+    fn values_display_on_one_line() {
+        assert_eq!(Value::Counter(-1).to_string(), "-1");
+        assert_eq!(Value::Register(bytes("a\"b")).to_string(), r#""a\"b""#);
+        assert_eq!(Value::Register(vec![0x00, 0xff]).to_string(), "0x00ff");
+        assert_eq!(Value::Flag(false).to_string(), "false");
+        assert_eq!(Value::Set(Vec::new()).to_string(), "{}");
+        assert_eq!(
+            user().to_string(),
+            r#"{"address"/rrmap: {"city"/lwwreg: "Paris"}, "active"/flag_ew: true, "mine"/type:42: 0x0807, "names"/mvreg: ["Ada", 0xff], "tags"/orset: {"a"}, "visits"/counter: 3}"#
+        );
+    }
+
+    #[test]
+    fn json_reads_back_as_the_value() {
+        let json = user().to_json();
+        assert_eq!(json[0]["value"][0]["value"], json!("Paris"));
+        assert_eq!(json[2]["type"], json!("type:42"));
+        assert_eq!(json[2]["value"], json!({ "base64": "CAc=" }));
+        assert_eq!(json[3]["value"], json!(["Ada", { "base64": "/w==" }]));
+        assert_eq!(Value::from_json(CRDT_type::RRMAP, &json), Some(user()));
+
+        for (crdt_type, value) in &[
+            (CRDT_type::BCOUNTER, Value::Counter(i64::MIN)),
+            (CRDT_type::LWWREG, Value::Register(Vec::new())),
+            (CRDT_type::MVREG, Value::MultiRegister(vec![bytes("x")])),
+            (CRDT_type::RWSET, Value::Set(vec![bytes("a"), bytes("b")])),
+            (CRDT_type::FLAG_DW, Value::Flag(true)),
+            (CRDT_type::GMAP, Value::Map(Vec::new())),
+        ] {
+            let json = value.to_json();
+            assert_eq!(Value::from_json(*crdt_type, &json).as_ref(), Some(value));
+        }
+    }
+
+    #[test]
+    fn json_of_another_type_is_refused() {
+        assert_eq!(Value::from_json(CRDT_type::COUNTER, &json!("a")), None);
+        assert_eq!(Value::from_json(CRDT_type::ORSET, &json!([1])), None);
+        assert_eq!(Value::from_json(CRDT_type::FLAG_EW, &json!(1)), None);
+        let unknown = json!([{ "key": "k", "type": "tree", "value": 1 }]);
+        assert_eq!(Value::from_json(CRDT_type::RRMAP, &unknown), None);
+    }
+
+    #[test]
+    fn trees_indent_nested_maps() {
+        let expected = "\
+map
+├── \"address\" (rrmap)
+│   └── \"city\" (lwwreg): \"Paris\"
+├── \"active\" (flag_ew): true
+├── \"mine\" (type:42): 0x0807
+├── \"names\" (mvreg)
+│   ├── \"Ada\"
+│   └── 0xff
+├── \"tags\" (orset)
+│   └── \"a\"
+└── \"visits\" (counter): 3
+";
+        assert_eq!(user().tree().to_string(), expected);
+        assert_eq!(Value::Counter(3).tree().to_string(), "3\n");
+        assert_eq!(
+            Value::Set(vec![bytes("a")]).tree().to_string(),
+            "set\n└── \"a\"\n"
+        );
+
+        let upper = |bytes: &[u8]| String::from_utf8_lossy(bytes).to_uppercase();
+        assert_eq!(
+            Value::Register(bytes("ada")).tree_with(&upper).to_string(),
+            "ADA\n"
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use super::antidote;
+use super::codec::{ProtobufEnum, TypedMessage};
+use super::crdt::{GMap, Object, RRMap, ReadT};
+
+use std::borrow::Cow;
+
+// Typed view of a map read, GMap or RRMap.
+//
+// An entry is identified by its key and its CRDT type, so the same key can
+// hold several entries of different types. Lookups take the type to decode
+// as a parameter, e.g. view.get::<Counter>("visits").
+//
+// A view borrows the response it is made from, and nested views borrow it
+// too. Reading a map as GMap or RRMap gives a view owning its entries.
+
+#[derive(Clone, Debug, Default)]
+pub struct MapView<'a> {
+    entries: Cow<'a, [antidote::ApbMapEntry]>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MapEntry<'a> {
+    entry: &'a antidote::ApbMapEntry,
+}
+
+impl<'a> MapEntry<'a> {
+    pub fn key(&self) -> &'a [u8] {
+        self.entry.get_key().get_key()
+    }
+
+    // None for custom kinds
+    pub fn crdt_type(&self) -> Option<antidote::CRDT_type> {
+        antidote::CRDT_type::from_i32(self.type_number())
+    }
+
+    pub fn type_number(&self) -> i32 {
+        self.entry.get_key().type_number()
+    }
+
+    pub fn value(&self) -> &'a antidote::ApbReadObjectResp {
+        self.entry.get_value()
+    }
+
+    pub fn is<C: Object>(&self) -> bool {
+        self.type_number() == C::type_number()
+    }
+
+    // the decoded value, if the entry holds a C
+    pub fn get<C: ReadT>(&self) -> Option<C::Value> {
+        if self.is::<C>() {
+            Some(C::decode(self.value()))
+        } else {
+            None
+        }
+    }
+
+    // the nested view, if the entry holds a map
+    pub fn map(&self) -> Option<MapView<'a>> {
+        self.entries().map(|entries| MapView {
+            entries: Cow::Borrowed(entries),
+        })
+    }
+
+    fn entries(&self) -> Option<&'a [antidote::ApbMapEntry]> {
+        if self.is::<RRMap>() || self.is::<GMap>() {
+            Some(self.value().get_map().get_entries())
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> MapView<'a> {
+    pub fn new(map: &'a antidote::ApbGetMapResp) -> Self {
+        MapView {
+            entries: Cow::Borrowed(map.get_entries()),
+        }
+    }
+
+    pub fn into_owned(self) -> MapView<'static> {
+        MapView {
+            entries: Cow::Owned(self.entries.into_owned()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = MapEntry<'_>> {
+        self.entries.iter().map(|entry| MapEntry { entry })
+    }
+
+    pub fn entry<C: Object>(&self, key: &str) -> Option<MapEntry<'_>> {
+        self.iter()
+            .find(|entry| entry.key() == key.as_bytes() && entry.is::<C>())
+    }
+
+    pub fn get<C: ReadT>(&self, key: &str) -> Option<C::Value> {
+        self.entry::<C>(key).map(|entry| C::decode(entry.value()))
+    }
+
+    // nested map under key, whether RRMap or GMap
+    pub fn map(&self, key: &str) -> Option<MapView<'_>> {
+        self.path(&[key])
+    }
+
+    // nested map at the end of a path of keys, e.g. &["profile", "address"]
+    pub fn path(&self, path: &[&str]) -> Option<MapView<'_>> {
+        let mut entries: &[antidote::ApbMapEntry] = &self.entries;
+        for key in path {
+            entries = entries
+                .iter()
+                .map(|entry| MapEntry { entry })
+                .filter(|entry| entry.key() == key.as_bytes())
+                .find_map(|entry| entry.entries())?;
+        }
+        Some(MapView {
+            entries: Cow::Borrowed(entries),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::codec::{self, RepeatedField};
+    use super::super::crdt::{Counter, FlagEW, LWWReg, ORSet};
+    use super::*;
+
+    fn counter(value: i32) -> antidote::ApbReadObjectResp {
+        let mut counter = antidote::ApbGetCounterResp::new();
+        counter.set_value(value);
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_counter(counter);
+        resp
+    }
+
+    fn reg(value: &'static str) -> antidote::ApbReadObjectResp {
+        let mut reg = antidote::ApbGetRegResp::new();
+        reg.set_value(codec::Bytes::from(value.as_bytes()));
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_reg(reg);
+        resp
+    }
+
+    fn map(entries: Vec<antidote::ApbMapEntry>) -> antidote::ApbReadObjectResp {
+        let mut map = antidote::ApbGetMapResp::new();
+        map.set_entries(RepeatedField::from_vec(entries));
+        let mut resp = antidote::ApbReadObjectResp::new();
+        resp.set_map(map);
+        resp
+    }
+
+    fn entry<C: Object>(key: &str, value: antidote::ApbReadObjectResp) -> antidote::ApbMapEntry {
+        let mut entry = antidote::ApbMapEntry::new();
+        entry.set_key(C::map_key(key));
+        entry.set_value(value);
+        entry
+    }
+
+    // profile: visits (counter and register), name, address: { city }, tags (gmap)
+    fn profile() -> antidote::ApbReadObjectResp {
+        let address = map(vec![entry::<LWWReg>("city", reg("Paris"))]);
+        map(vec![
+            entry::<Counter>("visits", counter(3)),
+            entry::<LWWReg>("visits", reg("many")),
+            entry::<LWWReg>("name", reg("ada")),
+            entry::<RRMap>("address", address),
+            entry::<GMap>("tags", map(Vec::new())),
+        ])
+    }
+
+    #[test]
+    fn entries_are_typed_by_key_and_type() {
+        let resp = profile();
+        let view = MapView::new(resp.get_map());
+        assert_eq!(view.len(), 5);
+        assert_eq!(view.get::<Counter>("visits"), Some(3));
+        assert_eq!(
+            view.get::<LWWReg>("visits"),
+            Some(codec::Bytes::from("many".as_bytes()))
+        );
+
+        let name = view.entry::<LWWReg>("name").unwrap();
+        assert_eq!(name.key(), b"name");
+        assert_eq!(name.crdt_type(), Some(antidote::CRDT_type::LWWREG));
+        assert!(name.is::<LWWReg>());
+        assert_eq!(name.get::<Counter>(), None);
+        assert!(name.map().is_none());
+
+        let types: Vec<_> = view.iter().map(|entry| entry.crdt_type()).collect();
+        assert_eq!(
+            types,
+            [
+                antidote::CRDT_type::COUNTER,
+                antidote::CRDT_type::LWWREG,
+                antidote::CRDT_type::LWWREG,
+                antidote::CRDT_type::RRMAP,
+                antidote::CRDT_type::GMAP,
+            ]
+            .iter()
+            .map(|t| Some(*t))
+            .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn missing_keys_and_other_types_are_none() {
+        let resp = profile();
+        let view = MapView::new(resp.get_map());
+        assert_eq!(view.get::<Counter>("clicks"), None);
+        assert_eq!(view.get::<Counter>("name"), None);
+        assert_eq!(view.get::<ORSet>("name"), None);
+        assert!(view.entry::<FlagEW>("visits").is_none());
+        assert!(view.map("name").is_none());
+        assert!(view.map("missing").is_none());
+        assert!(view.path(&["address", "city"]).is_none());
+
+        let empty = MapView::default();
+        assert!(empty.is_empty());
+        assert_eq!(empty.get::<Counter>("visits"), None);
+        assert!(empty.path(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn nested_maps_borrow_the_response() {
+        let resp = profile();
+        let view = MapView::new(resp.get_map());
+        let entries = resp.get_map().get_entries();
+        assert_eq!(
+            view.iter().next().unwrap().value() as *const _,
+            entries[0].get_value() as *const _
+        );
+
+        let address = view.map("address").unwrap();
+        assert_eq!(
+            address.get::<LWWReg>("city"),
+            Some(codec::Bytes::from("Paris".as_bytes()))
+        );
+        let city = address.iter().next().unwrap().value();
+        let stored = entries[3].get_value().get_map().get_entries()[0].get_value();
+        assert_eq!(city as *const _, stored as *const _);
+
+        // both kinds of maps nest
+        assert!(view.map("tags").unwrap().is_empty());
+        assert_eq!(view.path(&[]).unwrap().len(), 5);
+        assert_eq!(view.path(&["address"]).unwrap().len(), 1);
+
+        // decoding as a map type gives an owned view
+        let owned = RRMap::decode(&resp);
+        drop(resp);
+        assert_eq!(owned.path(&["address"]).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,55 @@
+extern crate antidotedb;
+
+use antidotedb::admin::ConnectionDescriptor;
+use antidotedb::server::StandIn;
+use antidotedb::{Error, ErrorCode};
+
+#[test]
+fn create_dc() {
+    let stand_in = StandIn::start().unwrap();
+    let mut db = stand_in.connect().unwrap();
+
+    db.create_dc(&["antidote@node1", "antidote@node2"]).unwrap();
+    assert_eq!(stand_in.dc_nodes(), ["antidote@node1", "antidote@node2"]);
+
+    // a DC without nodes is refused
+    assert!(matches!(
+        db.create_dc(&[]),
+        Err(Error::Rejected(ErrorCode::Unknown))
+    ));
+    assert_eq!(stand_in.dc_nodes(), ["antidote@node1", "antidote@node2"]);
+}
+
+#[test]
+fn get_connection_descriptor() {
+    let stand_in = StandIn::start().unwrap();
+    let mut db = stand_in.connect().unwrap();
+
+    let descriptor = db.get_connection_descriptor().unwrap();
+    assert_eq!(descriptor, stand_in.descriptor());
+    assert!(!descriptor.as_bytes().is_empty());
+}
+
+#[test]
+fn connect_to_dcs() {
+    let dc1 = StandIn::start().unwrap();
+    let dc2 = StandIn::start().unwrap();
+    let descriptors = [
+        dc1.connect().unwrap().get_connection_descriptor().unwrap(),
+        dc2.connect().unwrap().get_connection_descriptor().unwrap(),
+    ];
+    assert_ne!(descriptors[0], descriptors[1]);
+
+    for dc in &[&dc1, &dc2] {
+        dc.connect().unwrap().connect_to_dcs(&descriptors).unwrap();
+        assert_eq!(dc.connected_dcs(), descriptors);
+    }
+
+    // descriptors are opaque, any bytes go through
+    let other = ConnectionDescriptor::from(vec![0, 1, 2]);
+    dc1.connect()
+        .unwrap()
+        .connect_to_dcs(std::slice::from_ref(&other))
+        .unwrap();
+    assert_eq!(dc1.connected_dcs().last(), Some(&other));
+}
@@ -0,0 +1,126 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{LWWReg, MVReg, MapKey, MapT, ORSet, Object, RRMap, ReadT};
+use antidotedb::record::{AntidoteMap, DecodeError};
+use antidotedb::server::StandIn;
+use antidotedb::AntidoteDB;
+
+#[derive(AntidoteMap, Debug, Default, PartialEq)]
+struct Address {
+    city: String,
+    zip: u64,
+}
+
+#[derive(AntidoteMap, Debug, Default, PartialEq)]
+struct User {
+    #[antidote(counter)]
+    visits: i32,
+    #[antidote(orset)]
+    tags: Vec<String>,
+    #[antidote(mvreg, key = "display_name")]
+    names: Vec<String>,
+    #[antidote(flag_ew)]
+    active: bool,
+    #[antidote(map)]
+    address: Address,
+}
+
+fn user() -> User {
+    User {
+        visits: 0,
+        tags: vec!["admin".to_string()],
+        names: vec!["Ada".to_string()],
+        active: true,
+        address: Address {
+            city: "Paris".to_string(),
+            zip: 75001,
+        },
+    }
+}
+
+fn read(db: &mut AntidoteDB, map: &RRMap) -> antidotedb::proto::ApbReadObjectResp {
+    let (mut values, _) = db
+        .static_read_objects(std::slice::from_ref(map), None)
+        .unwrap();
+    values.pop().unwrap()
+}
+
+#[test]
+fn structs_read_back_as_written() {
+    let stand_in = StandIn::start().unwrap();
+    let mut db = stand_in.connect().unwrap();
+    let map = RRMap::new("written", "derive");
+
+    let visit = map.apply(vec![User::visits_key().inc(3)], Vec::new());
+    db.static_update_objects(&[user().write(&map), visit], None)
+        .unwrap();
+
+    let read = User::read(&read(&mut db, &map)).unwrap();
+    assert_eq!(
+        read,
+        User {
+            visits: 3,
+            ..user()
+        }
+    );
+}
+
+#[test]
+fn fields_are_stored_under_their_keys() {
+    let stand_in = StandIn::start().unwrap();
+    let mut db = stand_in.connect().unwrap();
+    let map = RRMap::new("keys", "derive");
+    db.static_update_objects(&[user().write(&map)], None)
+        .unwrap();
+
+    let resp = read(&mut db, &map);
+    let view = RRMap::decode(&resp);
+    let mut keys: Vec<_> = view.iter().map(|entry| entry.key().to_vec()).collect();
+    keys.sort();
+    // the counter is not written, and names is renamed
+    assert_eq!(
+        keys,
+        vec![
+            b"active".to_vec(),
+            b"address".to_vec(),
+            b"display_name".to_vec(),
+            b"tags".to_vec(),
+        ]
+    );
+    assert!(view.entry::<MVReg>("display_name").is_some());
+    assert!(view.entry::<ORSet>("tags").is_some());
+    let address = view.map("address").unwrap();
+    assert_eq!(
+        address.get::<LWWReg>("zip").unwrap().to_vec(),
+        75001u64.to_be_bytes().to_vec()
+    );
+}
+
+#[test]
+fn missing_entries_read_as_defaults() {
+    let stand_in = StandIn::start().unwrap();
+    let mut db = stand_in.connect().unwrap();
+    let map = RRMap::new("empty", "derive");
+
+    let read = User::read(&read(&mut db, &map)).unwrap();
+    assert_eq!(read, User::default());
+}
+
+#[test]
+fn entries_of_the_wrong_shape_are_decode_errors() {
+    let stand_in = StandIn::start().unwrap();
+    let mut db = stand_in.connect().unwrap();
+    let map = RRMap::new("bad", "derive");
+
+    // a u64 register of three bytes
+    let zip = MapKey::<LWWReg>::new("zip").set_value(&"abc".to_string());
+    db.static_update_objects(&[map.apply(vec![zip], Vec::new())], None)
+        .unwrap();
+
+    assert_eq!(
+        Address::read(&read(&mut db, &map)),
+        Err(DecodeError {
+            key: "zip".to_string()
+        })
+    );
+}
@@ -0,0 +1,53 @@
+extern crate antidotedb;
+extern crate serde_json;
+
+use antidotedb::document::{Document, Registers};
+use antidotedb::server::StandIn;
+use antidotedb::AntidoteDB;
+
+use serde_json::{json, Value};
+
+fn apply(db: &mut AntidoteDB, ops: &[antidotedb::proto::ApbUpdateOp]) {
+    db.static_update_objects(ops, None).unwrap();
+}
+
+fn read(db: &mut AntidoteDB, doc: &Document) -> Value {
+    let (values, _) = db
+        .static_read_objects(std::slice::from_ref(doc.object()), None)
+        .unwrap();
+    Document::read(&values[0]).unwrap()
+}
+
+#[test]
+fn documents_read_back_as_written() {
+    let stand_in = StandIn::start().unwrap();
+    let mut db = stand_in.connect().unwrap();
+    let doc = Document::new("order", "documents");
+
+    let order = json!({
+        "customer": { "name": "Ada", "vip": false, "tags": ["a", 1] },
+        "paid": true,
+        "shipped": false,
+        "total": 12.5,
+        "note": "42",
+    });
+    apply(&mut db, &doc.write_ops(&order).unwrap());
+    assert_eq!(read(&mut db, &doc), order);
+}
+
+#[test]
+fn patches_replace_and_remove_fields() {
+    let stand_in = StandIn::start().unwrap();
+    let mut db = stand_in.connect().unwrap();
+    let doc = Document::new("order", "documents").with_registers(Registers::MultiValue);
+
+    let order = json!({ "customer": { "vip": true }, "items": ["apple"], "total": 1 });
+    apply(&mut db, &doc.write_ops(&order).unwrap());
+
+    let patch = json!({ "customer": { "vip": false }, "items": ["pear"], "total": null });
+    apply(&mut db, &doc.patch_ops(&patch).unwrap());
+    assert_eq!(
+        read(&mut db, &doc),
+        json!({ "customer": { "vip": false }, "items": ["pear"] })
+    );
+}
@@ -0,0 +1,44 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{CRDT_type, Object, RRMap};
+use antidotedb::server::StandIn;
+use antidotedb::value::Value;
+
+fn entry(key: &str, crdt_type: CRDT_type, value: Value) -> (Vec<u8>, i32, Value) {
+    (key.as_bytes().to_vec(), crdt_type as i32, value)
+}
+
+#[test]
+fn objects_reach_the_desired_value() {
+    let stand_in = StandIn::start().unwrap();
+    let mut db = stand_in.connect().unwrap();
+    let map = RRMap::new("profile", "reconcile");
+
+    let desired = [
+        Value::Map(vec![
+            entry("n", CRDT_type::COUNTER, Value::Counter(4)),
+            entry("name", CRDT_type::LWWREG, Value::Register(b"ada".to_vec())),
+            entry("tags", CRDT_type::ORSET, Value::Set(vec![b"a".to_vec()])),
+        ]),
+        Value::Map(vec![
+            entry("n", CRDT_type::COUNTER, Value::Counter(-1)),
+            entry("tags", CRDT_type::ORSET, Value::Set(vec![b"b".to_vec()])),
+        ]),
+    ];
+    for desired in &desired {
+        let t = db.start_transaction(None).unwrap();
+        db.reconcile_in_transaction(&map, desired, &t).unwrap();
+        db.commit_transaction(&t).unwrap();
+
+        let (values, _) = db
+            .static_read_objects(std::slice::from_ref(&map), None)
+            .unwrap();
+        assert_eq!(&Value::decode(CRDT_type::RRMAP, &values[0]), desired);
+
+        // nothing left to do
+        let t = db.start_transaction(None).unwrap();
+        let ops = db.reconcile_in_transaction(&map, desired, &t).unwrap();
+        assert!(ops.is_empty());
+        db.commit_transaction(&t).unwrap();
+    }
+}
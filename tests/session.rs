@@ -0,0 +1,136 @@
+extern crate antidotedb;
+
+use antidotedb::crdt::{Counter, CounterT, Object};
+use antidotedb::server::StandIn;
+use antidotedb::session::{CommitTime, Session, SessionError};
+use antidotedb::Error;
+
+fn session(stand_in: &StandIn) -> Session {
+    Session::new(stand_in.connect().expect("failed to connect"))
+}
+
+fn count(session: &mut Session, counter: &Counter) -> i32 {
+    let values = session
+        .static_read_objects(std::slice::from_ref(counter))
+        .expect("failed to read");
+    values[0].get_counter().get_value()
+}
+
+#[test]
+fn static_operations_wait_for_the_open_transaction() {
+    let stand_in = StandIn::start().unwrap();
+    let mut session = session(&stand_in);
+    let counter = Counter::new("serialized", "session");
+
+    let t = session.start_transaction().unwrap();
+    session
+        .mult_update_in_transaction(&[counter.inc(1)], &t)
+        .unwrap();
+    assert!(session.in_transaction());
+    assert!(matches!(
+        session.static_update_objects(&[counter.inc(1)]),
+        Err(Error::Session(SessionError::TransactionOpen))
+    ));
+    assert!(matches!(
+        session.static_read_objects(std::slice::from_ref(&counter)),
+        Err(Error::Session(SessionError::TransactionOpen))
+    ));
+    assert!(matches!(
+        session.start_transaction(),
+        Err(Error::Session(SessionError::TransactionOpen))
+    ));
+
+    session.commit_transaction(&t).unwrap();
+    assert!(!session.in_transaction());
+    session.static_update_objects(&[counter.inc(2)]).unwrap();
+    assert_eq!(count(&mut session, &counter), 3);
+}
+
+#[test]
+fn transactions_end_with_commit_abort_or_reconnect() {
+    let stand_in = StandIn::start().unwrap();
+    let mut session = session(&stand_in);
+    let counter = Counter::new("ended", "session");
+
+    let t = session.start_transaction().unwrap();
+    session.commit_transaction(&t).unwrap();
+    assert!(matches!(
+        session.commit_transaction(&t),
+        Err(Error::Session(SessionError::NotOpen))
+    ));
+
+    let t = session.start_transaction().unwrap();
+    session.abort_transaction(&t).unwrap();
+    assert!(matches!(
+        session.mult_update_in_transaction(&[counter.inc(1)], &t),
+        Err(Error::Session(SessionError::NotOpen))
+    ));
+
+    // the transaction stays with the old connection
+    let t = session.start_transaction().unwrap();
+    session.reconnect(stand_in.connect().unwrap());
+    assert!(matches!(
+        session.mult_update_in_transaction(&[counter.inc(1)], &t),
+        Err(Error::Session(SessionError::NotOpen))
+    ));
+    assert_eq!(count(&mut session, &counter), 0);
+}
+
+#[test]
+fn sessions_read_their_writes() {
+    let stand_in = StandIn::start().unwrap();
+    let mut session = session(&stand_in);
+    let counter = Counter::new("own", "session");
+
+    session.static_update_objects(&[counter.inc(1)]).unwrap();
+    let t = session.start_transaction().unwrap();
+    session
+        .mult_update_in_transaction(&[counter.inc(1)], &t)
+        .unwrap();
+    session.commit_transaction(&t).unwrap();
+    assert_eq!(count(&mut session, &counter), 2);
+
+    // a new session continuing from the token sees them too
+    let token = session.token().unwrap().clone();
+    let mut other = Session::with_token(stand_in.connect().unwrap(), token);
+    assert_eq!(count(&mut other, &counter), 2);
+}
+
+#[test]
+fn resumed_sessions_read_the_writes_before_their_token() {
+    let stand_in = StandIn::start().unwrap();
+    let mut writer = session(&stand_in);
+    let counter = Counter::new("handed_over", "session");
+
+    writer.static_update_objects(&[counter.inc(1)]).unwrap();
+    let first = writer.token().unwrap().clone();
+    let t = writer.start_transaction().unwrap();
+    writer
+        .mult_update_in_transaction(&[counter.inc(2)], &t)
+        .unwrap();
+    writer.commit_transaction(&t).unwrap();
+    let second = writer.token().unwrap().clone();
+
+    // the token crosses to another process as text
+    let token: CommitTime = second.to_token().parse().unwrap();
+    let mut reader = Session::with_token(stand_in.connect().unwrap(), token);
+    let t = reader.start_transaction().unwrap();
+    let values = reader
+        .mult_read_in_transaction(std::slice::from_ref(&counter), &t)
+        .unwrap();
+    assert_eq!(values[0].get_counter().get_value(), 3);
+    reader.commit_transaction(&t).unwrap();
+
+    // an older token does not move the session back, a newer one moves it on
+    reader.observe(first.clone());
+    assert_eq!(reader.token(), Some(&second));
+    writer.static_update_objects(&[counter.inc(4)]).unwrap();
+    let third = writer.token().unwrap().clone();
+    reader.observe(third.clone());
+    assert_eq!(reader.token(), Some(&third));
+    assert_eq!(count(&mut reader, &counter), 7);
+
+    let mut stale = Session::with_token(stand_in.connect().unwrap(), third.clone());
+    stale.observe(first);
+    assert_eq!(stale.token(), Some(&third));
+}